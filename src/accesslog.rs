@@ -0,0 +1,341 @@
+//! Finding which tiles are requested, from web server access logs.
+//!
+//! Logs can be in the Apache/Nginx combined format, or the common format (without the referer &
+//! user agent).
+//!
+//! ```
+//! # use slippy_map_tiles::{Tile, UrlTemplate};
+//! # use slippy_map_tiles::accesslog::TileHits;
+//! let log = r#"203.0.113.9 - - [18/Oct/2026:13:55:36 +0000] "GET /osm/10/547/380.png HTTP/1.1" 200 2326 "-" "Mozilla/5.0"
+//! 203.0.113.9 - - [18/Oct/2026:13:55:37 +0000] "GET /osm/10/547/380.png?v=2 HTTP/1.1" 304 0 "-" "Mozilla/5.0"
+//! 198.51.100.7 - - [18/Oct/2026:13:55:38 +0000] "GET /osm/10/548/380.png HTTP/1.1" 200 512 "-" "curl/8.0"
+//! 198.51.100.7 - - [18/Oct/2026:13:55:39 +0000] "GET /index.html HTTP/1.1" 200 1024 "-" "curl/8.0"
+//! "#;
+//! let mut hits = TileHits::new();
+//! hits.add_template(UrlTemplate::new("/osm/{z}/{x}/{y}.png").unwrap());
+//! hits.read(log.as_bytes()).unwrap();
+//!
+//! assert_eq!(hits.hits(&Tile::new(10, 547, 380).unwrap()), 2);
+//! assert_eq!(hits.other_requests(), 1);
+//! assert_eq!(hits.top(1), vec![(Tile::new(10, 547, 380).unwrap(), 2)]);
+//! assert_eq!(hits.rolled_up(7).get(&Tile::new(7, 68, 47).unwrap()), Some(&3));
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use regex::Regex;
+
+use {Tile, Metatile, MetatilePolicy, UrlTemplate};
+use tilelist::open_input;
+
+/// One request from an access log
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct LogEntry {
+    host: String,
+    time: String,
+    request: String,
+    status: u16,
+    bytes: Option<u64>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl LogEntry {
+    /// Parse a line in the combined or common log format. `None` if it's not in either.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::accesslog::LogEntry;
+    /// let entry = LogEntry::parse(r#"::1 - bob [18/Oct/2026:13:55:36 +0000] "GET /0/0/0.png HTTP/1.1" 404 - "-" "QGIS/3""#).unwrap();
+    /// assert_eq!((entry.method(), entry.path(), entry.status()), (Some("GET"), Some("/0/0/0.png"), 404));
+    /// assert_eq!((entry.bytes(), entry.user_agent()), (None, Some("QGIS/3")));
+    /// ```
+    pub fn parse(line: &str) -> Option<LogEntry> {
+        thread_local! {
+            static RE: Regex = Regex::new(r#"^(?P<host>\S+) \S+ \S+ \[(?P<time>[^\]]*)\] "(?P<request>(?:[^"\\]|\\.)*)" (?P<status>[0-9]{3}) (?P<bytes>[0-9]+|-)(?: "(?P<referer>(?:[^"\\]|\\.)*)" "(?P<agent>(?:[^"\\]|\\.)*)")?"#).unwrap();
+        }
+        RE.with(|re| {
+            let caps = re.captures(line.trim_end())?;
+            Some(LogEntry{
+                host: caps.name("host")?.to_string(),
+                time: caps.name("time")?.to_string(),
+                request: caps.name("request")?.to_string(),
+                status: caps.name("status")?.parse().ok()?,
+                bytes: caps.name("bytes")?.parse().ok(),
+                referer: caps.name("referer").map(|s| s.to_string()),
+                user_agent: caps.name("agent").map(|s| s.to_string()),
+            })
+        })
+    }
+
+    /// The client's address (or hostname)
+    pub fn host(&self) -> &str { &self.host }
+
+    /// When the request was made, as written in the log, e.g. `18/Oct/2026:13:55:36 +0000`
+    pub fn time(&self) -> &str { &self.time }
+
+    /// The request line, e.g. `GET /10/547/380.png HTTP/1.1`
+    pub fn request(&self) -> &str { &self.request }
+
+    /// The HTTP method, if the request line has one
+    pub fn method(&self) -> Option<&str> {
+        self.request.split(' ').next().filter(|m| !m.is_empty() && *m != "-")
+    }
+
+    /// The requested URL, if the request line has one
+    pub fn path(&self) -> Option<&str> {
+        self.request.split(' ').nth(1)
+    }
+
+    /// The HTTP status code
+    pub fn status(&self) -> u16 { self.status }
+
+    /// The size of the response body, `None` for `-`
+    pub fn bytes(&self) -> Option<u64> { self.bytes }
+
+    /// The Referer header (combined format only)
+    pub fn referer(&self) -> Option<&str> { self.referer.as_deref() }
+
+    /// The User-Agent header (combined format only)
+    pub fn user_agent(&self) -> Option<&str> { self.user_agent.as_deref() }
+}
+
+/// How many times each tile was requested.
+///
+/// Request URLs are turned into tiles with the URL templates, or, if none are added, with
+/// `Tile::from_tms`. Query strings are ignored.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct TileHits {
+    pub(crate) templates: Vec<UrlTemplate>,
+    pub(crate) successful_only: bool,
+    pub(crate) hits: HashMap<Tile, u64>,
+    pub(crate) lines: u64,
+    pub(crate) unparsable: u64,
+    pub(crate) other: u64,
+}
+
+/// Sort by most hits, then zoom, x & y, and keep the first `n`
+fn top_n<K: Copy, F: Fn(&K) -> (u8, u32, u32)>(hits: &HashMap<K, u64>, n: usize, zxy: F) -> Vec<(K, u64)> {
+    let mut hits: Vec<(K, u64)> = hits.iter().map(|(k, v)| (*k, *v)).collect();
+    hits.sort_by_key(|&(k, v)| (::std::cmp::Reverse(v), zxy(&k)));
+    hits.truncate(n);
+    hits
+}
+
+impl TileHits {
+    /// No hits, and no templates
+    pub fn new() -> TileHits {
+        TileHits::default()
+    }
+
+    /// Also look for tiles with this template. Templates are tried in the order they're added.
+    pub fn add_template(&mut self, template: UrlTemplate) {
+        self.templates.push(template);
+    }
+
+    /// If true, only count successful requests (status 2xx or 304).
+    pub fn set_successful_only(&mut self, successful_only: bool) {
+        self.successful_only = successful_only;
+    }
+
+    /// The tile at this URL (or path)
+    pub fn tile_for_url(&self, url: &str) -> Option<Tile> {
+        let url = url.split(['?', '#']).next().unwrap_or(url);
+        if self.templates.is_empty() {
+            Tile::from_tms(url)
+        } else {
+            self.templates.iter().filter_map(|t| t.parse(url)).next()
+        }
+    }
+
+    /// Count this request, returning the tile, if it's a request for one.
+    pub fn add_entry(&mut self, entry: &LogEntry) -> Option<Tile> {
+        if self.successful_only && !(200..300).contains(&entry.status()) && entry.status() != 304 {
+            return None;
+        }
+        match entry.path().and_then(|p| self.tile_for_url(p)) {
+            Some(tile) => {
+                *self.hits.entry(tile).or_insert(0) += 1;
+                Some(tile)
+            },
+            None => {
+                self.other += 1;
+                None
+            },
+        }
+    }
+
+    /// Count the request on this log line, returning the tile, if it's a request for one.
+    pub fn add_line(&mut self, line: &str) -> Option<Tile> {
+        self.lines += 1;
+        match LogEntry::parse(line) {
+            Some(entry) => self.add_entry(&entry),
+            None => {
+                self.unparsable += 1;
+                None
+            },
+        }
+    }
+
+    /// Count every request in this log. Logs often have bytes which aren't UTF-8 (e.g. in URLs &
+    /// user agents), which are replaced with U+FFFD.
+    pub fn read<R: BufRead>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            let line = String::from_utf8_lossy(&line);
+            self.add_line(line.trim_end_matches(['\n', '\r']));
+        }
+    }
+
+    /// Count every request in this log file, which may be gzipped. `-` is stdin.
+    pub fn read_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.read(open_input(path)?)
+    }
+
+    /// How many lines have been read
+    pub fn lines(&self) -> u64 { self.lines }
+
+    /// How many lines weren't in a known log format
+    pub fn unparsable(&self) -> u64 { self.unparsable }
+
+    /// How many requests weren't for tiles
+    pub fn other_requests(&self) -> u64 { self.other }
+
+    /// Total requests for tiles
+    pub fn total(&self) -> u64 {
+        self.hits.values().sum()
+    }
+
+    /// How many times this tile was requested
+    pub fn hits(&self, tile: &Tile) -> u64 {
+        self.hits.get(tile).cloned().unwrap_or(0)
+    }
+
+    /// The hits of every requested tile
+    pub fn tile_hits(&self) -> &HashMap<Tile, u64> { &self.hits }
+
+    /// Total hits at each zoom
+    pub fn zoom_hits(&self) -> BTreeMap<u8, u64> {
+        let mut zooms = BTreeMap::new();
+        for (tile, n) in &self.hits {
+            *zooms.entry(tile.zoom()).or_insert(0) += n;
+        }
+        zooms
+    }
+
+    /// The hits of each tile at `minzoom` or above, including the hits of all the tiles inside
+    /// it.
+    pub fn rolled_up(&self, minzoom: u8) -> HashMap<Tile, u64> {
+        let mut rolled = HashMap::new();
+        for (tile, n) in &self.hits {
+            let mut tile = Some(*tile);
+            while let Some(t) = tile.filter(|t| t.zoom() >= minzoom) {
+                *rolled.entry(t).or_insert(0) += n;
+                tile = t.parent();
+            }
+        }
+        rolled
+    }
+
+    /// The hits of each metatile, i.e. of all the tiles in it
+    pub fn metatile_hits(&self, policy: &MetatilePolicy) -> HashMap<Metatile, u64> {
+        let mut metatiles = HashMap::new();
+        for (tile, n) in &self.hits {
+            *metatiles.entry(policy.metatile(tile)).or_insert(0) += n;
+        }
+        metatiles
+    }
+
+    /// The `n` tiles with the most hits, most first
+    pub fn top(&self, n: usize) -> Vec<(Tile, u64)> {
+        top_n(&self.hits, n, |t| (t.zoom(), t.x(), t.y()))
+    }
+
+    /// The `n` metatiles with the most hits, most first, e.g. to render them first
+    pub fn top_metatiles(&self, n: usize, policy: &MetatilePolicy) -> Vec<(Metatile, u64)> {
+        top_n(&self.metatile_hits(policy), n, |mt| (mt.zoom(), mt.x(), mt.y()))
+    }
+
+    /// Write the hits of every tile as CSV, with the columns `z,x,y,hits`, sorted by tile.
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut hits: Vec<_> = self.hits.iter().collect();
+        hits.sort_by_key(|&(t, _)| (t.zoom(), t.x(), t.y()));
+        writeln!(out, "z,x,y,hits")?;
+        for (t, n) in hits {
+            writeln!(out, "{},{},{},{}", t.zoom(), t.x(), t.y(), n)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let entry = LogEntry::parse(r#"203.0.113.9 - - [18/Oct/2026:13:55:36 +0000] "GET /a/3/1/2.png?x=\"y\" HTTP/2.0" 200 99 "https://example.org/map" "Mozilla/5.0 (X11)""#).unwrap();
+        assert_eq!((entry.host(), entry.time()), ("203.0.113.9", "18/Oct/2026:13:55:36 +0000"));
+        assert_eq!((entry.path(), entry.bytes(), entry.referer()), (Some(r#"/a/3/1/2.png?x=\"y\""#), Some(99), Some("https://example.org/map")));
+
+        // Common log format
+        let entry = LogEntry::parse("10.0.0.1 - - [18/Oct/2026:13:55:36 +0000] \"HEAD /3/1/2.png HTTP/1.0\" 304 -").unwrap();
+        assert_eq!((entry.method(), entry.status(), entry.bytes(), entry.user_agent()), (Some("HEAD"), 304, None, None));
+
+        // Garbage from a scanner
+        let entry = LogEntry::parse(r#"10.0.0.1 - - [18/Oct/2026:13:55:36 +0000] "-" 400 0 "-" "-""#).unwrap();
+        assert_eq!((entry.method(), entry.path()), (None, None));
+        assert_eq!(LogEntry::parse("not a log line"), None);
+    }
+
+    #[test]
+    fn hits() {
+        let log = "\
+1.2.3.4 - - [18/Oct/2026:10:00:00 +0000] \"GET /tiles/2/1/1.png HTTP/1.1\" 200 10
+1.2.3.4 - - [18/Oct/2026:10:00:01 +0000] \"GET /tiles/2/1/1.png HTTP/1.1\" 200 10
+1.2.3.4 - - [18/Oct/2026:10:00:02 +0000] \"GET /tiles/2/0/1.png HTTP/1.1\" 404 10
+1.2.3.4 - - [18/Oct/2026:10:00:03 +0000] \"GET /tiles/3/7/7.png HTTP/1.1\" 200 10
+1.2.3.4 - - [18/Oct/2026:10:00:04 +0000] \"GET /tiles/3/0/0.png HTTP/1.1\" 304 0
+1.2.3.4 - - [18/Oct/2026:10:00:05 +0000] \"GET /tiles/9/0/0.png HTTP/1.1\" 200 10
+1.2.3.4 - - [18/Oct/2026:10:00:06 +0000] \"GET /favicon.ico HTTP/1.1\" 200 10
+garbage
+";
+        let mut hits = TileHits::new();
+        hits.add_template(UrlTemplate::new("/tiles/{z}/{x}/{y}.png").unwrap());
+        hits.set_successful_only(true);
+        hits.read(log.as_bytes()).unwrap();
+        assert_eq!((hits.lines(), hits.unparsable(), hits.other_requests(), hits.total()), (8, 1, 1, 5));
+        assert_eq!(hits.zoom_hits().into_iter().collect::<Vec<_>>(), vec![(2, 2), (3, 2), (9, 1)]);
+
+        let t = |s: &str| s.parse::<Tile>().unwrap();
+        assert_eq!(hits.top(2), vec![(t("2/1/1"), 2), (t("3/0/0"), 1)]);
+        let rolled = hits.rolled_up(1);
+        assert_eq!((rolled[&t("1/0/0")], rolled[&t("1/1/1")], rolled.get(&t("0/0/0"))), (4, 1, None));
+
+        let policy = MetatilePolicy::fixed(2, 2).unwrap();
+        let top = hits.top_metatiles(10, &policy);
+        assert_eq!(top.iter().map(|(mt, n)| (mt.to_string(), *n)).collect::<Vec<_>>(),
+            vec![("2 2/0/0".to_string(), 2), ("2 3/0/0".to_string(), 1), ("2 3/6/6".to_string(), 1), ("2 9/0/0".to_string(), 1)]);
+
+        let mut csv = Vec::new();
+        hits.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "z,x,y,hits\n2,1,1,2\n3,0,0,1\n3,7,7,1\n9,0,0,1\n");
+
+        // Without templates, from_tms finds the tiles anywhere
+        let mut hits = TileHits::new();
+        hits.read(log.as_bytes()).unwrap();
+        assert_eq!((hits.total(), hits.hits(&t("2/0/1"))), (6, 1));
+
+        // Not UTF-8
+        let mut hits = TileHits::new();
+        let mut log = b"1.2.3.4 - - [18/Oct/2026:10:00:00 +0000] \"GET /2/1/1.png HTTP/1.1\" 200 10 \"-\" \"Bot\xff\"\r\n".to_vec();
+        log.extend_from_slice(b"1.2.3.4 - - [18/Oct/2026:10:00:01 +0000] \"GET /\xfe\xff.png HTTP/1.1\" 200 10\n\xff\n");
+        hits.read(&log[..]).unwrap();
+        assert_eq!((hits.lines(), hits.unparsable(), hits.other_requests(), hits.total()), (3, 1, 1, 1));
+    }
+}
@@ -0,0 +1,320 @@
+//! ArcGIS Compact Cache V2 `.bundle` files.
+//!
+//! Each bundle has 128×128 tiles, in `Lzz/RrrrrCcccc.bundle`, where `rrrr` & `cccc` are the row
+//! (y) & column (x), in hex, of the top left tile. The file has a 64 byte header, then an index
+//! of 8 byte entries (a 40 bit offset & 24 bit size), then the tiles, each after its 4 byte
+//! size. This assumes the cache uses the usual Web Mercator tiling scheme, with level = zoom.
+//!
+//! For the exploded layout, see `Tile::arcgis_path`.
+//!
+//! ```
+//! # use slippy_map_tiles::Tile;
+//! # use slippy_map_tiles::arcgis::{BundleId, BundleReader, BundleWriter};
+//! use std::io::Cursor;
+//!
+//! let tile = Tile::new(10, 547, 380).unwrap();
+//! let bundle = BundleId::for_tile(&tile);
+//! assert_eq!(bundle.path(), "L10/R0100C0200.bundle");
+//!
+//! let mut writer = BundleWriter::new();
+//! writer.put(bundle.index_of(&tile).unwrap(), b"png data".to_vec()).unwrap();
+//! let mut file = Vec::new();
+//! writer.write_to(&mut file).unwrap();
+//!
+//! let mut reader = BundleReader::new(Cursor::new(file)).unwrap();
+//! assert_eq!(reader.get(bundle.index_of(&tile).unwrap()).unwrap(), Some(b"png data".to_vec()));
+//! ```
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use Tile;
+
+/// How many tiles wide & high a bundle is
+pub const BUNDLE_SIZE: u32 = 128;
+
+const NUM_RECORDS: usize = (BUNDLE_SIZE * BUNDLE_SIZE) as usize;
+const HEADER_SIZE: u64 = 64;
+const INDEX_SIZE: u64 = 8 * NUM_RECORDS as u64;
+const MAX_TILE_SIZE: usize = (1 << 24) - 1;
+const MAX_OFFSET: u64 = (1 << 40) - 1;
+
+/// One bundle: a zoom, and the row & column of its top left tile
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct BundleId {
+    zoom: u8,
+    row: u32,
+    col: u32,
+}
+
+impl BundleId {
+    /// The bundle this tile is in
+    pub fn for_tile(tile: &Tile) -> BundleId {
+        BundleId{ zoom: tile.zoom, row: tile.y - tile.y % BUNDLE_SIZE, col: tile.x - tile.x % BUNDLE_SIZE }
+    }
+
+    /// Parse a bundle path (see `path`). Anything before the `Lzz` directory is ignored. `None`
+    /// if this isn't a valid bundle path.
+    pub fn from_path(path: &str) -> Option<BundleId> {
+        let mut parts = path.rsplit('/');
+        let name = parts.next()?.strip_suffix(".bundle")?;
+        let level = parts.next()?.strip_prefix('L')?;
+        let (row, col) = name.strip_prefix('R')?.split_at(name[1..].find('C')?);
+        if level.len() != 2 || row.len() < 4 || col.len() < 5 {
+            return None;
+        }
+        let zoom: u8 = level.parse().ok()?;
+        let row = u32::from_str_radix(row, 16).ok()?;
+        let col = u32::from_str_radix(&col[1..], 16).ok()?;
+        if row % BUNDLE_SIZE != 0 || col % BUNDLE_SIZE != 0 {
+            return None;
+        }
+        Tile::new(zoom, col, row)?;
+        Some(BundleId{ zoom, row, col })
+    }
+
+    /// Zoom (level) of this bundle
+    pub fn zoom(&self) -> u8 { self.zoom }
+
+    /// Row (y) of the top left tile
+    pub fn row(&self) -> u32 { self.row }
+
+    /// Column (x) of the top left tile
+    pub fn col(&self) -> u32 { self.col }
+
+    /// Path of this bundle file, e.g. `L10/R0100C0200.bundle`
+    pub fn path(&self) -> String {
+        format!("L{:02}/R{:04x}C{:04x}.bundle", self.zoom, self.row, self.col)
+    }
+
+    /// Position of this tile in the bundle's index. `None` if the tile isn't in this bundle.
+    pub fn index_of(&self, tile: &Tile) -> Option<usize> {
+        if BundleId::for_tile(tile) != *self {
+            return None;
+        }
+        Some(((tile.y - self.row) * BUNDLE_SIZE + (tile.x - self.col)) as usize)
+    }
+
+    /// The tile at this position in the index. `None` if the index is too large, or the tile
+    /// would be outside the world (at zooms below 7, bundles are only partly used).
+    pub fn tile_at(&self, index: usize) -> Option<Tile> {
+        if index >= NUM_RECORDS {
+            return None;
+        }
+        let index = index as u32;
+        Tile::new(self.zoom, self.col + index % BUNDLE_SIZE, self.row + index / BUNDLE_SIZE)
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32(buf: &[u8], at: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[at..at+4]);
+    u32::from_le_bytes(b)
+}
+
+/// Reads tiles from a bundle file
+#[derive(Debug)]
+pub struct BundleReader<R> {
+    reader: R,
+    // (offset, size) of each tile
+    index: Vec<(u64, u32)>,
+}
+
+impl<R: Read + Seek> BundleReader<R> {
+    /// Read the header & index. `Err` if it isn't a Compact Cache V2 bundle.
+    pub fn new(mut reader: R) -> io::Result<BundleReader<R>> {
+        let mut header = [0; HEADER_SIZE as usize];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+        if read_u32(&header, 0) != 3 || read_u32(&header, 4) as usize != NUM_RECORDS || read_u32(&header, 12) != 5 {
+            return Err(invalid_data("not a compact cache v2 bundle"));
+        }
+
+        let mut raw_index = vec![0; INDEX_SIZE as usize];
+        reader.read_exact(&mut raw_index)?;
+        let index = raw_index.chunks(8).map(|entry| {
+            let mut b = [0; 8];
+            b.copy_from_slice(entry);
+            let entry = u64::from_le_bytes(b);
+            (entry & MAX_OFFSET, (entry >> 40) as u32)
+        }).collect();
+
+        Ok(BundleReader{ reader, index })
+    }
+
+    /// The data for the tile at this position (see `BundleId::index_of`). `None` if there is no
+    /// tile there.
+    pub fn get(&mut self, index: usize) -> io::Result<Option<Vec<u8>>> {
+        let (offset, size) = match self.index.get(index) {
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "index is outside the bundle")),
+            Some(&(_, 0)) => return Ok(None),
+            Some(&entry) => entry,
+        };
+        let mut data = vec![0; size as usize];
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// The positions in the index which have a tile
+    pub fn indexes(&self) -> Vec<usize> {
+        self.index.iter().enumerate().filter(|&(_, &(_, size))| size > 0).map(|(i, _)| i).collect()
+    }
+}
+
+/// Builds a bundle file, in memory.
+#[derive(Debug, Clone, Default)]
+pub struct BundleWriter {
+    tiles: Vec<Option<Vec<u8>>>,
+}
+
+impl BundleWriter {
+    /// An empty bundle
+    pub fn new() -> BundleWriter {
+        BundleWriter{ tiles: vec![None; NUM_RECORDS] }
+    }
+
+    /// Start with all the tiles from this bundle, to change some of them.
+    pub fn from_reader<R: Read + Seek>(reader: &mut BundleReader<R>) -> io::Result<BundleWriter> {
+        let mut writer = BundleWriter::new();
+        for i in reader.indexes() {
+            writer.tiles[i] = reader.get(i)?;
+        }
+        Ok(writer)
+    }
+
+    /// Set the tile at this position (see `BundleId::index_of`). `Err` if the index is outside
+    /// the bundle, or the tile is 16MiB or larger.
+    pub fn put(&mut self, index: usize, data: Vec<u8>) -> io::Result<()> {
+        if index >= NUM_RECORDS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "index is outside the bundle"));
+        }
+        if data.len() > MAX_TILE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "tile is too large for a bundle"));
+        }
+        self.tiles[index] = Some(data);
+        Ok(())
+    }
+
+    /// Remove the tile at this position, returning it, if there was one
+    pub fn remove(&mut self, index: usize) -> Option<Vec<u8>> {
+        self.tiles.get_mut(index).and_then(|t| t.take())
+    }
+
+    /// Write the whole bundle file
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let data_size: u64 = self.tiles.iter().flatten().map(|t| 4 + t.len() as u64).sum();
+        let file_size = HEADER_SIZE + INDEX_SIZE + data_size;
+        if file_size > MAX_OFFSET {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "bundle is too large"));
+        }
+        let max_tile_size = self.tiles.iter().flatten().map(|t| t.len()).max().unwrap_or(0) as u32;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        for v in &[3u32, NUM_RECORDS as u32, max_tile_size, 5] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        // Slack space, file size, user header offset
+        for v in &[0u64, file_size, 40] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        // User header size, then the legacy fields & index size
+        for v in &[20 + INDEX_SIZE as u32, 3, 16, NUM_RECORDS as u32, 5, INDEX_SIZE as u32] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        w.write_all(&header)?;
+
+        let mut offset = HEADER_SIZE + INDEX_SIZE;
+        let mut index = Vec::with_capacity(INDEX_SIZE as usize);
+        for tile in &self.tiles {
+            let entry = match *tile {
+                None => 0,
+                Some(ref data) => {
+                    // The offset is of the data, after its size
+                    offset += 4;
+                    let entry = offset | (data.len() as u64) << 40;
+                    offset += data.len() as u64;
+                    entry
+                },
+            };
+            index.extend_from_slice(&entry.to_le_bytes());
+        }
+        w.write_all(&index)?;
+
+        for data in self.tiles.iter().flatten() {
+            w.write_all(&(data.len() as u32).to_le_bytes())?;
+            w.write_all(data)?;
+        }
+        w.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn bundle_id() {
+        let tile = Tile::new(10, 547, 380).unwrap();
+        let bundle = BundleId::for_tile(&tile);
+        assert_eq!((bundle.zoom(), bundle.row(), bundle.col()), (10, 256, 512));
+        assert_eq!(bundle.index_of(&tile), Some(124 * 128 + 35));
+        assert_eq!(bundle.tile_at(124 * 128 + 35), Some(tile));
+        assert_eq!(bundle.index_of(&Tile::new(10, 500, 380).unwrap()), None);
+        assert_eq!(bundle.tile_at(NUM_RECORDS), None);
+
+        assert_eq!(BundleId::from_path("/data/_alllayers/L10/R0100C0200.bundle"), Some(bundle));
+        assert_eq!(BundleId::from_path("L10/R0100C0200.bundle"), Some(bundle));
+        assert_eq!(BundleId::from_path("L20/R1ff80C00080.bundle").map(|b| b.path()), Some("L20/R1ff80C0080.bundle".to_string()));
+        assert_eq!(BundleId::from_path("L10/R0101C0200.bundle"), None);
+        assert_eq!(BundleId::from_path("L10/R0100C0200.bundlx"), None);
+        assert_eq!(BundleId::from_path("L03/R0080C0000.bundle"), None);
+
+        // At low zooms, the one bundle is only partly used
+        let bundle = BundleId::for_tile(&Tile::new(1, 1, 1).unwrap());
+        assert_eq!(bundle.path(), "L01/R0000C0000.bundle");
+        assert_eq!(bundle.tile_at(129), Tile::new(1, 1, 1));
+        assert_eq!(bundle.tile_at(2), None);
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let mut writer = BundleWriter::new();
+        writer.put(0, vec![1, 2, 3]).unwrap();
+        writer.put(5000, vec![4; 1000]).unwrap();
+        writer.put(NUM_RECORDS - 1, vec![5]).unwrap();
+        writer.put(7, vec![6]).unwrap();
+        assert_eq!(writer.remove(7), Some(vec![6]));
+        assert!(writer.put(NUM_RECORDS, vec![]).is_err());
+
+        let mut file = Vec::new();
+        writer.write_to(&mut file).unwrap();
+        assert_eq!(file.len() as u64, HEADER_SIZE + INDEX_SIZE + 4 + 3 + 4 + 1000 + 4 + 1);
+        assert_eq!(read_u32(&file, 8), 1000);
+        assert_eq!(&file[HEADER_SIZE as usize..HEADER_SIZE as usize + 8], &[0x44, 0x00, 0x02, 0x00, 0x00, 0x03, 0x00, 0x00]);
+
+        let mut reader = BundleReader::new(Cursor::new(file.clone())).unwrap();
+        assert_eq!(reader.indexes(), vec![0, 5000, NUM_RECORDS - 1]);
+        assert_eq!(reader.get(0).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(reader.get(5000).unwrap(), Some(vec![4; 1000]));
+        assert_eq!(reader.get(NUM_RECORDS - 1).unwrap(), Some(vec![5]));
+        assert_eq!(reader.get(7).unwrap(), None);
+        assert!(reader.get(NUM_RECORDS).is_err());
+
+        let mut writer = BundleWriter::from_reader(&mut reader).unwrap();
+        writer.put(1, vec![9, 9]).unwrap();
+        let mut file2 = Vec::new();
+        writer.write_to(&mut file2).unwrap();
+        let mut reader = BundleReader::new(Cursor::new(file2)).unwrap();
+        assert_eq!(reader.indexes(), vec![0, 1, 5000, NUM_RECORDS - 1]);
+        assert_eq!(reader.get(5000).unwrap(), Some(vec![4; 1000]));
+
+        file[0] = 2;
+        assert!(BundleReader::new(Cursor::new(file)).is_err());
+        assert!(BundleReader::new(Cursor::new(vec![0; 10])).is_err());
+    }
+}
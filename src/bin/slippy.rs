@@ -0,0 +1,838 @@
+//! `slippy` - slippy map tile maths on the command line.
+//!
+//! Run `slippy --help` for usage.
+extern crate slippy_map_tiles;
+extern crate serde_json;
+#[cfg(test)]
+extern crate tempfile;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use serde_json::{Value, Map};
+use slippy_map_tiles::{Tile, Metatile, MetatilesIterator, MetatilePolicy, BBox, LatLon, TileRange, lat_lon_to_tile, f32_json};
+use slippy_map_tiles::{TileStore, FsTileStore, FsLayout};
+use slippy_map_tiles::migrate::Migration;
+use slippy_map_tiles::inventory::Inventory;
+use slippy_map_tiles::estimate::{Estimator, Sample};
+use slippy_map_tiles::renderd::RenderListIterator;
+use slippy_map_tiles::accesslog::TileHits;
+use slippy_map_tiles::UrlTemplate;
+use slippy_map_tiles::{TileListWriter, TileListFormat};
+#[cfg(feature="mbtiles")]
+use slippy_map_tiles::mbtiles::MbTilesStore;
+#[cfg(feature="server")]
+use slippy_map_tiles::server::TileServer;
+#[cfg(feature="download")]
+use slippy_map_tiles::download::Downloader;
+
+const USAGE: &str = "Usage: slippy [--format text|csv|json] COMMAND [ARGS]
+
+Commands:
+    point-to-tile LAT LON ZOOM          The tile which contains this point
+    tile-bbox [TILE...]                 The bbox of each tile, as MINLON MINLAT MAXLON MAXLAT
+    tile-corners [TILE...]              The NW, NE, SE & SW corners of each tile, as LAT LON
+    bbox-tiles [--count] BBOX MINZOOM [MAXZOOM]
+                                        The tiles in this bbox (MINLON,MINLAT,MAXLON,MAXLAT),
+                                        or how many there are at each zoom
+    metatiles SCALE BBOX MINZOOM [MAXZOOM]
+                                        The metatiles, of this scale (e.g. 8, or 4x2), in
+                                        this bbox
+    render-list BBOX MINZOOM [MAXZOOM]  The metatiles in this bbox, in the same order as
+                                        mod_tile's render_list -a (8x8 metatiles, stepping 8
+                                        tiles from the bbox's top left tile at each zoom)
+    path [--ext EXT] LAYOUT [TILE...]   The path of each tile in this layout (tc, mp, ts, mt,
+                                        zxy, arcgis, gwc, mapcache, worldwind). EXT defaults
+                                        to png
+    quadkey [TILE|QUADKEY...]           Convert tiles to quadkeys, and quadkeys to tiles
+    parent [TILE...]                    The parent of each tile
+    children [TILE...]                  The 4 children of each tile
+    migrate [--dry-run] [--dedup] [--bbox BBOX] [--ext EXT] SRC DST [MINZOOM [MAXZOOM]]
+                                        Copy the tiles in the SRC cache to DST. Caches are
+                                        LAYOUT:PATH, with LAYOUT one of zxy, tc, mp, ts, meta
+                                        (mod_tile) or mbtiles. --dedup shares the data of
+                                        identical tiles. Files which aren't tiles are listed
+                                        on stderr
+    inventory [--ext EXT] [--missing FILE] CACHE BBOX MINZOOM [MAXZOOM]
+                                        For each zoom, how many tiles in this bbox should be in
+                                        the CACHE (LAYOUT:PATH, as for migrate), are there, are
+                                        missing, are there but outside the bbox, or are empty.
+                                        --missing writes the missing & empty tiles to FILE, to
+                                        render them
+    estimate [--tile-size BYTES] [--land RATIO] [--seconds LAND[,SEA]] [--sample CACHE]
+             BBOX MINZOOM [MAXZOOM]     For each zoom, how many tiles, metatiles (of scale 8)
+                                        and land tiles are in this bbox, the bytes they take,
+                                        and the hours to render them. BYTES is the average size
+                                        of a land tile, RATIO the fraction of tiles which are
+                                        land, and LAND & SEA the seconds to render a land or
+                                        sea metatile. --sample reads sizes & land ratio from
+                                        up to 1000 tiles per zoom in an existing CACHE
+    hits [--count] [--top N] [--template TEMPLATE...] [LOG...]
+                                        The tiles requested in these web server access logs
+                                        (combined or common format, maybe gzipped), and how
+                                        often, most first, or with --count the hits at each
+                                        zoom. URLs are matched with the URL templates (e.g.
+                                        /osm/{z}/{x}/{y}.png), or else as .../z/x/y.ext
+    serve [--ext EXT] [--template TEMPLATE...] CACHE [ADDR]
+                                        Serve the tiles in the CACHE over HTTP at ADDR (default
+                                        127.0.0.1:8080), as /{z}/{x}/{y}.EXT and at the URL
+                                        templates (e.g. /tms/{z}/{x}/{-y}.png or
+                                        /{quadkey}.png), with a TileJSON at /tilejson.json
+    download [--concurrency N] [--rate N] [--refresh] [--user-agent UA] [--ext EXT]
+             TEMPLATE CACHE BBOX MINZOOM [MAXZOOM]
+                                        Download the tiles in this bbox from the URL TEMPLATE
+                                        (e.g. https://tiles.example.com/{z}/{x}/{y}.png) into
+                                        the CACHE, making at most N (default 2) requests at
+                                        once and --rate (default 10, 0 for no limit) a second.
+                                        Tiles already in the CACHE are skipped, or with
+                                        --refresh requested again if they've changed
+
+TILEs are given as z/x/y. If none are given, they are read from stdin, one per line.
+
+Output is one line per result. --format text (the default) separates fields with spaces, csv
+writes a header and comma separated fields, and json writes one JSON object per line.";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+/// One line of output, a list of field names & values
+type Row = Vec<(&'static str, Value)>;
+
+/// Writes rows in the chosen format
+struct Output<W: Write> {
+    format: Format,
+    out: W,
+    header_written: bool,
+}
+
+impl<W: Write> Output<W> {
+    fn new(format: Format, out: W) -> Self {
+        Output{ format, out, header_written: false }
+    }
+
+    fn write(&mut self, row: Row) -> io::Result<()> {
+        match self.format {
+            Format::Text => {
+                let values: Vec<String> = row.iter().map(|(_, v)| value_to_string(v)).collect();
+                writeln!(self.out, "{}", values.join(" "))
+            },
+            Format::Csv => {
+                if !self.header_written {
+                    let names: Vec<&str> = row.iter().map(|(k, _)| *k).collect();
+                    writeln!(self.out, "{}", names.join(","))?;
+                    self.header_written = true;
+                }
+                let values: Vec<String> = row.iter().map(|(_, v)| csv_field(value_to_string(v))).collect();
+                writeln!(self.out, "{}", values.join(","))
+            },
+            Format::Json => {
+                let obj: Map<String, Value> = row.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+                writeln!(self.out, "{}", Value::Object(obj))
+            },
+        }
+    }
+}
+
+fn value_to_string(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// A CSV field, quoted if it has a comma, quote or line break in it
+fn csv_field(s: String) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s
+    }
+}
+
+fn tile_value(t: &Tile) -> Value {
+    Value::String(t.zxy())
+}
+
+/// A tile, as z/x/y
+fn parse_tile(s: &str) -> Result<Tile, String> {
+    s.trim().parse().map_err(|e| format!("invalid tile {:?}: {}", s.trim(), e))
+}
+
+/// A bbox. Ones which cross the antimeridian, or are upside down, are refused, since they have
+/// no tiles (as with `TileRange::new_for_bbox`, and in every command).
+fn parse_bbox(s: &str) -> Result<BBox, String> {
+    let bbox: BBox = s.parse().map_err(|_| format!("invalid bbox {:?}, expected MINLON,MINLAT,MAXLON,MAXLAT", s))?;
+    if bbox.left() > bbox.right() || bbox.bottom() > bbox.top() {
+        return Err(format!("invalid bbox {:?}, MINLON & MINLAT can't be more than MAXLON & MAXLAT", s));
+    }
+    Ok(bbox)
+}
+
+fn parse_zoom(s: &str) -> Result<u8, String> {
+    match s.parse() {
+        Ok(z) if z <= 31 => Ok(z),
+        _ => Err(format!("invalid zoom {:?}", s)),
+    }
+}
+
+fn parse_f32(s: &str, what: &str) -> Result<f32, String> {
+    s.parse().map_err(|_| format!("invalid {} {:?}", what, s))
+}
+
+/// The tile (or other) arguments, or else the non-blank lines of stdin. Each item is passed to
+/// `f`. Errors in stdin lines include the line number.
+fn for_each_input<F>(args: &[String], mut f: F) -> Result<(), String>
+    where F: FnMut(&str) -> Result<(), String>
+{
+    if !args.is_empty() && args != ["-"] {
+        return args.iter().try_for_each(|a| f(a));
+    }
+
+    let stdin = io::stdin();
+    for (i, line) in stdin.lock().lines().enumerate() {
+        let line = line.map_err(|e| format!("reading stdin: {}", e))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        f(line).map_err(|e| format!("line {}: {}", i+1, e))?;
+    }
+    Ok(())
+}
+
+fn for_each_tile<F>(args: &[String], mut f: F) -> Result<(), String>
+    where F: FnMut(Tile) -> Result<(), String>
+{
+    for_each_input(args, |s| f(parse_tile(s)?))
+}
+
+fn io_err(e: io::Error) -> String {
+    e.to_string()
+}
+
+/// Open a `LAYOUT:PATH` tile cache. `create` is false for caches which should already exist.
+fn open_store(spec: &str, ext: &str, create: bool) -> Result<Box<dyn TileStore>, String> {
+    let (layout, path) = match spec.find(':') {
+        Some(i) => (&spec[..i], &spec[i+1..]),
+        None => return Err(format!("invalid cache {:?}, expected LAYOUT:PATH", spec)),
+    };
+    if !create && !std::path::Path::new(path).exists() {
+        return Err(format!("{} doesn't exist", path));
+    }
+    if layout == "mbtiles" {
+        return open_mbtiles(path, ext, create);
+    }
+    let layout: FsLayout = layout.parse().map_err(|e| format!("{} in {:?}", e, spec))?;
+    Ok(Box::new(FsTileStore::new(path, layout, ext)))
+}
+
+#[cfg(feature="mbtiles")]
+fn open_mbtiles(path: &str, ext: &str, create: bool) -> Result<Box<dyn TileStore>, String> {
+    let store = MbTilesStore::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    if create {
+        // Required by the spec
+        let name = std::path::Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("tiles");
+        for &(key, value) in &[("name", name), ("format", ext)] {
+            if store.metadata_value(key).map_err(io_err)?.is_none() {
+                store.set_metadata_value(key, value).map_err(io_err)?;
+            }
+        }
+        store.flush().map_err(io_err)?;
+    }
+    Ok(Box::new(store))
+}
+
+#[cfg(not(feature="mbtiles"))]
+fn open_mbtiles(_path: &str, _ext: &str, _create: bool) -> Result<Box<dyn TileStore>, String> {
+    Err("mbtiles needs slippy to be built with the mbtiles feature".to_string())
+}
+
+#[cfg(feature="server")]
+fn serve(store: Box<dyn TileStore>, ext: &str, templates: Vec<UrlTemplate>, addr: &str) -> Result<(), String> {
+    let mut server = TileServer::new(store, ext);
+    for template in templates {
+        server.add_template(template);
+    }
+    let listener = std::net::TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+    eprintln!("Serving tiles at http://{}/", addr);
+    server.serve(listener).map_err(io_err)
+}
+
+/// How to download tiles
+#[derive(Debug, Default)]
+#[cfg_attr(not(feature="download"), allow(dead_code))]
+struct DownloadOptions {
+    concurrency: Option<usize>,
+    rate: Option<f64>,
+    refresh: bool,
+    user_agent: Option<String>,
+}
+
+/// Download tiles, returning the stats' row
+#[cfg(feature="download")]
+fn download(template: UrlTemplate, store: &dyn TileStore, bbox: &BBox, zooms: (u8, u8), options: DownloadOptions) -> Result<Row, String> {
+    let mut downloader = Downloader::new(template);
+    if let Some(concurrency) = options.concurrency {
+        downloader.set_concurrency(concurrency);
+    }
+    if let Some(rate) = options.rate {
+        downloader.set_rate_limit(Some(rate))?;
+    }
+    if let Some(user_agent) = options.user_agent {
+        downloader.set_user_agent(&user_agent);
+    }
+    downloader.set_refresh(options.refresh);
+    let stats = downloader.download(store, bbox, zooms.0, zooms.1).map_err(io_err)?;
+    for (tile, error) in stats.failures() {
+        eprintln!("failed: {} {}", tile.zxy(), error);
+    }
+    Ok(vec![
+        ("fetched", Value::from(stats.fetched())), ("not_modified", Value::from(stats.not_modified())),
+        ("skipped", Value::from(stats.skipped())), ("missing", Value::from(stats.missing())),
+        ("failed", Value::from(stats.failed())), ("bytes", Value::from(stats.bytes())),
+    ])
+}
+
+#[cfg(not(feature="download"))]
+fn download(_template: UrlTemplate, _store: &dyn TileStore, _bbox: &BBox, _zooms: (u8, u8), _options: DownloadOptions) -> Result<Row, String> {
+    Err("download needs slippy to be built with the download feature".to_string())
+}
+
+#[cfg(not(feature="server"))]
+fn serve(_store: Box<dyn TileStore>, _ext: &str, _templates: Vec<UrlTemplate>, _addr: &str) -> Result<(), String> {
+    Err("serve needs slippy to be built with the server feature".to_string())
+}
+
+fn run<W: Write>(args: &[String], out: W) -> Result<(), String> {
+    let mut format = Format::Text;
+    let mut count = false;
+    let mut ext = "png".to_string();
+    let mut dry_run = false;
+    let mut dedup = false;
+    let mut bbox_filter = None;
+    let mut missing_file = None;
+    let mut tile_size = None;
+    let mut land_ratio = None;
+    let mut render_seconds = None;
+    let mut sample_cache = None;
+    let mut top = None;
+    let mut templates = Vec::new();
+    let mut download_options = DownloadOptions::default();
+    let mut positional: Vec<String> = Vec::new();
+    // Options which only some commands take, checked once we know the command
+    let mut options: Vec<&str> = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            },
+            "-f" | "--format" => {
+                format = match args.next().map(|s| s.as_str()) {
+                    Some("text") => Format::Text,
+                    Some("csv") => Format::Csv,
+                    Some("json") => Format::Json,
+                    _ => return Err("--format must be one of text, csv or json".to_string()),
+                };
+                continue;
+            },
+            "--count" => { count = true; },
+            "--dry-run" => { dry_run = true; },
+            "--dedup" => { dedup = true; },
+            "--missing" => {
+                missing_file = Some(args.next().ok_or("--missing needs a value")?.clone());
+            },
+            "--bbox" => {
+                bbox_filter = Some(parse_bbox(args.next().ok_or("--bbox needs a value")?)?);
+            },
+            "--tile-size" => {
+                let v = args.next().ok_or("--tile-size needs a value")?;
+                tile_size = Some(v.parse::<u64>().map_err(|_| format!("invalid tile size {:?}", v))?);
+            },
+            "--land" => {
+                let v = args.next().ok_or("--land needs a value")?;
+                land_ratio = Some(parse_f32(v, "land ratio")? as f64);
+            },
+            "--seconds" => {
+                let v = args.next().ok_or("--seconds needs a value")?;
+                let mut parts = v.splitn(2, ',');
+                let land = parse_f32(parts.next().unwrap_or(""), "seconds")? as f64;
+                let sea = match parts.next() { Some(s) => parse_f32(s, "seconds")? as f64, None => land };
+                render_seconds = Some((land, sea));
+            },
+            "--sample" => {
+                sample_cache = Some(args.next().ok_or("--sample needs a value")?.clone());
+            },
+            "--top" => {
+                let v = args.next().ok_or("--top needs a value")?;
+                top = Some(v.parse::<usize>().map_err(|_| format!("invalid number {:?}", v))?);
+            },
+            "--template" => {
+                let v = args.next().ok_or("--template needs a value")?;
+                templates.push(UrlTemplate::new(v).map_err(|e| format!("{}: {}", e, v))?);
+            },
+            "--concurrency" => {
+                let v = args.next().ok_or("--concurrency needs a value")?;
+                download_options.concurrency = Some(v.parse::<usize>().map_err(|_| format!("invalid number {:?}", v))?);
+            },
+            "--rate" => {
+                let v = args.next().ok_or("--rate needs a value")?;
+                // 0 is no limit
+                download_options.rate = Some(parse_f32(v, "rate")? as f64);
+            },
+            "--refresh" => { download_options.refresh = true; },
+            "--user-agent" => {
+                download_options.user_agent = Some(args.next().ok_or("--user-agent needs a value")?.clone());
+            },
+            "--ext" => {
+                ext = args.next().ok_or("--ext needs a value")?.clone();
+            },
+            // Negative numbers, like longitudes & bboxes, aren't options
+            a if a.starts_with('-') && a.len() > 1 && !a[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                return Err(format!("unknown option {}", a));
+            },
+            _ => {
+                positional.push(arg.clone());
+                continue;
+            },
+        }
+        options.push(arg);
+    }
+
+    let (command, rest) = positional.split_first().ok_or("no command given, see --help")?;
+    if let Some(allowed) = command_options(command) {
+        if let Some(option) = options.iter().find(|o| !allowed.contains(o)) {
+            return Err(format!("{} doesn't take {}, see --help", command, option));
+        }
+    }
+    let mut out = Output::new(format, out);
+
+    match command.as_str() {
+        "point-to-tile" => {
+            if rest.len() != 3 {
+                return Err("point-to-tile needs LAT LON ZOOM".to_string());
+            }
+            let lat = parse_f32(&rest[0], "latitude")?;
+            let lon = parse_f32(&rest[1], "longitude")?;
+            let zoom = parse_zoom(&rest[2])?;
+            let point = LatLon::new(lat, lon).ok_or("invalid lat/lon")?;
+            let max = (1u64 << zoom) as u32 - 1;
+            let (x, y) = lat_lon_to_tile(point.lat(), point.lon(), zoom);
+            let tile = Tile::new(zoom, x.min(max), y.min(max)).ok_or("invalid tile")?;
+            out.write(vec![("tile", tile_value(&tile))]).map_err(io_err)
+        },
+
+        "tile-bbox" => for_each_tile(rest, |t| {
+            out.write(vec![
+                ("tile", tile_value(&t)),
+                ("minlon", f32_json(t.left())), ("minlat", f32_json(t.bottom())),
+                ("maxlon", f32_json(t.right())), ("maxlat", f32_json(t.top())),
+            ]).map_err(io_err)
+        }),
+
+        "tile-corners" => for_each_tile(rest, |t| {
+            let (nw, ne, se, sw) = (t.nw_corner(), t.ne_corner(), t.se_corner(), t.sw_corner());
+            out.write(vec![
+                ("tile", tile_value(&t)),
+                ("nw_lat", f32_json(nw.lat())), ("nw_lon", f32_json(nw.lon())),
+                ("ne_lat", f32_json(ne.lat())), ("ne_lon", f32_json(ne.lon())),
+                ("se_lat", f32_json(se.lat())), ("se_lon", f32_json(se.lon())),
+                ("sw_lat", f32_json(sw.lat())), ("sw_lon", f32_json(sw.lon())),
+            ]).map_err(io_err)
+        }),
+
+        "bbox-tiles" => {
+            if rest.len() != 2 && rest.len() != 3 {
+                return Err("bbox-tiles needs BBOX MINZOOM [MAXZOOM]".to_string());
+            }
+            let bbox = parse_bbox(&rest[0])?;
+            let minzoom = parse_zoom(&rest[1])?;
+            let maxzoom = match rest.get(2) { Some(z) => parse_zoom(z)?, None => minzoom };
+            for zoom in minzoom..=maxzoom {
+                let range = TileRange::new_for_bbox(&bbox, zoom).ok_or("invalid bbox")?;
+                if count {
+                    out.write(vec![("zoom", Value::from(zoom)), ("count", Value::from(range.num_tiles()))]).map_err(io_err)?;
+                } else {
+                    for tile in range.iter() {
+                        out.write(vec![("tile", tile_value(&tile))]).map_err(io_err)?;
+                    }
+                }
+            }
+            Ok(())
+        },
+
+        "metatiles" => {
+            if rest.len() != 3 && rest.len() != 4 {
+                return Err("metatiles needs SCALE BBOX MINZOOM [MAXZOOM]".to_string());
+            }
+            let size: Option<(u8, u8)> = match rest[0].find('x') {
+                None => rest[0].parse().ok().map(|s| (s, s)),
+                Some(i) => rest[0][..i].parse().ok().and_then(|w| rest[0][i+1..].parse().ok().map(|h| (w, h))),
+            };
+            let policy = match size.and_then(|(w, h)| MetatilePolicy::fixed(w, h)) {
+                Some(p) => p,
+                None => return Err(format!("invalid metatile scale {:?}", rest[0])),
+            };
+            let bbox = parse_bbox(&rest[1])?;
+            let minzoom = parse_zoom(&rest[2])?;
+            let maxzoom = match rest.get(3) { Some(z) => parse_zoom(z)?, None => minzoom };
+            for mt in MetatilesIterator::new_for_policy(policy, &Some(bbox), minzoom, maxzoom) {
+                out.write(metatile_row(&mt)).map_err(io_err)?;
+            }
+            Ok(())
+        },
+
+        "render-list" => {
+            if rest.len() != 2 && rest.len() != 3 {
+                return Err("render-list needs BBOX MINZOOM [MAXZOOM]".to_string());
+            }
+            let bbox = parse_bbox(&rest[0])?;
+            let minzoom = parse_zoom(&rest[1])?;
+            let maxzoom = match rest.get(2) { Some(z) => parse_zoom(z)?, None => minzoom };
+            for mt in RenderListIterator::new_for_bbox(&bbox, minzoom, maxzoom) {
+                out.write(metatile_row(&mt)).map_err(io_err)?;
+            }
+            Ok(())
+        },
+
+        "path" => {
+            let (layout, tiles) = rest.split_first().ok_or("path needs a LAYOUT")?;
+            let path: fn(&Tile, &str) -> String = match layout.as_str() {
+                "tc" => |t, ext| t.tc_path(ext),
+                "mp" => |t, ext| t.mp_path(ext),
+                "ts" => |t, ext| t.ts_path(ext),
+                "mt" => |t, ext| t.mt_path(ext),
+                "zxy" => |t, ext| t.zxy_path(ext),
+                "arcgis" => |t, ext| t.arcgis_path(ext),
+                "gwc" => |t, ext| t.gwc_path(ext),
+                "mapcache" => |t, ext| t.mapcache_path(ext),
+                "worldwind" => |t, ext| t.worldwind_path(ext),
+                _ => return Err(format!("unknown layout {:?}, expected tc, mp, ts, mt, zxy, arcgis, gwc, mapcache or worldwind", layout)),
+            };
+            for_each_tile(tiles, |t| {
+                out.write(vec![("tile", tile_value(&t)), ("path", Value::String(path(&t, &ext)))]).map_err(io_err)
+            })
+        },
+
+        "quadkey" => for_each_input(rest, |s| {
+            let t = if s.contains('/') {
+                parse_tile(s)?
+            } else {
+                Tile::from_quadkey(s).ok_or_else(|| format!("invalid quadkey {:?}", s))?
+            };
+            out.write(vec![("tile", tile_value(&t)), ("quadkey", Value::String(t.quadkey()))]).map_err(io_err)
+        }),
+
+        "parent" => for_each_tile(rest, |t| {
+            let parent = t.parent().ok_or_else(|| format!("{} has no parent", t.zxy()))?;
+            out.write(vec![("tile", tile_value(&parent))]).map_err(io_err)
+        }),
+
+        "children" => for_each_tile(rest, |t| {
+            let children = t.subtiles().ok_or_else(|| format!("{} has no children", t.zxy()))?;
+            children.iter().try_for_each(|c| out.write(vec![("tile", tile_value(c))]).map_err(io_err))
+        }),
+
+        "migrate" => {
+            if rest.len() < 2 || rest.len() > 4 {
+                return Err("migrate needs SRC DST [MINZOOM [MAXZOOM]]".to_string());
+            }
+            let mut migration = Migration::new();
+            migration.set_dry_run(dry_run);
+            migration.set_dedup(dedup);
+            migration.set_bbox(bbox_filter);
+            if let Some(minzoom) = rest.get(2) {
+                let minzoom = parse_zoom(minzoom)?;
+                let maxzoom = match rest.get(3) { Some(z) => parse_zoom(z)?, None => minzoom };
+                migration.set_zooms(minzoom, maxzoom);
+            }
+
+            let src = open_store(&rest[0], &ext, false)?;
+            // A dry run doesn't write anything, so doesn't need (or create) the destination
+            let dst = if dry_run { None } else { Some(open_store(&rest[1], &ext, true)?) };
+            let report = migration.run(&*src, dst.as_deref()).map_err(io_err)?;
+            for path in report.unparsable() {
+                eprintln!("unparsable: {}", path);
+            }
+            out.write(vec![
+                ("copied", Value::from(report.copied())), ("duplicates", Value::from(report.duplicates())),
+                ("skipped", Value::from(report.skipped())), ("bytes", Value::from(report.bytes())),
+                ("unparsable", Value::from(report.unparsable().len())),
+            ]).map_err(io_err)
+        },
+
+        "inventory" => {
+            if rest.len() != 3 && rest.len() != 4 {
+                return Err("inventory needs CACHE BBOX MINZOOM [MAXZOOM]".to_string());
+            }
+            let store = open_store(&rest[0], &ext, false)?;
+            let bbox = parse_bbox(&rest[1])?;
+            let minzoom = parse_zoom(&rest[2])?;
+            let maxzoom = match rest.get(3) { Some(z) => parse_zoom(z)?, None => minzoom };
+
+            let inventory = Inventory::scan(&*store, minzoom, maxzoom).map_err(io_err)?;
+            for path in inventory.unparsable() {
+                eprintln!("unparsable: {}", path);
+            }
+            let diff = inventory.compare_bbox(&bbox, minzoom, maxzoom);
+            if let Some(path) = missing_file {
+                let mut writer = TileListWriter::create(&path, TileListFormat::Zxy).map_err(|e| format!("{}: {}", path, e))?;
+                diff.write_missing(&mut writer).map_err(io_err)?;
+                writer.finish().map_err(|e| format!("{}: {}", path, e))?;
+            }
+            for zoom in minzoom..=maxzoom {
+                out.write(vec![
+                    ("zoom", Value::from(zoom)),
+                    ("expected", Value::from(diff.expected_count(zoom))), ("present", Value::from(diff.present_count(zoom))),
+                    ("missing", Value::from(diff.missing().len_at(zoom))), ("extra", Value::from(diff.extra().len_at(zoom))),
+                    ("zero_bytes", Value::from(diff.zero_bytes().len_at(zoom))),
+                ]).map_err(io_err)?;
+            }
+            Ok(())
+        },
+
+        "estimate" => {
+            if rest.len() != 2 && rest.len() != 3 {
+                return Err("estimate needs BBOX MINZOOM [MAXZOOM]".to_string());
+            }
+            let bbox = parse_bbox(&rest[0])?;
+            let minzoom = parse_zoom(&rest[1])?;
+            let maxzoom = match rest.get(2) { Some(z) => parse_zoom(z)?, None => minzoom };
+
+            let mut estimator = Estimator::new(bbox, minzoom, maxzoom);
+            if let Some(cache) = sample_cache {
+                let store = open_store(&cache, &ext, false)?;
+                estimator.use_sample(&Sample::read(&*store, 0, 31, 1000).map_err(io_err)?);
+            }
+            if let Some(bytes) = tile_size {
+                estimator.set_tile_size(bytes);
+            }
+            if let Some(ratio) = land_ratio {
+                estimator.set_land_ratio(ratio);
+            }
+            if let Some((land, sea)) = render_seconds {
+                estimator.set_render_seconds(land, sea);
+            }
+            for z in estimator.estimate().zooms() {
+                // JSON can't have numbers this large, so use a float
+                let bytes = if z.bytes() <= u64::MAX as u128 { Value::from(z.bytes() as u64) } else { Value::from(z.bytes() as f64) };
+                out.write(vec![
+                    ("zoom", Value::from(z.zoom())), ("tiles", Value::from(z.tiles())),
+                    ("metatiles", Value::from(z.metatiles())), ("land_tiles", Value::from(z.land_tiles())),
+                    ("bytes", bytes), ("render_hours", Value::from(z.render_hours())),
+                ]).map_err(io_err)?;
+            }
+            Ok(())
+        },
+
+        "hits" => {
+            let mut hits = TileHits::new();
+            for template in templates {
+                hits.add_template(template);
+            }
+            let logs: &[String] = if rest.is_empty() { &["-".to_string()] } else { rest };
+            for log in logs {
+                hits.read_file(log).map_err(|e| format!("{}: {}", log, e))?;
+            }
+            if hits.unparsable() > 0 {
+                eprintln!("{} lines aren't in the combined or common log format", hits.unparsable());
+            }
+            if count {
+                for (zoom, n) in hits.zoom_hits() {
+                    out.write(vec![("zoom", Value::from(zoom)), ("hits", Value::from(n))]).map_err(io_err)?;
+                }
+            } else {
+                for (tile, n) in hits.top(top.unwrap_or(usize::MAX)) {
+                    out.write(vec![("tile", tile_value(&tile)), ("hits", Value::from(n))]).map_err(io_err)?;
+                }
+            }
+            Ok(())
+        },
+
+        "serve" => {
+            if rest.len() != 1 && rest.len() != 2 {
+                return Err("serve needs CACHE [ADDR]".to_string());
+            }
+            let store = open_store(&rest[0], &ext, false)?;
+            let addr = rest.get(1).map_or("127.0.0.1:8080", |a| a.as_str());
+            serve(store, &ext, templates, addr)
+        },
+
+        "download" => {
+            if rest.len() != 4 && rest.len() != 5 {
+                return Err("download needs TEMPLATE CACHE BBOX MINZOOM [MAXZOOM]".to_string());
+            }
+            let template = UrlTemplate::new(&rest[0]).map_err(|e| format!("{}: {}", e, rest[0]))?;
+            let bbox = parse_bbox(&rest[2])?;
+            let minzoom = parse_zoom(&rest[3])?;
+            let maxzoom = match rest.get(4) { Some(z) => parse_zoom(z)?, None => minzoom };
+            let store = open_store(&rest[1], &ext, true)?;
+            let row = download(template, &*store, &bbox, (minzoom, maxzoom), download_options)?;
+            out.write(row).map_err(io_err)
+        },
+
+        c => Err(format!("unknown command {:?}, see --help", c)),
+    }
+}
+
+/// The options which this command takes, besides --format. `None` if it's not a command.
+fn command_options(command: &str) -> Option<&'static [&'static str]> {
+    Some(match command {
+        "point-to-tile" | "tile-bbox" | "tile-corners" | "metatiles" | "render-list" | "quadkey" | "parent" | "children" => &[],
+        "bbox-tiles" => &["--count"],
+        "path" => &["--ext"],
+        "migrate" => &["--dry-run", "--dedup", "--bbox", "--ext"],
+        "inventory" => &["--ext", "--missing"],
+        "estimate" => &["--tile-size", "--land", "--seconds", "--sample", "--ext"],
+        "hits" => &["--count", "--top", "--template"],
+        "serve" => &["--ext", "--template"],
+        "download" => &["--concurrency", "--rate", "--refresh", "--user-agent", "--ext"],
+        _ => return None,
+    })
+}
+
+fn metatile_row(mt: &Metatile) -> Row {
+    let scale = if mt.is_square() { Value::from(mt.scale()) } else { Value::String(format!("{}x{}", mt.width(), mt.height())) };
+    vec![("scale", scale), ("tile", Value::String(format!("{}/{}/{}", mt.zoom(), mt.x(), mt.y())))]
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stdout = io::stdout();
+    if let Err(e) = run(&args, stdout.lock()) {
+        eprintln!("slippy: {}", e);
+        process::exit(1);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn slippy(args: &[&str]) -> Result<String, String> {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut out = Vec::new();
+        run(&args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(slippy(&["point-to-tile", "53.35", "-6.26", "10"]).unwrap(), "10/494/331\n");
+        assert_eq!(slippy(&["tile-bbox", "1/1/0"]).unwrap(), "1/1/0 0.0 0.0 180.0 85.05112\n");
+        assert_eq!(slippy(&["parent", "3/3/5", "1/0/1"]).unwrap(), "2/1/2\n0/0/0\n");
+        assert_eq!(slippy(&["children", "0/0/0"]).unwrap(), "1/0/0\n1/1/0\n1/0/1\n1/1/1\n");
+        assert_eq!(slippy(&["quadkey", "3/3/5", "213"]).unwrap(), "3/3/5 213\n3/3/5 213\n");
+        assert_eq!(slippy(&["path", "mp", "--ext", "jpg", "1/1/0"]).unwrap(), "1/1/0 1/0000/0001/0000/0000.jpg\n");
+        assert_eq!(slippy(&["render-list", "-11,51,-5,54", "8", "9"]).unwrap(), "8 8/120/80\n8 9/240/160\n8 9/248/160\n");
+        assert_eq!(slippy(&["path", "gwc", "3/4/0"]).unwrap(), "3/4/0 EPSG_900913_03/1_1/04_07.png\n");
+        assert_eq!(slippy(&["bbox-tiles", "-180,-85,180,85", "0", "2", "--count"]).unwrap(), "0 1\n1 4\n2 16\n");
+        assert_eq!(slippy(&["bbox-tiles", "1,1,179,84", "1"]).unwrap(), "1/1/0\n");
+        assert_eq!(slippy(&["metatiles", "8", "-180,-85,180,85", "3"]).unwrap(), "8 3/0/0\n");
+        assert_eq!(slippy(&["metatiles", "8x4", "-180,-85,180,85", "3"]).unwrap(), "8x4 3/0/0\n8x4 3/0/4\n");
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(slippy(&["--format", "csv", "quadkey", "1/1/0", "1/0/1"]).unwrap(), "tile,quadkey\n1/1/0,1\n1/0/1,2\n");
+        assert_eq!(slippy(&["--format", "csv", "path", "--ext", "p,\"g\"", "zxy", "1/1/0"]).unwrap(), "tile,path\n1/1/0,\"1/1/0.p,\"\"g\"\"\"\n");
+        assert_eq!(slippy(&["--format", "json", "bbox-tiles", "--count", "0,0,1,1", "0"]).unwrap(), "{\"count\":1,\"zoom\":0}\n");
+        assert!(slippy(&["--format", "xml", "parent", "1/0/0"]).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(slippy(&[]).is_err());
+        assert!(slippy(&["foo"]).is_err());
+        assert!(slippy(&["parent", "0/0/0"]).is_err());
+        assert!(slippy(&["parent", "1/2/0"]).is_err());
+        assert!(slippy(&["path", "foo", "1/0/0"]).is_err());
+        assert!(slippy(&["point-to-tile", "100", "0", "1"]).is_err());
+        assert!(slippy(&["bbox-tiles", "--count", "170,0,-170,10", "3"]).is_err());
+        assert!(slippy(&["metatiles", "8", "0,10,1,0", "3"]).is_err());
+        assert!(slippy(&["migrate", "zxy:/does/not/exist", "tc:/tmp/x"]).is_err());
+        assert!(slippy(&["migrate", "/tmp", "tc:/tmp/x"]).is_err());
+        assert!(slippy(&["download", "http://localhost/{z}/{x}.png", "zxy:/tmp/x", "0,0,1,1", "1"]).is_err());
+
+        // Options for other commands
+        assert_eq!(slippy(&["parent", "--count", "1/0/0"]).unwrap_err(), "parent doesn't take --count, see --help");
+        assert!(slippy(&["--ext", "jpg", "bbox-tiles", "0,0,1,1", "3"]).is_err());
+        assert!(slippy(&["estimate", "--dry-run", "0,0,1,1", "3"]).is_err());
+        assert!(slippy(&["--format", "csv", "bbox-tiles", "--count", "0,0,1,1", "3"]).is_ok());
+    }
+
+    #[test]
+    fn inventory() {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path().join("cache"), FsLayout::Tc, "png");
+        for (t, data) in &[("1/0/0", "tile"), ("1/1/0", ""), ("2/0/0", "tile"), ("2/3/3", "tile")] {
+            store.put(&t.parse().unwrap(), data.as_bytes()).unwrap();
+        }
+        let cache = format!("tc:{}", dir.path().join("cache").display());
+        let missing = dir.path().join("missing.txt");
+        assert_eq!(slippy(&["inventory", "--missing", missing.to_str().unwrap(), &cache, "-170,70,170,80", "1", "2"]).unwrap(),
+            "1 2 2 0 0 1\n2 4 1 3 1 0\n");
+        assert_eq!(std::fs::read_to_string(&missing).unwrap(), "1/1/0\n2/1/0\n2/2/0\n2/3/0\n");
+    }
+
+    #[test]
+    fn estimate() {
+        assert_eq!(slippy(&["estimate", "--tile-size", "1000", "--land", "0.25", "--seconds", "36,0", "-180,-85,180,85", "0", "3"]).unwrap(),
+            "0 1 1 0 0 0.0025\n1 4 1 1 1000 0.0025\n2 16 1 4 4000 0.0025\n3 64 1 16 16000 0.0025\n");
+        assert_eq!(slippy(&["--format", "csv", "estimate", "0,0,1,1", "31"]).unwrap(),
+            "zoom,tiles,metatiles,land_tiles,bytes,render_hours\n31,35585818175121,556029713915,35585818175121,0,0.0\n");
+        assert!(slippy(&["estimate", "--seconds", "x", "0,0,1,1", "1"]).is_err());
+        // Like every other command, and estimate::bbox_tiles
+        assert!(slippy(&["estimate", "170,0,-170,10", "3"]).is_err());
+    }
+
+    #[test]
+    fn hits() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("access.log");
+        std::fs::write(&log, "\
+1.2.3.4 - - [18/Oct/2026:10:00:00 +0000] \"GET /osm/2/1/1.png HTTP/1.1\" 200 10 \"-\" \"test\"
+1.2.3.4 - - [18/Oct/2026:10:00:01 +0000] \"GET /osm/2/1/1.png HTTP/1.1\" 200 10 \"-\" \"test\"
+1.2.3.4 - - [18/Oct/2026:10:00:02 +0000] \"GET /osm/3/0/1.png HTTP/1.1\" 200 10 \"-\" \"test\"
+1.2.3.4 - - [18/Oct/2026:10:00:03 +0000] \"GET /other/3/0/2.png HTTP/1.1\" 200 10 \"-\" \"test\"
+").unwrap();
+        let log = log.to_str().unwrap();
+        assert_eq!(slippy(&["hits", log]).unwrap(), "2/1/1 2\n3/0/1 1\n3/0/2 1\n");
+        assert_eq!(slippy(&["hits", "--top", "1", "--template", "/osm/{z}/{x}/{y}.png", log]).unwrap(), "2/1/1 2\n");
+        assert_eq!(slippy(&["--format", "csv", "hits", "--count", "--template", "/osm/{z}/{x}/{y}.png", log]).unwrap(), "zoom,hits\n2,2\n3,1\n");
+        assert!(slippy(&["hits", "--template", "/osm/{z}", log]).is_err());
+    }
+
+    #[test]
+    fn migrate() {
+        let dir = tempdir().unwrap();
+        let src = FsTileStore::new(dir.path().join("src"), FsLayout::Zxy, "png");
+        for t in &["3/2/5", "3/2/6", "5/1/1"] {
+            src.put(&t.parse().unwrap(), b"tile").unwrap();
+        }
+        let (src, dst) = (format!("zxy:{}", dir.path().join("src").display()), format!("meta:{}", dir.path().join("dst").display()));
+        assert_eq!(slippy(&["migrate", "--dry-run", "--dedup", &src, &dst]).unwrap(), "3 2 0 12 0\n");
+        assert!(!dir.path().join("dst").exists());
+        assert_eq!(slippy(&["--format", "csv", "migrate", "--bbox", "-180,-85,180,0", &src, &dst, "3"]).unwrap(),
+            "copied,duplicates,skipped,bytes,unparsable\n2,0,0,8,0\n");
+        assert!(FsTileStore::new(dir.path().join("dst"), FsLayout::ModTileMeta, "png").exists(&"3/2/6".parse().unwrap()).unwrap());
+
+        #[cfg(feature="mbtiles")]
+        {
+            let mbtiles = dir.path().join("osm.mbtiles");
+            assert_eq!(slippy(&["migrate", "--dedup", &dst, &format!("mbtiles:{}", mbtiles.display())]).unwrap(), "2 1 0 8 0\n");
+            let store = MbTilesStore::open(&mbtiles).unwrap();
+            assert_eq!(store.metadata_value("name").unwrap(), Some("osm".to_string()));
+            assert_eq!(store.get(&"3/2/5".parse().unwrap()).unwrap(), Some(b"tile".to_vec()));
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! The fewest tiles, of mixed zooms, which cover an area, like the S2 `RegionCoverer`.
+//!
+//! Starting at the min zoom, tiles which are partly in the area are replaced with their
+//! subtiles (like `BBox::tiles()`, but tiles all inside the area aren't split), until the max
+//! zoom or max number of tiles is reached. Any 4 sibling tiles are then merged into their parent.
+//!
+//! ```
+//! # use slippy_map_tiles::{BBox, Tile, Coverer};
+//! let coverer = Coverer::new(0, 10, Some(20)).unwrap();
+//! let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+//! let tiles = coverer.cover(&bbox);
+//! assert!(tiles.len() <= 20);
+//! assert!(tiles.iter().all(|t| t.bbox().overlaps_bbox(&bbox)));
+//!
+//! // A bbox which is exactly a tile is covered by that tile
+//! assert_eq!(coverer.cover(&Tile::new(4, 7, 5).unwrap().bbox()), vec![Tile::new(4, 7, 5).unwrap()]);
+//! ```
+use std::collections::HashSet;
+
+use {Tile, BBox, LatLon, TileRange};
+
+/// An area which can be covered with tiles
+pub trait Region {
+    /// A bbox which contains all of this area
+    fn bbox(&self) -> BBox;
+
+    /// True iff some of this tile is in the area
+    fn intersects_tile(&self, tile: &Tile) -> bool;
+
+    /// True iff all of this tile is in the area. This can return false when it's not sure,
+    /// which just means more tiles are looked at.
+    fn contains_tile(&self, tile: &Tile) -> bool;
+}
+
+impl Region for BBox {
+    fn bbox(&self) -> BBox {
+        self.clone()
+    }
+
+    fn intersects_tile(&self, tile: &Tile) -> bool {
+        self.overlaps_bbox(&tile.bbox())
+    }
+
+    fn contains_tile(&self, tile: &Tile) -> bool {
+        let t = tile.bbox();
+        t.top <= self.top && t.bottom >= self.bottom && t.left >= self.left && t.right <= self.right
+    }
+}
+
+/// A polygon of lat/lons, with optional holes. Rings don't need to repeat the first point at
+/// the end. Edges are straight lines in lat/lon, the shorter way round, so an edge whose ends are
+/// more than 180° of longitude apart crosses the antimeridian.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Polygon {
+    exterior: Vec<LatLon>,
+    holes: Vec<Vec<LatLon>>,
+    /// Every ring as (lon, lat), with the longitudes unwrapped (going past ±180) so that no edge
+    /// jumps across the antimeridian
+    rings: Vec<Vec<(f64, f64)>>,
+}
+
+impl Polygon {
+    /// `None` if any ring has fewer than 3 points.
+    pub fn new(exterior: Vec<LatLon>, holes: Vec<Vec<LatLon>>) -> Option<Polygon> {
+        if exterior.len() < 3 || holes.iter().any(|h| h.len() < 3) {
+            None
+        } else {
+            let mut rings = vec![unwrap_ring(&exterior, exterior[0].lon() as f64)];
+            let start = rings[0][0].0;
+            rings.extend(holes.iter().map(|h| unwrap_ring(h, start)));
+            Some(Polygon{ exterior, holes, rings })
+        }
+    }
+
+    /// The outer ring
+    pub fn exterior(&self) -> &[LatLon] {
+        &self.exterior
+    }
+
+    /// The holes
+    pub fn holes(&self) -> &[Vec<LatLon>] {
+        &self.holes
+    }
+
+    /// Every edge, of every ring, as ((lon, lat), (lon, lat)), with unwrapped longitudes
+    fn edges(&self) -> impl Iterator<Item=((f64, f64), (f64, f64))> + '_ {
+        self.rings.iter().flat_map(|ring| {
+            (0..ring.len()).map(move |i| {
+                let (x, y) = ring[(i + 1) % ring.len()];
+                (ring[i], (nearest_lon(x, ring[i].0), y))
+            })
+        })
+    }
+
+    /// True iff this (lon, lat) is inside the polygon (and not in a hole)
+    fn contains_xy(&self, (x, y): (f64, f64)) -> bool {
+        // Even-odd rule, so holes are outside
+        self.edges().filter(|&((x1, y1), (x2, y2))| {
+            (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1)
+        }).count() % 2 == 1
+    }
+
+    /// True iff any edge goes through the inside of this tile (not just along the edge), with
+    /// the tile moved this many degrees east
+    fn edge_crosses(&self, tile: &Tile, offset: f64) -> bool {
+        let rect = (tile.left() as f64 + offset, tile.bottom() as f64, tile.right() as f64 + offset, tile.top() as f64);
+        self.edges().any(|(a, b)| segment_crosses_rect(a, b, rect))
+    }
+}
+
+/// How far east a tile can be moved to line up with the unwrapped longitudes
+const OFFSETS: [f64; 3] = [-360., 0., 360.];
+
+fn xy(p: &LatLon) -> (f64, f64) {
+    (p.lon() as f64, p.lat() as f64)
+}
+
+/// This longitude, moved a whole number of turns so it's within 180° of `near`
+fn nearest_lon(lon: f64, near: f64) -> f64 {
+    lon + 360. * ((near - lon) / 360.).round()
+}
+
+/// This ring as (lon, lat), starting near `start`, and taking the shorter way round each edge
+fn unwrap_ring(ring: &[LatLon], start: f64) -> Vec<(f64, f64)> {
+    let mut prev = start;
+    ring.iter().map(|p| {
+        let (x, y) = xy(p);
+        prev = nearest_lon(x, prev);
+        (prev, y)
+    }).collect()
+}
+
+/// Does the line from `a` to `b` go through the inside of this (min x, min y, max x, max y)
+/// rectangle? (Liang-Barsky clipping)
+fn segment_crosses_rect((x1, y1): (f64, f64), (x2, y2): (f64, f64), (minx, miny, maxx, maxy): (f64, f64, f64, f64)) -> bool {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let (mut t0, mut t1) = (0f64, 1f64);
+    for &(p, q) in &[(-dx, x1 - minx), (dx, maxx - x1), (-dy, y1 - miny), (dy, maxy - y1)] {
+        if p == 0. {
+            if q <= 0. {
+                return false;
+            }
+        } else {
+            let t = q / p;
+            if p < 0. {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    t0 < t1
+}
+
+impl Region for Polygon {
+    /// A polygon which crosses the antimeridian gets a bbox the whole width of the world, since a
+    /// `BBox` can't cross it.
+    fn bbox(&self) -> BBox {
+        let lats = self.exterior.iter().map(|p| p.lat());
+        let (top, bottom) = lats.fold((-90f32, 90f32), |(top, bottom), lat| (top.max(lat), bottom.min(lat)));
+        let lons = self.rings[0].iter().map(|&(x, _)| x);
+        let (left, right) = lons.fold((f64::INFINITY, f64::NEG_INFINITY), |(left, right), lon| (left.min(lon), right.max(lon)));
+        if left < -180. || right > 180. {
+            BBox{ top, left: -180., bottom, right: 180. }
+        } else {
+            BBox{ top, left: left as f32, bottom, right: right as f32 }
+        }
+    }
+
+    fn intersects_tile(&self, tile: &Tile) -> bool {
+        let (x, y) = xy(&tile.centre_point());
+        OFFSETS.iter().any(|&o| self.edge_crosses(tile, o) || self.contains_xy((x + o, y)))
+    }
+
+    fn contains_tile(&self, tile: &Tile) -> bool {
+        let (x, y) = xy(&tile.centre_point());
+        OFFSETS.iter().any(|&o| !self.edge_crosses(tile, o) && self.contains_xy((x + o, y)))
+    }
+}
+
+/// Works out which tiles cover an area.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct Coverer {
+    min_zoom: u8,
+    max_zoom: u8,
+    max_tiles: Option<usize>,
+}
+
+impl Coverer {
+    /// Use tiles from `min_zoom` to `max_zoom`, and at most `max_tiles` tiles (`None` for no
+    /// limit). `None` if `min_zoom` is more than `max_zoom`, or `max_zoom` is more than 31.
+    ///
+    /// The max number of tiles is a goal, which is only broken when the area needs more tiles
+    /// at `min_zoom`.
+    pub fn new(min_zoom: u8, max_zoom: u8, max_tiles: Option<usize>) -> Option<Coverer> {
+        if min_zoom > max_zoom || max_zoom > 31 {
+            None
+        } else {
+            Some(Coverer{ min_zoom, max_zoom, max_tiles })
+        }
+    }
+
+    /// The lowest zoom used
+    pub fn min_zoom(&self) -> u8 { self.min_zoom }
+
+    /// The highest zoom used
+    pub fn max_zoom(&self) -> u8 { self.max_zoom }
+
+    /// The max number of tiles, if there is one
+    pub fn max_tiles(&self) -> Option<usize> { self.max_tiles }
+
+    /// The tiles which cover this area, sorted by zoom, then x, then y. Every point of the area
+    /// is in one of the tiles, and no tile is inside another.
+    pub fn cover<R: Region>(&self, region: &R) -> Vec<Tile> {
+        let mut frontier: Vec<Tile> = match TileRange::new_for_bbox(&region.bbox(), self.min_zoom) {
+            None => vec![],
+            Some(range) => range.iter().filter(|t| region.intersects_tile(t)).collect(),
+        };
+        let mut result = Vec::new();
+
+        while !frontier.is_empty() {
+            let (done, candidates): (Vec<Tile>, Vec<Tile>) = frontier.into_iter()
+                .partition(|t| t.zoom >= self.max_zoom || region.contains_tile(t));
+            result.extend(done);
+
+            let mut next = Vec::new();
+            for (i, tile) in candidates.iter().enumerate() {
+                let children: Vec<Tile> = tile.subtiles().unwrap().iter().cloned().filter(|c| region.intersects_tile(c)).collect();
+                // How many tiles there'd be if this is split
+                let total = result.len() + next.len() + (candidates.len() - i - 1) + children.len();
+                if self.max_tiles.is_some_and(|max| total > max) {
+                    result.push(*tile);
+                } else {
+                    next.extend(children);
+                }
+            }
+            frontier = next;
+        }
+
+        self.merge_siblings(&mut result);
+        result.sort_by_key(|t| (t.zoom, t.x, t.y));
+        result
+    }
+
+    /// Replace any 4 siblings with their parent, as long as that's not below the min zoom.
+    fn merge_siblings(&self, tiles: &mut Vec<Tile>) {
+        let mut set: HashSet<Tile> = tiles.drain(..).collect();
+        for zoom in (self.min_zoom + 1..=self.max_zoom).rev() {
+            let parents: HashSet<Tile> = set.iter().filter(|t| t.zoom == zoom).map(|t| t.parent().unwrap()).collect();
+            for parent in parents {
+                let children = parent.subtiles().unwrap();
+                if children.iter().all(|c| set.contains(c)) {
+                    for c in children.iter() {
+                        set.remove(c);
+                    }
+                    set.insert(parent);
+                }
+            }
+        }
+        tiles.extend(set);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn latlons(points: &[(f32, f32)]) -> Vec<LatLon> {
+        points.iter().map(|&(lat, lon)| LatLon::new(lat, lon).unwrap()).collect()
+    }
+
+    fn covers_without_overlap(tiles: &[Tile]) {
+        for a in tiles {
+            assert!(!tiles.iter().any(|b| a.is_ancestor_of(b)), "{:?} overlaps", a);
+        }
+    }
+
+    #[test]
+    fn cover_bbox() {
+        assert!(Coverer::new(5, 4, None).is_none());
+        assert!(Coverer::new(0, 32, None).is_none());
+
+        // Exactly 4 zoom 5 tiles, which are merged into one zoom 4 tile
+        let bbox = Tile::new(4, 7, 5).unwrap().bbox();
+        assert_eq!(Coverer::new(0, 10, None).unwrap().cover(&bbox), vec![Tile::new(4, 7, 5).unwrap()]);
+        assert_eq!(Coverer::new(5, 10, None).unwrap().cover(&bbox).len(), 4);
+        assert_eq!(Coverer::new(0, 2, None).unwrap().cover(&bbox), vec![Tile::new(2, 1, 1).unwrap()]);
+
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let unlimited = Coverer::new(0, 8, None).unwrap().cover(&bbox);
+        covers_without_overlap(&unlimited);
+        assert!(unlimited.iter().all(|t| t.bbox().overlaps_bbox(&bbox)));
+        // Every z8 tile in the bbox is covered
+        let z8 = TileRange::new_for_bbox(&bbox, 8).unwrap();
+        assert!(z8.iter().all(|t| unlimited.iter().any(|c| *c == t || c.is_ancestor_of(&t))));
+        assert!((unlimited.len() as u64) < z8.num_tiles());
+
+        for max in &[1, 4, 10, 50] {
+            let tiles = Coverer::new(0, 8, Some(*max)).unwrap().cover(&bbox);
+            assert!(tiles.len() <= *max);
+            covers_without_overlap(&tiles);
+            assert!(z8.iter().all(|t| tiles.iter().any(|c| *c == t || c.is_ancestor_of(&t))));
+        }
+
+        // The min zoom beats the max tiles
+        assert_eq!(Coverer::new(6, 8, Some(2)).unwrap().cover(&bbox).len() as u64, TileRange::new_for_bbox(&bbox, 6).unwrap().num_tiles());
+    }
+
+    #[test]
+    fn cover_polygon() {
+        assert!(Polygon::new(latlons(&[(0., 0.), (1., 1.)]), vec![]).is_none());
+
+        // A triangle needs fewer tiles than its bbox
+        let triangle = Polygon::new(latlons(&[(60., -10.), (60., 10.), (40., -10.)]), vec![]).unwrap();
+        let coverer = Coverer::new(0, 7, None).unwrap();
+        let tiles = coverer.cover(&triangle);
+        covers_without_overlap(&tiles);
+        let count = |tiles: &[Tile]| -> u64 { tiles.iter().map(|t| t.descendants_at(7).unwrap().num_tiles()).sum() };
+        let bbox_count = count(&coverer.cover(&triangle.bbox()));
+        assert!(count(&tiles) < bbox_count * 2 / 3);
+        assert!(tiles.iter().any(|t| t.bbox().contains_point(&LatLon::new(59., -9.).unwrap())));
+        assert!(!tiles.iter().any(|t| t.bbox().contains_point(&LatLon::new(41., 9.).unwrap())));
+
+        // Tiles inside a hole aren't used
+        let square = latlons(&[(50., -10.), (50., 10.), (30., 10.), (30., -10.)]);
+        let hole = latlons(&[(45., -5.), (45., 5.), (35., 5.), (35., -5.)]);
+        let with_hole = Polygon::new(square, vec![hole]).unwrap();
+        let tiles = coverer.cover(&with_hole);
+        covers_without_overlap(&tiles);
+        assert!(!tiles.iter().any(|t| t.bbox().contains_point(&LatLon::new(40., 0.).unwrap())));
+        assert!(tiles.iter().any(|t| t.bbox().contains_point(&LatLon::new(40., -8.).unwrap())));
+
+        assert!(Coverer::new(0, 7, Some(12)).unwrap().cover(&with_hole).len() <= 12);
+    }
+
+    #[test]
+    fn cover_polygon_antimeridian() {
+        // From 170°E to 170°W, across the antimeridian, not the long way round
+        let square = latlons(&[(10., 170.), (10., -170.), (-10., -170.), (-10., 170.)]);
+        let hole = latlons(&[(5., -175.), (5., 175.), (-5., 175.), (-5., -175.)]);
+        let polygon = Polygon::new(square, vec![hole]).unwrap();
+        let bbox = polygon.bbox();
+        assert_eq!((bbox.left(), bbox.right(), bbox.top(), bbox.bottom()), (-180., 180., 10., -10.));
+
+        let tiles = Coverer::new(0, 7, None).unwrap().cover(&polygon);
+        covers_without_overlap(&tiles);
+        let covered = |lat, lon| tiles.iter().any(|t| t.bbox().contains_point(&LatLon::new(lat, lon).unwrap()));
+        assert!(covered(0., 172.));
+        assert!(covered(0., -172.));
+        assert!(!covered(0., 0.));
+        assert!(!covered(0., 160.));
+        assert!(!covered(0., 179.5));
+        assert!(!covered(0., -179.5));
+
+        // Going the long way round, with no edge crossing the antimeridian
+        let polygon = Polygon::new(latlons(&[(10., -170.), (10., 0.), (10., 170.), (-10., 170.), (-10., 0.), (-10., -170.)]), vec![]).unwrap();
+        assert_eq!((polygon.bbox().left(), polygon.bbox().right()), (-170., 170.));
+        let tiles = Coverer::new(0, 7, None).unwrap().cover(&polygon);
+        assert!(tiles.iter().any(|t| t.bbox().contains_point(&LatLon::new(0., 0.).unwrap())));
+        assert!(!tiles.iter().any(|t| t.bbox().contains_point(&LatLon::new(0., 179.5).unwrap())));
+    }
+}
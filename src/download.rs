@@ -0,0 +1,407 @@
+//! Downloading (mirroring) tiles from a tile server into a `TileStore`, e.g. for offline use.
+//! Needs the `download` feature.
+//!
+//! ```no_run
+//! # use slippy_map_tiles::{BBox, FsTileStore, FsLayout, UrlTemplate};
+//! # use slippy_map_tiles::download::Downloader;
+//! let template = UrlTemplate::new("https://tiles.example.com/{z}/{x}/{y}.png").unwrap();
+//! let mut downloader = Downloader::new(template);
+//! downloader.set_user_agent("my-mirror/1.0 (me@example.com)");
+//! let store = FsTileStore::new("/var/cache/mirror", FsLayout::Zxy, "png");
+//! let ireland = BBox::new(55.5, -10.7, 51.4, -5.4).unwrap();
+//! let stats = downloader.download(&store, &ireland, 0, 10).unwrap();
+//! println!("{} tiles downloaded, {} failed", stats.fetched(), stats.failed());
+//! ```
+use std::io::{self, Read};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use httpdate;
+use ureq;
+
+use {Tile, BBox, UrlTemplate};
+use store::TileStore;
+use tilerange::TileRange;
+
+/// What happened to each tile of a download
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct DownloadStats {
+    pub(crate) fetched: u64,
+    pub(crate) not_modified: u64,
+    pub(crate) skipped: u64,
+    pub(crate) missing: u64,
+    pub(crate) bytes: u64,
+    pub(crate) failures: Vec<(Tile, String)>,
+}
+
+impl DownloadStats {
+    /// Tiles downloaded and stored
+    pub fn fetched(&self) -> u64 { self.fetched }
+
+    /// Tiles which the server said hadn't changed since they were stored (only when refreshing)
+    pub fn not_modified(&self) -> u64 { self.not_modified }
+
+    /// Tiles which were already stored, so weren't requested
+    pub fn skipped(&self) -> u64 { self.skipped }
+
+    /// Tiles which the server doesn't have (404 or 410)
+    pub fn missing(&self) -> u64 { self.missing }
+
+    /// Tiles which couldn't be downloaded, even after retrying
+    pub fn failed(&self) -> u64 { self.failures.len() as u64 }
+
+    /// Bytes downloaded
+    pub fn bytes(&self) -> u64 { self.bytes }
+
+    /// The tiles which couldn't be downloaded, and why
+    pub fn failures(&self) -> &[(Tile, String)] { &self.failures }
+
+    /// Count this result, storing the tile if it was downloaded
+    fn add(&mut self, store: &dyn TileStore, tile: Tile, fetched: Fetched) -> io::Result<()> {
+        match fetched {
+            Fetched::Data(data) => {
+                store.put(&tile, &data)?;
+                self.fetched += 1;
+                self.bytes += data.len() as u64;
+            },
+            Fetched::NotModified => self.not_modified += 1,
+            Fetched::Missing => self.missing += 1,
+            Fetched::Failed(e) => self.failures.push((tile, e)),
+        }
+        Ok(())
+    }
+}
+
+/// The result of requesting one tile
+enum Fetched {
+    Data(Vec<u8>),
+    NotModified,
+    Missing,
+    Failed(String),
+}
+
+/// Downloads tiles from a tile server.
+///
+/// Tiles which are already stored are skipped, so an interrupted download can be resumed by
+/// running it again. With `set_refresh(true)` they're requested again, with an
+/// `If-Modified-Since` of when they were stored, and only replaced if they've changed.
+///
+/// To be polite to the server, there are at most 2 requests at a time and 10 a second, and
+/// failed requests (connection errors, 429 & 5xx) are retried 3 times, waiting 1, 2 then 4
+/// seconds (or as long as the `Retry-After` header says, up to 5 minutes) first.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    template: UrlTemplate,
+    user_agent: String,
+    concurrency: usize,
+    // Time between requests, from the rate limit
+    request_interval: Option<Duration>,
+    retries: u32,
+    backoff: Duration,
+    max_retry_wait: Duration,
+    timeout: Duration,
+    refresh: bool,
+}
+
+impl Downloader {
+    /// Download tiles from the URLs of this template
+    pub fn new(template: UrlTemplate) -> Downloader {
+        Downloader{
+            template,
+            user_agent: format!("slippy-map-tiles/{}", env!("CARGO_PKG_VERSION")),
+            concurrency: 2,
+            request_interval: Some(Duration::from_millis(100)),
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            max_retry_wait: Duration::from_secs(300),
+            timeout: Duration::from_secs(30),
+            refresh: false,
+        }
+    }
+
+    /// The `User-Agent` header. Many tile servers require one which identifies your app.
+    pub fn set_user_agent(&mut self, user_agent: &str) {
+        self.user_agent = user_agent.to_string();
+    }
+
+    /// How many requests can be made at once. 0 is treated as 1.
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    /// The most requests (including retries) per second, or `None` (or 0) for no limit. `Err` if
+    /// the rate is so low that the time between requests is too long to wait for.
+    pub fn set_rate_limit(&mut self, rate_limit: Option<f64>) -> Result<(), &'static str> {
+        self.request_interval = match rate_limit.filter(|r| *r > 0.) {
+            None => None,
+            Some(r) => {
+                let interval = Duration::try_from_secs_f64(1. / r).map_err(|_| "rate limit is too low")?;
+                Instant::now().checked_add(interval).ok_or("rate limit is too low")?;
+                Some(interval)
+            },
+        };
+        Ok(())
+    }
+
+    /// How many times to retry a failed request, and how long to wait before the first retry.
+    /// The wait doubles for each further retry.
+    pub fn set_retries(&mut self, retries: u32, backoff: Duration) {
+        self.retries = retries;
+        self.backoff = backoff;
+    }
+
+    /// The longest to wait before a retry, however long `Retry-After` says
+    pub fn set_max_retry_wait(&mut self, max_retry_wait: Duration) {
+        self.max_retry_wait = max_retry_wait;
+    }
+
+    /// How long to wait for the server
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// If true, tiles which are already stored are requested again
+    pub fn set_refresh(&mut self, refresh: bool) {
+        self.refresh = refresh;
+    }
+
+    /// Download the tiles in this bbox, at these zooms, into `store`
+    pub fn download(&self, store: &dyn TileStore, bbox: &BBox, minzoom: u8, maxzoom: u8) -> io::Result<DownloadStats> {
+        let tiles = (minzoom..=maxzoom.min(31)).filter_map(|z| TileRange::new_for_bbox(bbox, z)).flat_map(|r| r.iter());
+        self.download_tiles(store, tiles)
+    }
+
+    /// Download these tiles into `store`. Returns an error if a tile can't be stored; tiles
+    /// which can't be downloaded are in `DownloadStats::failures`.
+    pub fn download_tiles<I: IntoIterator<Item=Tile>>(&self, store: &dyn TileStore, tiles: I) -> io::Result<DownloadStats> {
+        let agent = ureq::AgentBuilder::new().user_agent(&self.user_agent).timeout(self.timeout).build();
+        let next_request = Mutex::new(Instant::now());
+        let mut stats = DownloadStats::default();
+
+        // Tiles are downloaded by worker threads, and stored on this one, so stores needn't be
+        // Sync
+        thread::scope(|scope| {
+            let (job_tx, job_rx) = mpsc::sync_channel::<(Tile, Option<SystemTime>)>(self.concurrency);
+            let job_rx = Arc::new(Mutex::new(job_rx));
+            let (result_tx, result_rx) = mpsc::channel::<(Tile, Fetched)>();
+            for _ in 0..self.concurrency {
+                let (job_rx, result_tx, agent, next_request) = (job_rx.clone(), result_tx.clone(), &agent, &next_request);
+                scope.spawn(move || loop {
+                    let job = job_rx.lock().unwrap().recv();
+                    let (tile, modified) = match job {
+                        Ok(j) => j,
+                        Err(_) => break,
+                    };
+                    let fetched = self.fetch(agent, next_request, &tile, modified);
+                    if result_tx.send((tile, fetched)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(result_tx);
+
+            for tile in tiles {
+                let modified = match store.metadata(&tile)? {
+                    None => None,
+                    Some(_) if !self.refresh => {
+                        stats.skipped += 1;
+                        continue;
+                    },
+                    Some(m) => Some(m.modified().unwrap_or(SystemTime::UNIX_EPOCH)),
+                };
+                job_tx.send((tile, modified)).unwrap();
+                while let Ok((tile, fetched)) = result_rx.try_recv() {
+                    stats.add(store, tile, fetched)?;
+                }
+            }
+            drop(job_tx);
+            for (tile, fetched) in result_rx {
+                stats.add(store, tile, fetched)?;
+            }
+            store.flush()
+        })?;
+        Ok(stats)
+    }
+
+    /// Wait until we can make another request
+    fn wait_turn(&self, next_request: &Mutex<Instant>) {
+        let interval = match self.request_interval {
+            None => return,
+            Some(interval) => interval,
+        };
+        let wait = {
+            let mut next_request = next_request.lock().unwrap();
+            let now = Instant::now();
+            let at = (*next_request).max(now);
+            *next_request = at.checked_add(interval).unwrap_or(at);
+            at - now
+        };
+        thread::sleep(wait);
+    }
+
+    /// Request this tile, retrying if needed
+    fn fetch(&self, agent: &ureq::Agent, next_request: &Mutex<Instant>, tile: &Tile, modified: Option<SystemTime>) -> Fetched {
+        let url = self.template.url(tile);
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            self.wait_turn(next_request);
+            let mut request = agent.get(&url);
+            if let Some(modified) = modified {
+                request = request.set("If-Modified-Since", &httpdate::fmt_http_date(modified));
+            }
+            let (error, retry_after) = match request.call() {
+                Ok(response) => {
+                    if response.status() == 304 {
+                        return Fetched::NotModified;
+                    }
+                    let mut data = Vec::new();
+                    match response.into_reader().read_to_end(&mut data) {
+                        Ok(_) => return Fetched::Data(data),
+                        Err(e) => (format!("{}: {}", url, e), None),
+                    }
+                },
+                Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(410, _)) => return Fetched::Missing,
+                Err(ureq::Error::Status(status, response)) if status == 429 || status >= 500 => {
+                    let retry_after = response.header("Retry-After").and_then(|s| parse_retry_after(s, SystemTime::now()));
+                    (format!("{}: status code {}", url, status), retry_after)
+                },
+                Err(e @ ureq::Error::Status(..)) => return Fetched::Failed(e.to_string()),
+                Err(e) => (e.to_string(), None),
+            };
+            if attempt >= self.retries {
+                return Fetched::Failed(error);
+            }
+            thread::sleep(retry_after.map_or(backoff, |r| r.max(backoff)).min(self.max_retry_wait));
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// How long a `Retry-After` header says to wait. It's either seconds, or an HTTP date.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value).ok().map(|at| at.duration_since(now).unwrap_or_default()),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use {FsTileStore, FsLayout};
+    use tempfile::tempdir;
+
+    /// The paths & headers of the requests a server got
+    type Requests = Arc<Mutex<Vec<(String, HashMap<String, String>)>>>;
+
+    /// A stand-in tile server, on a loopback socket. Tiles are their z/x/y, except 2/3/3 is
+    /// missing, 2/1/2 is empty, 1/0/0 is a bad request, 0/0/0 fails twice before working, and
+    /// 2/1/1 is rate limited once. Both say to retry much later.
+    /// Returns its URL template, and the requests' paths & headers.
+    fn tile_server() -> (UrlTemplate, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let template = UrlTemplate::new(&format!("http://{}/{{z}}/{{x}}/{{y}}.png", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        thread::spawn(move || for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines().map(|l| l.unwrap());
+            let path = lines.next().unwrap().split(' ').nth(1).unwrap().to_string();
+            let headers: HashMap<String, String> = lines.take_while(|l| !l.is_empty())
+                .filter_map(|l| l.find(':').map(|i| (l[..i].to_ascii_lowercase(), l[i+1..].trim().to_string()))).collect();
+            let tries = log.lock().unwrap().iter().filter(|r: &&(String, _)| r.0 == path).count();
+            let tile = path[1..path.len()-4].to_string();
+            let (status, body) = match tile.as_str() {
+                _ if headers.contains_key("if-modified-since") => ("304 Not Modified", String::new()),
+                "2/3/3" => ("404 Not Found", String::new()),
+                "2/1/2" => ("204 No Content", String::new()),
+                "1/0/0" => ("400 Bad Request", String::new()),
+                "0/0/0" if tries < 2 => ("503 Service Unavailable", String::new()),
+                "2/1/1" if tries < 1 => ("429 Too Many Requests", String::new()),
+                _ => ("200 OK", tile),
+            };
+            let retry_after = match status {
+                "503 Service Unavailable" => "Retry-After: Fri, 31 Dec 2100 23:59:59 GMT\r\n",
+                "429 Too Many Requests" => "Retry-After: 3600\r\n",
+                _ => "",
+            };
+            log.lock().unwrap().push((path, headers));
+            let response = format!("HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, retry_after, body.len(), body);
+            let _ = stream.write_all(response.as_bytes());
+        });
+        (template, requests)
+    }
+
+    #[test]
+    fn download() {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path(), FsLayout::Zxy, "png");
+        let (template, requests) = tile_server();
+        let mut downloader = Downloader::new(template);
+        downloader.set_user_agent("test-mirror/1.0");
+        downloader.set_concurrency(3);
+        downloader.set_rate_limit(None).unwrap();
+        downloader.set_retries(3, Duration::from_millis(1));
+        downloader.set_max_retry_wait(Duration::from_millis(10));
+        let world = BBox::new(85., -180., -85., 180.).unwrap();
+
+        let stats = downloader.download(&store, &world, 0, 2).unwrap();
+        assert_eq!((stats.fetched(), stats.missing(), stats.failed(), stats.skipped()), (19, 1, 1, 0));
+        assert_eq!(stats.failures()[0].0, Tile::new(1, 0, 0).unwrap());
+        assert_eq!(stats.bytes(), 5 + 3 * 5 + 14 * 5);
+        assert_eq!(store.get(&Tile::new(2, 1, 1).unwrap()).unwrap(), Some(b"2/1/1".to_vec()));
+        assert_eq!(store.get(&Tile::new(2, 1, 2).unwrap()).unwrap(), Some(Vec::new()));
+        assert_eq!(store.get(&Tile::new(2, 3, 3).unwrap()).unwrap(), None);
+        {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.iter().filter(|r| r.0 == "/0/0/0.png").count(), 3);
+            assert_eq!(requests.iter().filter(|r| r.0 == "/1/0/0.png").count(), 1);
+            assert_eq!(requests.iter().filter(|r| r.0 == "/2/1/1.png").count(), 2);
+            assert!(requests.iter().all(|r| r.1["user-agent"] == "test-mirror/1.0"));
+        }
+
+        // Resuming only requests what isn't stored
+        store.delete(&Tile::new(2, 0, 1).unwrap()).unwrap();
+        requests.lock().unwrap().clear();
+        let stats = downloader.download(&store, &world, 0, 2).unwrap();
+        assert_eq!((stats.fetched(), stats.missing(), stats.failed(), stats.skipped()), (1, 1, 1, 18));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        downloader.set_refresh(true);
+        let stats = downloader.download(&store, &world, 2, 2).unwrap();
+        assert_eq!((stats.fetched(), stats.not_modified(), stats.missing()), (0, 15, 1));
+        assert_eq!(requests.lock().unwrap().iter().filter(|r| r.1.get("if-modified-since").is_some_and(|d| d.ends_with(" GMT"))).count(), 15);
+    }
+
+    #[test]
+    fn retry_after() {
+        let now = httpdate::parse_http_date("Sun, 18 Oct 2026 10:00:00 GMT").unwrap();
+        assert_eq!(parse_retry_after(" 120 ", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Sun, 18 Oct 2026 10:01:30 GMT", now), Some(Duration::from_secs(90)));
+        assert_eq!(parse_retry_after("Sun, 18 Oct 2026 09:00:00 GMT", now), Some(Duration::from_secs(0)));
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut downloader = Downloader::new(UrlTemplate::new("http://localhost/{z}/{x}/{y}.png").unwrap());
+        assert_eq!(downloader.set_rate_limit(Some(4.)), Ok(()));
+        assert_eq!(downloader.request_interval, Some(Duration::from_millis(250)));
+        assert_eq!(downloader.set_rate_limit(Some(0.)), Ok(()));
+        assert_eq!(downloader.request_interval, None);
+
+        // Far too long between requests to fit in a Duration
+        assert!(downloader.set_rate_limit(Some(1e-30)).is_err());
+        assert!(downloader.set_rate_limit(Some(f64::MIN_POSITIVE)).is_err());
+    }
+}
@@ -0,0 +1,440 @@
+//! Estimating how many tiles & metatiles rendering an area makes, how much space they take, and
+//! how long rendering them takes.
+//!
+//! ```
+//! # use slippy_map_tiles::BBox;
+//! # use slippy_map_tiles::estimate::Estimator;
+//! let planet = BBox::new(90., -180., -90., 180.).unwrap();
+//! let mut estimator = Estimator::new(planet, 0, 18);
+//! estimator.set_tile_size(15_000);
+//! estimator.set_land_ratio(0.3);
+//! estimator.set_sea_tile_size(100);
+//! estimator.set_render_seconds(2., 0.1);
+//!
+//! let estimate = estimator.estimate();
+//! assert_eq!(estimate.tiles(), 91_625_968_981);
+//! assert_eq!(estimate.zooms()[18].land_tiles(), 20_615_843_021);
+//! assert!(estimate.bytes() > 300_000_000_000_000);
+//! ```
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::BinaryHeap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+use {Tile, BBox, TileRange};
+use shard::hash_tile;
+use store::{TileStore, ScanEntry};
+
+/// How many tiles does this bbox cover at this zoom. Unlike `size_bbox_zoom` this can't
+/// overflow. Like `TileRange::new_for_bbox`, a bbox which crosses the antimeridian (`left` more
+/// than `right`), or is upside down, has no tiles.
+pub fn bbox_tiles(bbox: &BBox, zoom: u8) -> u64 {
+    bbox_metatiles(bbox, zoom, 1)
+}
+
+/// How many metatiles, of this scale, does this bbox cover at this zoom. Unlike
+/// `size_bbox_zoom_metatiles` this can't overflow. Bboxes are treated like in `bbox_tiles`.
+pub fn bbox_metatiles(bbox: &BBox, zoom: u8, metatile_scale: u8) -> u64 {
+    TileRange::new_for_bbox_metatiles(bbox, zoom, (metatile_scale, metatile_scale)).map_or(0, |r| r.num_tiles())
+}
+
+/// A value which can be different at each zoom
+#[derive(PartialEq, Debug, Clone)]
+struct ZoomValues {
+    initial: f64,
+    all: Option<f64>,
+    zooms: BTreeMap<u8, f64>,
+}
+
+impl ZoomValues {
+    fn new(initial: f64) -> ZoomValues {
+        ZoomValues{ initial, all: None, zooms: BTreeMap::new() }
+    }
+
+    /// The value for this zoom, else the value for all zooms, else the value for the nearest
+    /// lower zoom with one, else the nearest higher zoom, else the initial value.
+    fn get(&self, zoom: u8) -> f64 {
+        if let Some(v) = self.zooms.get(&zoom) {
+            return *v;
+        }
+        self.all.or_else(|| {
+            self.zooms.range(..zoom).next_back()
+                .or_else(|| self.zooms.range(zoom..).next())
+                .map(|(_, v)| *v)
+        }).unwrap_or(self.initial)
+    }
+}
+
+/// The tile sizes, and how much is land, in a sample of the tiles in a `TileStore`.
+///
+/// The most common tile data at each zoom, if more than one tile has it, is taken to be sea
+/// (or some other blank area). All the other tiles are land.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Sample {
+    pub(crate) zooms: BTreeMap<u8, SampleZoom>,
+}
+
+/// How many tiles have each data, by hash & size of the data
+type DataCounts = HashMap<(u64, usize), u64>;
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub(crate) struct SampleZoom {
+    pub(crate) tiles: u64,
+    pub(crate) land_tiles: u64,
+    pub(crate) land_bytes: u64,
+    pub(crate) sea_tile_size: u64,
+}
+
+impl Sample {
+    /// Read up to `per_zoom` tiles at each zoom from `minzoom` to `maxzoom` (inclusive). The
+    /// tiles are the ones with the lowest hash of their coordinates, so they're spread out over
+    /// all the tiles at that zoom, and the same tiles are read each time. The store is scanned
+    /// once.
+    pub fn read(store: &dyn TileStore, minzoom: u8, maxzoom: u8, per_zoom: u64) -> io::Result<Sample> {
+        // For each zoom, the `per_zoom` tiles with the lowest hashes so far
+        let mut picked: BTreeMap<u8, BinaryHeap<(u64, u32, u32)>> = BTreeMap::new();
+        store.scan(minzoom..=maxzoom, &mut |entry| {
+            if let ScanEntry::Tile(t) = entry {
+                let heap = picked.entry(t.zoom()).or_default();
+                let key = (hash_tile(t.zoom(), t.x(), t.y()), t.x(), t.y());
+                if (heap.len() as u64) < per_zoom {
+                    heap.push(key);
+                } else if heap.peek() > Some(&key) {
+                    heap.pop();
+                    heap.push(key);
+                }
+            }
+            Ok(())
+        })?;
+
+        // For each zoom, how many of each (hash, size) of data was read
+        let mut seen: BTreeMap<u8, DataCounts> = BTreeMap::new();
+        for (zoom, heap) in picked {
+            let sizes = seen.entry(zoom).or_default();
+            for tile in heap.into_iter().filter_map(|(_, x, y)| Tile::new(zoom, x, y)) {
+                if let Some(data) = store.get(&tile)? {
+                    let mut hasher = DefaultHasher::new();
+                    data.hash(&mut hasher);
+                    *sizes.entry((hasher.finish(), data.len())).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let zooms = seen.into_iter().filter(|(_, sizes)| !sizes.is_empty()).map(|(zoom, sizes)| {
+            // The most common data, ties broken by the smallest
+            let sea = sizes.iter().filter(|&(_, &n)| n > 1).max_by_key(|&(&(hash, len), &n)| (n, ::std::cmp::Reverse((len, hash)))).map(|(k, _)| *k);
+            let mut z = SampleZoom::default();
+            for (key, n) in sizes {
+                z.tiles += n;
+                if Some(key) == sea {
+                    z.sea_tile_size = key.1 as u64;
+                } else {
+                    z.land_tiles += n;
+                    z.land_bytes += n * key.1 as u64;
+                }
+            }
+            (zoom, z)
+        }).collect();
+        Ok(Sample{ zooms })
+    }
+
+    /// The zooms which have sampled tiles
+    pub fn zooms(&self) -> Vec<u8> {
+        self.zooms.keys().cloned().collect()
+    }
+
+    /// How many tiles were read at this zoom
+    pub fn tiles(&self, zoom: u8) -> u64 {
+        self.zooms.get(&zoom).map_or(0, |z| z.tiles)
+    }
+
+    /// What fraction of the tiles at this zoom are land
+    pub fn land_ratio(&self, zoom: u8) -> Option<f64> {
+        self.zooms.get(&zoom).map(|z| z.land_tiles as f64 / z.tiles as f64)
+    }
+
+    /// The average size of a land tile at this zoom, if any were read
+    pub fn land_tile_size(&self, zoom: u8) -> Option<f64> {
+        self.zooms.get(&zoom).filter(|z| z.land_tiles > 0).map(|z| z.land_bytes as f64 / z.land_tiles as f64)
+    }
+
+    /// The size of a sea tile at this zoom, if there are any
+    pub fn sea_tile_size(&self, zoom: u8) -> Option<u64> {
+        self.zooms.get(&zoom).filter(|z| z.land_tiles < z.tiles).map(|z| z.sea_tile_size)
+    }
+}
+
+/// Estimates the tiles, metatiles, bytes & render time of a bbox over a range of zooms.
+///
+/// Tile sizes & land ratio can be set for one zoom, or all zooms, or come from a `Sample`. A
+/// zoom without its own value uses the value for all zooms, if set, else the value of the
+/// nearest lower zoom with one (or higher, if there is none).
+#[derive(PartialEq, Debug, Clone)]
+pub struct Estimator {
+    bbox: BBox,
+    minzoom: u8,
+    maxzoom: u8,
+    metatile_scale: u8,
+    tile_sizes: ZoomValues,
+    sea_tile_sizes: ZoomValues,
+    land_ratios: ZoomValues,
+    land_seconds: f64,
+    sea_seconds: f64,
+    block_size: u64,
+}
+
+impl Estimator {
+    /// Estimate the tiles in `bbox` from `minzoom` to `maxzoom` (inclusive), in metatiles of
+    /// scale 8. Until sizes & render times are set, all tiles are land, and take no space or
+    /// time.
+    pub fn new(bbox: BBox, minzoom: u8, maxzoom: u8) -> Estimator {
+        Estimator{
+            bbox, minzoom, maxzoom,
+            metatile_scale: 8,
+            tile_sizes: ZoomValues::new(0.),
+            sea_tile_sizes: ZoomValues::new(0.),
+            land_ratios: ZoomValues::new(1.),
+            land_seconds: 0.,
+            sea_seconds: 0.,
+            block_size: 1,
+        }
+    }
+
+    /// Metatiles are `scale`×`scale` tiles
+    pub fn set_metatile_scale(&mut self, scale: u8) {
+        self.metatile_scale = scale;
+    }
+
+    /// The average size of a land tile, at all zooms
+    pub fn set_tile_size(&mut self, bytes: u64) {
+        self.tile_sizes.all = Some(bytes as f64);
+    }
+
+    /// The average size of a land tile at this zoom
+    pub fn set_zoom_tile_size(&mut self, zoom: u8, bytes: u64) {
+        self.tile_sizes.zooms.insert(zoom, bytes as f64);
+    }
+
+    /// The size of a sea tile, at all zooms
+    pub fn set_sea_tile_size(&mut self, bytes: u64) {
+        self.sea_tile_sizes.all = Some(bytes as f64);
+    }
+
+    /// What fraction (from 0 to 1) of the tiles, and metatiles, are land, at all zooms
+    pub fn set_land_ratio(&mut self, ratio: f64) {
+        self.land_ratios.all = Some(ratio.clamp(0., 1.));
+    }
+
+    /// How long it takes to render a land metatile, and a sea metatile, in seconds
+    pub fn set_render_seconds(&mut self, land: f64, sea: f64) {
+        self.land_seconds = land;
+        self.sea_seconds = sea;
+    }
+
+    /// Each tile takes a whole number of blocks of this size on disk. Defaults to 1, i.e. the
+    /// tile sizes are used as they are.
+    pub fn set_block_size(&mut self, bytes: u64) {
+        self.block_size = bytes.max(1);
+    }
+
+    /// Use the tile sizes & land ratio from this sample, at the zooms it has tiles for.
+    pub fn use_sample(&mut self, sample: &Sample) {
+        for zoom in sample.zooms() {
+            if let Some(size) = sample.land_tile_size(zoom) {
+                self.tile_sizes.zooms.insert(zoom, size);
+            }
+            if let Some(size) = sample.sea_tile_size(zoom) {
+                self.sea_tile_sizes.zooms.insert(zoom, size as f64);
+            }
+            if let Some(ratio) = sample.land_ratio(zoom) {
+                self.land_ratios.zooms.insert(zoom, ratio);
+            }
+        }
+    }
+
+    /// The size on disk of a tile of this size
+    fn on_disk(&self, size: f64) -> u128 {
+        let size = size.round() as u128;
+        let block = self.block_size as u128;
+        size.div_ceil(block) * block
+    }
+
+    /// Estimate each zoom
+    pub fn estimate(&self) -> Estimate {
+        let zooms = (self.minzoom..=self.maxzoom.min(31)).map(|zoom| {
+            let tiles = bbox_tiles(&self.bbox, zoom);
+            let metatiles = bbox_metatiles(&self.bbox, zoom, self.metatile_scale);
+            let ratio = self.land_ratios.get(zoom);
+            let land_tiles = ((tiles as f64 * ratio).round() as u64).min(tiles);
+            let land_metatiles = metatiles as f64 * ratio;
+
+            let bytes = land_tiles as u128 * self.on_disk(self.tile_sizes.get(zoom))
+                + (tiles - land_tiles) as u128 * self.on_disk(self.sea_tile_sizes.get(zoom));
+            let seconds = land_metatiles * self.land_seconds + (metatiles as f64 - land_metatiles) * self.sea_seconds;
+            ZoomEstimate{ zoom, tiles, metatiles, land_tiles, bytes, render_hours: seconds / 3600. }
+        }).collect();
+        Estimate{ zooms }
+    }
+}
+
+/// The estimate for one zoom
+#[derive(PartialEq, Debug, Clone)]
+pub struct ZoomEstimate {
+    pub(crate) zoom: u8,
+    pub(crate) tiles: u64,
+    pub(crate) metatiles: u64,
+    pub(crate) land_tiles: u64,
+    pub(crate) bytes: u128,
+    pub(crate) render_hours: f64,
+}
+
+impl ZoomEstimate {
+    /// The zoom
+    pub fn zoom(&self) -> u8 { self.zoom }
+
+    /// Number of tiles
+    pub fn tiles(&self) -> u64 { self.tiles }
+
+    /// Number of metatiles
+    pub fn metatiles(&self) -> u64 { self.metatiles }
+
+    /// Number of tiles which are land
+    pub fn land_tiles(&self) -> u64 { self.land_tiles }
+
+    /// Space taken by the tiles
+    pub fn bytes(&self) -> u128 { self.bytes }
+
+    /// Time taken to render the metatiles
+    pub fn render_hours(&self) -> f64 { self.render_hours }
+}
+
+/// The result of `Estimator::estimate`
+#[derive(PartialEq, Debug, Clone)]
+pub struct Estimate {
+    pub(crate) zooms: Vec<ZoomEstimate>,
+}
+
+impl Estimate {
+    /// The estimate for each zoom, lowest first
+    pub fn zooms(&self) -> &[ZoomEstimate] { &self.zooms }
+
+    /// Total number of tiles
+    pub fn tiles(&self) -> u64 {
+        self.zooms.iter().map(|z| z.tiles).sum()
+    }
+
+    /// Total number of metatiles
+    pub fn metatiles(&self) -> u64 {
+        self.zooms.iter().map(|z| z.metatiles).sum()
+    }
+
+    /// Total space taken
+    pub fn bytes(&self) -> u128 {
+        self.zooms.iter().map(|z| z.bytes).sum()
+    }
+
+    /// Total render time
+    pub fn render_hours(&self) -> f64 {
+        self.zooms.iter().map(|z| z.render_hours).sum()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {MetatilesIterator, size_bbox_zoom, size_bbox_zoom_metatiles};
+    use store::{FsTileStore, FsLayout};
+    use tempfile::tempdir;
+
+    #[test]
+    fn counts() {
+        let bbox = BBox::new(60., -10., 40., 10.).unwrap();
+        for zoom in 0..16 {
+            assert_eq!(Some(bbox_tiles(&bbox, zoom) as usize), size_bbox_zoom(&bbox, zoom));
+            assert_eq!(Some(bbox_metatiles(&bbox, zoom, 8) as usize), size_bbox_zoom_metatiles(&bbox, zoom, 8));
+        }
+
+        let world = BBox::new(90., -180., -90., 180.).unwrap();
+        assert_eq!(bbox_tiles(&world, 31), 1 << 62);
+        assert_eq!(bbox_metatiles(&world, 31, 8), 1 << 56);
+        assert_eq!(bbox_metatiles(&world, 2, 8), 1);
+
+        // Fiji, across the antimeridian, and upside down
+        let fiji = BBox::new(10., 170., 1., -170.).unwrap();
+        assert_eq!(bbox_tiles(&fiji, 3), 0);
+        assert_eq!(bbox_metatiles(&fiji, 0, 8), 0);
+        assert_eq!(size_bbox_zoom(&fiji, 5), Some(0));
+        assert_eq!(size_bbox_zoom_metatiles(&fiji, 5, 8), Some(0));
+        let metatiles = MetatilesIterator::new_for_bbox_zoom(8, &Some(fiji), 0, 5);
+        assert_eq!(metatiles.total(), Some(0));
+        assert_eq!(metatiles.count(), 0);
+        assert_eq!(bbox_tiles(&BBox::new(0., 0., 10., 1.).unwrap(), 3), 0);
+
+        let mut estimator = Estimator::new(world, 31, 31);
+        estimator.set_tile_size(100);
+        assert_eq!(estimator.estimate().bytes(), 100 << 62);
+    }
+
+    #[test]
+    fn estimate() {
+        let world = BBox::new(90., -180., -90., 180.).unwrap();
+        let mut estimator = Estimator::new(world, 0, 4);
+        estimator.set_metatile_scale(2);
+        estimator.set_tile_size(1000);
+        estimator.set_zoom_tile_size(2, 2000);
+        estimator.set_sea_tile_size(10);
+        estimator.set_land_ratio(0.25);
+        estimator.set_render_seconds(3600., 360.);
+
+        let estimate = estimator.estimate();
+        let z: Vec<_> = estimate.zooms().iter().map(|z| (z.zoom(), z.tiles(), z.metatiles(), z.land_tiles(), z.bytes())).collect();
+        assert_eq!(z, vec![
+            (0, 1, 1, 0, 10),
+            (1, 4, 1, 1, 1030),
+            (2, 16, 4, 4, 8120),
+            (3, 64, 16, 16, 16480),
+            (4, 256, 64, 64, 65920),
+        ]);
+        assert_eq!(estimate.tiles(), 341);
+        assert_eq!(estimate.metatiles(), 86);
+        assert_eq!(estimate.bytes(), 91560);
+        assert!((estimate.zooms()[2].render_hours() - 1.3).abs() < 1e-9);
+        assert!((estimate.render_hours() - 86. * 0.325).abs() < 1e-9);
+
+        estimator.set_block_size(4096);
+        assert_eq!(estimator.estimate().zooms()[1].bytes(), 4 * 4096);
+    }
+
+    #[test]
+    fn sample() {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path(), FsLayout::Zxy, "png");
+        // z2: 12 sea tiles, 4 land tiles
+        for x in 0..4 {
+            for y in 0..4 {
+                let data: &[u8] = if y == 1 { b"land land" } else { b"sea" };
+                store.put(&Tile::new(2, x, y).unwrap(), data).unwrap();
+            }
+        }
+        // z3: all different, so all land
+        for x in 0..4 {
+            store.put(&Tile::new(3, x, 0).unwrap(), &vec![b'x'; 10 + x as usize]).unwrap();
+        }
+
+        let sample = Sample::read(&store, 0, 5, 100).unwrap();
+        assert_eq!(sample.zooms(), vec![2, 3]);
+        assert_eq!((sample.tiles(2), sample.land_ratio(2), sample.land_tile_size(2), sample.sea_tile_size(2)), (16, Some(0.25), Some(9.), Some(3)));
+        assert_eq!((sample.tiles(3), sample.land_ratio(3), sample.land_tile_size(3), sample.sea_tile_size(3)), (4, Some(1.), Some(11.5), None));
+        assert_eq!(Sample::read(&store, 2, 2, 8).unwrap().tiles(2), 8);
+
+        let mut estimator = Estimator::new(BBox::new(90., -180., -90., 180.).unwrap(), 1, 4);
+        estimator.set_sea_tile_size(5);
+        estimator.use_sample(&sample);
+        let bytes: Vec<_> = estimator.estimate().zooms().iter().map(|z| (z.land_tiles(), z.bytes())).collect();
+        // z1 uses z2's values, & z4 uses z3's, but the sea tile size is set for all zooms
+        assert_eq!(bytes, vec![(1, 9 + 3*5), (4, 4*9 + 12*3), (64, 64*12), (256, 256*12)]);
+    }
+}
@@ -0,0 +1,286 @@
+//! Conversions to & from [`geo-types`](https://docs.rs/geo-types), and GeoJSON export, with the
+//! `geo` feature.
+//!
+//! Points are (x, y) = (lon, lat).
+//!
+//! ```
+//! use slippy_map_tiles::{Tile, LatLon};
+//! use slippy_map_tiles::geo::tiles_to_geojson;
+//!
+//! let point: geo_types::Point<f64> = LatLon::new(53.35, -6.26).unwrap().into();
+//! let polygon: geo_types::Polygon<f64> = Tile::new(1, 0, 0).unwrap().into();
+//!
+//! let geojson = tiles_to_geojson(Tile::new(1, 0, 0).unwrap().subtiles().unwrap().iter().cloned());
+//! assert!(geojson.starts_with(r#"{"type":"FeatureCollection","features":["#));
+//! ```
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use geo_types::{Point, Rect, Polygon, Coord};
+use serde_json::{self, Value, Map};
+
+use {Tile, Metatile, ModTileMetatile, LatLon, BBox, f32_json};
+use coverer;
+
+impl From<LatLon> for Point<f64> {
+    fn from(p: LatLon) -> Point<f64> {
+        Point::new(p.lon() as f64, p.lat() as f64)
+    }
+}
+
+impl TryFrom<Point<f64>> for LatLon {
+    type Error = &'static str;
+
+    /// `Err` if the point is not a valid lat/lon
+    fn try_from(p: Point<f64>) -> Result<LatLon, Self::Error> {
+        LatLon::new(p.y() as f32, p.x() as f32).ok_or("invalid lat/lon")
+    }
+}
+
+impl From<BBox> for Rect<f64> {
+    fn from(b: BBox) -> Rect<f64> {
+        Rect::new(Coord{ x: b.left() as f64, y: b.bottom() as f64 }, Coord{ x: b.right() as f64, y: b.top() as f64 })
+    }
+}
+
+impl From<BBox> for Polygon<f64> {
+    fn from(b: BBox) -> Polygon<f64> {
+        Rect::from(b).to_polygon()
+    }
+}
+
+impl TryFrom<Rect<f64>> for BBox {
+    type Error = &'static str;
+
+    /// `Err` if the rectangle is not within valid lat/lons
+    fn try_from(r: Rect<f64>) -> Result<BBox, Self::Error> {
+        BBox::new(r.max().y as f32, r.min().x as f32, r.min().y as f32, r.max().x as f32).ok_or("bad lat/lon")
+    }
+}
+
+impl TryFrom<Polygon<f64>> for BBox {
+    type Error = &'static str;
+
+    /// The bounding box of this polygon. `Err` if it's empty, or not within valid lat/lons
+    fn try_from(p: Polygon<f64>) -> Result<BBox, Self::Error> {
+        let mut coords = p.exterior().coords();
+        let first = coords.next().ok_or("empty polygon")?;
+        let (min, max) = coords.fold((*first, *first), |(min, max), c| {
+            (Coord{ x: min.x.min(c.x), y: min.y.min(c.y) }, Coord{ x: max.x.max(c.x), y: max.y.max(c.y) })
+        });
+        BBox::try_from(Rect::new(min, max))
+    }
+}
+
+impl From<Tile> for Polygon<f64> {
+    fn from(t: Tile) -> Polygon<f64> {
+        t.bbox().into()
+    }
+}
+
+impl From<Metatile> for Polygon<f64> {
+    fn from(mt: Metatile) -> Polygon<f64> {
+        BBox::new_from_points(&mt.nw_corner(), &mt.se_corner()).into()
+    }
+}
+
+impl From<ModTileMetatile> for Polygon<f64> {
+    fn from(mt: ModTileMetatile) -> Polygon<f64> {
+        mt.inner.into()
+    }
+}
+
+impl TryFrom<Polygon<f64>> for coverer::Polygon {
+    type Error = &'static str;
+
+    /// `Err` if a point is not a valid lat/lon, or a ring has fewer than 3 points
+    fn try_from(p: Polygon<f64>) -> Result<coverer::Polygon, Self::Error> {
+        fn ring(line: &::geo_types::LineString<f64>) -> Result<Vec<LatLon>, &'static str> {
+            let mut points = line.points().map(LatLon::try_from).collect::<Result<Vec<_>, _>>()?;
+            // geo-types rings repeat the first point at the end
+            if points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+            Ok(points)
+        }
+        let holes = p.interiors().iter().map(ring).collect::<Result<Vec<_>, _>>()?;
+        coverer::Polygon::new(ring(p.exterior())?, holes).ok_or("polygon ring has fewer than 3 points")
+    }
+}
+
+fn feature(bbox: BBox, properties: Map<String, Value>) -> Value {
+    // Same ring as `Rect::to_polygon`, counter clockwise from the bottom right
+    let ring: Vec<Value> = [(bbox.right(), bbox.bottom()), (bbox.right(), bbox.top()), (bbox.left(), bbox.top()), (bbox.left(), bbox.bottom()), (bbox.right(), bbox.bottom())]
+        .iter().map(|&(x, y)| Value::Array(vec![f32_json(x), f32_json(y)])).collect();
+
+    let mut geometry = Map::new();
+    geometry.insert("type".to_string(), Value::String("Polygon".to_string()));
+    geometry.insert("coordinates".to_string(), Value::Array(vec![Value::Array(ring)]));
+
+    let mut feature = Map::new();
+    feature.insert("type".to_string(), Value::String("Feature".to_string()));
+    feature.insert("geometry".to_string(), Value::Object(geometry));
+    feature.insert("properties".to_string(), Value::Object(properties));
+    Value::Object(feature)
+}
+
+fn write_features<W: Write, I: Iterator<Item=Value>>(mut w: W, features: I) -> io::Result<()> {
+    w.write_all(br#"{"type":"FeatureCollection","features":["#)?;
+    for (i, f) in features.enumerate() {
+        if i > 0 {
+            w.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut w, &f)?;
+    }
+    w.write_all(b"]}")?;
+    Ok(())
+}
+
+fn tile_feature(t: Tile) -> Value {
+    let mut properties = Map::new();
+    properties.insert("z".to_string(), Value::from(t.zoom()));
+    properties.insert("x".to_string(), Value::from(t.x()));
+    properties.insert("y".to_string(), Value::from(t.y()));
+    feature(t.bbox(), properties)
+}
+
+fn metatile_feature(mt: Metatile) -> Value {
+    let mut properties = Map::new();
+    properties.insert("z".to_string(), Value::from(mt.zoom()));
+    properties.insert("x".to_string(), Value::from(mt.x()));
+    properties.insert("y".to_string(), Value::from(mt.y()));
+    if mt.is_square() {
+        properties.insert("scale".to_string(), Value::from(mt.scale()));
+    } else {
+        properties.insert("width".to_string(), Value::from(mt.width()));
+        properties.insert("height".to_string(), Value::from(mt.height()));
+    }
+    feature(BBox::new_from_points(&mt.nw_corner(), &mt.se_corner()), properties)
+}
+
+/// Write these tiles as a GeoJSON `FeatureCollection` of polygons, with `z`, `x` & `y`
+/// properties. Tiles are written as they are iterated over, so this works for large iterators.
+pub fn write_tiles_geojson<W: Write, I: IntoIterator<Item=Tile>>(w: W, tiles: I) -> io::Result<()> {
+    write_features(w, tiles.into_iter().map(tile_feature))
+}
+
+/// These tiles as a GeoJSON `FeatureCollection` of polygons, with `z`, `x` & `y` properties.
+pub fn tiles_to_geojson<I: IntoIterator<Item=Tile>>(tiles: I) -> String {
+    let mut buf = Vec::new();
+    write_tiles_geojson(&mut buf, tiles).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+/// Write these tiles, and how many hits each had (e.g. from `accesslog::TileHits`), as a
+/// GeoJSON `FeatureCollection` of polygons, with `z`, `x`, `y` & `hits` properties, i.e. a map of
+/// which areas are used most.
+pub fn write_tile_hits_geojson<W: Write, I: IntoIterator<Item=(Tile, u64)>>(w: W, hits: I) -> io::Result<()> {
+    write_features(w, hits.into_iter().map(|(t, n)| {
+        let mut feature = tile_feature(t);
+        feature["properties"]["hits"] = Value::from(n);
+        feature
+    }))
+}
+
+/// Write these metatiles as a GeoJSON `FeatureCollection` of polygons, with `z`, `x`, `y` &
+/// `scale` (or `width` & `height` for non-square metatiles) properties.
+pub fn write_metatiles_geojson<W: Write, I: IntoIterator<Item=Metatile>>(w: W, metatiles: I) -> io::Result<()> {
+    write_features(w, metatiles.into_iter().map(metatile_feature))
+}
+
+/// These metatiles as a GeoJSON `FeatureCollection` of polygons, with `z`, `x`, `y` & `scale`
+/// properties.
+pub fn metatiles_to_geojson<I: IntoIterator<Item=Metatile>>(metatiles: I) -> String {
+    let mut buf = Vec::new();
+    write_metatiles_geojson(&mut buf, metatiles).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn geo_latlon() {
+        let p: Point<f64> = LatLon::new(54.5, -6.25).unwrap().into();
+        assert_eq!(p, Point::new(-6.25, 54.5));
+        assert_eq!(LatLon::try_from(p), Ok(LatLon::new(54.5, -6.25).unwrap()));
+        assert!(LatLon::try_from(Point::new(200., 0.)).is_err());
+    }
+
+    #[test]
+    fn geo_bbox() {
+        let bbox = BBox::new(55.5, -11.25, 51.0, -5.0).unwrap();
+        let rect: Rect<f64> = bbox.clone().into();
+        assert_eq!(rect.min(), Coord{ x: -11.25, y: 51.0 });
+        assert_eq!(rect.max(), Coord{ x: -5.0, y: 55.5 });
+        assert_eq!(BBox::try_from(rect), Ok(bbox.clone()));
+
+        let polygon: Polygon<f64> = bbox.clone().into();
+        assert_eq!(polygon.exterior().coords().count(), 5);
+        assert_eq!(BBox::try_from(polygon), Ok(bbox));
+
+        assert!(BBox::try_from(Rect::new(Coord{ x: 0., y: 0. }, Coord{ x: 190., y: 10. })).is_err());
+    }
+
+    #[test]
+    fn geo_coverer_polygon() {
+        let polygon: Polygon<f64> = Tile::new(2, 1, 1).unwrap().into();
+        let polygon = coverer::Polygon::try_from(polygon).unwrap();
+        assert_eq!(polygon.exterior().len(), 4);
+        assert_eq!(::Coverer::new(0, 5, None).unwrap().cover(&polygon), vec![Tile::new(2, 1, 1).unwrap()]);
+
+        let line = ::geo_types::LineString::from(vec![(0., 0.), (1., 1.), (0., 0.)]);
+        assert!(coverer::Polygon::try_from(Polygon::new(line, vec![])).is_err());
+    }
+
+    #[test]
+    fn geo_tile() {
+        let polygon: Polygon<f64> = Tile::new(1, 1, 0).unwrap().into();
+        let bbox = BBox::try_from(polygon).unwrap();
+        assert_eq!(bbox.left(), 0.);
+        assert_eq!(bbox.right(), 180.);
+        assert_eq!(bbox.bottom(), 0.);
+        assert!((bbox.top() - 85.05112).abs() < 1e-4);
+
+        let polygon: Polygon<f64> = Metatile::new(8, 2, 0, 0).unwrap().into();
+        let bbox = BBox::try_from(polygon).unwrap();
+        assert_eq!((bbox.left(), bbox.right()), (-180., 180.));
+    }
+
+    #[test]
+    fn geojson() {
+        let geojson = tiles_to_geojson(vec![Tile::new(0, 0, 0).unwrap()]);
+        let v: Value = serde_json::from_str(&geojson).unwrap();
+        assert_eq!(v["type"], "FeatureCollection");
+        assert_eq!(v["features"].as_array().unwrap().len(), 1);
+        assert_eq!(v["features"][0]["type"], "Feature");
+        assert_eq!(v["features"][0]["geometry"]["type"], "Polygon");
+        assert_eq!(v["features"][0]["geometry"]["coordinates"][0].as_array().unwrap().len(), 5);
+        assert_eq!(v["features"][0]["properties"].to_string(), r#"{"x":0,"y":0,"z":0}"#);
+
+        let geojson = tiles_to_geojson(Tile::all().take(5));
+        let v: Value = serde_json::from_str(&geojson).unwrap();
+        assert_eq!(v["features"].as_array().unwrap().len(), 5);
+        assert_eq!(v["features"][4]["properties"].to_string(), r#"{"x":1,"y":1,"z":1}"#);
+
+        let mut buf = Vec::new();
+        write_tile_hits_geojson(&mut buf, vec![(Tile::new(1, 0, 1).unwrap(), 42)]).unwrap();
+        let v: Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(v["features"][0]["properties"].to_string(), r#"{"hits":42,"x":0,"y":1,"z":1}"#);
+
+        assert_eq!(tiles_to_geojson(vec![]), r#"{"type":"FeatureCollection","features":[]}"#);
+
+        // Coordinates are the f32s, not widened to f64
+        let geojson = tiles_to_geojson(vec![Tile::new(3, 2, 1).unwrap()]);
+        let v: Value = serde_json::from_str(&geojson).unwrap();
+        let bbox = Tile::new(3, 2, 1).unwrap().bbox();
+        assert_eq!(v["features"][0]["geometry"]["coordinates"][0][1].to_string(), format!("[{:?},{:?}]", bbox.right(), bbox.top()));
+        assert!(geojson.contains(&format!("[-90.0,{:?}]", bbox.bottom())), "{}", geojson);
+
+        let geojson = metatiles_to_geojson(Metatile::all(8).take(2));
+        let v: Value = serde_json::from_str(&geojson).unwrap();
+        assert_eq!(v["features"][1]["properties"].to_string(), r#"{"scale":8,"x":0,"y":0,"z":1}"#);
+    }
+}
@@ -0,0 +1,152 @@
+//! Ancestors & descendants of tiles, worked out directly from the x & y bits.
+//!
+//! ```
+//! # use slippy_map_tiles::Tile;
+//! let t = Tile::new(10, 494, 331).unwrap();
+//! let a = t.ancestor_at(4).unwrap();
+//! assert_eq!(a, Tile::new(4, 7, 5).unwrap());
+//! assert!(a.is_ancestor_of(&t));
+//! assert_eq!(a.descendants_at(10).unwrap().num_tiles(), 64*64);
+//! ```
+use std::ops::RangeInclusive;
+
+use {Tile, TileRange};
+
+impl Tile {
+    /// The tile at this (lower or equal) zoom which contains this tile. `None` if the zoom is
+    /// greater than this tile's zoom.
+    pub fn ancestor_at(&self, zoom: u8) -> Option<Tile> {
+        if zoom > self.zoom {
+            return None;
+        }
+        let shift = (self.zoom - zoom) as u32;
+        Some(Tile{ zoom, x: self.x.checked_shr(shift).unwrap_or(0), y: self.y.checked_shr(shift).unwrap_or(0) })
+    }
+
+    /// True iff `other` is inside this tile, and at a higher zoom. A tile isn't its own
+    /// ancestor.
+    pub fn is_ancestor_of(&self, other: &Tile) -> bool {
+        other.zoom > self.zoom && other.ancestor_at(self.zoom) == Some(*self)
+    }
+
+    /// The highest zoom tile which contains both tiles (which could be one of them). There
+    /// always is one, since 0/0/0 contains everything.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// let a = Tile::new(5, 16, 3).unwrap();
+    /// let b = Tile::new(6, 35, 7).unwrap();
+    /// assert_eq!(Tile::lowest_common_ancestor(&a, &b), Tile::new(4, 8, 1).unwrap());
+    /// ```
+    pub fn lowest_common_ancestor(a: &Tile, b: &Tile) -> Tile {
+        let zoom = a.zoom.min(b.zoom);
+        let (a, b) = (a.ancestor_at(zoom).unwrap(), b.ancestor_at(zoom).unwrap());
+        // How many levels up until all the differing bits are gone
+        let diff = (a.x ^ b.x) | (a.y ^ b.y);
+        let levels = (32 - diff.leading_zeros()) as u8;
+        a.ancestor_at(zoom - levels).unwrap()
+    }
+
+    /// All the tiles, at this (higher or equal) zoom, inside this tile. `None` if the zoom is
+    /// lower than this tile's zoom, or more than 31.
+    pub fn descendants_at(&self, zoom: u8) -> Option<TileRange> {
+        if zoom < self.zoom || zoom > 31 {
+            return None;
+        }
+        let shift = zoom - self.zoom;
+        let (x, y) = (self.x << shift, self.y << shift);
+        let size = 1u32 << shift;
+        TileRange::new(zoom, x, x + (size - 1), y, y + (size - 1))
+    }
+
+    /// The tiles inside this tile, for each of these zooms. Zooms lower than this tile's are
+    /// skipped, and this tile's own zoom is just this tile.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// let t = Tile::new(3, 2, 5).unwrap();
+    /// let ranges = t.descendants_in_zooms(0..=5);
+    /// assert_eq!(ranges.iter().map(|r| r.num_tiles()).collect::<Vec<_>>(), vec![1, 4, 16]);
+    /// ```
+    pub fn descendants_in_zooms(&self, zooms: RangeInclusive<u8>) -> Vec<TileRange> {
+        zooms.filter_map(|zoom| self.descendants_at(zoom)).collect()
+    }
+
+    /// Which subtile to go into, from 0/0/0, at each zoom, to get to this tile. Each step is
+    /// 0 (north west), 1 (north east), 2 (south west) or 3 (south east), the same order as
+    /// `subtiles()`.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::new(2, 1, 2).unwrap().path_from_root(), vec![2, 1]);
+    /// assert!(Tile::new(0, 0, 0).unwrap().path_from_root().is_empty());
+    /// ```
+    pub fn path_from_root(&self) -> Vec<u8> {
+        (0..self.zoom).rev().map(|bit| {
+            let bit = bit as u32;
+            (self.x.checked_shr(bit).unwrap_or(0) & 1) as u8 + 2 * (self.y.checked_shr(bit).unwrap_or(0) & 1) as u8
+        }).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ancestors() {
+        let t = Tile::new(10, 494, 331).unwrap();
+        assert_eq!(t.ancestor_at(10), Some(t));
+        assert_eq!(t.ancestor_at(11), None);
+        assert_eq!(t.ancestor_at(0), Tile::new(0, 0, 0));
+        assert_eq!(t.ancestor_at(9), t.parent());
+        let mut parent = t;
+        for zoom in (0..10).rev() {
+            parent = parent.parent().unwrap();
+            assert_eq!(t.ancestor_at(zoom), Some(parent));
+            assert!(parent.is_ancestor_of(&t));
+            assert!(!t.is_ancestor_of(&parent));
+        }
+        assert!(!t.is_ancestor_of(&t));
+        assert!(!Tile::new(10, 495, 331).unwrap().ancestor_at(3).unwrap().is_ancestor_of(&Tile::new(4, 0, 0).unwrap()));
+
+        let lca = Tile::lowest_common_ancestor;
+        assert_eq!(lca(&t, &t), t);
+        assert_eq!(lca(&t, &t.ancestor_at(4).unwrap()), t.ancestor_at(4).unwrap());
+        assert_eq!(lca(&t.ancestor_at(4).unwrap(), &t), t.ancestor_at(4).unwrap());
+        assert_eq!(lca(&t, &Tile::new(10, 495, 331).unwrap()), Tile::new(9, 247, 165).unwrap());
+        assert_eq!(lca(&Tile::new(1, 0, 0).unwrap(), &Tile::new(1, 1, 1).unwrap()), Tile::new(0, 0, 0).unwrap());
+        assert_eq!(lca(&Tile::new(31, 0, 0).unwrap(), &Tile::new(31, (1<<31) - 1, 0).unwrap()), Tile::new(0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn descendants() {
+        let t = Tile::new(3, 2, 5).unwrap();
+        assert_eq!(t.descendants_at(3), TileRange::new(3, 2, 2, 5, 5));
+        assert_eq!(t.descendants_at(2), None);
+        assert_eq!(t.descendants_at(32), None);
+        let range = t.descendants_at(6).unwrap();
+        assert_eq!(range, TileRange::new(6, 16, 23, 40, 47).unwrap());
+        assert!(range.iter().all(|d| t.is_ancestor_of(&d)));
+        assert_eq!(t.descendants_at(31).unwrap().num_tiles(), 1 << 56);
+
+        let ranges = t.descendants_in_zooms(4..=8);
+        assert_eq!(ranges.iter().map(|r| r.zoom()).collect::<Vec<_>>(), vec![4, 5, 6, 7, 8]);
+        let count: u64 = ranges.iter().map(|r| r.num_tiles()).sum();
+        assert_eq!(count as usize, t.all_subtiles_iter().take_while(|s| s.zoom() <= 8).count());
+        assert_eq!(Tile::new(0, 0, 0).unwrap().descendants_in_zooms(30..=40).len(), 2);
+    }
+
+    #[test]
+    fn path_from_root() {
+        let t = Tile::new(10, 494, 331).unwrap();
+        let mut tile = Tile::new(0, 0, 0).unwrap();
+        for step in t.path_from_root() {
+            tile = tile.subtiles().unwrap()[step as usize];
+        }
+        assert_eq!(tile, t);
+        let path: String = t.path_from_root().iter().map(|s| s.to_string()).collect();
+        assert_eq!(path, t.quadkey());
+    }
+}
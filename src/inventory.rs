@@ -0,0 +1,411 @@
+//! Finding out which tiles are in a `TileStore`, and comparing that with the tiles which should
+//! be there.
+//!
+//! ```
+//! # use slippy_map_tiles::{Tile, BBox, TileStore, FsTileStore, FsLayout};
+//! # use slippy_map_tiles::inventory::Inventory;
+//! # extern crate tempfile;
+//! # let tmp = tempfile::tempdir().unwrap();
+//! # let dir = tmp.path();
+//! let store = FsTileStore::new(&dir, FsLayout::Zxy, "png");
+//! store.put(&Tile::new(1, 0, 0).unwrap(), b"png data").unwrap();
+//! store.put(&Tile::new(1, 1, 0).unwrap(), b"").unwrap();
+//!
+//! let inventory = Inventory::scan(&store, 0, 1).unwrap();
+//! let diff = inventory.compare_bbox(&BBox::new(80., -170., 10., 170.).unwrap(), 0, 1);
+//! assert_eq!(diff.expected_count(1), 2);
+//! assert_eq!(diff.missing().iter().collect::<Vec<_>>(), vec![Tile::new(0, 0, 0).unwrap()]);
+//! assert_eq!(diff.zero_bytes().iter().collect::<Vec<_>>(), vec![Tile::new(1, 1, 0).unwrap()]);
+//! ```
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::iter;
+
+use {Tile, TileRange, BBox};
+use coverer::Region;
+use store::{TileStore, ScanEntry};
+use tilelist::TileListWriter;
+
+/// A set of tiles, at any zooms. Tiles next to each other in a column are stored together, so
+/// the large, mostly complete, areas found in tile caches take little memory.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct TileSet {
+    // For each zoom & x, sorted runs of y (inclusive), which don't overlap or touch
+    pub(crate) columns: BTreeMap<u8, BTreeMap<u32, Vec<(u32, u32)>>>,
+}
+
+/// Add y from `a` to `b` to these runs
+fn add_run(runs: &mut Vec<(u32, u32)>, a: u32, b: u32) {
+    let (mut a, mut b) = (a, b);
+    // The first run which overlaps or touches, and the one after the last
+    let start = runs.partition_point(|r| (r.1 as u64) + 1 < a as u64);
+    let mut end = start;
+    while end < runs.len() && runs[end].0 as u64 <= b as u64 + 1 {
+        a = a.min(runs[end].0);
+        b = b.max(runs[end].1);
+        end += 1;
+    }
+    runs.splice(start..end, iter::once((a, b)));
+}
+
+/// The parts of the runs in `a` which aren't in `b`
+fn subtract_runs(a: &[(u32, u32)], b: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut result = Vec::new();
+    let mut j = 0;
+    for &(start, end) in a {
+        let (mut start, end) = (start as u64, end as u64);
+        while j < b.len() && (b[j].1 as u64) < start {
+            j += 1;
+        }
+        let mut k = j;
+        while k < b.len() && b[k].0 as u64 <= end {
+            if b[k].0 as u64 > start {
+                result.push((start as u32, b[k].0 - 1));
+            }
+            start = start.max(b[k].1 as u64 + 1);
+            k += 1;
+        }
+        if start <= end {
+            result.push((start as u32, end as u32));
+        }
+    }
+    result
+}
+
+impl TileSet {
+    /// An empty set
+    pub fn new() -> TileSet {
+        TileSet::default()
+    }
+
+    /// Add this tile
+    pub fn insert(&mut self, tile: &Tile) {
+        let runs = self.columns.entry(tile.zoom).or_default().entry(tile.x).or_default();
+        add_run(runs, tile.y, tile.y);
+    }
+
+    /// Add all the tiles in this range
+    pub fn insert_range(&mut self, range: &TileRange) {
+        let columns = self.columns.entry(range.zoom()).or_default();
+        for x in range.min_x()..=range.max_x() {
+            add_run(columns.entry(x).or_default(), range.min_y(), range.max_y());
+        }
+    }
+
+    /// True iff this tile is in the set
+    pub fn contains(&self, tile: &Tile) -> bool {
+        self.columns.get(&tile.zoom).and_then(|c| c.get(&tile.x)).is_some_and(|runs| {
+            // The first run which doesn't end before this tile
+            let i = runs.partition_point(|r| r.1 < tile.y);
+            runs.get(i).is_some_and(|r| r.0 <= tile.y)
+        })
+    }
+
+    /// Number of tiles in the set
+    pub fn len(&self) -> u64 {
+        self.columns.keys().map(|&z| self.len_at(z)).sum()
+    }
+
+    /// Number of tiles at this zoom
+    pub fn len_at(&self, zoom: u8) -> u64 {
+        self.columns.get(&zoom).map_or(0, |c| c.values().flatten().map(|&(a, b)| (b - a) as u64 + 1).sum())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// The zooms which have tiles
+    pub fn zooms(&self) -> Vec<u8> {
+        self.columns.keys().cloned().collect()
+    }
+
+    /// All the tiles, in zoom, then x, then y, order
+    pub fn iter(&self) -> impl Iterator<Item=Tile> + '_ {
+        self.columns.iter().flat_map(|(&zoom, columns)| {
+            columns.iter().flat_map(move |(&x, runs)| runs.iter().flat_map(move |&(a, b)| (a..=b).map(move |y| Tile{ zoom, x, y })))
+        })
+    }
+
+    /// The tiles in this set, but not in `other`
+    pub fn difference(&self, other: &TileSet) -> TileSet {
+        let mut result = TileSet::new();
+        for (&zoom, columns) in &self.columns {
+            for (&x, runs) in columns {
+                let runs = match other.columns.get(&zoom).and_then(|c| c.get(&x)) {
+                    None => runs.clone(),
+                    Some(other_runs) => subtract_runs(runs, other_runs),
+                };
+                if !runs.is_empty() {
+                    result.columns.entry(zoom).or_default().insert(x, runs);
+                }
+            }
+        }
+        result
+    }
+
+    /// Only the tiles from `minzoom` to `maxzoom`
+    fn zooms_between(&self, minzoom: u8, maxzoom: u8) -> TileSet {
+        TileSet{ columns: self.columns.range(minzoom..=maxzoom.max(minzoom)).map(|(&z, c)| (z, c.clone())).collect() }
+    }
+}
+
+/// The tiles in a `TileStore`
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Inventory {
+    tiles: TileSet,
+    zero_bytes: TileSet,
+    unparsable: Vec<String>,
+}
+
+impl Inventory {
+    /// Find all the tiles in this store from `minzoom` to `maxzoom`.
+    pub fn scan(store: &dyn TileStore, minzoom: u8, maxzoom: u8) -> io::Result<Inventory> {
+        let mut inventory = Inventory{ tiles: TileSet::new(), zero_bytes: TileSet::new(), unparsable: Vec::new() };
+        store.scan_metadata(minzoom..=maxzoom, &mut |entry, metadata| {
+            match entry {
+                ScanEntry::Unparsable(s) => inventory.unparsable.push(s),
+                ScanEntry::Tile(t) => {
+                    inventory.tiles.insert(&t);
+                    if metadata.is_some_and(|m| m.size() == 0) {
+                        inventory.zero_bytes.insert(&t);
+                    }
+                },
+            }
+            Ok(())
+        })?;
+        inventory.unparsable.sort();
+        Ok(inventory)
+    }
+
+    /// All the tiles found, including empty ones
+    pub fn tiles(&self) -> &TileSet { &self.tiles }
+
+    /// The tiles which are empty (0 bytes)
+    pub fn zero_bytes(&self) -> &TileSet { &self.zero_bytes }
+
+    /// Everything in the store which isn't a tile (see `ScanEntry::Unparsable`)
+    pub fn unparsable(&self) -> &[String] { &self.unparsable }
+
+    /// Compare with the tiles which cover this bbox, from `minzoom` to `maxzoom`, i.e. the tiles
+    /// from `TileRange::new_for_bbox`.
+    pub fn compare_bbox(&self, bbox: &BBox, minzoom: u8, maxzoom: u8) -> InventoryDiff {
+        let mut expected = TileSet::new();
+        let mut expected_counts = Vec::new();
+        for zoom in minzoom..=maxzoom.min(31) {
+            let range = TileRange::new_for_bbox(bbox, zoom);
+            if let Some(ref range) = range {
+                expected.insert_range(range);
+            }
+            expected_counts.push((zoom, range.map_or(0, |r| r.num_tiles())));
+        }
+        self.diff(expected, expected_counts, minzoom, maxzoom)
+    }
+
+    /// Compare with the tiles which overlap this region (e.g. a `Polygon`), from `minzoom` to
+    /// `maxzoom`.
+    pub fn compare<R: Region>(&self, region: &R, minzoom: u8, maxzoom: u8) -> InventoryDiff {
+        let maxzoom = maxzoom.min(31);
+        let mut expected = TileSet::new();
+        if minzoom <= maxzoom {
+            add_region(region, Tile{ zoom: 0, x: 0, y: 0 }, minzoom, maxzoom, &mut expected);
+        }
+        let expected_counts = (minzoom..=maxzoom).map(|z| (z, expected.len_at(z))).collect();
+        self.diff(expected, expected_counts, minzoom, maxzoom)
+    }
+
+    fn diff(&self, expected: TileSet, expected_counts: Vec<(u8, u64)>, minzoom: u8, maxzoom: u8) -> InventoryDiff {
+        let tiles = self.tiles.zooms_between(minzoom, maxzoom);
+        InventoryDiff{
+            missing: expected.difference(&tiles),
+            extra: tiles.difference(&expected),
+            zero_bytes: self.zero_bytes.zooms_between(minzoom, maxzoom),
+            expected_counts,
+        }
+    }
+}
+
+/// Add the tiles, inside this tile, which overlap the region
+fn add_region<R: Region>(region: &R, tile: Tile, minzoom: u8, maxzoom: u8, set: &mut TileSet) {
+    if !region.intersects_tile(&tile) {
+        return;
+    }
+    if tile.zoom == maxzoom || region.contains_tile(&tile) {
+        for zoom in tile.zoom.max(minzoom)..=maxzoom {
+            set.insert_range(&tile.descendants_at(zoom).unwrap());
+        }
+        return;
+    }
+    if tile.zoom >= minzoom {
+        set.insert(&tile);
+    }
+    for child in tile.subtiles().unwrap().iter() {
+        add_region(region, *child, minzoom, maxzoom, set);
+    }
+}
+
+/// The differences between an `Inventory` and the tiles which should be there
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct InventoryDiff {
+    pub(crate) missing: TileSet,
+    pub(crate) extra: TileSet,
+    pub(crate) zero_bytes: TileSet,
+    pub(crate) expected_counts: Vec<(u8, u64)>,
+}
+
+impl InventoryDiff {
+    /// Tiles which should be there, but aren't
+    pub fn missing(&self) -> &TileSet { &self.missing }
+
+    /// Tiles which are there, but shouldn't be
+    pub fn extra(&self) -> &TileSet { &self.extra }
+
+    /// Tiles which are there, but empty (0 bytes)
+    pub fn zero_bytes(&self) -> &TileSet { &self.zero_bytes }
+
+    /// How many tiles should be there at this zoom
+    pub fn expected_count(&self, zoom: u8) -> u64 {
+        self.expected_counts.iter().find(|&&(z, _)| z == zoom).map_or(0, |&(_, c)| c)
+    }
+
+    /// How many of the tiles which should be there, at this zoom, are there (including empty
+    /// ones)
+    pub fn present_count(&self, zoom: u8) -> u64 {
+        self.expected_count(zoom).saturating_sub(self.missing.len_at(zoom))
+    }
+
+    /// True iff nothing is missing or empty
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.zero_bytes.is_empty()
+    }
+
+    /// Write the tiles which need to be rendered, i.e. the missing & empty tiles. Returns how many
+    /// were written.
+    pub fn write_missing<W: Write>(&self, out: &mut TileListWriter<W>) -> io::Result<u64> {
+        let mut tiles = self.missing.clone();
+        for tile in self.zero_bytes.iter() {
+            tiles.insert(&tile);
+        }
+        for tile in tiles.iter() {
+            out.write_tile(&tile)?;
+        }
+        out.flush()?;
+        Ok(tiles.len())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use coverer::Polygon;
+    use store::{FsTileStore, FsLayout};
+    use tilelist::TileListFormat;
+    use LatLon;
+    use tempfile::tempdir;
+
+    #[test]
+    fn runs() {
+        let mut runs = Vec::new();
+        add_run(&mut runs, 5, 5);
+        add_run(&mut runs, 7, 9);
+        add_run(&mut runs, 0, 1);
+        assert_eq!(runs, vec![(0, 1), (5, 5), (7, 9)]);
+        add_run(&mut runs, 6, 6);
+        assert_eq!(runs, vec![(0, 1), (5, 9)]);
+        add_run(&mut runs, 2, 20);
+        assert_eq!(runs, vec![(0, 20)]);
+        add_run(&mut runs, u32::MAX, u32::MAX);
+        assert_eq!(runs, vec![(0, 20), (u32::MAX, u32::MAX)]);
+
+        assert_eq!(subtract_runs(&[(0, 20)], &[(3, 4), (10, 12), (20, 30)]), vec![(0, 2), (5, 9), (13, 19)]);
+        assert_eq!(subtract_runs(&[(0, 2), (5, 8), (10, 15)], &[(2, 11)]), vec![(0, 1), (12, 15)]);
+        assert_eq!(subtract_runs(&[(0, 2)], &[]), vec![(0, 2)]);
+        assert_eq!(subtract_runs(&[(3, 4)], &[(0, 10)]), vec![]);
+    }
+
+    #[test]
+    fn tile_set() {
+        let mut set = TileSet::new();
+        assert!(set.is_empty());
+        set.insert_range(&TileRange::new(5, 1, 2, 3, 5).unwrap());
+        set.insert(&Tile::new(5, 1, 6).unwrap());
+        set.insert(&Tile::new(5, 1, 6).unwrap());
+        set.insert(&Tile::new(0, 0, 0).unwrap());
+        assert_eq!((set.len(), set.len_at(5), set.len_at(4)), (8, 7, 0));
+        assert_eq!(set.zooms(), vec![0, 5]);
+        assert!(set.contains(&Tile::new(5, 2, 4).unwrap()));
+        assert!(!set.contains(&Tile::new(5, 2, 6).unwrap()));
+        assert_eq!(set.columns[&5][&1], vec![(3, 6)]);
+        assert_eq!(set.iter().take(3).map(|t| t.zxy()).collect::<Vec<_>>(), vec!["0/0/0", "5/1/3", "5/1/4"]);
+
+        let mut other = TileSet::new();
+        other.insert_range(&TileRange::new(5, 0, 1, 0, 31).unwrap());
+        let diff = set.difference(&other);
+        assert_eq!(diff.iter().map(|t| t.zxy()).collect::<Vec<_>>(), vec!["0/0/0", "5/2/3", "5/2/4", "5/2/5"]);
+        assert_eq!(other.difference(&set).len(), 64 - 4);
+
+        let mut gaps = TileSet::new();
+        for y in &[2, 5, 6, 9] {
+            gaps.insert(&Tile::new(5, 1, *y).unwrap());
+        }
+        let found: Vec<u32> = (0..12).filter(|&y| gaps.contains(&Tile::new(5, 1, y).unwrap())).collect();
+        assert_eq!(found, vec![2, 5, 6, 9]);
+    }
+
+    #[test]
+    fn inventory() {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path(), FsLayout::Zxy, "png");
+        let bbox = BBox::new(50., -30., 30., 10.).unwrap();
+        let range = TileRange::new_for_bbox(&bbox, 4).unwrap();
+        assert_eq!(range.num_tiles(), 6);
+        for t in range.iter().skip(1) {
+            store.put(&t, b"tile").unwrap();
+        }
+        store.put(&range.iter().nth(2).unwrap(), b"").unwrap();
+        store.put(&Tile::new(4, 0, 0).unwrap(), b"tile").unwrap();
+        store.put(&Tile::new(5, 0, 0).unwrap(), b"tile").unwrap();
+        fs::write(dir.path().join("4/extra.txt"), b"").unwrap();
+
+        let inventory = Inventory::scan(&store, 0, 4).unwrap();
+        assert_eq!(inventory.tiles().len(), 6);
+        assert_eq!(inventory.unparsable().len(), 1);
+
+        let diff = inventory.compare_bbox(&bbox, 3, 4);
+        assert_eq!((diff.expected_count(3), diff.expected_count(4)), (TileRange::new_for_bbox(&bbox, 3).unwrap().num_tiles(), 6));
+        assert_eq!((diff.present_count(3), diff.present_count(4)), (0, 5));
+        assert_eq!(diff.missing().len_at(4), 1);
+        assert!(diff.missing().contains(&range.iter().next().unwrap()));
+        assert_eq!(diff.extra().iter().collect::<Vec<_>>(), vec![Tile::new(4, 0, 0).unwrap()]);
+        assert_eq!(diff.zero_bytes().iter().collect::<Vec<_>>(), vec![range.iter().nth(2).unwrap()]);
+        assert!(!diff.is_complete());
+
+        let mut out = TileListWriter::new(Vec::new(), TileListFormat::Zxy);
+        assert_eq!(diff.write_missing(&mut out).unwrap(), diff.missing().len() + 1);
+        let list = String::from_utf8(out.finish().unwrap()).unwrap();
+        assert_eq!(list.lines().count() as u64, diff.missing().len() + 1);
+        assert!(list.contains(&format!("{}\n", range.iter().nth(2).unwrap().zxy())));
+
+        // A triangle, with the tiles found in the west, but not the east
+        let triangle = Polygon::new(vec![LatLon::new(50., -30.).unwrap(), LatLon::new(30., -30.).unwrap(), LatLon::new(30., 10.).unwrap()], vec![]).unwrap();
+        let diff = inventory.compare(&triangle, 4, 4);
+        assert!(diff.expected_count(4) < 6);
+        assert!(diff.missing().iter().all(|t| range.contains(&t)));
+        assert!(diff.extra().contains(&Tile::new(4, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn region_cover() {
+        let mut set = TileSet::new();
+        let bbox = BBox::new(50., -30., 30., 10.).unwrap();
+        add_region(&bbox, Tile::new(0, 0, 0).unwrap(), 2, 9, &mut set);
+        assert_eq!(set.zooms(), (2..=9).collect::<Vec<_>>());
+        for zoom in 2..=9 {
+            let range = TileRange::new_for_bbox(&bbox, zoom).unwrap();
+            assert!(range.iter().all(|t| set.contains(&t)));
+            assert!(set.len_at(zoom) <= range.num_tiles());
+        }
+    }
+}
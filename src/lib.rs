@@ -15,18 +15,112 @@
 //! ```
 #[macro_use] extern crate lazy_static;
 extern crate regex;
+#[cfg(any(feature="json", test))]
+extern crate serde_json;
+extern crate flate2;
 
 #[cfg(feature="world_file")]
 extern crate world_image_file;
 
+#[cfg(feature="serde")]
+extern crate serde;
+
+#[cfg(feature="geo")]
+extern crate geo_types;
+
+#[cfg(feature="rayon")]
+extern crate rayon;
+
+#[cfg(feature="mbtiles")]
+extern crate rusqlite;
+
+#[cfg(feature="server")]
+extern crate tiny_http;
+
+#[cfg(feature="download")]
+extern crate ureq;
+#[cfg(feature="download")]
+extern crate httpdate;
+#[cfg(test)]
+extern crate tempfile;
+#[cfg(all(test, feature="serde"))]
+extern crate bincode;
+
 use regex::Regex;
 use std::str::FromStr;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::io::{self, BufRead};
+use std::path::Path;
 
 #[cfg(feature="world_file")]
 use world_image_file::WorldFile;
 
+pub mod tilematrixset;
+pub use tilematrixset::{TileMatrixSet, TileMatrix, MatrixTile, CornerOfOrigin};
+
+pub mod urltemplate;
+pub use urltemplate::UrlTemplate;
+
+pub mod wmts;
+
+#[cfg(feature="json")]
+pub mod tilejson;
+#[cfg(feature="json")]
+pub use tilejson::TileJson;
+
+pub mod tilerange;
+pub use tilerange::TileRange;
+
+pub mod shard;
+pub use shard::{ShardStrategy, Sharder, Shardable, ShardExt};
+
+pub mod neighbours;
+pub use neighbours::Direction;
+
+pub mod hierarchy;
+
+pub mod coverer;
+pub use coverer::{Coverer, Region, Polygon};
+
+pub mod arcgis;
+
+pub mod store;
+pub use store::{TileStore, TileMetadata, ScanEntry, FsTileStore, FsLayout};
+
+pub mod migrate;
+
+pub mod inventory;
+
+pub mod estimate;
+
+pub mod renderd;
+
+pub mod scheduler;
+
+pub mod accesslog;
+
+pub mod tilelist;
+pub use tilelist::{TileListFormat, TileListReader, MetatileListReader, TileListWriter, TileListError};
+
+#[cfg(feature="serde")]
+mod serde_support;
+#[cfg(feature="serde")]
+pub use serde_support::zxy as serde_zxy;
+
+#[cfg(feature="geo")]
+pub mod geo;
+
+#[cfg(feature="rayon")]
+pub mod par;
+
+#[cfg(feature="mbtiles")]
+pub mod mbtiles;
+
+#[cfg(feature="server")]
+pub mod server;
+
+#[cfg(feature="download")]
+pub mod download;
+
 /// A single tile.
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub struct Tile {
@@ -241,7 +335,175 @@ impl Tile {
     /// Returns the ModTileMetatile path for storing this tile
     pub fn mt_path<T: std::fmt::Display>(&self, ext: T) -> String {
         let tc = xy_to_mt(self.x, self.y);
-        format!("{}/{}/{}/{}/{}/{}/{}.{}", self.zoom, tc[0], tc[1], tc[2], tc[3], tc[4], tc[5], ext)
+        format!("{}/{}/{}/{}/{}/{}.{}", self.zoom, tc[0], tc[1], tc[2], tc[3], tc[4], ext)
+    }
+
+    /// Returns the ArcGIS exploded cache path for storing this tile, i.e. `Lzz/Rrrrrrrrr/Ccccccccc`
+    /// with the row (y) & column (x) in hex.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::new(10, 547, 380).unwrap().arcgis_path("png"), "L10/R0000017c/C00000223.png");
+    /// ```
+    pub fn arcgis_path<T: std::fmt::Display>(&self, ext: T) -> String {
+        format!("L{:02}/R{:08x}/C{:08x}.{}", self.zoom, self.y, self.x, ext)
+    }
+
+    /// Constructs a Tile from an ArcGIS exploded cache path (see `arcgis_path`). Anything before
+    /// the `Lzz` directory is ignored. Returns None if this isn't a valid path.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::from_arcgis_path("cache/_alllayers/L10/R0000017c/C00000223.png"), Tile::new(10, 547, 380));
+    /// assert_eq!(Tile::from_arcgis_path("L10/R17c/C223.png"), None);
+    /// ```
+    pub fn from_arcgis_path(path: &str) -> Option<Tile> {
+        thread_local! {
+            static RE: Regex = Regex::new("(^|/)L(?P<zoom>[0-9]{2})/R(?P<row>[0-9a-fA-F]{8})/C(?P<col>[0-9a-fA-F]{8})(\\.[a-zA-Z]{3,4})?$").unwrap();
+        }
+
+        RE.with(|re| {
+            let caps = re.captures(path)?;
+            let zoom = caps.name("zoom")?.parse().ok()?;
+            let y = u32::from_str_radix(caps.name("row")?, 16).ok()?;
+            let x = u32::from_str_radix(caps.name("col")?, 16).ok()?;
+            Tile::new(zoom, x, y)
+        })
+    }
+
+    /// Returns the GWC (GeoWebCache) path for storing this tile, in the `EPSG:900913` gridset.
+    /// GeoWebCache counts y from the bottom, so y is flipped.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::new(12, 1234, 2861).unwrap().gwc_path("png"), "EPSG_900913_12/009_009/001234_001234.png");
+    /// ```
+    pub fn gwc_path<T: std::fmt::Display>(&self, ext: T) -> String {
+        let gwc = xy_to_gwc(self.zoom, self.x, flip_y(self.zoom, self.y).unwrap());
+        format!("EPSG_900913_{:02}/{}/{}.{}", self.zoom, gwc[0], gwc[1], ext)
+    }
+
+    /// Constructs a Tile from a GWC (GeoWebCache) path (see `gwc_path`), in any gridset with the
+    /// same tiles as `EPSG:900913`. Anything before the gridset directory is ignored. Returns
+    /// None if this isn't a valid path.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::from_gwc_path("gwc/osm/EPSG_900913_12/009_009/001234_001234.png"), Tile::new(12, 1234, 2861));
+    /// ```
+    pub fn from_gwc_path(path: &str) -> Option<Tile> {
+        thread_local! {
+            static RE: Regex = Regex::new("(^|/)[^/]+_(?P<zoom>[0-9]{2})/(?P<halfx>[0-9]+)_(?P<halfy>[0-9]+)/(?P<x>[0-9]+)_(?P<y>[0-9]+)(\\.[a-zA-Z]{3,4})?$").unwrap();
+        }
+
+        RE.with(|re| {
+            let caps = re.captures(path)?;
+            let zoom = caps.name("zoom")?.parse().ok()?;
+            let x = caps.name("x")?.parse().ok()?;
+            let y = caps.name("y")?.parse().ok()?;
+            let tile = Tile::new(zoom, x, flip_y(zoom, y)?)?;
+            // The directory has to match the file name
+            let half = 2u32 << (zoom / 2);
+            if caps.name("halfx")?.parse::<u32>().ok()? != x / half || caps.name("halfy")?.parse::<u32>().ok()? != y / half {
+                return None;
+            }
+            Some(tile)
+        })
+    }
+
+    /// Returns the MapCache path for storing this tile, in the default disk cache layout. MapCache
+    /// counts y from the bottom, so y is flipped. (MapCache's `arcgis` layout is `arcgis_path`)
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::new(12, 1234, 2861).unwrap().mapcache_path("png"), "12/000/001/234/000/001/234.png");
+    /// ```
+    pub fn mapcache_path<T: std::fmt::Display>(&self, ext: T) -> String {
+        let tc = xy_to_tc(self.x, flip_y(self.zoom, self.y).unwrap());
+        format!("{:02}/{}/{}/{}/{}/{}/{}.{}", self.zoom, tc[0], tc[1], tc[2], tc[3], tc[4], tc[5], ext)
+    }
+
+    /// Constructs a Tile from a MapCache default layout path (see `mapcache_path`). Anything
+    /// before the zoom directory is ignored. Returns None if this isn't a valid path.
+    pub fn from_mapcache_path(path: &str) -> Option<Tile> {
+        thread_local! {
+            static RE: Regex = Regex::new("(^|/)(?P<zoom>[0-9]{2})/(?P<x1>[0-9]{3})/(?P<x2>[0-9]{3})/(?P<x3>[0-9]{3})/(?P<y1>[0-9]{3})/(?P<y2>[0-9]{3})/(?P<y3>[0-9]{3})(\\.[a-zA-Z]{3,4})?$").unwrap();
+        }
+
+        RE.with(|re| {
+            let caps = re.captures(path)?;
+            let zoom = caps.name("zoom")?.parse().ok()?;
+            let x = format!("{}{}{}", caps.name("x1")?, caps.name("x2")?, caps.name("x3")?).parse().ok()?;
+            let y = format!("{}{}{}", caps.name("y1")?, caps.name("y2")?, caps.name("y3")?).parse().ok()?;
+            Tile::new(zoom, x, flip_y(zoom, y)?)
+        })
+    }
+
+    /// Returns the MapCache `worldwind` layout path for storing this tile, i.e. `z/yyyy/yyyy_xxxx`.
+    /// y is counted from the bottom, so it is flipped.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::new(5, 3, 20).unwrap().worldwind_path("jpg"), "5/0011/0011_0003.jpg");
+    /// ```
+    pub fn worldwind_path<T: std::fmt::Display>(&self, ext: T) -> String {
+        let y = flip_y(self.zoom, self.y).unwrap();
+        format!("{}/{:04}/{:04}_{:04}.{}", self.zoom, y, y, self.x, ext)
+    }
+
+    /// Constructs a Tile from a MapCache `worldwind` layout path (see `worldwind_path`). Anything
+    /// before the zoom directory is ignored. Returns None if this isn't a valid path.
+    pub fn from_worldwind_path(path: &str) -> Option<Tile> {
+        thread_local! {
+            static RE: Regex = Regex::new("(^|/)(?P<zoom>[0-9]{1,2})/(?P<dir>[0-9]{4,10})/(?P<y>[0-9]{4,10})_(?P<x>[0-9]{4,10})(\\.[a-zA-Z]{3,4})?$").unwrap();
+        }
+
+        RE.with(|re| {
+            let caps = re.captures(path)?;
+            if caps.name("dir")? != caps.name("y")? {
+                return None;
+            }
+            let zoom = caps.name("zoom")?.parse().ok()?;
+            let x = caps.name("x")?.parse().ok()?;
+            let y = caps.name("y")?.parse().ok()?;
+            Tile::new(zoom, x, flip_y(zoom, y)?)
+        })
+    }
+
+    /// Returns the Bing Maps quadkey for this tile. `0/0/0` has an empty quadkey.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::new(3, 3, 5).unwrap().quadkey(), "213");
+    /// ```
+    pub fn quadkey(&self) -> String {
+        (1..=self.zoom).rev().map(|i| {
+            let mask = 1 << (i - 1);
+            let mut digit = 0;
+            if self.x & mask != 0 { digit += 1; }
+            if self.y & mask != 0 { digit += 2; }
+            char::from(b'0' + digit)
+        }).collect()
+    }
+
+    /// Constructs a Tile from a Bing Maps quadkey. Returns None if this isn't a valid quadkey.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Tile;
+    /// assert_eq!(Tile::from_quadkey("213"), Tile::new(3, 3, 5));
+    /// assert_eq!(Tile::from_quadkey("214"), None);
+    /// ```
+    pub fn from_quadkey(quadkey: &str) -> Option<Tile> {
+        if quadkey.len() > 31 {
+            return None;
+        }
+        let (mut x, mut y) = (0, 0);
+        for c in quadkey.chars() {
+            let digit = c.to_digit(4)?;
+            x = (x << 1) | (digit & 1);
+            y = (y << 1) | (digit >> 1);
+        }
+        Tile::new(quadkey.len() as u8, x, y)
     }
 
     /// Returns an iterator that yields all the tiles possible, starting from `0/0/0`. Tiles are
@@ -480,10 +742,11 @@ impl Iterator for AllSubTilesIterator {
     }
 }
 
-/// Metatiles are NxN tiles
+/// Metatiles are NxN tiles, or, more generally, WxH tiles. The width & height are powers of 2.
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub struct Metatile {
-    scale: u8,
+    width: u8,
+    height: u8,
     zoom: u8,
     x: u32,
     y: u32,
@@ -491,39 +754,80 @@ pub struct Metatile {
 
 impl Metatile {
     pub fn new(scale: u8, zoom: u8, x: u32, y: u32) -> Option<Self> {
-        if ! scale.is_power_of_two() {
+        Metatile::new_with_size(scale, scale, zoom, x, y)
+    }
+
+    /// A metatile which is `width` tiles wide and `height` tiles high. `None` if the width or
+    /// height isn't a power of 2, or the x/y are invalid for the zoom.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::Metatile;
+    /// let mt = Metatile::new_with_size(4, 2, 6, 35, 23).unwrap();
+    /// assert_eq!((mt.x(), mt.y()), (32, 22));
+    /// assert_eq!(mt.tiles().len(), 8);
+    /// assert!(Metatile::new_with_size(3, 2, 6, 35, 23).is_none());
+    /// ```
+    pub fn new_with_size(width: u8, height: u8, zoom: u8, x: u32, y: u32) -> Option<Self> {
+        if ! width.is_power_of_two() || ! height.is_power_of_two() {
             return None;
         }
         if zoom >= 100 {
             None
         } else if x < 2u32.pow(zoom as u32) && y < 2u32.pow(zoom as u32) {
-            let s = scale as u32;
-            let x = (x / s) * s;
-            let y = (y / s) * s;
-            Some(Metatile { scale: scale, zoom: zoom, x: x, y: y })
+            let x = (x / width as u32) * width as u32;
+            let y = (y / height as u32) * height as u32;
+            Some(Metatile { width, height, zoom, x, y })
         } else {
             None
         }
     }
 
-    pub fn scale(&self) -> u8 { self.scale }
+    /// The width of this metatile, which for square metatiles is also the height. For
+    /// non-square metatiles, use `width()` & `height()`.
+    pub fn scale(&self) -> u8 { self.width }
+
+    /// How many tiles wide this metatile is (before clipping to the world)
+    pub fn width(&self) -> u8 { self.width }
+
+    /// How many tiles high this metatile is (before clipping to the world)
+    pub fn height(&self) -> u8 { self.height }
+
+    /// True iff the width & height are the same
+    pub fn is_square(&self) -> bool { self.width == self.height }
 
     pub fn zoom(&self) -> u8 { self.zoom }
 
     /// What is the width or height of this metatile. For small zoom numbers (e.g. z1), there will
-    /// not be the full `scale` tiles across.
+    /// not be the full `scale` tiles across. For non-square metatiles this is the width.
     pub fn size(&self) -> u8 {
+        self.clipped_width()
+    }
+
+    /// How many tiles wide this metatile is. At low zooms, where the world is smaller than the
+    /// metatile, this is less than `width()`.
+    pub fn clipped_width(&self) -> u8 {
         let num_tiles_in_zoom = 2u32.pow(self.zoom as u32);
-        if num_tiles_in_zoom < (self.scale as u32) {
+        if num_tiles_in_zoom < (self.width as u32) {
             num_tiles_in_zoom as u8
         } else {
-            self.scale
+            self.width
+        }
+    }
+
+    /// How many tiles high this metatile is. At low zooms, where the world is smaller than the
+    /// metatile, this is less than `height()`.
+    pub fn clipped_height(&self) -> u8 {
+        let num_tiles_in_zoom = 2u32.pow(self.zoom as u32);
+        if num_tiles_in_zoom < (self.height as u32) {
+            num_tiles_in_zoom as u8
+        } else {
+            self.height
         }
     }
 
     /// Returns the LatLon for the centre of this metatile.
     pub fn centre_point(&self) -> LatLon {
-        tile_nw_lat_lon(self.zoom, (self.x as f32)+(self.size() as f32)/2., (self.y as f32)+(self.size() as f32)/2.)
+        tile_nw_lat_lon(self.zoom, (self.x as f32)+(self.clipped_width() as f32)/2., (self.y as f32)+(self.clipped_height() as f32)/2.)
     }
 
     /// Returns the LatLon for the centre of this metatile.
@@ -538,17 +842,17 @@ impl Metatile {
 
     /// Returns the LatLon of the top right, i.e. north east corner, of this metatile.
     pub fn ne_corner(&self) -> LatLon {
-        tile_nw_lat_lon(self.zoom, (self.x+self.size() as u32) as f32, self.y as f32)
+        tile_nw_lat_lon(self.zoom, (self.x+self.clipped_width() as u32) as f32, self.y as f32)
     }
 
     /// Returns the LatLon of the bottom left, i.e. south west corner, of this metatile.
     pub fn sw_corner(&self) -> LatLon {
-        tile_nw_lat_lon(self.zoom, self.x as f32, (self.y+self.size() as u32) as f32)
+        tile_nw_lat_lon(self.zoom, self.x as f32, (self.y+self.clipped_height() as u32) as f32)
     }
 
     /// Returns the LatLon of the bottom right, i.e. south east corner, of this metatile.
     pub fn se_corner(&self) -> LatLon {
-        tile_nw_lat_lon(self.zoom, (self.x+self.size() as u32) as f32, (self.y+self.size() as u32) as f32)
+        tile_nw_lat_lon(self.zoom, (self.x+self.clipped_width() as u32) as f32, (self.y+self.clipped_height() as u32) as f32)
     }
 
     /// X value of this metatile
@@ -557,11 +861,13 @@ impl Metatile {
     /// Y value of metatile
     pub fn y(&self) -> u32 { self.y }
 
+    /// The tiles in this metatile, by x then y. At low zooms, only the tiles which are in the
+    /// world are included.
     pub fn tiles(&self) -> Vec<Tile> {
-        let size = self.size() as u32;
-        (0..(size*size)).map(|n| {
+        let (width, height) = (self.clipped_width() as u32, self.clipped_height() as u32);
+        (0..(width*height)).map(|n| {
             // oh for a divmod
-            let (i, j) = (n / size, n % size);
+            let (i, j) = (n / height, n % height);
             // being cheeky and skipping the usuall Tile::new checks here, since we know it's valid
             Tile{ zoom: self.zoom, x: self.x+i, y: self.y+j }
         }).collect()
@@ -575,11 +881,12 @@ impl Metatile {
 
 impl FromStr for Metatile {
     type Err = ();
-    
+
+    /// `"scale z/x/y"` for square metatiles, or `"WxH z/x/y"`
     fn from_str(s: &str) -> Result<Self, Self::Err> {
 
         lazy_static! {
-            static ref METATILE_RE: Regex = Regex::new("^(?P<scale>[0-9]+) (?P<zoom>[0-9]?[0-9])/(?P<x>[0-9]{1,10})/(?P<y>[0-9]{1,10})$").unwrap();
+            static ref METATILE_RE: Regex = Regex::new("^(?P<width>[0-9]+)(x(?P<height>[0-9]+))? (?P<zoom>[0-9]?[0-9])/(?P<x>[0-9]{1,10})/(?P<y>[0-9]{1,10})$").unwrap();
         }
 
         let caps = METATILE_RE.captures(s);
@@ -589,13 +896,17 @@ impl FromStr for Metatile {
         }
         let caps = caps.unwrap();
 
-        // If the regex matches, then none of these should fail, right?
-        let scale = caps.name("scale").unwrap().parse().unwrap();
+        // The numbers can still be too big for a u8
+        let width = caps.name("width").unwrap().parse().map_err(|_| ())?;
+        let height = match caps.name("height") {
+            None => width,
+            Some(h) => h.parse().map_err(|_| ())?,
+        };
         let zoom = caps.name("zoom").unwrap().parse().unwrap();
-        let x = caps.name("x").unwrap().parse().unwrap();
-        let y = caps.name("y").unwrap().parse().unwrap();
+        let x = caps.name("x").unwrap().parse().map_err(|_| ())?;
+        let y = caps.name("y").unwrap().parse().map_err(|_| ())?;
         
-        match Metatile::new(scale, zoom, x, y) {
+        match Metatile::new_with_size(width, height, zoom, x, y) {
             None => {
                 // Invalid x or y for the zoom
                 Err(())
@@ -607,28 +918,97 @@ impl FromStr for Metatile {
     }
 }
 
+impl std::fmt::Display for Metatile {
+    /// `"scale z/x/y"` for square metatiles, otherwise `"WxH z/x/y"`, which `FromStr` reads.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_square() {
+            write!(f, "{} {}/{}/{}", self.width, self.zoom, self.x, self.y)
+        } else {
+            write!(f, "{}x{} {}/{}/{}", self.width, self.height, self.zoom, self.x, self.y)
+        }
+    }
+}
+
+/// Which size of metatile to use at each zoom.
+///
+/// ```
+/// # use slippy_map_tiles::{MetatilePolicy, Tile};
+/// let policy = MetatilePolicy::per_zoom(&[(0, 2, 2), (10, 8, 4)]).unwrap();
+/// assert_eq!(policy.size_at(3), (2, 2));
+/// assert_eq!(policy.size_at(12), (8, 4));
+/// assert_eq!(policy.metatile(&Tile::new(12, 100, 100).unwrap()).to_string(), "8x4 12/96/100");
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct MetatilePolicy {
+    // (from this zoom, width, height), sorted by zoom, starting at 0
+    sizes: Vec<(u8, u8, u8)>,
+}
+
+impl MetatilePolicy {
+    /// The same size at every zoom. `None` if the width or height isn't a power of 2.
+    pub fn fixed(width: u8, height: u8) -> Option<MetatilePolicy> {
+        MetatilePolicy::per_zoom(&[(0, width, height)])
+    }
+
+    /// Each `(zoom, width, height)` is the size from that zoom until the next one. The first
+    /// must be for zoom 0, and the zooms must go up. `None` if not, or if a width or height
+    /// isn't a power of 2.
+    pub fn per_zoom(sizes: &[(u8, u8, u8)]) -> Option<MetatilePolicy> {
+        if sizes.first().map(|s| s.0) != Some(0) {
+            return None;
+        }
+        if sizes.windows(2).any(|w| w[0].0 >= w[1].0) || sizes.iter().any(|&(_, w, h)| !w.is_power_of_two() || !h.is_power_of_two()) {
+            return None;
+        }
+        Some(MetatilePolicy{ sizes: sizes.to_vec() })
+    }
+
+    /// Square metatiles of this scale, not checked to be a power of 2
+    fn square(scale: u8) -> MetatilePolicy {
+        MetatilePolicy{ sizes: vec![(0, scale, scale)] }
+    }
+
+    /// The width & height of metatiles at this zoom
+    pub fn size_at(&self, zoom: u8) -> (u8, u8) {
+        let &(_, width, height) = self.sizes.iter().rev().find(|s| s.0 <= zoom).unwrap();
+        (width, height)
+    }
+
+    /// The metatile which this tile is in
+    pub fn metatile(&self, tile: &Tile) -> Metatile {
+        let (width, height) = self.size_at(tile.zoom);
+        Metatile::new_with_size(width, height, tile.zoom, tile.x, tile.y).unwrap()
+    }
+}
+
 
 /// Iterates over all the metatiles in the world.
 #[derive(Debug)]
 pub struct MetatilesIterator {
-    scale: u8,
+    policy: MetatilePolicy,
     curr_zoom: u8,
     maxzoom: u8,
     curr_zorder: u64,
     bbox: Option<BBox>,
 
-    // In metatile coords, i.e. x/scale
+    // In metatile coords, i.e. x/width & y/height
     curr_zoom_width_height: Option<(u32, u32)>,
     curr_zoom_start_xy: Option<(u32, u32)>,
 
-    // If we're reading from a file
     total: Option<usize>,
-    tile_list_file: Option<BufReader<File>>,
+    // How many metatiles have been returned so far
+    position: usize,
+
+    // If we're reading from a file
+    tile_list_file: Option<MetatileListReader<Box<dyn BufRead + Send>>>,
+    error: Option<TileListError>,
 }
 
 impl MetatilesIterator {
     pub fn all(scale: u8) -> Self {
-        MetatilesIterator{ scale: scale, curr_zoom: 0, curr_zorder: 0, bbox: None, maxzoom: 32, curr_zoom_width_height: None, curr_zoom_start_xy: None, total: None, tile_list_file: None }
+        let mut it = MetatilesIterator{ policy: MetatilePolicy::square(scale), curr_zoom: 0, curr_zorder: 0, bbox: None, maxzoom: 32, curr_zoom_width_height: None, curr_zoom_start_xy: None, total: None, position: 0, tile_list_file: None, error: None };
+        it.total = it.zorder_total();
+        it
     }
     
     pub fn new_for_bbox(scale: u8, bbox: &BBox) -> Self {
@@ -637,43 +1017,73 @@ impl MetatilesIterator {
 
     /// `None` for bbox means 'whole world'
     pub fn new_for_bbox_zoom(scale: u8, bbox: &Option<BBox>, minzoom: u8, maxzoom: u8) -> Self {
-        let mut it = MetatilesIterator{ scale: scale, curr_zoom: minzoom, curr_zorder: 0, bbox: bbox.clone(), maxzoom: maxzoom, curr_zoom_width_height: None, curr_zoom_start_xy: None, total: None, tile_list_file: None };
+        MetatilesIterator::new_for_policy(MetatilePolicy::square(scale), bbox, minzoom, maxzoom)
+    }
+
+    /// Metatiles with the size from this policy at each zoom. `None` for bbox means 'whole
+    /// world'.
+    pub fn new_for_policy(policy: MetatilePolicy, bbox: &Option<BBox>, minzoom: u8, maxzoom: u8) -> Self {
+        let mut it = MetatilesIterator{ policy, curr_zoom: minzoom, curr_zorder: 0, bbox: bbox.clone(), maxzoom, curr_zoom_width_height: None, curr_zoom_start_xy: None, total: None, position: 0, tile_list_file: None, error: None };
         it.set_zoom_width_height();
         it.set_zoom_start_xy();
+        it.total = it.zorder_total();
 
         it
     }
 
-    pub fn new_from_filelist(filename: String) -> Self {
-        let mut file = BufReader::new(File::open(&filename).unwrap());
-        file.seek(SeekFrom::Start(0)).unwrap();
-
-        // we're intentionally ignore usize overflow. If you have that many lines in a file,
-        // you're probably doing something wrong.
-        let total = file.lines().count();
+    /// Read the metatiles from this file (see `MetatileListReader` for the formats), which may
+    /// be gzipped. `-` is stdin. Tiles in the file are returned as metatiles of scale 1.
+    pub fn new_from_filelist<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut it = MetatilesIterator::new_from_list(MetatileListReader::open(path, TileListFormat::Auto, 1)?);
+        // Count the lines first, so `total()` is known. Only for regular files, stdin or a pipe
+        // can only be read once.
+        if path != Path::new("-") && path.metadata()?.is_file() {
+            it.total = Some(MetatileListReader::open(path, TileListFormat::Auto, 1)?.count());
+        }
+        Ok(it)
+    }
 
-        let file = BufReader::new(File::open(filename).unwrap());
+    /// Read the metatiles from this list. Iteration stops at the first error, which is then
+    /// available from `error()`.
+    pub fn new_from_list(list: MetatileListReader<Box<dyn BufRead + Send>>) -> Self {
+        MetatilesIterator{ policy: MetatilePolicy::square(1), curr_zoom: 0, curr_zorder: 0, bbox: None, maxzoom: 0, curr_zoom_width_height: None, curr_zoom_start_xy: None, total: None, position: 0, tile_list_file: Some(list), error: None }
+    }
 
-        MetatilesIterator{ scale: 0, curr_zoom: 0, curr_zorder: 0, bbox: None, maxzoom: 0, curr_zoom_width_height: None, curr_zoom_start_xy: None, total: Some(total), tile_list_file: Some(file) }
+    /// The width & height, in metatiles, of the bbox at this zoom
+    fn bbox_width_height(bbox: &BBox, size: (u8, u8), zoom: u8) -> (u32, u32) {
+        match TileRange::new_for_bbox_metatiles(bbox, zoom, size) {
+            Some(r) => (r.max_x() - r.min_x() + 1, r.max_y() - r.min_y() + 1),
+            None => (0, 0),
+        }
     }
 
     /// Update the `self.curr_zoom_width_height` variable with the correct value for this zoom
     /// (`self.curr_zoom`)
     fn set_zoom_width_height(&mut self) {
         if let Some(ref bbox) = self.bbox {
-            let scale = self.scale as u32;
-            let zoom = self.curr_zoom;
-            // TODO is this x/y lat/lon the right way around?
-            let (x1, y1) = lat_lon_to_tile(bbox.top, bbox.left, zoom);
-            let (x1, y1) = (x1/scale, y1/scale);
-            let (x2, y2) = lat_lon_to_tile(bbox.bottom, bbox.right, zoom);
-            let (x2, y2) = (x2/scale, y2/scale);
-
-            let width = x2 - x1 + 1;
-            let height = y2 - y1 + 1;
+            self.curr_zoom_width_height = Some(MetatilesIterator::bbox_width_height(bbox, self.policy.size_at(self.curr_zoom), self.curr_zoom));
+        }
+    }
 
-            self.curr_zoom_width_height = Some((width, height));
+    /// How many metatiles this will return from `curr_zoom` to `maxzoom`, or `None` on overflow
+    fn zorder_total(&self) -> Option<usize> {
+        let mut total: usize = 0;
+        for zoom in self.curr_zoom..=self.maxzoom {
+            let size = self.policy.size_at(zoom);
+            let (width, height) = match self.bbox {
+                Some(ref bbox) => {
+                    let (w, h) = MetatilesIterator::bbox_width_height(bbox, size, zoom);
+                    (w as u64, h as u64)
+                },
+                None => {
+                    let max = 2u64.pow(zoom as u32);
+                    (max.div_ceil(size.0 as u64), max.div_ceil(size.1 as u64))
+                },
+            };
+            total = total.checked_add(width.checked_mul(height)? as usize)?;
         }
+        Some(total)
     }
 
     fn set_zoom_start_xy(&mut self) {
@@ -691,7 +1101,8 @@ impl MetatilesIterator {
         };
         // TODO is this x/y lat/lon the right way around?
         let (x1, y1) = lat_lon_to_tile(top, left, self.curr_zoom);
-        self.curr_zoom_start_xy = Some((x1/self.scale as u32, y1/self.scale as u32));
+        let (width, height) = self.policy.size_at(self.curr_zoom);
+        self.curr_zoom_start_xy = Some((x1/width as u32, y1/height as u32));
     }
 
     fn next_from_zorder(&mut self) -> Option<Metatile> {
@@ -704,8 +1115,6 @@ impl MetatilesIterator {
         #[allow(unused_assignments)]
         let mut y = 0;
 
-        let scale = self.scale as u32;
-
         loop {
 
             if self.curr_zoom > self.maxzoom {
@@ -717,16 +1126,14 @@ impl MetatilesIterator {
             let (width, height) = match self.curr_zoom_width_height {
                 None => {
                     let max_num = 2u32.pow(zoom as u32);
-                    let mut max = max_num/scale;
-                    if max_num % scale > 0 {
-                        max += 1
-                    }
-                    (max, max)
+                    let (mt_width, mt_height) = self.policy.size_at(zoom);
+                    (max_num.div_ceil(mt_width as u32), max_num.div_ceil(mt_height as u32))
                 },
                 Some((width, height)) => (width, height),
             };
 
-            let max_zorder_for_zoom = xy_to_zorder(width-1, height-1);
+            // A bbox with no metatiles at this zoom is 0 wide, so every z order is past its edge
+            let max_zorder_for_zoom = xy_to_zorder(width.saturating_sub(1), height.saturating_sub(1));
 
             let (i, j) = zorder_to_xy(self.curr_zorder);
             let bits = match self.curr_zoom_start_xy {
@@ -746,7 +1153,7 @@ impl MetatilesIterator {
                 self.set_zoom_start_xy();
                 self.set_zoom_width_height();
 
-            } else if i > width || j > height {
+            } else if i >= width || j >= height {
                 // If the bbox is non-square, there will be X (or Y) tiles which are outside
                 // the bbox. Rather than go to the next zoom level, we want to contine to look at
                 // the next tile in order, and keep going until we get a tile that's inside the
@@ -760,34 +1167,158 @@ impl MetatilesIterator {
             }
         }
 
-        let (x, y) = (x*scale, y*scale);
-        Metatile::new(self.scale, zoom, x, y)
+        let (width, height) = self.policy.size_at(zoom);
+        Metatile::new_with_size(width, height, zoom, x*width as u32, y*height as u32)
     }
 
     fn next_from_file(&mut self) -> Option<Metatile> {
-        let mut s = String::new();
-        if let Some(ref mut file) = self.tile_list_file {
-            file.read_line(&mut s).unwrap();
+        if self.error.is_some() {
+            return None;
         }
-        // remove trailing newline
-        let s = s.trim_end();
+        match self.tile_list_file.as_mut()?.next()? {
+            Ok(metatile) => Some(metatile),
+            Err(e) => { self.error = Some(e); None },
+        }
+    }
 
-        s.parse().ok()
+    /// The error which stopped reading from a file list, if any.
+    pub fn error(&self) -> Option<&TileListError> {
+        self.error.as_ref()
     }
 
+    /// How many metatiles this will return in total (including any already returned), if known.
+    /// For a file list, this is how many entries are in the file, and `None` for stdin or a pipe.
     pub fn total(&self) -> Option<usize> {
         self.total
     }
+
+    /// How many metatiles have been returned so far. This includes those before the cursor this
+    /// was resumed from.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Where this iterator is now. Save this, and `resume` a new iterator (made the same way)
+    /// from it to carry on from exactly the same metatile.
+    pub fn cursor(&self) -> MetatilesCursor {
+        if self.tile_list_file.is_some() {
+            let offset = self.tile_list_file.as_ref().map(|f| f.offset());
+            MetatilesCursor{ zoom: 0, zorder: 0, file_offset: offset, position: self.position }
+        } else {
+            MetatilesCursor{ zoom: self.curr_zoom, zorder: self.curr_zorder, file_offset: None, position: self.position }
+        }
+    }
+
+    /// Carry on from this cursor. This iterator must be made the same way as the one the cursor
+    /// came from (same scale, bbox, zooms, or file), which isn't checked. Returns `Err` if the
+    /// cursor is from a different kind of iterator (file list vs. z-order), is past the end, or
+    /// is before where a file list has read up to.
+    pub fn resume(&mut self, cursor: &MetatilesCursor) -> Result<(), &'static str> {
+        match (self.tile_list_file.as_mut(), cursor.file_offset) {
+            (Some(file), Some(offset)) => {
+                if offset < file.offset() {
+                    return Err("Cursor is before where this file list has read up to");
+                }
+                file.skip_to_offset(offset).map_err(|_| "Unable to skip to the cursor in the file list")?;
+            },
+            (None, None) => {
+                if cursor.zoom > self.maxzoom.saturating_add(1) {
+                    return Err("Cursor zoom is after the end of this iterator");
+                }
+                self.curr_zoom = cursor.zoom;
+                self.curr_zorder = cursor.zorder;
+                self.set_zoom_start_xy();
+                self.set_zoom_width_height();
+            },
+            (Some(_), None) => { return Err("Cursor is for a z-order iterator, not a file list"); },
+            (None, Some(_)) => { return Err("Cursor is for a file list, not a z-order iterator"); },
+        }
+        self.position = cursor.position;
+        Ok(())
+    }
 }
 
 impl Iterator for MetatilesIterator {
     type Item = Metatile;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.tile_list_file.is_some() {
+        let next = if self.tile_list_file.is_some() {
             self.next_from_file()
         } else {
             self.next_from_zorder()
+        };
+        if next.is_some() {
+            self.position += 1;
+        }
+        next
+    }
+}
+
+/// Where a `MetatilesIterator` is up to, so it can be resumed later.
+///
+/// As text, this is `zorder ZOOM ZORDER POSITION` or `file OFFSET POSITION`.
+///
+/// ```
+/// # use slippy_map_tiles::{MetatilesIterator, MetatilesCursor};
+/// let mut metatiles = MetatilesIterator::all(8);
+/// metatiles.nth(100);
+/// let saved = metatiles.cursor().to_string();
+///
+/// let mut resumed = MetatilesIterator::all(8);
+/// resumed.resume(&saved.parse().unwrap()).unwrap();
+/// assert_eq!(resumed.position(), 101);
+/// assert_eq!(resumed.next(), metatiles.next());
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct MetatilesCursor {
+    zoom: u8,
+    zorder: u64,
+    file_offset: Option<u64>,
+    position: usize,
+}
+
+impl MetatilesCursor {
+    /// The zoom a z-order iterator is on (0 for file lists)
+    pub fn zoom(&self) -> u8 { self.zoom }
+
+    /// The z-order, within the zoom, of the next metatile (0 for file lists)
+    pub fn zorder(&self) -> u64 { self.zorder }
+
+    /// For file lists, the byte offset in the file of the next line
+    pub fn file_offset(&self) -> Option<u64> { self.file_offset }
+
+    /// How many metatiles had been returned
+    pub fn position(&self) -> usize { self.position }
+}
+
+impl std::fmt::Display for MetatilesCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.file_offset {
+            Some(offset) => write!(f, "file {} {}", offset, self.position),
+            None => write!(f, "zorder {} {} {}", self.zoom, self.zorder, self.position),
+        }
+    }
+}
+
+impl FromStr for MetatilesCursor {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts.as_slice() {
+            ["zorder", zoom, zorder, position] => Ok(MetatilesCursor{
+                zoom: zoom.parse().map_err(|_| "Invalid zoom")?,
+                zorder: zorder.parse().map_err(|_| "Invalid zorder")?,
+                file_offset: None,
+                position: position.parse().map_err(|_| "Invalid position")?,
+            }),
+            ["file", offset, position] => Ok(MetatilesCursor{
+                zoom: 0,
+                zorder: 0,
+                file_offset: Some(offset.parse().map_err(|_| "Invalid file offset")?),
+                position: position.parse().map_err(|_| "Invalid position")?,
+            }),
+            _ => Err("Invalid metatiles cursor"),
         }
     }
 }
@@ -880,33 +1411,38 @@ pub fn merc_location_to_tile_coords(x: f64, y: f64, zoom: u8) -> ((u32, u32), (u
     )
 }
 
+/// `lat_lon_to_tile` returns `2^zoom` for lon 180 & the southern edge, which is 1 past the last
+/// tile.
+fn clamp_to_world((x, y): (u32, u32), zoom: u8) -> (u32, u32) {
+    let max = (2u64.pow(zoom as u32) - 1) as u32;
+    (x.min(max), y.min(max))
+}
+
+/// The top left & bottom right tile x/y of this bbox at this zoom
+pub(crate) fn bbox_tile_corners(bbox: &BBox, zoom: u8) -> ((u32, u32), (u32, u32)) {
+    (
+        clamp_to_world(lat_lon_to_tile(bbox.top(), bbox.left(), zoom), zoom),
+        clamp_to_world(lat_lon_to_tile(bbox.bottom(), bbox.right(), zoom), zoom),
+    )
+}
+
 /// How many tiles does this bbox cover at this zoom
 /// If there is an overflow for usize, `None` is returned, if not, a `Some(...)`
+/// A bbox which crosses the antimeridian, or is upside down, has no tiles (see `TileRange::new_for_bbox`)
 pub fn size_bbox_zoom(bbox: &BBox, zoom: u8) -> Option<usize> {
-    let top_left_tile = lat_lon_to_tile(bbox.top(), bbox.left(), zoom);
-    let bottom_right_tile = lat_lon_to_tile(bbox.bottom(), bbox.right(), zoom);
-    let height = (bottom_right_tile.0 - top_left_tile.0) as usize + 1;
-    let width = (bottom_right_tile.1 - top_left_tile.1) as usize + 1;
-    
-    height.checked_mul(width)
+    size_bbox_zoom_metatiles(bbox, zoom, 1)
 }
 
 /// How many metatiles, of this scale, does this bbox cover at this zoom
 /// If there is an overflow for usize, `None` is returned, if not, a `Some(...)`
 /// This is less likely to overflow than `size_bbox_zoom` because metatiles are larger
 pub fn size_bbox_zoom_metatiles(bbox: &BBox, zoom: u8, metatile_scale: u8) -> Option<usize> {
-    let metatile_scale = metatile_scale as u32;
-    let top_left_tile = lat_lon_to_tile(bbox.top(), bbox.left(), zoom);
-    let bottom_right_tile = lat_lon_to_tile(bbox.bottom(), bbox.right(), zoom);
-    let bottom = (bottom_right_tile.0 / metatile_scale)*metatile_scale;
-    let top = (top_left_tile.0 / metatile_scale)*metatile_scale;
-    let left = (top_left_tile.1 / metatile_scale)*metatile_scale;
-    let right = (bottom_right_tile.1 / metatile_scale)*metatile_scale;
-
-    let height = ((bottom - top)/metatile_scale as u32) as usize + 1;
-    let width = ((right - left)/metatile_scale as u32) as usize + 1;
-    
-    height.checked_mul(width)
+    let tiles = TileRange::new_for_bbox_metatiles(bbox, zoom, (metatile_scale, metatile_scale)).map_or(0, |r| r.num_tiles());
+    if tiles > usize::MAX as u64 {
+        None
+    } else {
+        Some(tiles as usize)
+    }
 }
 
 /// A single point in the world.
@@ -1000,8 +1536,7 @@ impl BBox {
 
     /// Iterate over all the metatiles from z0 onwards that this bbox is in
     pub fn metatiles(&self, scale: u8) -> MetatilesIterator {
-        let bbox: BBox = (*self).clone();
-        MetatilesIterator{ curr_zoom: 0, maxzoom: 32, bbox: Some(bbox), curr_zorder: 0, scale: scale, curr_zoom_width_height: None, curr_zoom_start_xy: None, total: None, tile_list_file: None }
+        MetatilesIterator::new_for_bbox(scale, self)
     }
 
     /// Return the top value of this bbox
@@ -1120,6 +1655,27 @@ fn xy_to_tc(x: u32, y: u32) -> [String; 6] {
     ]
 }
 
+/// Convert x & y (counted from the bottom) to a GeoWebCache (gwc) directory & file name
+fn xy_to_gwc(zoom: u8, x: u32, y: u32) -> [String; 2] {
+    // The directory groups 2^(zoom/2+1) tiles each way, and everything is padded to the number
+    // of digits in that.
+    let half = 2u32 << (zoom / 2);
+    let digits = half.to_string().len();
+    [
+        format!("{:0w$}_{:0w$}", x / half, y / half, w=digits),
+        format!("{:0w$}_{:0w$}", x, y, w=2*digits),
+    ]
+}
+
+/// Convert y between counting from the top & from the bottom. None if y isn't at this zoom.
+fn flip_y(zoom: u8, y: u32) -> Option<u32> {
+    if zoom > 31 || y as u64 >= 1u64 << zoom {
+        None
+    } else {
+        Some(((1u64 << zoom) - 1 - y as u64) as u32)
+    }
+}
+
 /// Convert x & y to a MapProxy (mp) directory parts
 fn xy_to_mp(x: u32, y: u32) -> [String; 4] {
     [
@@ -1226,12 +1782,24 @@ pub fn zorder_to_xy(zorder: u64) -> (u32, u32) {
     (x, y)
 }
 
+/// A JSON number with the shortest representation of this `f32`, rather than the noise digits of
+/// widening it to `f64`, e.g. `-6.26` not `-6.260000228881836`. Only public for the `slippy`
+/// binary.
+#[cfg(feature="json")]
+#[doc(hidden)]
+pub fn f32_json(x: f32) -> serde_json::Value {
+    x.to_string().parse::<f64>().map(serde_json::Value::from).unwrap_or(serde_json::Value::Null)
+}
+
 
 // TODO do mod_tile tile format
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::fs::File;
+    use std::collections::HashSet;
+    use tempfile::tempdir;
 
     #[test]
     fn tc() {
@@ -1262,6 +1830,47 @@ mod test {
         assert_eq!(res[3], "582");
     }
 
+    #[test]
+    fn arcgis() {
+        let tile = Tile::new(20, 1_000_000, 300_000).unwrap();
+        assert_eq!(tile.arcgis_path("jpg"), "L20/R000493e0/C000f4240.jpg");
+        assert_eq!(Tile::from_arcgis_path(&tile.arcgis_path("jpg")), Some(tile));
+        assert_eq!(Tile::from_arcgis_path("L20/R000493E0/C000F4240"), Some(tile));
+        assert_eq!(Tile::from_arcgis_path("L02/R00000004/C00000000.png"), None);
+        assert_eq!(Tile::from_arcgis_path("L2/R00000000/C00000000.png"), None);
+    }
+
+    #[test]
+    fn gwc() {
+        assert_eq!(Tile::new(3, 4, 0).unwrap().gwc_path("png"), "EPSG_900913_03/1_1/04_07.png");
+        assert_eq!(Tile::new(0, 0, 0).unwrap().gwc_path("png"), "EPSG_900913_00/0_0/00_00.png");
+        assert_eq!(Tile::new(18, 1000, 0).unwrap().gwc_path("png"), "EPSG_900913_18/0000_0255/00001000_00262143.png");
+        for tile in &[Tile::new(3, 4, 0).unwrap(), Tile::new(18, 1000, 0).unwrap(), Tile::new(31, 5, 1 << 30).unwrap()] {
+            assert_eq!(Tile::from_gwc_path(&tile.gwc_path("jpeg")), Some(*tile));
+        }
+        assert_eq!(Tile::from_gwc_path("EPSG_3857_03/1_1/04_07"), Tile::new(3, 4, 0));
+        assert_eq!(Tile::from_gwc_path("EPSG_900913_03/0_1/04_07.png"), None);
+        assert_eq!(Tile::from_gwc_path("EPSG_900913_03/2_1/08_07.png"), None);
+        assert_eq!(Tile::from_gwc_path("03/1_1/04_07.png"), None);
+    }
+
+    #[test]
+    fn mapcache() {
+        let tile = Tile::new(20, 1_000_000, 300_000).unwrap();
+        assert_eq!(tile.mapcache_path("png"), "20/001/000/000/000/748/575.png");
+        assert_eq!(Tile::from_mapcache_path(&tile.mapcache_path("png")), Some(tile));
+        assert_eq!(Tile::from_mapcache_path("/var/cache/osm/GoogleMapsCompatible/20/001/000/000/000/748/575.png"), Some(tile));
+        assert_eq!(Tile::from_mapcache_path("02/000/000/000/000/000/004.png"), None);
+        assert_eq!(Tile::from_mapcache_path("2/000/000/000/000/000/000.png"), None);
+
+        assert_eq!(tile.worldwind_path("png"), "20/748575/748575_1000000.png");
+        assert_eq!(Tile::from_worldwind_path(&tile.worldwind_path("png")), Some(tile));
+        assert_eq!(Tile::from_worldwind_path("0/0000/0000_0000.png"), Tile::new(0, 0, 0));
+        assert_eq!(Tile::from_worldwind_path("5/0011/0012_0003.jpg"), None);
+        assert_eq!(Tile::from_worldwind_path("5/0032/0032_0003.jpg"), None);
+        assert_eq!(Tile::from_worldwind_path("40/0000/0000_0000.jpg"), None);
+    }
+
     #[test]
     fn tiles_parsing() {
         let tile = Tile::new(1, 5, 5);
@@ -1328,6 +1937,21 @@ mod test {
         
     }
 
+    #[test]
+    fn quadkey() {
+        assert_eq!(Tile::new(0, 0, 0).unwrap().quadkey(), "");
+        assert_eq!(Tile::new(1, 1, 0).unwrap().quadkey(), "1");
+        assert_eq!(Tile::new(1, 0, 1).unwrap().quadkey(), "2");
+        assert_eq!(Tile::new(3, 3, 5).unwrap().quadkey(), "213");
+
+        assert_eq!(Tile::from_quadkey(""), Tile::new(0, 0, 0));
+        assert_eq!(Tile::from_quadkey("3"), Tile::new(1, 1, 1));
+        assert_eq!(Tile::from_quadkey("12x"), None);
+
+        let t = Tile::new(18, 130_000, 90_000).unwrap();
+        assert_eq!(Tile::from_quadkey(&t.quadkey()), Some(t));
+    }
+
     #[test]
     fn tile_from_tms() {
         fn known_good(tms: &str, zoom: u8, x: u32, y: u32) {
@@ -1688,6 +2312,61 @@ mod test {
 
     }
 
+    #[test]
+    fn metatile_non_square() {
+        let mt = Metatile::new_with_size(4, 2, 3, 5, 3).unwrap();
+        assert_eq!((mt.width(), mt.height(), mt.x(), mt.y()), (4, 2, 4, 2));
+        assert!(!mt.is_square());
+        assert_eq!(mt.tiles().iter().map(|t| t.zxy()).collect::<Vec<_>>(), vec!["3/4/2", "3/4/3", "3/5/2", "3/5/3", "3/6/2", "3/6/3", "3/7/2", "3/7/3"]);
+        assert_eq!(mt.to_string(), "4x2 3/4/2");
+        assert_eq!("4x2 3/5/3".parse(), Ok(mt));
+        assert_eq!("8 3/5/3".parse(), Metatile::new(8, 3, 0, 0).ok_or(()));
+        assert_eq!(Metatile::new(8, 3, 0, 0).unwrap().to_string(), "8 3/0/0");
+        assert!("3x2 3/5/3".parse::<Metatile>().is_err());
+        assert!("400 3/5/3".parse::<Metatile>().is_err());
+
+        // Clipped to the world at low zooms
+        let mt = Metatile::new_with_size(8, 2, 1, 1, 1).unwrap();
+        assert_eq!((mt.clipped_width(), mt.clipped_height()), (2, 2));
+        assert_eq!(mt.tiles().len(), 4);
+        assert_eq!(mt.se_corner(), LatLon::new(-85.05112, 180.0).unwrap());
+        let mt = Metatile::new_with_size(2, 8, 2, 3, 3).unwrap();
+        assert_eq!((mt.x(), mt.y(), mt.clipped_width(), mt.clipped_height()), (2, 0, 2, 4));
+        assert_eq!(mt.tiles().len(), 8);
+        assert_eq!(Metatile::new_with_size(4, 2, 0, 0, 0).unwrap().tiles(), vec![Tile::new(0, 0, 0).unwrap()]);
+    }
+
+    #[test]
+    fn metatile_policy() {
+        assert!(MetatilePolicy::fixed(4, 3).is_none());
+        assert!(MetatilePolicy::per_zoom(&[]).is_none());
+        assert!(MetatilePolicy::per_zoom(&[(1, 2, 2)]).is_none());
+        assert!(MetatilePolicy::per_zoom(&[(0, 2, 2), (5, 4, 4), (5, 8, 8)]).is_none());
+
+        let policy = MetatilePolicy::per_zoom(&[(0, 2, 2), (3, 4, 2)]).unwrap();
+        let metatiles: Vec<Metatile> = MetatilesIterator::new_for_policy(policy.clone(), &None, 0, 4).collect();
+        assert_eq!(metatiles.iter().map(|mt| mt.to_string()).take(7).collect::<Vec<_>>(),
+            vec!["2 0/0/0", "2 1/0/0", "2 2/0/0", "2 2/2/0", "2 2/0/2", "2 2/2/2", "4x2 3/0/0"]);
+        // Every tile is in exactly one metatile
+        for zoom in 0..5 {
+            let mut tiles: Vec<Tile> = metatiles.iter().filter(|mt| mt.zoom() == zoom).flat_map(|mt| mt.tiles()).collect();
+            assert_eq!(tiles.len(), 1 << (2 * zoom));
+            tiles.sort_by_key(|t| (t.x(), t.y()));
+            tiles.dedup();
+            assert_eq!(tiles.len(), 1 << (2 * zoom));
+        }
+        assert_eq!(MetatilesIterator::new_for_policy(policy.clone(), &None, 0, 4).total(), Some(metatiles.len()));
+        assert_eq!(metatiles.len(), 1 + 1 + 4 + 8 + 32);
+
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let metatiles: Vec<Metatile> = MetatilesIterator::new_for_policy(policy.clone(), &Some(bbox.clone()), 0, 12).collect();
+        for zoom in 0..13 {
+            let expected: HashSet<Metatile> = TileRange::new_for_bbox(&bbox, zoom).unwrap().iter().map(|t| policy.metatile(&t)).collect();
+            let got: HashSet<Metatile> = metatiles.iter().filter(|mt| mt.zoom() == zoom).cloned().collect();
+            assert_eq!(got, expected);
+        }
+    }
+
     #[test]
     fn test_metatile_subtiles() {
 
@@ -1776,6 +2455,77 @@ mod test {
         assert_eq!(metatiles.next(), None);
     }
     
+    #[test]
+    fn metatiles_bbox_edge() {
+        // 3 metatiles wide & 1 high, so some z orders are past the right or bottom of the bbox
+        let bbox = BBox::new(60., -170., 10., 10.).unwrap();
+        let metatiles = MetatilesIterator::new_for_bbox_zoom(1, &Some(bbox), 2, 2);
+        assert_eq!(metatiles.total(), Some(3));
+        assert_eq!(metatiles.collect::<Vec<_>>(), vec![Metatile::new(1, 2, 0, 1).unwrap(), Metatile::new(1, 2, 1, 1).unwrap(), Metatile::new(1, 2, 2, 1).unwrap()]);
+    }
+
+    #[test]
+    fn metatiles_total_resume() {
+        let ie_bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let metatiles = MetatilesIterator::new_for_bbox_zoom(8, &Some(ie_bbox.clone()), 0, 12);
+        assert_eq!(metatiles.total(), Some(metatiles.count()));
+        assert_eq!(MetatilesIterator::all(1).total(), None);
+        assert_eq!(MetatilesIterator::new_for_bbox_zoom(1, &None, 0, 3).total(), Some(85));
+
+        let all: Vec<Metatile> = MetatilesIterator::new_for_bbox_zoom(8, &Some(ie_bbox.clone()), 0, 12).collect();
+        let mut metatiles = MetatilesIterator::new_for_bbox_zoom(8, &Some(ie_bbox.clone()), 0, 12);
+        metatiles.nth(20);
+        let cursor = metatiles.cursor();
+        assert_eq!(cursor.position(), 21);
+        assert_eq!(cursor.to_string().parse(), Ok(cursor));
+
+        let mut resumed = MetatilesIterator::new_for_bbox_zoom(8, &Some(ie_bbox), 0, 12);
+        resumed.resume(&cursor).unwrap();
+        assert_eq!(resumed.position(), 21);
+        assert_eq!(resumed.collect::<Vec<_>>(), &all[21..]);
+
+        assert!("file 10".parse::<MetatilesCursor>().is_err());
+        assert!(MetatilesIterator::all(8).resume(&"file 10 2".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn metatiles_file_resume() {
+        use std::io::Write;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("metatiles.txt");
+        let filename = path.to_str().unwrap().to_string();
+        {
+            let mut f = File::create(&path).unwrap();
+            writeln!(f, "8 0/0/0\n8 3/0/0\n8 4/8/8\n8 5/16/8").unwrap();
+        }
+
+        let mut metatiles = MetatilesIterator::new_from_filelist(&filename).unwrap();
+        assert_eq!(metatiles.total(), Some(4));
+        assert_eq!(metatiles.next(), Metatile::new(8, 0, 0, 0));
+        assert_eq!(metatiles.next(), Metatile::new(8, 3, 0, 0));
+        let cursor = metatiles.cursor();
+        assert_eq!(cursor.to_string(), "file 16 2");
+
+        let mut resumed = MetatilesIterator::new_from_filelist(&filename).unwrap();
+        resumed.resume(&cursor).unwrap();
+        assert_eq!(resumed.next(), Metatile::new(8, 4, 8, 8));
+        assert_eq!(resumed.position(), 3);
+        assert_eq!(resumed.next(), Metatile::new(8, 5, 16, 8));
+        assert_eq!(resumed.next(), None);
+        assert!(resumed.resume(&"zorder 1 0 0".parse().unwrap()).is_err());
+        assert!(resumed.error().is_none());
+
+        {
+            let mut f = File::create(&path).unwrap();
+            writeln!(f, "# comment\n8 0/0/0\n8 3/0\n8 4/8/8").unwrap();
+        }
+        let mut metatiles = MetatilesIterator::new_from_filelist(&filename).unwrap();
+        assert_eq!(metatiles.next(), Metatile::new(8, 0, 0, 0));
+        assert_eq!(metatiles.next(), None);
+        assert_eq!(metatiles.error().unwrap().to_string(), r#"line 3: expected z/x/y ("8 3/0")"#);
+        assert!(MetatilesIterator::new_from_filelist(path.with_extension("missing")).is_err());
+    }
+
     #[test]
     fn test_lat_lon_to_tile1() {
 
@@ -1936,4 +2686,12 @@ mod test {
         assert_eq!(format!("{}", wf), "2445.98490512564\n0\n0\n-2445.98490512564\n626172.1357121654\n6887893.4928338025\n");
     }
 
+    #[test]
+    #[cfg(feature="json")]
+    fn f32_json_is_shortest() {
+        assert_eq!(f32_json(-6.26).to_string(), "-6.26");
+        assert_eq!(f32_json(53.0).to_string(), "53.0");
+        assert_eq!(f32_json(f32::NAN), serde_json::Value::Null);
+    }
+
 }
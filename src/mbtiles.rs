@@ -0,0 +1,337 @@
+//! [MBTiles](https://github.com/mapbox/mbtiles-spec) files, i.e. tiles in an SQLite database.
+//!
+//! Files can have the simple `tiles` table, or the `map` & `images` tables (with a `tiles` view),
+//! where tiles with the same data share it. New files are created with `map` & `images`.
+//!
+//! ```
+//! # use slippy_map_tiles::{Tile, TileStore};
+//! # use slippy_map_tiles::mbtiles::MbTilesStore;
+//! let store = MbTilesStore::open(":memory:").unwrap();
+//! let tile = Tile::new(3, 2, 5).unwrap();
+//! store.put(&tile, b"png data").unwrap();
+//! assert_eq!(store.get(&tile).unwrap(), Some(b"png data".to_vec()));
+//! ```
+use std::cell::Cell;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use rusqlite::{Connection, OptionalExtension};
+
+use {Tile, TileRange, flip_y};
+use store::{TileStore, TileMetadata, ScanEntry};
+
+/// How many changes to make in each transaction
+const BATCH_SIZE: usize = 1_000;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Schema {
+    /// One `tiles` table
+    Plain,
+    /// `map` & `images` tables
+    Dedup,
+}
+
+fn sql_err(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Tiles in an MBTiles file. Changes are made in transactions of many tiles, which are
+/// committed by `flush`. Call `flush` when finished writing: the last changes are also committed
+/// when this is dropped, but errors doing so are ignored.
+#[derive(Debug)]
+pub struct MbTilesStore {
+    conn: Connection,
+    schema: Schema,
+    // Changes made in the current transaction
+    pending: Cell<usize>,
+}
+
+impl MbTilesStore {
+    /// Open this MBTiles file, creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MbTilesStore> {
+        let conn = Connection::open(path).map_err(sql_err)?;
+        let tiles_type: Option<String> = conn.query_row("SELECT type FROM sqlite_master WHERE name = 'tiles'", (), |r| r.get(0))
+            .optional().map_err(sql_err)?;
+        let schema = match tiles_type.as_deref() {
+            Some("table") => Schema::Plain,
+            Some(_) => {
+                let tables: i64 = conn.query_row("SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name IN ('map', 'images')", (), |r| r.get(0))
+                    .map_err(sql_err)?;
+                if tables != 2 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "tiles view isn't over map & images tables"));
+                }
+                Schema::Dedup
+            },
+            None => {
+                conn.execute_batch("
+                    CREATE TABLE IF NOT EXISTS metadata (name TEXT, value TEXT);
+                    CREATE UNIQUE INDEX IF NOT EXISTS name ON metadata (name);
+                    CREATE TABLE map (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_id TEXT);
+                    CREATE UNIQUE INDEX map_index ON map (zoom_level, tile_column, tile_row);
+                    CREATE INDEX map_tile_id ON map (tile_id);
+                    CREATE TABLE images (tile_data BLOB, tile_id TEXT);
+                    CREATE UNIQUE INDEX images_id ON images (tile_id);
+                    CREATE VIEW tiles AS SELECT map.zoom_level AS zoom_level, map.tile_column AS tile_column,
+                        map.tile_row AS tile_row, images.tile_data AS tile_data
+                        FROM map JOIN images ON images.tile_id = map.tile_id;
+                ").map_err(sql_err)?;
+                Schema::Dedup
+            },
+        };
+        Ok(MbTilesStore{ conn, schema, pending: Cell::new(0) })
+    }
+
+    /// The value of this key in the `metadata` table
+    pub fn metadata_value(&self, name: &str) -> io::Result<Option<String>> {
+        self.conn.query_row("SELECT value FROM metadata WHERE name = ?1", (name,), |r| r.get(0)).optional().map_err(sql_err)
+    }
+
+    /// Set this key in the `metadata` table
+    pub fn set_metadata_value(&self, name: &str, value: &str) -> io::Result<()> {
+        self.write(|conn| {
+            conn.execute("DELETE FROM metadata WHERE name = ?1", (name,))?;
+            conn.execute("INSERT INTO metadata (name, value) VALUES (?1, ?2)", (name, value)).map(|_| ())
+        })
+    }
+
+    /// The table with the tile coordinates
+    fn coords_table(&self) -> &'static str {
+        if self.schema == Schema::Dedup { "map" } else { "tiles" }
+    }
+
+    /// Make a change, in the current transaction (starting one if needed). If it fails, it's
+    /// undone, and the rest of the transaction is kept.
+    fn write<T, F>(&self, f: F) -> io::Result<T> where F: FnOnce(&Connection) -> rusqlite::Result<T> {
+        let began = self.pending.get() == 0;
+        if began {
+            self.conn.execute_batch("BEGIN").map_err(sql_err)?;
+        }
+        self.conn.execute_batch("SAVEPOINT change").map_err(sql_err)?;
+        let result = match f(&self.conn) {
+            Ok(r) => r,
+            Err(e) => {
+                let undo = if began { "ROLLBACK" } else { "ROLLBACK TO change; RELEASE change" };
+                self.conn.execute_batch(undo).map_err(sql_err)?;
+                return Err(sql_err(e));
+            },
+        };
+        self.conn.execute_batch("RELEASE change").map_err(sql_err)?;
+        self.pending.set(self.pending.get() + 1);
+        if self.pending.get() >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(result)
+    }
+
+    /// The `images` id of this tile (with the `map` & `images` tables)
+    fn tile_id(conn: &Connection, tile: &Tile) -> rusqlite::Result<Option<String>> {
+        conn.query_row("SELECT tile_id FROM map WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            (tile.zoom, tile.x, flip_y(tile.zoom, tile.y).unwrap()), |r| r.get(0)).optional()
+    }
+
+    /// Point this tile at this image (or remove it, if `None`), removing its old image if
+    /// nothing else uses it. Returns true if the tile was there before.
+    fn set_tile_id(conn: &Connection, tile: &Tile, tile_id: Option<&str>) -> rusqlite::Result<bool> {
+        let old_id = MbTilesStore::tile_id(conn, tile)?;
+        let coords = (tile.zoom, tile.x, flip_y(tile.zoom, tile.y).unwrap());
+        match tile_id {
+            Some(id) => conn.execute("INSERT OR REPLACE INTO map (zoom_level, tile_column, tile_row, tile_id) VALUES (?1, ?2, ?3, ?4)",
+                (coords.0, coords.1, coords.2, id))?,
+            None => conn.execute("DELETE FROM map WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3", coords)?,
+        };
+        if let Some(ref old_id) = old_id {
+            if Some(old_id.as_str()) != tile_id {
+                conn.execute("DELETE FROM images WHERE tile_id = ?1 AND NOT EXISTS (SELECT 1 FROM map WHERE tile_id = ?1)", (old_id,))?;
+            }
+        }
+        Ok(old_id.is_some())
+    }
+}
+
+impl Drop for MbTilesStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl TileStore for MbTilesStore {
+    fn get(&self, tile: &Tile) -> io::Result<Option<Vec<u8>>> {
+        self.conn.query_row("SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            (tile.zoom, tile.x, flip_y(tile.zoom, tile.y).unwrap()), |r| r.get(0)).optional().map_err(sql_err)
+    }
+
+    fn put(&self, tile: &Tile, data: &[u8]) -> io::Result<()> {
+        let row = flip_y(tile.zoom, tile.y).unwrap();
+        if self.schema == Schema::Plain {
+            return self.write(|conn| {
+                conn.execute("INSERT OR REPLACE INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                    (tile.zoom, tile.x, row, data)).map(|_| ())
+            });
+        }
+        self.write(|conn| {
+            // The image's id is its rowid, which is unique
+            conn.execute("INSERT INTO images (tile_data) VALUES (?1)", (data,))?;
+            let id = conn.last_insert_rowid().to_string();
+            conn.execute("UPDATE images SET tile_id = ?1 WHERE rowid = ?2", (&id, conn.last_insert_rowid()))?;
+            MbTilesStore::set_tile_id(conn, tile, Some(&id)).map(|_| ())
+        })
+    }
+
+    /// With the `map` & `images` tables, the tile uses the same image as `original`.
+    fn put_copy(&self, tile: &Tile, original: &Tile, data: &[u8]) -> io::Result<()> {
+        if self.schema == Schema::Plain {
+            return self.put(tile, data);
+        }
+        match MbTilesStore::tile_id(&self.conn, original).map_err(sql_err)? {
+            None => self.put(tile, data),
+            Some(id) => self.write(|conn| MbTilesStore::set_tile_id(conn, tile, Some(&id)).map(|_| ())),
+        }
+    }
+
+    fn delete(&self, tile: &Tile) -> io::Result<bool> {
+        if self.schema == Schema::Plain {
+            return self.write(|conn| {
+                conn.execute("DELETE FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                    (tile.zoom, tile.x, flip_y(tile.zoom, tile.y).unwrap())).map(|n| n > 0)
+            });
+        }
+        self.write(|conn| MbTilesStore::set_tile_id(conn, tile, None))
+    }
+
+    fn metadata(&self, tile: &Tile) -> io::Result<Option<TileMetadata>> {
+        let size: Option<i64> = self.conn.query_row("SELECT length(tile_data) FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            (tile.zoom, tile.x, flip_y(tile.zoom, tile.y).unwrap()), |r| r.get(0)).optional().map_err(sql_err)?;
+        Ok(size.map(|s| TileMetadata::new(s as u64, None)))
+    }
+
+    fn list(&self, range: &TileRange) -> io::Result<Vec<Tile>> {
+        let zoom = range.zoom();
+        let sql = format!("SELECT tile_column, tile_row FROM {} WHERE zoom_level = ?1 AND tile_column BETWEEN ?2 AND ?3 AND tile_row BETWEEN ?4 AND ?5", self.coords_table());
+        let mut stmt = self.conn.prepare(&sql).map_err(sql_err)?;
+        let rows = stmt.query_map((zoom, range.min_x(), range.max_x(), flip_y(zoom, range.max_y()).unwrap(), flip_y(zoom, range.min_y()).unwrap()),
+            |r| Ok(Tile{ zoom, x: r.get(0)?, y: flip_y(zoom, r.get(1)?).unwrap() })).map_err(sql_err)?;
+        let mut tiles = rows.collect::<rusqlite::Result<Vec<Tile>>>().map_err(sql_err)?;
+        tiles.sort_by_key(|t| (t.x, t.y));
+        Ok(tiles)
+    }
+
+    /// Rows with coordinates which aren't a valid tile are `Unparsable`.
+    fn scan(&self, zooms: RangeInclusive<u8>, f: &mut dyn FnMut(ScanEntry) -> io::Result<()>) -> io::Result<()> {
+        let sql = format!("SELECT zoom_level, tile_column, tile_row FROM {} WHERE zoom_level BETWEEN ?1 AND ?2", self.coords_table());
+        let mut stmt = self.conn.prepare(&sql).map_err(sql_err)?;
+        let mut rows = stmt.query((zooms.start(), zooms.end())).map_err(sql_err)?;
+        while let Some(row) = rows.next().map_err(sql_err)? {
+            let (zoom, x, row): (i64, i64, i64) = (row.get(0).map_err(sql_err)?, row.get(1).map_err(sql_err)?, row.get(2).map_err(sql_err)?);
+            let tile = if (0..=31).contains(&zoom) && (0..1 << zoom).contains(&x) && (0..1 << zoom).contains(&row) {
+                Tile::new(zoom as u8, x as u32, flip_y(zoom as u8, row as u32).unwrap())
+            } else {
+                None
+            };
+            f(match tile {
+                Some(t) => ScanEntry::Tile(t),
+                None => ScanEntry::Unparsable(format!("zoom_level={} tile_column={} tile_row={}", zoom, x, row)),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        if self.pending.get() > 0 {
+            self.pending.set(0);
+            self.conn.execute_batch("COMMIT").map_err(sql_err)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn mbtiles() {
+        let store = MbTilesStore::open(":memory:").unwrap();
+        let (a, b, c) = (Tile::new(3, 2, 5).unwrap(), Tile::new(3, 2, 6).unwrap(), Tile::new(10, 547, 380).unwrap());
+        assert_eq!(store.get(&a).unwrap(), None);
+        store.put(&a, b"aa").unwrap();
+        store.put_copy(&b, &a, b"aa").unwrap();
+        store.put(&c, b"c").unwrap();
+        assert_eq!(store.get(&b).unwrap(), Some(b"aa".to_vec()));
+        assert_eq!(store.metadata(&a).unwrap().map(|m| m.size()), Some(2));
+        assert!(!store.exists(&Tile::new(3, 2, 7).unwrap()).unwrap());
+        // Rows are counted from the bottom
+        let mut stmt = store.conn.prepare("SELECT tile_row FROM map WHERE zoom_level = 3 ORDER BY tile_row").unwrap();
+        let rows: Vec<u32> = stmt.query_map((), |r| r.get(0)).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(rows, vec![1, 2]);
+        let images: i64 = store.conn.query_row("SELECT count(*) FROM images", (), |r| r.get(0)).unwrap();
+        assert_eq!(images, 2);
+
+        assert_eq!(store.list(&TileRange::all(3).unwrap()).unwrap(), vec![a, b]);
+        assert_eq!(store.list(&TileRange::new(3, 0, 7, 6, 7).unwrap()).unwrap(), vec![b]);
+
+        // Changing a shared image doesn't change the other tile
+        store.put(&a, b"new").unwrap();
+        assert_eq!(store.get(&b).unwrap(), Some(b"aa".to_vec()));
+        assert!(store.delete(&b).unwrap());
+        assert!(!store.delete(&b).unwrap());
+        let images: i64 = store.conn.query_row("SELECT count(*) FROM images", (), |r| r.get(0)).unwrap();
+        assert_eq!(images, 2);
+
+        store.set_metadata_value("format", "png").unwrap();
+        store.set_metadata_value("format", "jpg").unwrap();
+        assert_eq!(store.metadata_value("format").unwrap(), Some("jpg".to_string()));
+        assert_eq!(store.metadata_value("name").unwrap(), None);
+
+        store.conn.execute("INSERT INTO map (zoom_level, tile_column, tile_row, tile_id) VALUES (3, 8, 0, 'x')", ()).unwrap();
+        let mut entries = Vec::new();
+        store.scan(0..=31, &mut |e| { entries.push(e); Ok(()) }).unwrap();
+        entries.sort_by_key(|e| format!("{:?}", e));
+        assert_eq!(entries, vec![ScanEntry::Tile(c), ScanEntry::Tile(a), ScanEntry::Unparsable("zoom_level=3 tile_column=8 tile_row=0".to_string())]);
+    }
+
+    #[test]
+    fn failed_writes() {
+        let store = MbTilesStore::open(":memory:").unwrap();
+        let (a, b) = (Tile::new(3, 2, 5).unwrap(), Tile::new(3, 2, 6).unwrap());
+        // Failing first doesn't leave a transaction open
+        assert!(store.write(|conn| conn.execute("INSERT INTO nowhere VALUES (1)", ())).is_err());
+        store.put(&a, b"a").unwrap();
+        // Failing later keeps the earlier changes, but not the failed one's
+        assert!(store.write(|conn| {
+            conn.execute("INSERT INTO images (tile_data) VALUES (x'00')", ())?;
+            conn.execute("INSERT INTO nowhere VALUES (1)", ())
+        }).is_err());
+        store.put(&b, b"b").unwrap();
+        store.flush().unwrap();
+        assert_eq!((store.get(&a).unwrap(), store.get(&b).unwrap()), (Some(b"a".to_vec()), Some(b"b".to_vec())));
+        let images: i64 = store.conn.query_row("SELECT count(*) FROM images", (), |r| r.get(0)).unwrap();
+        assert_eq!(images, 2);
+    }
+
+    #[test]
+    fn plain_schema() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("plain.mbtiles");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch("
+                CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+                CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+                INSERT INTO tiles VALUES (1, 0, 1, x'0102');
+            ").unwrap();
+        }
+        let tile = Tile::new(1, 0, 0).unwrap();
+        {
+            let store = MbTilesStore::open(&path).unwrap();
+            assert_eq!(store.schema, Schema::Plain);
+            assert_eq!(store.get(&tile).unwrap(), Some(vec![1, 2]));
+            store.put_copy(&Tile::new(1, 1, 0).unwrap(), &tile, &[1, 2]).unwrap();
+            assert!(store.delete(&tile).unwrap());
+        }
+        let store = MbTilesStore::open(&path).unwrap();
+        assert_eq!(store.list(&TileRange::all(1).unwrap()).unwrap(), vec![Tile::new(1, 1, 0).unwrap()]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Copying a tile cache from one `TileStore` to another, e.g. to change its layout.
+//!
+//! ```
+//! # use slippy_map_tiles::{Tile, TileStore, FsTileStore, FsLayout};
+//! # use slippy_map_tiles::migrate::Migration;
+//! # extern crate tempfile;
+//! # let tmp = tempfile::tempdir().unwrap();
+//! # let dir = tmp.path();
+//! let src = FsTileStore::new(dir.join("mapproxy"), FsLayout::Mp, "png");
+//! let dst = FsTileStore::new(dir.join("tilecache"), FsLayout::Tc, "png");
+//! src.put(&Tile::new(3, 2, 5).unwrap(), b"png data").unwrap();
+//! src.put(&Tile::new(3, 2, 6).unwrap(), b"png data").unwrap();
+//!
+//! let mut migration = Migration::new();
+//! migration.set_dedup(true);
+//! let report = migration.run(&src, Some(&dst)).unwrap();
+//! assert_eq!((report.copied(), report.duplicates()), (2, 1));
+//! assert!(dst.exists(&Tile::new(3, 2, 6).unwrap()).unwrap());
+//! ```
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+use {Tile, BBox};
+use coverer::Region;
+use store::{TileStore, ScanEntry};
+
+/// What to copy, and how
+#[derive(PartialEq, Debug, Clone)]
+pub struct Migration {
+    minzoom: u8,
+    maxzoom: u8,
+    bbox: Option<BBox>,
+    dry_run: bool,
+    dedup: bool,
+}
+
+/// What a `Migration` did (or, for a dry run, would have done)
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct MigrationReport {
+    pub(crate) copied: u64,
+    pub(crate) duplicates: u64,
+    pub(crate) skipped: u64,
+    pub(crate) bytes: u64,
+    pub(crate) unparsable: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Number of tiles copied, including duplicates
+    pub fn copied(&self) -> u64 { self.copied }
+
+    /// Number of copied tiles which had the same data as an earlier tile, and were stored with
+    /// `TileStore::put_copy`
+    pub fn duplicates(&self) -> u64 { self.duplicates }
+
+    /// Number of tiles not copied, because they are outside the bbox
+    pub fn skipped(&self) -> u64 { self.skipped }
+
+    /// Total size of the copied tiles
+    pub fn bytes(&self) -> u64 { self.bytes }
+
+    /// Everything in the source which isn't a tile (see `ScanEntry::Unparsable`)
+    pub fn unparsable(&self) -> &[String] { &self.unparsable }
+}
+
+impl Default for Migration {
+    fn default() -> Self {
+        Migration::new()
+    }
+}
+
+impl Migration {
+    /// Copy every tile, at all zooms, and don't deduplicate.
+    pub fn new() -> Migration {
+        Migration{ minzoom: 0, maxzoom: 31, bbox: None, dry_run: false, dedup: false }
+    }
+
+    /// Only copy tiles from `minzoom` to `maxzoom` (inclusive).
+    pub fn set_zooms(&mut self, minzoom: u8, maxzoom: u8) {
+        self.minzoom = minzoom;
+        self.maxzoom = maxzoom;
+    }
+
+    /// Only copy tiles which overlap this bbox (or all tiles, if `None`).
+    pub fn set_bbox(&mut self, bbox: Option<BBox>) {
+        self.bbox = bbox;
+    }
+
+    /// If true, read everything, but don't write anything.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// If true, tiles with the same data as an earlier tile are stored with
+    /// `TileStore::put_copy`, so the destination can share the data.
+    pub fn set_dedup(&mut self, dedup: bool) {
+        self.dedup = dedup;
+    }
+
+    /// Copy tiles from `src` to `dst`, then `flush` it. Tiles already in `dst` are replaced.
+    /// Things in `src` which aren't tiles are listed in the report, and not copied. With no `dst`
+    /// (or a dry run) nothing is written, but the report is the same.
+    pub fn run(&self, src: &dyn TileStore, dst: Option<&dyn TileStore>) -> io::Result<MigrationReport> {
+        let dst = if self.dry_run { None } else { dst };
+        let mut report = MigrationReport::default();
+        // The first tile with each hash & size
+        let mut seen: HashMap<(u64, usize), Tile> = HashMap::new();
+
+        src.scan(self.minzoom..=self.maxzoom, &mut |entry| {
+            let tile = match entry {
+                ScanEntry::Unparsable(s) => {
+                    report.unparsable.push(s);
+                    return Ok(());
+                },
+                ScanEntry::Tile(t) => t,
+            };
+            if self.bbox.as_ref().is_some_and(|b| !b.intersects_tile(&tile)) {
+                report.skipped += 1;
+                return Ok(());
+            }
+            // It could have been removed since the scan
+            let data = match src.get(&tile)? {
+                None => return Ok(()),
+                Some(d) => d,
+            };
+
+            let mut original = None;
+            if self.dedup {
+                let mut hasher = DefaultHasher::new();
+                data.hash(&mut hasher);
+                let key = (hasher.finish(), data.len());
+                match seen.get(&key) {
+                    // Check it really is the same, in case of hash collisions
+                    Some(first) => if src.get(first)?.as_ref() == Some(&data) {
+                        original = Some(*first);
+                    },
+                    None => { seen.insert(key, tile); },
+                }
+            }
+
+            if let Some(dst) = dst {
+                match original {
+                    Some(ref first) => dst.put_copy(&tile, first, &data)?,
+                    None => dst.put(&tile, &data)?,
+                }
+            }
+            report.copied += 1;
+            report.bytes += data.len() as u64;
+            if original.is_some() {
+                report.duplicates += 1;
+            }
+            Ok(())
+        })?;
+
+        if let Some(dst) = dst {
+            dst.flush()?;
+        }
+        report.unparsable.sort();
+        Ok(report)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use store::{FsTileStore, FsLayout};
+    use TileRange;
+    use tempfile::tempdir;
+
+    #[test]
+    fn migrate() {
+        let dir = tempdir().unwrap();
+        let src = FsTileStore::new(dir.path().join("src"), FsLayout::Ts, "png");
+        let tiles = [Tile::new(2, 0, 0).unwrap(), Tile::new(2, 3, 3).unwrap(), Tile::new(5, 1, 1).unwrap(), Tile::new(5, 30, 30).unwrap(), Tile::new(12, 3, 4).unwrap()];
+        for (i, t) in tiles.iter().enumerate() {
+            src.put(t, if i % 2 == 0 { b"sea" } else { b"land" }).unwrap();
+        }
+        fs::write(dir.path().join("src/5/notes.txt"), b"").unwrap();
+
+        let meta = FsTileStore::new(dir.path().join("meta"), FsLayout::ModTileMeta, "png");
+        let mut migration = Migration::new();
+        migration.set_dry_run(true);
+        migration.set_dedup(true);
+        let report = migration.run(&src, Some(&meta)).unwrap();
+        assert_eq!((report.copied(), report.duplicates(), report.skipped(), report.bytes()), (5, 3, 0, 17));
+        assert_eq!(report.unparsable(), &[dir.path().join("src/5/notes.txt").display().to_string()]);
+        assert!(!dir.path().join("meta").exists());
+        migration.set_dry_run(false);
+        assert_eq!(migration.run(&src, None).unwrap(), report);
+        assert!(!dir.path().join("meta").exists());
+
+        // Only the north west
+        migration.set_zooms(2, 5);
+        migration.set_bbox(Some(BBox::new(85., -180., 0., 0.).unwrap()));
+        let report = migration.run(&src, Some(&meta)).unwrap();
+        assert_eq!((report.copied(), report.duplicates(), report.skipped()), (2, 1, 2));
+        assert_eq!(meta.list(&TileRange::all(2).unwrap()).unwrap(), vec![tiles[0]]);
+        assert_eq!(meta.get(&tiles[2]).unwrap(), Some(b"sea".to_vec()));
+
+        // And back, with everything
+        let zxy = FsTileStore::new(dir.path().join("zxy"), FsLayout::Zxy, "png");
+        let report = Migration::new().run(&meta, Some(&zxy)).unwrap();
+        assert_eq!((report.copied(), report.duplicates(), report.unparsable().len()), (2, 0, 0));
+        assert_eq!(zxy.get(&tiles[0]).unwrap(), Some(b"sea".to_vec()));
+    }
+}
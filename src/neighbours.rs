@@ -0,0 +1,221 @@
+//! Neighbouring tiles, rings of tiles around a tile, and distances between tiles.
+//!
+//! Tiles can wrap around the antimeridian in x, if `wrap_x` is true. They never wrap in y, so
+//! there is nothing north of the top row, or south of the bottom row.
+//!
+//! ```
+//! # use slippy_map_tiles::{Tile, Direction};
+//! let t = Tile::new(2, 3, 0).unwrap();
+//! assert_eq!(t.neighbour(Direction::East, true), Tile::new(2, 0, 0));
+//! assert_eq!(t.neighbour(Direction::East, false), None);
+//! assert_eq!(t.neighbour(Direction::North, true), None);
+//! assert_eq!(t.neighbours(true).len(), 5);
+//! ```
+use std::collections::HashSet;
+
+use Tile;
+
+/// The 8 directions from a tile to its neighbours. North is towards y = 0.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    /// All the directions, clockwise from north.
+    pub fn all() -> [Direction; 8] {
+        use self::Direction::*;
+        [North, NorthEast, East, SouthEast, South, SouthWest, West, NorthWest]
+    }
+
+    /// How x & y change going one tile in this direction.
+    pub fn offset(&self) -> (i32, i32) {
+        match *self {
+            Direction::North => (0, -1),
+            Direction::NorthEast => (1, -1),
+            Direction::East => (1, 0),
+            Direction::SouthEast => (1, 1),
+            Direction::South => (0, 1),
+            Direction::SouthWest => (-1, 1),
+            Direction::West => (-1, 0),
+            Direction::NorthWest => (-1, -1),
+        }
+    }
+
+    /// The direction going the other way.
+    pub fn opposite(&self) -> Direction {
+        let all = Direction::all();
+        all[(all.iter().position(|d| d == self).unwrap() + 4) % 8]
+    }
+}
+
+impl Tile {
+    /// The number of tiles across the world at this tile's zoom
+    fn world_size(&self) -> i64 {
+        1i64 << self.zoom
+    }
+
+    /// The tile `dx` & `dy` away from this, or `None` if that is off the world.
+    fn offset_by(&self, dx: i64, dy: i64, wrap_x: bool) -> Option<Tile> {
+        let size = self.world_size();
+        let (mut x, y) = (self.x as i64 + dx, self.y as i64 + dy);
+        if wrap_x {
+            x = x.rem_euclid(size);
+        }
+        if x < 0 || x >= size || y < 0 || y >= size {
+            None
+        } else {
+            Some(Tile{ zoom: self.zoom, x: x as u32, y: y as u32 })
+        }
+    }
+
+    /// The x & y differences to another tile, taking the shortest way around if `wrap_x`.
+    /// `None` if they are at different zooms.
+    fn distances(&self, other: &Tile, wrap_x: bool) -> Option<(u32, u32)> {
+        if self.zoom != other.zoom {
+            return None;
+        }
+        let dx = (self.x as i64 - other.x as i64).abs();
+        let dx = if wrap_x { dx.min(self.world_size() - dx) } else { dx };
+        Some((dx as u32, (self.y as i64 - other.y as i64).unsigned_abs() as u32))
+    }
+
+    /// The tile next to this in this direction. `None` if that would be past a pole, or past the
+    /// antimeridian when `wrap_x` is false.
+    pub fn neighbour(&self, direction: Direction, wrap_x: bool) -> Option<Tile> {
+        let (dx, dy) = direction.offset();
+        self.offset_by(dx as i64, dy as i64, wrap_x)
+    }
+
+    /// The (up to 8) tiles touching this one, clockwise from north west. Tiles off the world are
+    /// skipped, and at low zooms, where wrapping would repeat tiles, each is only included once,
+    /// and this tile never is.
+    pub fn neighbours(&self, wrap_x: bool) -> Vec<Tile> {
+        self.ring(1, wrap_x)
+    }
+
+    /// All the tiles which are exactly `k` tiles away (Chebyshev distance), clockwise from the
+    /// north west corner. `ring(0)` is just this tile.
+    pub fn ring(&self, k: u32, wrap_x: bool) -> Vec<Tile> {
+        if k == 0 {
+            return vec![*self];
+        }
+        let (k, size) = (k as i64, self.world_size());
+        if k >= size {
+            return vec![];
+        }
+        let (x, y) = (self.x as i64, self.y as i64);
+        // Only look at offsets which could be on the world. With wrapping, a row never needs
+        // more than the whole width of the world.
+        let (dx_min, dx_max) = if wrap_x { (-k, k.min(size - 1 - k)) } else { ((-k).max(-x), k.min(size - 1 - x)) };
+        let (dy_min, dy_max) = ((1 - k).max(-y), (k - 1).min(size - 1 - y));
+
+        let mut offsets = Vec::new();
+        if y - k >= 0 {
+            offsets.extend((dx_min..=dx_max).map(|dx| (dx, -k)));
+        }
+        offsets.extend((dy_min..=dy_max).map(|dy| (k, dy)));
+        if y + k < size {
+            offsets.extend((dx_min..=dx_max).rev().map(|dx| (dx, k)));
+        }
+        offsets.extend((dy_min..=dy_max).rev().map(|dy| (-k, dy)));
+
+        let mut seen = HashSet::new();
+        offsets.into_iter()
+            .filter_map(|(dx, dy)| self.offset_by(dx, dy, wrap_x))
+            .filter(|t| self.chebyshev_distance(t, wrap_x) == Some(k as u32))
+            .filter(|t| seen.insert(*t))
+            .collect()
+    }
+
+    /// All the tiles which are at most `k` tiles away (Chebyshev distance), starting with this
+    /// tile, then each ring outwards.
+    pub fn disk(&self, k: u32, wrap_x: bool) -> Vec<Tile> {
+        (0..=k).flat_map(|i| self.ring(i, wrap_x)).collect()
+    }
+
+    /// The number of steps, only moving north, south, east or west, to the other tile. `None`
+    /// if they are at different zooms.
+    pub fn manhattan_distance(&self, other: &Tile, wrap_x: bool) -> Option<u32> {
+        self.distances(other, wrap_x).map(|(dx, dy)| dx + dy)
+    }
+
+    /// The number of steps, also moving diagonally, to the other tile. `None` if they are at
+    /// different zooms.
+    pub fn chebyshev_distance(&self, other: &Tile, wrap_x: bool) -> Option<u32> {
+        self.distances(other, wrap_x).map(|(dx, dy)| dx.max(dy))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn zxys(tiles: &[Tile]) -> Vec<String> {
+        tiles.iter().map(|t| t.zxy()).collect()
+    }
+
+    #[test]
+    fn neighbour() {
+        let t = Tile::new(3, 4, 5).unwrap();
+        for d in Direction::all().iter() {
+            assert_eq!(t.neighbour(*d, false).unwrap().neighbour(d.opposite(), false), Some(t));
+        }
+        assert_eq!(t.neighbour(Direction::NorthWest, false), Tile::new(3, 3, 4));
+        assert_eq!(zxys(&t.neighbours(false)), vec!["3/3/4", "3/4/4", "3/5/4", "3/5/5", "3/5/6", "3/4/6", "3/3/6", "3/3/5"]);
+
+        let t = Tile::new(3, 0, 7).unwrap();
+        assert_eq!(t.neighbour(Direction::South, true), None);
+        assert_eq!(t.neighbour(Direction::West, false), None);
+        assert_eq!(t.neighbour(Direction::SouthWest, true), None);
+        assert_eq!(t.neighbour(Direction::NorthWest, true), Tile::new(3, 7, 6));
+        assert_eq!(zxys(&t.neighbours(false)), vec!["3/0/6", "3/1/6", "3/1/7"]);
+        assert_eq!(zxys(&t.neighbours(true)), vec!["3/7/6", "3/0/6", "3/1/6", "3/1/7", "3/7/7"]);
+
+        assert_eq!(Tile::new(0, 0, 0).unwrap().neighbours(true), vec![]);
+        assert_eq!(zxys(&Tile::new(1, 0, 0).unwrap().neighbours(true)), vec!["1/1/0", "1/0/1", "1/1/1"]);
+    }
+
+    #[test]
+    fn rings() {
+        let t = Tile::new(5, 10, 10).unwrap();
+        assert_eq!(t.ring(0, false), vec![t]);
+        for k in 1..4 {
+            let ring = t.ring(k, false);
+            assert_eq!(ring.len() as u32, 8*k);
+            assert!(ring.iter().all(|r| t.chebyshev_distance(r, false) == Some(k)));
+        }
+        assert_eq!(t.disk(2, false).len(), 25);
+
+        // The whole world, with each tile only once
+        let t = Tile::new(3, 1, 2).unwrap();
+        let mut disk = t.disk(8, true);
+        assert_eq!(disk.len(), 64);
+        disk.sort_by_key(|t| (t.x(), t.y()));
+        disk.dedup();
+        assert_eq!(disk.len(), 64);
+        assert_eq!(t.disk(8, false).len(), 64);
+        assert_eq!(t.ring(7, true), vec![]);
+        assert_eq!(t.ring(6, false).len(), 8);
+        assert_eq!(t.ring(100, false), vec![]);
+    }
+
+    #[test]
+    fn distances() {
+        let a = Tile::new(4, 1, 3).unwrap();
+        let b = Tile::new(4, 14, 5).unwrap();
+        assert_eq!(a.manhattan_distance(&b, false), Some(15));
+        assert_eq!(a.chebyshev_distance(&b, false), Some(13));
+        assert_eq!(a.manhattan_distance(&b, true), Some(5));
+        assert_eq!(a.chebyshev_distance(&b, true), Some(3));
+        assert_eq!(a.chebyshev_distance(&a.parent().unwrap(), false), None);
+    }
+}
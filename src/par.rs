@@ -0,0 +1,205 @@
+//! Parallel iterators, with the `rayon` feature.
+//!
+//! Work is split along quadtree boundaries, so each thread gets tiles which are near each other.
+//!
+//! ```
+//! extern crate rayon;
+//! extern crate slippy_map_tiles;
+//! use rayon::prelude::*;
+//! use slippy_map_tiles::{BBox, Tile};
+//!
+//! # fn main() {
+//! let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+//! let even = bbox.par_tiles(0, 10).filter(|t| t.x() % 2 == 0).count();
+//!
+//! let n = Tile::new(5, 10, 10).unwrap().par_subtiles(10).count();
+//! assert_eq!(n, 4 + 16 + 64 + 256 + 1024);
+//! # }
+//! ```
+use rayon::iter::{ParallelIterator, IntoParallelIterator};
+use rayon::iter::plumbing::{UnindexedProducer, UnindexedConsumer, Folder, bridge_unindexed};
+
+use {Tile, Metatile, BBox, TileRange};
+
+/// The largest zoom which can be iterated over
+const MAX_ZOOM: u8 = 31;
+
+/// A parallel iterator over tiles or metatiles
+pub struct ParIter<T> {
+    producer: RangesProducer<T>,
+}
+
+impl<T: Send> ParallelIterator for ParIter<T> {
+    type Item = T;
+
+    fn drive_unindexed<C: UnindexedConsumer<T>>(self, consumer: C) -> C::Result {
+        bridge_unindexed(self.producer, consumer)
+    }
+}
+
+/// Produces the items in some ranges. For metatiles, the ranges are in metatile coords (i.e.
+/// x/scale), and `make` turns them into metatiles.
+struct RangesProducer<T> {
+    ranges: Vec<TileRange>,
+    scale: u8,
+    make: fn(u8, &Tile) -> T,
+}
+
+impl<T> ParIter<T> {
+    fn new(ranges: Vec<TileRange>, scale: u8, make: fn(u8, &Tile) -> T) -> ParIter<T> {
+        ParIter{ producer: RangesProducer{ ranges, scale, make } }
+    }
+}
+
+impl<T: Send> UnindexedProducer for RangesProducer<T> {
+    type Item = T;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        let (scale, make) = (self.scale, self.make);
+        if self.ranges.len() > 1 {
+            // Split so both halves have about the same number of tiles
+            let total: u64 = self.ranges.iter().map(|r| r.num_tiles()).sum();
+            let mut sum = 0;
+            let mut mid = self.ranges.iter().position(|r| { sum += r.num_tiles(); sum >= total / 2 }).unwrap_or(0) + 1;
+            mid = mid.min(self.ranges.len() - 1);
+            let right = self.ranges.split_off(mid);
+            (self, Some(RangesProducer{ ranges: right, scale, make }))
+        } else {
+            match self.ranges.first().and_then(|r| r.split()) {
+                None => (self, None),
+                Some((a, b)) => (RangesProducer{ ranges: vec![a], scale, make }, Some(RangesProducer{ ranges: vec![b], scale, make })),
+            }
+        }
+    }
+
+    fn fold_with<F: Folder<T>>(self, mut folder: F) -> F {
+        for range in self.ranges {
+            for tile in range.iter() {
+                folder = folder.consume((self.make)(self.scale, &tile));
+                if folder.full() {
+                    return folder;
+                }
+            }
+        }
+        folder
+    }
+}
+
+fn make_tile(_scale: u8, t: &Tile) -> Tile {
+    *t
+}
+
+fn make_metatile(scale: u8, t: &Tile) -> Metatile {
+    Metatile{ width: scale, height: scale, zoom: t.zoom, x: t.x * scale as u32, y: t.y * scale as u32 }
+}
+
+impl IntoParallelIterator for TileRange {
+    type Iter = ParIter<Tile>;
+    type Item = Tile;
+
+    fn into_par_iter(self) -> ParIter<Tile> {
+        ParIter::new(vec![self], 1, make_tile)
+    }
+}
+
+impl TileRange {
+    /// Iterate over these tiles in parallel
+    pub fn par_iter(&self) -> ParIter<Tile> {
+        self.into_par_iter()
+    }
+}
+
+impl Tile {
+    /// Iterate, in parallel, over all the tiles inside this tile, from the next zoom down to
+    /// `maxzoom` (at most 31).
+    pub fn par_subtiles(&self, maxzoom: u8) -> ParIter<Tile> {
+        let ranges = (self.zoom+1..=maxzoom.min(MAX_ZOOM)).map(|zoom| {
+            let shift = zoom - self.zoom;
+            TileRange::new(zoom, self.x << shift, ((self.x + 1) << shift) - 1, self.y << shift, ((self.y + 1) << shift) - 1).unwrap()
+        }).collect();
+        ParIter::new(ranges, 1, make_tile)
+    }
+}
+
+impl Metatile {
+    /// Iterate, in parallel, over all the metatiles of this scale, from zoom 0 to `maxzoom` (at
+    /// most 31). Panics if the scale isn't a power of 2.
+    pub fn par_all(scale: u8, maxzoom: u8) -> ParIter<Metatile> {
+        assert!(scale.is_power_of_two());
+        let ranges = (0..=maxzoom.min(MAX_ZOOM)).filter_map(|zoom| {
+            let max = ((1u32 << zoom) - 1) / scale as u32;
+            TileRange::new(zoom, 0, max, 0, max)
+        }).collect();
+        ParIter::new(ranges, scale, make_metatile)
+    }
+}
+
+impl BBox {
+    /// Iterate, in parallel, over the tiles which cover this bbox, from `minzoom` to `maxzoom`
+    /// (at most 31). There are none if the bbox crosses the antimeridian, like
+    /// `TileRange::new_for_bbox`.
+    pub fn par_tiles(&self, minzoom: u8, maxzoom: u8) -> ParIter<Tile> {
+        let ranges = (minzoom..=maxzoom.min(MAX_ZOOM)).filter_map(|zoom| TileRange::new_for_bbox(self, zoom)).collect();
+        ParIter::new(ranges, 1, make_tile)
+    }
+
+    /// Iterate, in parallel, over the metatiles of this scale which cover this bbox, from
+    /// `minzoom` to `maxzoom` (at most 31), or none if the bbox crosses the antimeridian. Panics
+    /// if the scale isn't a power of 2.
+    pub fn par_metatiles(&self, scale: u8, minzoom: u8, maxzoom: u8) -> ParIter<Metatile> {
+        assert!(scale.is_power_of_two());
+        let ranges = (minzoom..=maxzoom.min(MAX_ZOOM)).filter_map(|zoom| TileRange::new_for_bbox_metatiles(self, zoom, (scale, scale))).collect();
+        ParIter::new(ranges, scale, make_metatile)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use MetatilesIterator;
+
+    fn sorted<T: Ord>(mut v: Vec<T>) -> Vec<T> {
+        v.sort();
+        v
+    }
+
+    fn zxy(t: &Tile) -> (u8, u32, u32) {
+        (t.zoom(), t.x(), t.y())
+    }
+
+    #[test]
+    fn par_tiles() {
+        let range = TileRange::new(12, 100, 1000, 2000, 2100).unwrap();
+        let par: Vec<_> = range.par_iter().map(|t| zxy(&t)).collect();
+        assert_eq!(sorted(par), sorted(range.iter().map(|t| zxy(&t)).collect()));
+
+        let t = Tile::new(3, 2, 5).unwrap();
+        assert_eq!(t.par_subtiles(9).count(), t.all_subtiles_iter().take_while(|s| s.zoom() <= 9).count());
+        assert!(t.par_subtiles(9).all(|s| s.zoom() > 3 && s.x() >> (s.zoom() - 3) == 2 && s.y() >> (s.zoom() - 3) == 5));
+        assert_eq!(t.par_subtiles(3).count(), 0);
+
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let par: Vec<_> = bbox.par_tiles(0, 12).map(|t| zxy(&t)).collect();
+        let seq: Vec<_> = (0..=12).flat_map(|z| TileRange::new_for_bbox(&bbox, z).unwrap()).map(|t| zxy(&t)).collect();
+        assert_eq!(sorted(par), sorted(seq));
+    }
+
+    #[test]
+    fn par_metatiles() {
+        let key = |mt: &Metatile| (mt.zoom(), mt.x(), mt.y());
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let par: Vec<_> = bbox.par_metatiles(8, 0, 14).map(|mt| key(&mt)).collect();
+        let seq: Vec<_> = MetatilesIterator::new_for_bbox_zoom(8, &Some(bbox), 0, 14).map(|mt| key(&mt)).collect();
+        assert_eq!(sorted(par), sorted(seq));
+
+        let par: Vec<_> = Metatile::par_all(4, 7).map(|mt| key(&mt)).collect();
+        let seq: Vec<_> = MetatilesIterator::new_for_bbox_zoom(4, &None, 0, 7).map(|mt| key(&mt)).collect();
+        assert_eq!(sorted(par), sorted(seq));
+
+        // Crosses the antimeridian
+        let bbox = BBox::new(10., 170., 0., -170.).unwrap();
+        assert_eq!(bbox.par_metatiles(8, 0, 14).count(), 0);
+        assert_eq!(bbox.par_tiles(0, 14).count(), 0);
+    }
+}
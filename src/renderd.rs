@@ -0,0 +1,274 @@
+//! Metatiles in the same order as mod_tile/renderd's `render_list` & `render_old` tools, so a
+//! run can be reproduced, or compared, with them.
+//!
+//! ```
+//! # use slippy_map_tiles::renderd::RenderListIterator;
+//! // render_list -a -z 10 -Z 10 -x 5 -X 17 -y 0 -Y 8
+//! let metatiles: Vec<String> = RenderListIterator::new_for_xy(10, 10, 5, 17, 0, 8)
+//!     .map(|mt| format!("{}/{}/{}", mt.zoom(), mt.x(), mt.y()))
+//!     .collect();
+//! // render_list steps 8 tiles from the minimum, so the metatile with x 17 isn't rendered
+//! assert_eq!(metatiles, vec!["10/0/0", "10/0/8", "10/8/0", "10/8/8"]);
+//! ```
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use {Metatile, BBox, TileRange};
+use store::FsLayout;
+
+/// mod_tile's metatile size
+const METATILE: u32 = 8;
+
+/// The metatiles `render_list -a` renders, in the same order: for each zoom, x from the
+/// minimum x, then y from the minimum y, both in steps of 8. Each x/y is rounded down to its
+/// metatile, as renderd does, so a maximum x or y which isn't 8 past a step (after the
+/// minimum) isn't included.
+#[derive(Debug, Clone)]
+pub struct RenderListIterator {
+    // (zoom, min x, max x, min y, max y) for each zoom
+    ranges: Vec<(u8, u32, u32, u32, u32)>,
+    index: usize,
+    // The next x & y (u64 so it can go past the end of the world)
+    x: u64,
+    y: u64,
+}
+
+impl RenderListIterator {
+    /// Every metatile from `minzoom` to `maxzoom` (inclusive), like `render_list -a -z MINZOOM
+    /// -Z MAXZOOM`.
+    pub fn new(minzoom: u8, maxzoom: u8) -> RenderListIterator {
+        RenderListIterator::new_for_ranges((minzoom..=maxzoom.min(31)).filter_map(TileRange::all).collect())
+    }
+
+    /// The metatiles for these tile x & y (inclusive) at each zoom, like `render_list -a -z
+    /// MINZOOM -Z MAXZOOM -x MIN_X -X MAX_X -y MIN_Y -Y MAX_Y`. x & y past the end of the world
+    /// at a zoom are skipped.
+    pub fn new_for_xy(minzoom: u8, maxzoom: u8, min_x: u32, max_x: u32, min_y: u32, max_y: u32) -> RenderListIterator {
+        RenderListIterator{
+            ranges: (minzoom..=maxzoom.min(31)).map(|z| (z, min_x, max_x, min_y, max_y)).collect(),
+            index: 0, x: min_x as u64, y: min_y as u64,
+        }
+    }
+
+    /// The metatiles for this bbox, like running `render_list -a` for each zoom, with the x &
+    /// y of the tiles at the bbox's corners (as `render_list_geo.pl` does).
+    pub fn new_for_bbox(bbox: &BBox, minzoom: u8, maxzoom: u8) -> RenderListIterator {
+        RenderListIterator::new_for_ranges((minzoom..=maxzoom.min(31)).filter_map(|z| TileRange::new_for_bbox(bbox, z)).collect())
+    }
+
+    fn new_for_ranges(ranges: Vec<TileRange>) -> RenderListIterator {
+        let ranges: Vec<_> = ranges.iter().map(|r| (r.zoom(), r.min_x(), r.max_x(), r.min_y(), r.max_y())).collect();
+        let (x, y) = ranges.first().map_or((0, 0), |r| (r.1 as u64, r.3 as u64));
+        RenderListIterator{ ranges, index: 0, x, y }
+    }
+}
+
+impl Iterator for RenderListIterator {
+    type Item = Metatile;
+
+    fn next(&mut self) -> Option<Metatile> {
+        loop {
+            let &(zoom, _, max_x, min_y, max_y) = self.ranges.get(self.index)?;
+            let end = 1u64 << zoom;
+            if self.y > max_y as u64 || self.y >= end {
+                self.x += METATILE as u64;
+                self.y = min_y as u64;
+            }
+            if self.x > max_x as u64 || self.x >= end || min_y as u64 >= end {
+                self.index += 1;
+                if let Some(r) = self.ranges.get(self.index) {
+                    self.x = r.1 as u64;
+                    self.y = r.3 as u64;
+                }
+                continue;
+            }
+            let (x, y) = (self.x as u32, self.y as u32);
+            self.y += METATILE as u64;
+            return Metatile::new(METATILE as u8, zoom, x, y);
+        }
+    }
+}
+
+/// The metatiles of the `.meta` files in a mod_tile style directory (e.g.
+/// `/var/lib/mod_tile/default`), in the same order as `render_old` finds them: for each zoom,
+/// the directory entries in the order the filesystem returns them, going into each
+/// subdirectory as it's found.
+///
+/// Like `render_old`, symlinks are followed, and files which can't be read, or don't have a
+/// valid mod_tile path, are skipped.
+#[derive(Debug)]
+pub struct RenderOldIterator {
+    root: PathBuf,
+    zooms: Vec<u8>,
+    older_than: Option<u64>,
+    // The directories being read, innermost last, and the zoom
+    stack: Vec<fs::ReadDir>,
+    zoom: Option<u8>,
+}
+
+/// Whole seconds since the epoch (or before it, as 0)
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl RenderOldIterator {
+    /// The metatiles from `minzoom` to `maxzoom` (inclusive) in this directory. If `older_than`
+    /// is given, only `.meta` files modified before then (to the second, as with `render_old`'s
+    /// planet timestamp) are included.
+    pub fn new<P: AsRef<Path>>(dir: P, minzoom: u8, maxzoom: u8, older_than: Option<SystemTime>) -> RenderOldIterator {
+        RenderOldIterator{
+            root: dir.as_ref().to_path_buf(),
+            zooms: (minzoom..=maxzoom).rev().collect(),
+            older_than: older_than.map(unix_secs),
+            stack: Vec::new(),
+            zoom: None,
+        }
+    }
+
+    /// The metatile for this `.meta` file, which is under this zoom's directory
+    fn metatile_for_path(&self, zoom: u8, path: &Path) -> Option<Metatile> {
+        let rel = path.strip_prefix(self.root.join(zoom.to_string())).ok()?;
+        let mut parts = rel.iter().map(|p| p.to_str()).collect::<Option<Vec<&str>>>()?;
+        let last = parts.pop()?;
+        parts.push(&last[..last.len() - ".meta".len()]);
+        if parts.len() != 5 {
+            return None;
+        }
+        let nums = parts.iter().map(|p| p.parse().ok()).collect::<Option<Vec<u32>>>()?;
+        let (x, y) = FsLayout::ModTileMeta.xy_from_parts(&nums);
+        Metatile::new(METATILE as u8, zoom, x, y)
+    }
+}
+
+impl Iterator for RenderOldIterator {
+    type Item = io::Result<Metatile>;
+
+    fn next(&mut self) -> Option<io::Result<Metatile>> {
+        loop {
+            let zoom = match self.zoom {
+                Some(z) if !self.stack.is_empty() => z,
+                _ => {
+                    let zoom = self.zooms.pop()?;
+                    self.zoom = Some(zoom);
+                    match fs::read_dir(self.root.join(zoom.to_string())) {
+                        Ok(entries) => self.stack.push(entries),
+                        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+                        Err(e) => return Some(Err(e)),
+                    }
+                    continue;
+                },
+            };
+
+            let entry = match self.stack.last_mut().and_then(|d| d.next()) {
+                None => {
+                    self.stack.pop();
+                    continue;
+                },
+                Some(Err(e)) => return Some(Err(e)),
+                Some(Ok(entry)) => entry,
+            };
+            let path = entry.path();
+            let metadata = match fs::metadata(&path) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                match fs::read_dir(&path) {
+                    Ok(entries) => self.stack.push(entries),
+                    Err(e) => return Some(Err(e)),
+                }
+                continue;
+            }
+            if path.extension().and_then(|e| e.to_str()) != Some("meta") {
+                continue;
+            }
+            if let Some(older_than) = self.older_than {
+                match metadata.modified() {
+                    Ok(m) if unix_secs(m) < older_than => {},
+                    _ => continue,
+                }
+            }
+            if let Some(mt) = self.metatile_for_path(zoom, &path) {
+                return Some(Ok(mt));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use {Tile, TileStore, FsTileStore};
+    use tempfile::tempdir;
+
+    fn zxy(it: impl Iterator<Item=Metatile>) -> Vec<(u8, u32, u32)> {
+        it.map(|mt| (mt.zoom(), mt.x(), mt.y())).collect()
+    }
+
+    #[test]
+    fn render_list() {
+        assert_eq!(zxy(RenderListIterator::new(0, 4)), vec![
+            (0, 0, 0), (1, 0, 0), (2, 0, 0), (3, 0, 0),
+            (4, 0, 0), (4, 0, 8), (4, 8, 0), (4, 8, 8),
+        ]);
+        // Past the end of the world at the lower zooms
+        assert_eq!(zxy(RenderListIterator::new_for_xy(2, 4, 3, 12, 12, 12)), vec![(4, 0, 8), (4, 8, 8)]);
+        assert_eq!(zxy(RenderListIterator::new_for_xy(4, 4, 9, 16, 1, 8)), vec![(4, 8, 0)]);
+        assert_eq!(RenderListIterator::new_for_xy(3, 3, 2, 1, 0, 7).count(), 0);
+        assert_eq!(RenderListIterator::new(0, 10).count(), 4 + (1..8).map(|i| 1usize << (2 * i)).sum::<usize>());
+
+        // At zoom 9, y goes from 164 to 171, so the metatiles at y 168 aren't rendered
+        let bbox = BBox::new(54., -11., 51., -5.).unwrap();
+        assert_eq!(zxy(RenderListIterator::new_for_bbox(&bbox, 8, 9)), vec![
+            (8, 120, 80),
+            (9, 240, 160), (9, 248, 160),
+        ]);
+        assert_eq!(TileRange::new_for_bbox(&bbox, 9).map(|r| (r.min_x(), r.max_x(), r.min_y(), r.max_y())), Some((240, 248, 164, 171)));
+    }
+
+    #[test]
+    fn render_old() {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path(), FsLayout::ModTileMeta, "png");
+        let tiles = ["3/1/1", "5/1/1", "5/9/30", "5/31/8", "12/3000/1000"];
+        for t in &tiles {
+            store.put(&t.parse::<Tile>().unwrap(), b"png").unwrap();
+        }
+        fs::write(dir.path().join("5/0/0/0/0/notes.txt"), b"").unwrap();
+        fs::write(dir.path().join("5/bad.meta"), b"").unwrap();
+
+        let metatiles = RenderOldIterator::new(dir.path(), 0, 10, None).collect::<io::Result<Vec<_>>>().unwrap();
+        let mut found = zxy(metatiles.into_iter());
+        // Each zoom in turn
+        assert_eq!(found.iter().map(|m| m.0).collect::<Vec<_>>(), vec![3, 5, 5, 5]);
+        found.sort();
+        assert_eq!(found, vec![(3, 0, 0), (5, 0, 0), (5, 8, 24), (5, 24, 8)]);
+
+        // The same order as a plain walk of the directory
+        let mut walk = Vec::new();
+        fn descend(dir: &Path, walk: &mut Vec<PathBuf>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    descend(&path, walk);
+                } else if path.extension().is_some_and(|e| e == "meta") {
+                    walk.push(path);
+                }
+            }
+        }
+        descend(&dir.path().join("5"), &mut walk);
+        walk.retain(|p| !p.ends_with("bad.meta"));
+        let paths: Vec<PathBuf> = RenderOldIterator::new(dir.path(), 5, 5, None)
+            .map(|mt| { let mt = mt.unwrap(); dir.path().join(Tile::new(mt.zoom(), mt.x(), mt.y()).unwrap().modtile_metatile().unwrap().path("meta")) })
+            .collect();
+        assert_eq!(paths, walk);
+
+        let now = SystemTime::now();
+        assert_eq!(RenderOldIterator::new(dir.path(), 0, 20, Some(now - Duration::from_secs(3600))).count(), 0);
+        assert_eq!(RenderOldIterator::new(dir.path(), 0, 20, Some(now + Duration::from_secs(3600))).count(), 5);
+    }
+}
@@ -0,0 +1,401 @@
+//! Choosing which metatiles to render (seed) first, from several jobs.
+//!
+//! A job is any list of metatiles, e.g. a `MetatilesIterator` for a bbox & zooms, or an expiry
+//! list. Each job's metatiles are ordered by the `PriorityKey`s, and the jobs take turns.
+//!
+//! ```
+//! # use slippy_map_tiles::{Metatile, MetatilePolicy, BBox, LatLon};
+//! # use slippy_map_tiles::scheduler::{Scheduler, PriorityKey};
+//! let mut scheduler = Scheduler::new(vec![PriorityKey::Zoom, PriorityKey::HotSpotDistance]);
+//! // Dublin
+//! scheduler.set_hot_spots(vec![LatLon::new(53.35, -6.26).unwrap()]);
+//! let ireland = BBox::new(55.5, -10.7, 51.4, -5.4).unwrap();
+//! scheduler.add_bbox_job("ireland", MetatilePolicy::fixed(8, 8).unwrap(), Some(ireland), 9, 10);
+//!
+//! let first = scheduler.next().unwrap();
+//! assert_eq!((first.zoom(), first.x(), first.y()), (9, 240, 160));
+//! assert_eq!(scheduler.count(), 12);
+//! ```
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use {Tile, Metatile, MetatilePolicy, MetatilesIterator, LatLon, BBox};
+use tilelist::{MetatileListReader, TileListFormat, TileListError};
+use inventory::TileSet;
+
+/// What to render first. A `Scheduler` orders metatiles by each of its keys in turn.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum PriorityKey {
+    /// Lower zooms first
+    Zoom,
+    /// Metatiles with their centre nearer a hot spot (see `Scheduler::set_hot_spots`) first
+    HotSpotDistance,
+    /// Metatiles with more requests (see `Scheduler::set_request_counts`) first
+    Requests,
+}
+
+/// A queued metatile. The smallest key (then the first added) is the highest priority.
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct Queued {
+    // One value per key. There are only 3 kinds of key, and repeating one changes nothing.
+    key: [u64; 3],
+    seq: u64,
+    metatile: Metatile,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Queued) -> Ordering {
+        // BinaryHeap is a max heap
+        (&other.key, other.seq).cmp(&(&self.key, self.seq))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Queued) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug)]
+struct Job {
+    name: String,
+    weight: u32,
+    queue: BinaryHeap<Queued>,
+    pending: Option<Pending>,
+}
+
+/// The metatiles of a bbox job which haven't been queued yet
+#[derive(Debug)]
+struct Pending {
+    policy: MetatilePolicy,
+    bbox: Option<BBox>,
+    // Zooms not started yet
+    zooms: RangeInclusive<u8>,
+    // The zoom being returned in order, without queueing it
+    current: Option<MetatilesIterator>,
+}
+
+impl Pending {
+    fn remaining(&self) -> usize {
+        let current = self.current.as_ref().map_or(0, |it| it.total().unwrap_or(0).saturating_sub(it.position()));
+        let later = if self.zooms.is_empty() {
+            0
+        } else {
+            MetatilesIterator::new_for_policy(self.policy.clone(), &self.bbox, *self.zooms.start(), *self.zooms.end()).total().unwrap_or(0)
+        };
+        current + later
+    }
+}
+
+/// Metatiles from several jobs, highest priority first. Each job gets, in turn, as many
+/// metatiles as its weight (default 1). Each metatile is only returned once, even if it's in
+/// several jobs. The metatiles returned are remembered as runs of y, like a `TileSet`, so a
+/// finished bbox takes about one run per column of metatiles.
+///
+/// All the metatiles of a job are read when it's added, so they can be sorted, except for
+/// `add_bbox_job`.
+#[derive(Debug)]
+pub struct Scheduler {
+    keys: Vec<PriorityKey>,
+    hot_spots: Vec<LatLon>,
+    requests: HashMap<Tile, u64>,
+    jobs: Vec<Job>,
+    // The job whose turn it is, and how many metatiles it has had this turn
+    current: usize,
+    served: u32,
+    seq: u64,
+    // The metatiles returned, for each width & height, in metatile coordinates (x & y divided by
+    // the width & height) so that neighbours are next to each other
+    done: HashMap<(u8, u8), TileSet>,
+}
+
+/// Great circle distance, in metres
+fn distance(a: &LatLon, b: &LatLon) -> f64 {
+    let (lat1, lat2) = ((a.lat() as f64).to_radians(), (b.lat() as f64).to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.lon() as f64 - a.lon() as f64).to_radians();
+    let h = (dlat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.).sin().powi(2);
+    2. * 6_371_000. * h.sqrt().min(1.).asin()
+}
+
+impl Scheduler {
+    /// Order metatiles by these keys (the first is the most important), then in the order they
+    /// were added.
+    pub fn new(keys: Vec<PriorityKey>) -> Scheduler {
+        let mut unique = Vec::new();
+        for key in keys {
+            if !unique.contains(&key) {
+                unique.push(key);
+            }
+        }
+        Scheduler{ keys: unique, hot_spots: Vec::new(), requests: HashMap::new(), jobs: Vec::new(), current: 0, served: 0, seq: 0, done: HashMap::new() }
+    }
+
+    /// The places for `PriorityKey::HotSpotDistance`
+    pub fn set_hot_spots(&mut self, hot_spots: Vec<LatLon>) {
+        self.hot_spots = hot_spots;
+        self.requeue();
+    }
+
+    /// How often each tile has been requested (e.g. from an access log), for
+    /// `PriorityKey::Requests`. A metatile's requests are those of all its tiles.
+    pub fn set_request_counts(&mut self, requests: HashMap<Tile, u64>) {
+        self.requests = requests;
+        self.requeue();
+    }
+
+    /// Add a job with these metatiles, returning its number (the first is 0).
+    pub fn add_job<I: IntoIterator<Item=Metatile>>(&mut self, name: &str, metatiles: I) -> usize {
+        self.jobs.push(Job{ name: name.to_string(), weight: 1, queue: BinaryHeap::new(), pending: None });
+        let job = self.jobs.len() - 1;
+        self.enqueue(job, metatiles);
+        job
+    }
+
+    /// Add a job with the metatiles (of this policy) in this bbox (`None` for the whole world)
+    /// between these zooms, returning its number.
+    ///
+    /// If the first key is `Zoom`, each zoom is only read when the ones before it are done, and
+    /// if it's the only key, the metatiles aren't queued at all. So this can be the whole planet.
+    pub fn add_bbox_job(&mut self, name: &str, policy: MetatilePolicy, bbox: Option<BBox>, minzoom: u8, maxzoom: u8) -> usize {
+        if self.keys.first().is_some_and(|k| *k != PriorityKey::Zoom) {
+            return self.add_job(name, MetatilesIterator::new_for_policy(policy, &bbox, minzoom, maxzoom));
+        }
+        let pending = Pending{ policy, bbox, zooms: minzoom..=maxzoom, current: None };
+        self.jobs.push(Job{ name: name.to_string(), weight: 1, queue: BinaryHeap::new(), pending: Some(pending) });
+        self.jobs.len() - 1
+    }
+
+    /// Add a job with the metatiles in this list (e.g. a tile expiry list, see
+    /// `MetatileListReader`), with tiles turned into metatiles of this scale.
+    pub fn add_expiry_list<P: AsRef<Path>>(&mut self, name: &str, path: P, scale: u8) -> Result<usize, TileListError> {
+        let metatiles = MetatileListReader::open(path, TileListFormat::Auto, scale)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(self.add_job(name, metatiles))
+    }
+
+    /// This job gets this many metatiles in a row on each turn. A weight of 0 is treated as 1.
+    pub fn set_job_weight(&mut self, job: usize, weight: u32) {
+        if let Some(j) = self.jobs.get_mut(job) {
+            j.weight = weight.max(1);
+        }
+    }
+
+    /// The name of this job
+    pub fn job_name(&self, job: usize) -> Option<&str> {
+        self.jobs.get(job).map(|j| j.name.as_str())
+    }
+
+    /// How many metatiles are still queued for this job, including ones which will be skipped
+    /// as they've already been returned for another job
+    pub fn queued(&self, job: usize) -> usize {
+        self.jobs.get(job).map_or(0, |j| j.queue.len() + j.pending.as_ref().map_or(0, |p| p.remaining()))
+    }
+
+    /// The next metatile, and the job it's for
+    pub fn next_with_job(&mut self) -> Option<(usize, Metatile)> {
+        for _ in 0..=self.jobs.len() {
+            if self.current >= self.jobs.len() {
+                self.current = 0;
+            }
+            let job = self.current;
+            if self.served < self.jobs.get(job)?.weight {
+                while let Some(metatile) = self.pop(job) {
+                    if self.mark_done(&metatile) {
+                        self.served += 1;
+                        return Some((job, metatile));
+                    }
+                }
+            }
+            self.current += 1;
+            self.served = 0;
+        }
+        None
+    }
+
+    /// Remember that this metatile has been returned. False if it already had been.
+    fn mark_done(&mut self, metatile: &Metatile) -> bool {
+        let (width, height) = (metatile.width(), metatile.height());
+        let tile = Tile::new(metatile.zoom(), metatile.x() / width as u32, metatile.y() / height as u32).unwrap();
+        let done = self.done.entry((width, height)).or_default();
+        if done.contains(&tile) {
+            false
+        } else {
+            done.insert(&tile);
+            true
+        }
+    }
+
+    /// Queue these metatiles for this job
+    fn enqueue<I: IntoIterator<Item=Metatile>>(&mut self, job: usize, metatiles: I) {
+        for metatile in metatiles {
+            let queued = Queued{ key: self.key(&metatile), seq: self.seq, metatile };
+            self.jobs[job].queue.push(queued);
+            self.seq += 1;
+        }
+    }
+
+    /// The highest priority metatile of this job, starting the next zoom of a bbox job if needed
+    fn pop(&mut self, job: usize) -> Option<Metatile> {
+        // With no other keys, metatiles of the same zoom are in the order they're added
+        let in_order = self.keys.iter().all(|k| *k == PriorityKey::Zoom);
+        loop {
+            if let Some(q) = self.jobs[job].queue.pop() {
+                return Some(q.metatile);
+            }
+            let pending = self.jobs[job].pending.as_mut()?;
+            if let Some(metatile) = pending.current.as_mut().and_then(|it| it.next()) {
+                return Some(metatile);
+            }
+            let zoom = match pending.zooms.next() {
+                Some(z) => z,
+                None => {
+                    self.jobs[job].pending = None;
+                    return None;
+                },
+            };
+            let metatiles = MetatilesIterator::new_for_policy(pending.policy.clone(), &pending.bbox, zoom, zoom);
+            if in_order {
+                pending.current = Some(metatiles);
+            } else {
+                pending.current = None;
+                self.enqueue(job, metatiles);
+            }
+        }
+    }
+
+    /// The sort key for this metatile
+    fn key(&self, metatile: &Metatile) -> [u64; 3] {
+        let mut key = [0; 3];
+        for (value, k) in key.iter_mut().zip(self.keys.iter()) {
+            *value = match *k {
+                PriorityKey::Zoom => metatile.zoom() as u64,
+                PriorityKey::HotSpotDistance => {
+                    let centre = metatile.centre_point();
+                    self.hot_spots.iter().map(|h| distance(h, &centre).round() as u64).min().unwrap_or(0)
+                },
+                PriorityKey::Requests => {
+                    let requests: u64 = metatile.tiles().iter().filter_map(|t| self.requests.get(t)).sum();
+                    u64::MAX - requests
+                },
+            };
+        }
+        key
+    }
+
+    /// Recalculate the keys of all the queued metatiles
+    fn requeue(&mut self) {
+        for i in 0..self.jobs.len() {
+            let queue = ::std::mem::take(&mut self.jobs[i].queue);
+            self.jobs[i].queue = queue.into_iter().map(|q| Queued{ key: self.key(&q.metatile), ..q }).collect();
+        }
+    }
+}
+
+impl Iterator for Scheduler {
+    type Item = Metatile;
+
+    fn next(&mut self) -> Option<Metatile> {
+        self.next_with_job().map(|(_, mt)| mt)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn zxy(it: impl Iterator<Item=Metatile>) -> Vec<String> {
+        it.map(|mt| format!("{}/{}/{}", mt.zoom(), mt.x(), mt.y())).collect()
+    }
+
+    #[test]
+    fn priority() {
+        let metatiles = || MetatilesIterator::new_for_bbox_zoom(1, &None, 0, 2);
+
+        let mut scheduler = Scheduler::new(vec![PriorityKey::Zoom]);
+        scheduler.add_job("all", metatiles().collect::<Vec<_>>().into_iter().rev());
+        assert_eq!(zxy(scheduler.by_ref().take(3)), vec!["0/0/0", "1/1/1", "1/0/1"]);
+
+        // Nearest to Sydney, ties (e.g. the world) in order
+        let mut scheduler = Scheduler::new(vec![PriorityKey::HotSpotDistance, PriorityKey::Zoom]);
+        scheduler.add_job("all", metatiles());
+        scheduler.set_hot_spots(vec![LatLon::new(-33.87, 151.21).unwrap()]);
+        assert_eq!(zxy(scheduler.take(4)), vec!["2/3/2", "2/3/3", "1/1/1", "2/0/3"]);
+
+        let mut scheduler = Scheduler::new(vec![PriorityKey::Requests, PriorityKey::Zoom]);
+        scheduler.add_job("all", metatiles());
+        let requests = [("2/0/0", 5), ("2/1/1", 3), ("1/1/0", 4), ("1/0/0", 1)];
+        scheduler.set_request_counts(requests.iter().map(|&(t, n)| (t.parse().unwrap(), n)).collect());
+        assert_eq!(zxy(scheduler.take(5)), vec!["2/0/0", "1/1/0", "2/1/1", "1/0/0", "0/0/0"]);
+    }
+
+    #[test]
+    fn jobs() {
+        let mut scheduler = Scheduler::new(vec![PriorityKey::Zoom]);
+        let world = scheduler.add_job("world", MetatilesIterator::new_for_bbox_zoom(2, &None, 0, 4));
+        let ireland = BBox::new(55.5, -10.7, 51.4, -5.4).unwrap();
+        let ie = scheduler.add_job("ireland", MetatilesIterator::new_for_bbox_zoom(2, &Some(ireland), 4, 6));
+        scheduler.set_job_weight(ie, 2);
+        assert_eq!((scheduler.job_name(ie), scheduler.queued(world), scheduler.queued(ie)), (Some("ireland"), 1 + 1 + 4 + 16 + 64, 3));
+
+        let order: Vec<(usize, String)> = ::std::iter::from_fn(|| scheduler.next_with_job())
+            .map(|(j, mt)| (j, format!("{}/{}/{}", mt.zoom(), mt.x(), mt.y()))).take(6).collect();
+        assert_eq!(order, vec![
+            (0, "0/0/0".to_string()), (1, "4/6/4".to_string()), (1, "5/14/10".to_string()),
+            (0, "1/0/0".to_string()), (1, "6/30/20".to_string()), (0, "2/0/0".to_string()),
+        ]);
+        // 4/6/4 was rendered for ireland, so isn't again for the world
+        let rest = zxy(scheduler.by_ref());
+        assert_eq!(rest.len(), 86 + 3 - 1 - 6);
+        assert!(!rest.contains(&"4/6/4".to_string()));
+        assert_eq!(scheduler.next(), None);
+    }
+
+    #[test]
+    fn bbox_jobs() {
+        // Only the metatiles being returned are in memory
+        let mut scheduler = Scheduler::new(vec![PriorityKey::Zoom]);
+        let planet = scheduler.add_bbox_job("planet", MetatilePolicy::fixed(8, 8).unwrap(), None, 0, 18);
+        let total = MetatilesIterator::new_for_bbox_zoom(8, &None, 0, 18).total().unwrap();
+        assert_eq!(scheduler.queued(planet), total);
+        assert_eq!(zxy(scheduler.by_ref().take(6)), vec!["0/0/0", "1/0/0", "2/0/0", "3/0/0", "4/0/0", "4/8/0"]);
+        assert_eq!(scheduler.queued(planet), total - 6);
+
+        // What's been returned is remembered as one run per column
+        let mut scheduler = Scheduler::new(vec![PriorityKey::Zoom]);
+        scheduler.add_bbox_job("planet", MetatilePolicy::fixed(8, 8).unwrap(), None, 0, 12);
+        assert_eq!(scheduler.by_ref().count(), MetatilesIterator::new_for_bbox_zoom(8, &None, 0, 12).total().unwrap());
+        let runs: usize = scheduler.done[&(8, 8)].columns.values().flat_map(|c| c.values()).map(|runs| runs.len()).sum();
+        assert_eq!(runs, 1 + 1 + 1 + 1 + 2 + 4 + 8 + 16 + 32 + 64 + 128 + 256 + 512);
+
+        // The same order as reading them all first
+        let ireland = Some(BBox::new(55.5, -10.7, 51.4, -5.4).unwrap());
+        for keys in &[vec![PriorityKey::Zoom, PriorityKey::HotSpotDistance], vec![PriorityKey::HotSpotDistance, PriorityKey::Zoom], vec![]] {
+            let mut lazy = Scheduler::new(keys.clone());
+            lazy.set_hot_spots(vec![LatLon::new(53.35, -6.26).unwrap()]);
+            lazy.add_bbox_job("ireland", MetatilePolicy::fixed(2, 2).unwrap(), ireland.clone(), 4, 9);
+            let mut eager = Scheduler::new(keys.clone());
+            eager.set_hot_spots(vec![LatLon::new(53.35, -6.26).unwrap()]);
+            eager.add_job("ireland", MetatilesIterator::new_for_bbox_zoom(2, &ireland, 4, 9));
+            assert_eq!(lazy.queued(0), eager.queued(0));
+            assert_eq!(zxy(lazy), zxy(eager));
+        }
+    }
+
+    #[test]
+    fn expiry_list() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("expired.txt");
+        fs::write(&path, "12/2047/1362\n12/2046/1363\n10/511/340\nnot a tile\n").unwrap();
+        let mut scheduler = Scheduler::new(vec![PriorityKey::Zoom]);
+        assert!(scheduler.add_expiry_list("expired", &path, 8).is_err());
+        fs::write(&path, "12/2047/1362\n12/2046/1363\n10/511/340\n").unwrap();
+        let job = scheduler.add_expiry_list("expired", &path, 8).unwrap();
+        assert_eq!(scheduler.queued(job), 3);
+        assert_eq!(zxy(scheduler), vec!["10/504/336", "12/2040/1360"]);
+    }
+}
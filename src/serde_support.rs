@@ -0,0 +1,1364 @@
+//! `Serialize`/`Deserialize` for the public types, with the `serde` feature.
+//!
+//! Deserialising goes through the normal constructors, so you can't deserialise an invalid tile,
+//! lat/lon etc.
+//!
+//! * `Tile` is a `{"zoom": 6, "x": 35, "y": 23}` struct, and can be read from a `"6/35/23"`
+//!   string too. Use `#[serde(with = "slippy_map_tiles::serde_zxy")]` to always write the string.
+//! * `Metatile` is a `{"scale": 8, "zoom": 6, "x": 32, "y": 16}` struct, or a `"8 6/32/16"`
+//!   string. Non-square metatiles have `width` & `height` instead of `scale`, or are `"4x2 6/32/16"`.
+//! * `BBox` is a `[minlon, minlat, maxlon, maxlat]` array.
+//! * `LatLon` is a `{"lat": 53.3, "lon": -6.2}` struct.
+//! * `TileMatrixSet` & `TileJson` (with the `json` feature) are their usual JSON formats, and
+//!   `UrlTemplate` is a string.
+//!   A `TileMatrix` is its OGC JSON, with a `cellSize` and an easting first `pointOfOrigin`.
+//! * `MatrixTile` is a `{"zoom": 0, "x": 1, "y": 0}` struct. It can't be checked without its
+//!   `TileMatrixSet`.
+//! * `MetatilesCursor` is a `{"zoom": 5, "zorder": 10, "file_offset": null, "position": 25}`
+//!   struct.
+//! * `ShardStrategy` is `"zorder_range"`, `"hash"` or `{"ancestor": 6}`.
+//! * `TileRange` is a `{"zoom": 6, "min_x": 30, "max_x": 35, "min_y": 20, "max_y": 23}` struct.
+//! * `TileListFormat` is `"auto"`, `"zxy"`, `"space_separated"`, `"csv"` or `"render_list"`.
+//! * `Direction` is `"north"`, `"north_east"`, `"east"` etc.
+//! * `Polygon` is a `{"exterior": [latlon, ...], "holes": [[latlon, ...], ...]}` struct.
+//! * `MetatilePolicy` is a `[[zoom, width, height], ...]` array, e.g. `[[0, 8, 8], [12, 8, 4]]`.
+//! * `FsLayout` is `"zxy"`, `"tc"`, `"mp"`, `"ts"` or `"meta"`, and `PriorityKey` is `"zoom"`,
+//!   `"hot_spot_distance"` or `"requests"`.
+//! * `TileSet` is a `[[zoom, x, min_y, max_y], ...]` array of its runs of tiles.
+//! * `TileHits` has its `hits` as a `[[tile, hits], ...]` array, sorted by tile.
+//! * `Sample` is a `{"5": {"tiles": 100, ...}}` map from zoom.
+//! * `TileMetadata`, `BundleId`, `Estimate`, `ZoomEstimate`, `MigrationReport`, `InventoryDiff` &
+//!   `DownloadStats` are structs of what their getters return.
+//!
+//! Formats which aren't self-describing (e.g. bincode) can't have a struct *or* a string, so
+//! `Tile` & `Metatile` are always structs in them, and `Metatile`s always have `width` & `height`.
+//! `TileMatrixSet`, `TileMatrix` & `TileJson` are structs of their fields in them, and a
+//! `TileJson`'s unknown keys are JSON text.
+use std::fmt;
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor, MapAccess, SeqAccess};
+use serde::ser::SerializeStruct;
+#[cfg(feature="json")]
+use serde_json::{self, Value};
+
+use {Tile, Metatile, ModTileMetatile, MetatilesCursor, LatLon, BBox, TileMatrixSet, TileMatrix, MatrixTile, CornerOfOrigin, UrlTemplate};
+#[cfg(feature="json")]
+use TileJson;
+use {ShardStrategy, MetatilePolicy, Polygon, Direction, TileListFormat, TileRange, FsLayout, TileMetadata};
+use tilematrixset;
+use wmts::WmtsLayer;
+use scheduler::PriorityKey;
+use estimate::{Estimate, ZoomEstimate, Sample, SampleZoom};
+use arcgis::BundleId;
+use migrate::MigrationReport;
+use inventory::{TileSet, InventoryDiff};
+use accesslog::TileHits;
+#[cfg(feature="download")]
+use download::DownloadStats;
+
+impl Serialize for Tile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Tile", 3)?;
+        s.serialize_field("zoom", &self.zoom)?;
+        s.serialize_field("x", &self.x)?;
+        s.serialize_field("y", &self.y)?;
+        s.end()
+    }
+}
+
+struct TileVisitor;
+
+impl<'de> Visitor<'de> for TileVisitor {
+    type Value = Tile;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tile as a {zoom, x, y} struct or a \"z/x/y\" string")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Tile, E> {
+        s.parse().map_err(E::custom)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Tile, A::Error> {
+        let (mut zoom, mut x, mut y) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "zoom" => { zoom = Some(map.next_value()?); },
+                "x" => { x = Some(map.next_value()?); },
+                "y" => { y = Some(map.next_value()?); },
+                _ => { map.next_value::<de::IgnoredAny>()?; },
+            }
+        }
+        let zoom = zoom.ok_or_else(|| de::Error::missing_field("zoom"))?;
+        let x = x.ok_or_else(|| de::Error::missing_field("x"))?;
+        let y = y.ok_or_else(|| de::Error::missing_field("y"))?;
+
+        Tile::new(zoom, x, y).ok_or_else(|| de::Error::custom("Invalid X or Y for this zoom"))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Tile, A::Error> {
+        let zoom = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let x = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let y = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+
+        Tile::new(zoom, x, y).ok_or_else(|| de::Error::custom("Invalid X or Y for this zoom"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Tile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tile, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TileVisitor)
+        } else {
+            deserializer.deserialize_struct("Tile", &["zoom", "x", "y"], TileVisitor)
+        }
+    }
+}
+
+/// (De)serialise a `Tile` as a compact `"z/x/y"` string, for use with `#[serde(with = ...)]`.
+pub mod zxy {
+    use serde::{Serializer, Deserializer};
+
+    use Tile;
+    use super::TileVisitor;
+
+    pub fn serialize<S: Serializer>(tile: &Tile, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&tile.zxy())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tile, D::Error> {
+        deserializer.deserialize_str(TileVisitor)
+    }
+}
+
+impl Serialize for Metatile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !self.is_square() || !serializer.is_human_readable() {
+            let mut s = serializer.serialize_struct("Metatile", 5)?;
+            s.serialize_field("width", &self.width)?;
+            s.serialize_field("height", &self.height)?;
+            s.serialize_field("zoom", &self.zoom)?;
+            s.serialize_field("x", &self.x)?;
+            s.serialize_field("y", &self.y)?;
+            return s.end();
+        }
+        let mut s = serializer.serialize_struct("Metatile", 4)?;
+        s.serialize_field("scale", &self.width)?;
+        s.serialize_field("zoom", &self.zoom)?;
+        s.serialize_field("x", &self.x)?;
+        s.serialize_field("y", &self.y)?;
+        s.end()
+    }
+}
+
+struct MetatileVisitor;
+
+impl<'de> Visitor<'de> for MetatileVisitor {
+    type Value = Metatile;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a metatile as a {scale, zoom, x, y} or {width, height, zoom, x, y} struct, or a \"scale z/x/y\" string")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Metatile, E> {
+        s.parse().map_err(|_| E::custom("Invalid metatile"))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Metatile, A::Error> {
+        let (mut scale, mut width, mut height, mut zoom, mut x, mut y) = (None, None, None, None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "scale" => { scale = Some(map.next_value()?); },
+                "width" => { width = Some(map.next_value()?); },
+                "height" => { height = Some(map.next_value()?); },
+                "zoom" => { zoom = Some(map.next_value()?); },
+                "x" => { x = Some(map.next_value()?); },
+                "y" => { y = Some(map.next_value()?); },
+                _ => { map.next_value::<de::IgnoredAny>()?; },
+            }
+        }
+        let (width, height) = match (scale, width, height) {
+            (Some(scale), _, _) => (scale, scale),
+            (None, Some(width), Some(height)) => (width, height),
+            (None, None, _) => { return Err(de::Error::missing_field("scale")); },
+            (None, Some(_), None) => { return Err(de::Error::missing_field("height")); },
+        };
+        let zoom = zoom.ok_or_else(|| de::Error::missing_field("zoom"))?;
+        let x = x.ok_or_else(|| de::Error::missing_field("x"))?;
+        let y = y.ok_or_else(|| de::Error::missing_field("y"))?;
+
+        Metatile::new_with_size(width, height, zoom, x, y).ok_or_else(|| de::Error::custom("Invalid metatile"))
+    }
+
+    /// Always with width & height
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Metatile, A::Error> {
+        let width = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let height = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let zoom = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let x = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        let y = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(4, &self))?;
+
+        Metatile::new_with_size(width, height, zoom, x, y).ok_or_else(|| de::Error::custom("Invalid metatile"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Metatile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Metatile, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(MetatileVisitor)
+        } else {
+            deserializer.deserialize_struct("Metatile", &["width", "height", "zoom", "x", "y"], MetatileVisitor)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ModTileMetatileRepr {
+    zoom: u8,
+    x: u32,
+    y: u32,
+}
+
+impl Serialize for ModTileMetatile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ModTileMetatileRepr{ zoom: self.inner.zoom, x: self.inner.x, y: self.inner.y }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ModTileMetatile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ModTileMetatile, D::Error> {
+        let repr = ModTileMetatileRepr::deserialize(deserializer)?;
+        ModTileMetatile::new(repr.zoom, repr.x, repr.y).ok_or_else(|| de::Error::custom("Invalid metatile"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MetatilesCursorRepr {
+    zoom: u8,
+    zorder: u64,
+    file_offset: Option<u64>,
+    position: usize,
+}
+
+impl Serialize for MetatilesCursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MetatilesCursorRepr{ zoom: self.zoom, zorder: self.zorder, file_offset: self.file_offset, position: self.position }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MetatilesCursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MetatilesCursor, D::Error> {
+        let r = MetatilesCursorRepr::deserialize(deserializer)?;
+        Ok(MetatilesCursor{ zoom: r.zoom, zorder: r.zorder, file_offset: r.file_offset, position: r.position })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LatLonRepr {
+    lat: f32,
+    lon: f32,
+}
+
+impl Serialize for LatLon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LatLonRepr{ lat: self.lat, lon: self.lon }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LatLon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LatLon, D::Error> {
+        let repr = LatLonRepr::deserialize(deserializer)?;
+        LatLon::new(repr.lat, repr.lon).ok_or_else(|| de::Error::custom("Invalid lat/lon"))
+    }
+}
+
+impl Serialize for BBox {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        [self.left, self.bottom, self.right, self.top].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BBox {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BBox, D::Error> {
+        let [minlon, minlat, maxlon, maxlat] = <[f32; 4]>::deserialize(deserializer)?;
+        BBox::new(maxlat, minlon, minlat, maxlon).ok_or_else(|| de::Error::custom("bad lat/lon"))
+    }
+}
+
+impl Serialize for CornerOfOrigin {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match *self {
+            CornerOfOrigin::TopLeft => "topLeft",
+            CornerOfOrigin::BottomLeft => "bottomLeft",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for CornerOfOrigin {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CornerOfOrigin, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "topLeft" => Ok(CornerOfOrigin::TopLeft),
+            "bottomLeft" => Ok(CornerOfOrigin::BottomLeft),
+            _ => Err(de::Error::custom("invalid cornerOfOrigin")),
+        }
+    }
+}
+
+/// A tile matrix ID is usually a string, but can be a number in JSON
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum TileMatrixId {
+    String(String),
+    Integer(u64),
+    Float(f64),
+}
+
+/// The OGC 2.0 JSON encoding of a tile matrix
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TileMatrixJson {
+    id: TileMatrixId,
+    scale_denominator: f64,
+    cell_size: Option<f64>,
+    corner_of_origin: Option<CornerOfOrigin>,
+    point_of_origin: [f64; 2],
+    tile_width: u32,
+    tile_height: u32,
+    matrix_width: u32,
+    matrix_height: u32,
+    #[serde(default, skip_serializing)]
+    variable_matrix_widths: Option<de::IgnoredAny>,
+}
+
+impl TileMatrixJson {
+    fn new(tm: &TileMatrix) -> TileMatrixJson {
+        TileMatrixJson {
+            id: TileMatrixId::String(tm.id.clone()),
+            scale_denominator: tm.scale_denominator,
+            cell_size: Some(tm.cell_size),
+            corner_of_origin: Some(tm.corner_of_origin),
+            point_of_origin: [tm.point_of_origin.0, tm.point_of_origin.1],
+            tile_width: tm.tile_width,
+            tile_height: tm.tile_height,
+            matrix_width: tm.matrix_width,
+            matrix_height: tm.matrix_height,
+            variable_matrix_widths: None,
+        }
+    }
+
+    fn tile_matrix(self, northing_first: bool, metres_per_unit: f64) -> Result<TileMatrix, &'static str> {
+        if self.variable_matrix_widths.is_some() {
+            return Err("variableMatrixWidths are not supported");
+        }
+        let [a, b] = self.point_of_origin;
+        let scale_denominator = self.scale_denominator;
+        TileMatrix {
+            id: match self.id {
+                TileMatrixId::String(id) => id,
+                TileMatrixId::Integer(id) => id.to_string(),
+                TileMatrixId::Float(id) => id.to_string(),
+            },
+            scale_denominator,
+            cell_size: self.cell_size.unwrap_or_else(|| tilematrixset::cell_size_for_scale(scale_denominator, metres_per_unit)),
+            corner_of_origin: self.corner_of_origin.unwrap_or(CornerOfOrigin::TopLeft),
+            point_of_origin: if northing_first { (b, a) } else { (a, b) },
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+            matrix_width: self.matrix_width,
+            matrix_height: self.matrix_height,
+        }.check()
+    }
+}
+
+/// A tile matrix, field by field, for formats which aren't human readable
+#[derive(Serialize, Deserialize)]
+struct TileMatrixRepr {
+    id: String,
+    scale_denominator: f64,
+    cell_size: f64,
+    corner_of_origin: CornerOfOrigin,
+    point_of_origin: (f64, f64),
+    tile_width: u32,
+    tile_height: u32,
+    matrix_width: u32,
+    matrix_height: u32,
+}
+
+impl Serialize for TileMatrix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            TileMatrixJson::new(self).serialize(serializer)
+        } else {
+            TileMatrixRepr {
+                id: self.id.clone(),
+                scale_denominator: self.scale_denominator,
+                cell_size: self.cell_size,
+                corner_of_origin: self.corner_of_origin,
+                point_of_origin: self.point_of_origin,
+                tile_width: self.tile_width,
+                tile_height: self.tile_height,
+                matrix_width: self.matrix_width,
+                matrix_height: self.matrix_height,
+            }.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TileMatrix {
+    /// A tile matrix on its own has no CRS, so the `pointOfOrigin` must be easting first, and
+    /// there must be a `cellSize`.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileMatrix, D::Error> {
+        if deserializer.is_human_readable() {
+            let tm = TileMatrixJson::deserialize(deserializer)?;
+            if tm.cell_size.is_none() {
+                return Err(de::Error::missing_field("cellSize"));
+            }
+            tm.tile_matrix(false, 1.).map_err(de::Error::custom)
+        } else {
+            let r = TileMatrixRepr::deserialize(deserializer)?;
+            TileMatrix {
+                id: r.id,
+                scale_denominator: r.scale_denominator,
+                cell_size: r.cell_size,
+                corner_of_origin: r.corner_of_origin,
+                point_of_origin: r.point_of_origin,
+                tile_width: r.tile_width,
+                tile_height: r.tile_height,
+                matrix_width: r.matrix_width,
+                matrix_height: r.matrix_height,
+            }.check().map_err(de::Error::custom)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MatrixTileRepr {
+    zoom: u8,
+    x: u32,
+    y: u32,
+}
+
+impl Serialize for MatrixTile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MatrixTileRepr{ zoom: self.zoom, x: self.x, y: self.y }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatrixTile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MatrixTile, D::Error> {
+        let r = MatrixTileRepr::deserialize(deserializer)?;
+        Ok(MatrixTile{ zoom: r.zoom, x: r.x, y: r.y })
+    }
+}
+
+/// The CRS of a tile matrix set can be a URI, or an object with a URI
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CrsJson {
+    Uri(String),
+    Object { uri: String },
+}
+
+/// The OGC 2.0 JSON encoding of a tile matrix set
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TileMatrixSetJson {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uri: Option<String>,
+    crs: CrsJson,
+    ordered_axes: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    well_known_scale_set: Option<String>,
+    tile_matrices: Vec<TileMatrixJson>,
+}
+
+/// A tile matrix set, field by field, for formats which aren't human readable
+#[derive(Serialize, Deserialize)]
+struct TileMatrixSetRepr {
+    id: String,
+    title: Option<String>,
+    uri: Option<String>,
+    crs: String,
+    well_known_scale_set: Option<String>,
+    tile_matrices: Vec<TileMatrix>,
+}
+
+impl Serialize for TileMatrixSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            TileMatrixSetJson {
+                id: self.id.clone(),
+                title: self.title.clone(),
+                uri: self.uri.clone(),
+                crs: CrsJson::Uri(self.crs.clone()),
+                ordered_axes: Some(tilematrixset::ordered_axes(&self.crs).iter().map(|a| a.to_string()).collect()),
+                well_known_scale_set: self.well_known_scale_set.clone(),
+                tile_matrices: self.tile_matrices.iter().map(TileMatrixJson::new).collect(),
+            }.serialize(serializer)
+        } else {
+            TileMatrixSetRepr {
+                id: self.id.clone(),
+                title: self.title.clone(),
+                uri: self.uri.clone(),
+                crs: self.crs.clone(),
+                well_known_scale_set: self.well_known_scale_set.clone(),
+                tile_matrices: self.tile_matrices.clone(),
+            }.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TileMatrixSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileMatrixSet, D::Error> {
+        if deserializer.is_human_readable() {
+            let r = TileMatrixSetJson::deserialize(deserializer)?;
+            let crs = match r.crs {
+                CrsJson::Uri(uri) | CrsJson::Object{ uri } => uri,
+            };
+            let first_axis = match r.ordered_axes {
+                Some(ref axes) => Some(axes.first().ok_or_else(|| de::Error::custom("invalid orderedAxes"))?.as_str()),
+                None => None,
+            };
+            let northing_first = tilematrixset::northing_first(&crs, first_axis);
+            let metres_per_unit = tilematrixset::metres_per_unit(&crs);
+            let tile_matrices = r.tile_matrices.into_iter().map(|tm| tm.tile_matrix(northing_first, metres_per_unit)).collect::<Result<Vec<_>, _>>().map_err(de::Error::custom)?;
+            TileMatrixSet{ id: r.id, title: r.title, uri: r.uri, crs, well_known_scale_set: r.well_known_scale_set, tile_matrices }.check().map_err(de::Error::custom)
+        } else {
+            let r = TileMatrixSetRepr::deserialize(deserializer)?;
+            TileMatrixSet{ id: r.id, title: r.title, uri: r.uri, crs: r.crs, well_known_scale_set: r.well_known_scale_set, tile_matrices: r.tile_matrices }.check().map_err(de::Error::custom)
+        }
+    }
+}
+
+impl Serialize for UrlTemplate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for UrlTemplate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<UrlTemplate, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        UrlTemplate::new(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature="json")]
+/// A TileJSON, field by field, for formats which aren't human readable. Everything else in the
+/// TileJSON can be any JSON, so is kept as JSON text.
+#[derive(Serialize, Deserialize)]
+struct TileJsonRepr {
+    tiles: Vec<String>,
+    name: Option<String>,
+    description: Option<String>,
+    version: Option<String>,
+    attribution: Option<String>,
+    scheme: Option<String>,
+    minzoom: u8,
+    maxzoom: u8,
+    bounds: BBox,
+    centre: LatLon,
+    centre_zoom: u8,
+    other: String,
+}
+
+#[cfg(feature="json")]
+impl Serialize for TileJson {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            self.to_json_value().serialize(serializer)
+        } else {
+            TileJsonRepr {
+                tiles: self.tiles.clone(),
+                name: self.name.clone(),
+                description: self.description.clone(),
+                version: self.version.clone(),
+                attribution: self.attribution.clone(),
+                scheme: self.scheme.clone(),
+                minzoom: self.minzoom,
+                maxzoom: self.maxzoom,
+                bounds: self.bounds.clone(),
+                centre: self.centre.clone(),
+                centre_zoom: self.centre_zoom,
+                other: Value::Object(self.other.clone()).to_string(),
+            }.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature="json")]
+impl<'de> Deserialize<'de> for TileJson {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileJson, D::Error> {
+        if deserializer.is_human_readable() {
+            let value = Value::deserialize(deserializer)?;
+            TileJson::from_json_value(value).map_err(de::Error::custom)
+        } else {
+            let r = TileJsonRepr::deserialize(deserializer)?;
+            let tilejson = TileJson {
+                tiles: r.tiles,
+                name: r.name,
+                description: r.description,
+                version: r.version,
+                attribution: r.attribution,
+                scheme: r.scheme,
+                minzoom: r.minzoom,
+                maxzoom: r.maxzoom,
+                bounds: r.bounds,
+                centre: r.centre,
+                centre_zoom: r.centre_zoom,
+                other: serde_json::from_str(&r.other).map_err(de::Error::custom)?,
+            };
+            tilejson.validate().map_err(de::Error::custom)?;
+            Ok(tilejson)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct WmtsLayerRepr {
+    identifier: String,
+    title: String,
+    formats: Vec<String>,
+    bbox: BBox,
+    minzoom: u8,
+    maxzoom: u8,
+    url_template: String,
+}
+
+impl Serialize for WmtsLayer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        WmtsLayerRepr {
+            identifier: self.identifier().to_string(),
+            title: self.title().to_string(),
+            formats: self.formats().to_vec(),
+            bbox: self.bbox().clone(),
+            minzoom: self.minzoom(),
+            maxzoom: self.maxzoom(),
+            url_template: self.url_template().as_str().to_string(),
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for WmtsLayer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<WmtsLayer, D::Error> {
+        let r = WmtsLayerRepr::deserialize(deserializer)?;
+        WmtsLayer::new(&r.identifier, &r.title, r.formats, r.bbox, r.minzoom, r.maxzoom, &r.url_template).ok_or_else(|| de::Error::custom("invalid WMTS layer"))
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ShardStrategy", rename_all = "snake_case")]
+enum ShardStrategyDef {
+    ZorderRange,
+    Hash,
+    Ancestor(u8),
+}
+
+impl Serialize for ShardStrategy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ShardStrategyDef::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ShardStrategy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ShardStrategy, D::Error> {
+        ShardStrategyDef::deserialize(deserializer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TileRangeRepr {
+    zoom: u8,
+    min_x: u32,
+    max_x: u32,
+    min_y: u32,
+    max_y: u32,
+}
+
+impl Serialize for TileRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TileRangeRepr{ zoom: self.zoom(), min_x: self.min_x(), max_x: self.max_x(), min_y: self.min_y(), max_y: self.max_y() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TileRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileRange, D::Error> {
+        let r = TileRangeRepr::deserialize(deserializer)?;
+        TileRange::new(r.zoom, r.min_x, r.max_x, r.min_y, r.max_y).ok_or_else(|| de::Error::custom("Invalid tile range"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "TileListFormat", rename_all = "snake_case")]
+enum TileListFormatDef {
+    Auto,
+    Zxy,
+    SpaceSeparated,
+    Csv,
+    RenderList,
+}
+
+impl Serialize for TileListFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TileListFormatDef::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TileListFormat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileListFormat, D::Error> {
+        TileListFormatDef::deserialize(deserializer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Direction", rename_all = "snake_case")]
+enum DirectionDef {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Serialize for Direction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DirectionDef::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Direction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Direction, D::Error> {
+        DirectionDef::deserialize(deserializer)
+    }
+}
+
+#[derive(Deserialize)]
+struct PolygonRepr {
+    exterior: Vec<LatLon>,
+    #[serde(default)]
+    holes: Vec<Vec<LatLon>>,
+}
+
+impl Serialize for Polygon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Polygon", 2)?;
+        s.serialize_field("exterior", self.exterior())?;
+        s.serialize_field("holes", self.holes())?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Polygon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Polygon, D::Error> {
+        let r = PolygonRepr::deserialize(deserializer)?;
+        Polygon::new(r.exterior, r.holes).ok_or_else(|| de::Error::custom("Invalid polygon, rings need at least 3 points"))
+    }
+}
+
+impl Serialize for MetatilePolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.sizes.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MetatilePolicy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MetatilePolicy, D::Error> {
+        let sizes = Vec::<(u8, u8, u8)>::deserialize(deserializer)?;
+        MetatilePolicy::per_zoom(&sizes).ok_or_else(|| de::Error::custom("Invalid metatile policy"))
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "FsLayout", rename_all = "snake_case")]
+enum FsLayoutDef {
+    Zxy,
+    Tc,
+    Mp,
+    Ts,
+    #[serde(rename = "meta")]
+    ModTileMeta,
+}
+
+impl Serialize for FsLayout {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FsLayoutDef::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FsLayout {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FsLayout, D::Error> {
+        FsLayoutDef::deserialize(deserializer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TileMetadataRepr {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Serialize for TileMetadata {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TileMetadataRepr{ size: self.size(), modified: self.modified() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TileMetadata {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileMetadata, D::Error> {
+        let r = TileMetadataRepr::deserialize(deserializer)?;
+        Ok(TileMetadata::new(r.size, r.modified))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PriorityKey", rename_all = "snake_case")]
+enum PriorityKeyDef {
+    Zoom,
+    HotSpotDistance,
+    Requests,
+}
+
+impl Serialize for PriorityKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PriorityKeyDef::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PriorityKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PriorityKey, D::Error> {
+        PriorityKeyDef::deserialize(deserializer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ZoomEstimateRepr {
+    zoom: u8,
+    tiles: u64,
+    metatiles: u64,
+    land_tiles: u64,
+    bytes: u128,
+    render_hours: f64,
+}
+
+impl Serialize for ZoomEstimate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ZoomEstimateRepr{ zoom: self.zoom, tiles: self.tiles, metatiles: self.metatiles, land_tiles: self.land_tiles, bytes: self.bytes, render_hours: self.render_hours }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ZoomEstimate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ZoomEstimate, D::Error> {
+        let r = ZoomEstimateRepr::deserialize(deserializer)?;
+        if r.land_tiles > r.tiles || r.metatiles > r.tiles {
+            return Err(de::Error::custom("more land tiles or metatiles than tiles"));
+        }
+        Ok(ZoomEstimate{ zoom: r.zoom, tiles: r.tiles, metatiles: r.metatiles, land_tiles: r.land_tiles, bytes: r.bytes, render_hours: r.render_hours })
+    }
+}
+
+#[derive(Deserialize)]
+struct EstimateRepr {
+    zooms: Vec<ZoomEstimate>,
+}
+
+impl Serialize for Estimate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Estimate", 1)?;
+        s.serialize_field("zooms", self.zooms())?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Estimate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Estimate, D::Error> {
+        let r = EstimateRepr::deserialize(deserializer)?;
+        if r.zooms.windows(2).any(|w| w[0].zoom >= w[1].zoom) {
+            return Err(de::Error::custom("zooms are not in order"));
+        }
+        Ok(Estimate{ zooms: r.zooms })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SampleZoomRepr {
+    tiles: u64,
+    land_tiles: u64,
+    land_bytes: u64,
+    sea_tile_size: u64,
+}
+
+impl Serialize for SampleZoom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SampleZoomRepr{ tiles: self.tiles, land_tiles: self.land_tiles, land_bytes: self.land_bytes, sea_tile_size: self.sea_tile_size }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SampleZoom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SampleZoom, D::Error> {
+        let r = SampleZoomRepr::deserialize(deserializer)?;
+        if r.land_tiles > r.tiles {
+            return Err(de::Error::custom("more land tiles than tiles"));
+        }
+        Ok(SampleZoom{ tiles: r.tiles, land_tiles: r.land_tiles, land_bytes: r.land_bytes, sea_tile_size: r.sea_tile_size })
+    }
+}
+
+/// A `Sample` is a map from zoom to what was sampled at that zoom
+impl Serialize for Sample {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.zooms.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sample {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Sample, D::Error> {
+        Ok(Sample{ zooms: BTreeMap::deserialize(deserializer)? })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundleIdRepr {
+    zoom: u8,
+    row: u32,
+    col: u32,
+}
+
+impl Serialize for BundleId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        BundleIdRepr{ zoom: self.zoom(), row: self.row(), col: self.col() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BundleId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BundleId, D::Error> {
+        let r = BundleIdRepr::deserialize(deserializer)?;
+        // The bundle of its top left tile, which must be that tile's bundle
+        let bundle = Tile::new(r.zoom, r.col, r.row).map(|t| BundleId::for_tile(&t));
+        match bundle {
+            Some(bundle) if bundle.row() == r.row && bundle.col() == r.col => Ok(bundle),
+            _ => Err(de::Error::custom("Invalid bundle")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MigrationReportRepr {
+    copied: u64,
+    duplicates: u64,
+    skipped: u64,
+    bytes: u64,
+    unparsable: Vec<String>,
+}
+
+impl Serialize for MigrationReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("MigrationReport", 5)?;
+        s.serialize_field("copied", &self.copied)?;
+        s.serialize_field("duplicates", &self.duplicates)?;
+        s.serialize_field("skipped", &self.skipped)?;
+        s.serialize_field("bytes", &self.bytes)?;
+        s.serialize_field("unparsable", &self.unparsable)?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for MigrationReport {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MigrationReport, D::Error> {
+        let r = MigrationReportRepr::deserialize(deserializer)?;
+        if r.duplicates > r.copied {
+            return Err(de::Error::custom("more duplicates than copied tiles"));
+        }
+        Ok(MigrationReport{ copied: r.copied, duplicates: r.duplicates, skipped: r.skipped, bytes: r.bytes, unparsable: r.unparsable })
+    }
+}
+
+#[cfg(feature="download")]
+#[derive(Deserialize)]
+struct DownloadStatsRepr {
+    fetched: u64,
+    not_modified: u64,
+    skipped: u64,
+    missing: u64,
+    bytes: u64,
+    failures: Vec<(Tile, String)>,
+}
+
+#[cfg(feature="download")]
+impl Serialize for DownloadStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("DownloadStats", 6)?;
+        s.serialize_field("fetched", &self.fetched)?;
+        s.serialize_field("not_modified", &self.not_modified)?;
+        s.serialize_field("skipped", &self.skipped)?;
+        s.serialize_field("missing", &self.missing)?;
+        s.serialize_field("bytes", &self.bytes)?;
+        s.serialize_field("failures", &self.failures)?;
+        s.end()
+    }
+}
+
+#[cfg(feature="download")]
+impl<'de> Deserialize<'de> for DownloadStats {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DownloadStats, D::Error> {
+        let r = DownloadStatsRepr::deserialize(deserializer)?;
+        Ok(DownloadStats{ fetched: r.fetched, not_modified: r.not_modified, skipped: r.skipped, missing: r.missing, bytes: r.bytes, failures: r.failures })
+    }
+}
+
+/// A `TileSet` is its runs of tiles, `[zoom, x, min_y, max_y]`, so a complete area is small
+impl Serialize for TileSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Collected first, since some formats need the length
+        let runs: Vec<(u8, u32, u32, u32)> = self.columns.iter().flat_map(|(&zoom, columns)| {
+            columns.iter().flat_map(move |(&x, runs)| runs.iter().map(move |&(min_y, max_y)| (zoom, x, min_y, max_y)))
+        }).collect();
+        runs.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TileSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileSet, D::Error> {
+        let runs = Vec::<(u8, u32, u32, u32)>::deserialize(deserializer)?;
+        let mut tiles = TileSet::new();
+        for (zoom, x, min_y, max_y) in runs {
+            let range = TileRange::new(zoom, x, x, min_y, max_y).ok_or_else(|| de::Error::custom("Invalid run of tiles"))?;
+            tiles.insert_range(&range);
+        }
+        Ok(tiles)
+    }
+}
+
+#[derive(Deserialize)]
+struct InventoryDiffRepr {
+    missing: TileSet,
+    extra: TileSet,
+    zero_bytes: TileSet,
+    expected_counts: Vec<(u8, u64)>,
+}
+
+impl Serialize for InventoryDiff {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("InventoryDiff", 4)?;
+        s.serialize_field("missing", &self.missing)?;
+        s.serialize_field("extra", &self.extra)?;
+        s.serialize_field("zero_bytes", &self.zero_bytes)?;
+        s.serialize_field("expected_counts", &self.expected_counts)?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for InventoryDiff {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<InventoryDiff, D::Error> {
+        let r = InventoryDiffRepr::deserialize(deserializer)?;
+        Ok(InventoryDiff{ missing: r.missing, extra: r.extra, zero_bytes: r.zero_bytes, expected_counts: r.expected_counts })
+    }
+}
+
+#[derive(Deserialize)]
+struct TileHitsRepr {
+    templates: Vec<UrlTemplate>,
+    successful_only: bool,
+    hits: Vec<(Tile, u64)>,
+    lines: u64,
+    unparsable: u64,
+    other_requests: u64,
+}
+
+impl Serialize for TileHits {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Sorted, so the same hits are always written the same way
+        let mut hits: Vec<(Tile, u64)> = self.hits.iter().map(|(t, n)| (*t, *n)).collect();
+        hits.sort_by_key(|&(t, _)| (t.zoom, t.x, t.y));
+
+        let mut s = serializer.serialize_struct("TileHits", 6)?;
+        s.serialize_field("templates", &self.templates)?;
+        s.serialize_field("successful_only", &self.successful_only)?;
+        s.serialize_field("hits", &hits)?;
+        s.serialize_field("lines", &self.lines)?;
+        s.serialize_field("unparsable", &self.unparsable)?;
+        s.serialize_field("other_requests", &self.other)?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for TileHits {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TileHits, D::Error> {
+        let r = TileHitsRepr::deserialize(deserializer)?;
+        let mut hits = HashMap::with_capacity(r.hits.len());
+        for (tile, n) in r.hits {
+            *hits.entry(tile).or_insert(0) += n;
+        }
+        Ok(TileHits{ templates: r.templates, successful_only: r.successful_only, hits, lines: r.lines, unparsable: r.unparsable, other: r.other_requests })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn serde_tile() {
+        let t = Tile::new(6, 35, 23).unwrap();
+        assert_eq!(serde_json::to_string(&t).unwrap(), r#"{"zoom":6,"x":35,"y":23}"#);
+        assert_eq!(serde_json::from_str::<Tile>(r#"{"zoom":6,"x":35,"y":23}"#).unwrap(), t);
+        assert_eq!(serde_json::from_str::<Tile>(r#""6/35/23""#).unwrap(), t);
+        assert!(serde_json::from_str::<Tile>(r#"{"zoom":0,"x":1,"y":0}"#).is_err());
+        assert!(serde_json::from_str::<Tile>(r#""0/1/0""#).is_err());
+        assert!(serde_json::from_str::<Tile>(r#"{"zoom":0,"x":0}"#).is_err());
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Job {
+            #[serde(with = "::serde_zxy")]
+            tile: Tile,
+        }
+        let job = Job{ tile: t };
+        assert_eq!(serde_json::to_string(&job).unwrap(), r#"{"tile":"6/35/23"}"#);
+        assert_eq!(serde_json::from_str::<Job>(r#"{"tile":"6/35/23"}"#).unwrap(), job);
+    }
+
+    #[test]
+    fn serde_metatile() {
+        let mt = Metatile::new(8, 6, 32, 16).unwrap();
+        assert_eq!(serde_json::to_string(&mt).unwrap(), r#"{"scale":8,"zoom":6,"x":32,"y":16}"#);
+        assert_eq!(serde_json::from_str::<Metatile>(r#"{"scale":8,"zoom":6,"x":32,"y":16}"#).unwrap(), mt);
+        assert_eq!(serde_json::from_str::<Metatile>(r#""8 6/32/16""#).unwrap(), mt);
+        assert!(serde_json::from_str::<Metatile>(r#"{"scale":3,"zoom":6,"x":32,"y":16}"#).is_err());
+
+        let mt = Metatile::new_with_size(4, 2, 6, 32, 16).unwrap();
+        assert_eq!(serde_json::to_string(&mt).unwrap(), r#"{"width":4,"height":2,"zoom":6,"x":32,"y":16}"#);
+        assert_eq!(serde_json::from_str::<Metatile>(&serde_json::to_string(&mt).unwrap()).unwrap(), mt);
+        assert_eq!(serde_json::from_str::<Metatile>(r#""4x2 6/32/16""#).unwrap(), mt);
+        assert!(serde_json::from_str::<Metatile>(r#"{"width":4,"zoom":6,"x":32,"y":16}"#).is_err());
+
+        let mut metatiles = Metatile::all(8);
+        metatiles.nth(30);
+        let cursor = metatiles.cursor();
+        assert_eq!(serde_json::to_string(&cursor).unwrap(), r#"{"zoom":6,"zorder":7,"file_offset":null,"position":31}"#);
+        assert_eq!(serde_json::from_str::<MetatilesCursor>(&serde_json::to_string(&cursor).unwrap()).unwrap(), cursor);
+
+        let mt = ModTileMetatile::new(6, 32, 16).unwrap();
+        assert_eq!(serde_json::from_str::<ModTileMetatile>(&serde_json::to_string(&mt).unwrap()).unwrap(), mt);
+    }
+
+    /// Like bincode, which isn't self-describing: structs are sequences of their fields
+    struct Compact(Vec<u32>);
+
+    impl<'de> Deserializer<'de> for Compact {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not self-describing"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+            visitor.visit_seq(de::value::SeqDeserializer::new(self.0.into_iter()))
+        }
+
+        fn is_human_readable(&self) -> bool { false }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit
+            unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    #[test]
+    fn serde_not_self_describing() {
+        assert_eq!(Tile::deserialize(Compact(vec![6, 35, 23])).unwrap(), Tile::new(6, 35, 23).unwrap());
+        assert!(Tile::deserialize(Compact(vec![0, 1, 0])).is_err());
+        assert!(Tile::deserialize(Compact(vec![6, 35])).is_err());
+        assert_eq!(Metatile::deserialize(Compact(vec![8, 8, 6, 32, 16])).unwrap(), Metatile::new(8, 6, 32, 16).unwrap());
+        assert_eq!(Metatile::deserialize(Compact(vec![4, 2, 6, 32, 16])).unwrap(), Metatile::new_with_size(4, 2, 6, 32, 16).unwrap());
+    }
+
+    #[test]
+    fn serde_bincode() {
+        fn round_trip<T: Serialize + for<'de> Deserialize<'de> + PartialEq + ::std::fmt::Debug>(value: &T) {
+            let bytes = bincode::serialize(value).unwrap();
+            assert_eq!(&bincode::deserialize::<T>(&bytes).unwrap(), value);
+        }
+
+        round_trip(&Tile::new(6, 35, 23).unwrap());
+        round_trip(&Metatile::new_with_size(4, 2, 6, 32, 16).unwrap());
+        round_trip(&TileMatrixSet::web_mercator_quad());
+        round_trip(&TileMatrixSet::world_crs84_quad());
+        round_trip(TileMatrixSet::world_crs84_quad().tile_matrix(3).unwrap());
+
+        #[cfg(feature="json")]
+        {
+            let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+            let json = r#"{"tilejson":"3.0.0","tiles":["/{z}/{x}/{y}.png"],"bounds":[-11.32,51.11,-4.97,55.7],"vector_layers":[{"id":"roads"}]}"#;
+            let tilejson: TileJson = json.parse().unwrap();
+            round_trip(&tilejson);
+            round_trip(&TileJson::new(vec!["/{z}/{x}/{y}.png".to_string()], bbox, 0, 10, LatLon::new(53.35, -6.26).unwrap()).unwrap());
+        }
+
+        let mut tiles = TileSet::new();
+        tiles.insert_range(&TileRange::new(6, 30, 35, 20, 23).unwrap());
+        tiles.insert(&Tile::new(2, 1, 1).unwrap());
+        round_trip(&tiles);
+        round_trip(&BundleId::for_tile(&Tile::new(10, 300, 700).unwrap()));
+        round_trip(&TileMetadata::new(10, Some(::std::time::UNIX_EPOCH)));
+        round_trip(&FsLayout::ModTileMeta);
+        round_trip(&PriorityKey::HotSpotDistance);
+
+        // A bad tile matrix set is still an error
+        let mut bytes = bincode::serialize(&TileMatrixSet::world_crs84_quad()).unwrap();
+        let len = bytes.len();
+        bytes[len - 4..].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(bincode::deserialize::<TileMatrixSet>(&bytes).is_err());
+    }
+
+    #[test]
+    fn serde_bbox_latlon() {
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        assert_eq!(serde_json::to_string(&bbox).unwrap(), "[-11.32,51.11,-4.97,55.7]");
+        assert_eq!(serde_json::from_str::<BBox>("[-11.32,51.11,-4.97,55.7]").unwrap(), bbox);
+        assert!(serde_json::from_str::<BBox>("[-11.32,51.11,-4.97,95.7]").is_err());
+        assert!(serde_json::from_str::<BBox>("[-11.32,51.11,-4.97]").is_err());
+
+        let p = LatLon::new(53.35, -6.26).unwrap();
+        assert_eq!(serde_json::to_string(&p).unwrap(), r#"{"lat":53.35,"lon":-6.26}"#);
+        assert_eq!(serde_json::from_str::<LatLon>(r#"{"lat":53.35,"lon":-6.26}"#).unwrap(), p);
+        assert!(serde_json::from_str::<LatLon>(r#"{"lat":93.35,"lon":-6.26}"#).is_err());
+    }
+
+    #[test]
+    fn serde_others() {
+        let tms = TileMatrixSet::world_crs84_quad();
+        assert_eq!(serde_json::from_str::<TileMatrixSet>(&serde_json::to_string(&tms).unwrap()).unwrap(), tms);
+        let t = tms.tile(0, 1, 0).unwrap();
+        assert_eq!(serde_json::to_string(&t).unwrap(), r#"{"zoom":0,"x":1,"y":0}"#);
+        assert_eq!(serde_json::from_str::<MatrixTile>(r#"{"zoom":0,"x":1,"y":0}"#).unwrap(), t);
+        let tm = tms.tile_matrix(3).unwrap();
+        assert_eq!(&serde_json::from_str::<TileMatrix>(&serde_json::to_string(tm).unwrap()).unwrap(), tm);
+
+        // The same as from_json
+        let json = r#"{"id": "x", "crs": {"uri": "http://www.opengis.net/def/crs/EPSG/0/4326"},
+            "tileMatrices": [{"id": 0, "scaleDenominator": 279541132.0143589, "pointOfOrigin": [90, -180],
+            "tileWidth": 256, "tileHeight": 256, "matrixWidth": 2, "matrixHeight": 1}]}"#;
+        let tms = serde_json::from_str::<TileMatrixSet>(json).unwrap();
+        #[cfg(feature="json")]
+        assert_eq!(Ok(tms.clone()), TileMatrixSet::from_json(json));
+        assert_eq!(tms.tile_matrix(0).unwrap().point_of_origin(), (-180., 90.));
+        assert_eq!(serde_json::from_str::<TileMatrixSet>(&serde_json::to_string(&tms).unwrap()).unwrap(), tms);
+        assert!(serde_json::from_str::<TileMatrixSet>(r#"{"id": "x", "crs": "foo", "tileMatrices": []}"#).is_err());
+        assert!(serde_json::from_str::<TileMatrixSet>(&json.replace(r#""id": 0,"#, r#""id": 0, "variableMatrixWidths": [],"#)).is_err());
+        assert!(serde_json::from_str::<TileMatrixSet>(&json.replace(r#""matrixWidth": 2"#, r#""matrixWidth": 0"#)).is_err());
+        assert!(serde_json::from_str::<TileMatrix>(r#"{"id": "0", "scaleDenominator": 1, "pointOfOrigin": [0, 0], "tileWidth": 256, "tileHeight": 256, "matrixWidth": 2, "matrixHeight": 1}"#).is_err());
+
+        let template = UrlTemplate::new("/{z}/{x}/{y}.png").unwrap();
+        assert_eq!(serde_json::to_string(&template).unwrap(), r#""/{z}/{x}/{y}.png""#);
+        assert!(serde_json::from_str::<UrlTemplate>(r#""/{z}/{x}.png""#).is_err());
+
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        #[cfg(feature="json")]
+        {
+            let tilejson = TileJson::new(vec!["/{z}/{x}/{y}.png".to_string()], bbox.clone(), 0, 10, LatLon::new(53.35, -6.26).unwrap()).unwrap();
+            assert_eq!(serde_json::from_str::<TileJson>(&serde_json::to_string(&tilejson).unwrap()).unwrap(), tilejson);
+        }
+
+        let layer = WmtsLayer::new("ie", "Ireland", vec!["image/png".to_string()], bbox, 0, 10, "/{z}/{x}/{y}.png").unwrap();
+        assert_eq!(serde_json::from_str::<WmtsLayer>(&serde_json::to_string(&layer).unwrap()).unwrap(), layer);
+        assert!(serde_json::from_str::<WmtsLayer>(r#"{"identifier":"ie","title":"Ireland","formats":[],"bbox":[-11.32,51.11,-4.97,55.7],"minzoom":0,"maxzoom":10,"url_template":"/{z}/{x}/{y}.png"}"#).is_err());
+    }
+
+    #[test]
+    fn serde_enums() {
+        assert_eq!(serde_json::to_string(&ShardStrategy::ZorderRange).unwrap(), r#""zorder_range""#);
+        assert_eq!(serde_json::to_string(&ShardStrategy::Ancestor(6)).unwrap(), r#"{"ancestor":6}"#);
+        assert_eq!(serde_json::from_str::<ShardStrategy>(r#""hash""#).unwrap(), ShardStrategy::Hash);
+        assert!(serde_json::from_str::<ShardStrategy>(r#""random""#).is_err());
+
+        assert_eq!(serde_json::to_string(&TileListFormat::SpaceSeparated).unwrap(), r#""space_separated""#);
+        assert_eq!(serde_json::from_str::<TileListFormat>(r#""render_list""#).unwrap(), TileListFormat::RenderList);
+
+        assert_eq!(serde_json::to_string(&Direction::NorthEast).unwrap(), r#""north_east""#);
+        assert_eq!(serde_json::from_str::<Direction>(r#""south_west""#).unwrap(), Direction::SouthWest);
+    }
+
+    #[test]
+    fn serde_tile_range() {
+        let r = TileRange::new(6, 30, 35, 20, 23).unwrap();
+        let json = serde_json::to_string(&r).unwrap();
+        assert_eq!(json, r#"{"zoom":6,"min_x":30,"max_x":35,"min_y":20,"max_y":23}"#);
+        assert_eq!(serde_json::from_str::<TileRange>(&json).unwrap(), r);
+        assert!(serde_json::from_str::<TileRange>(r#"{"zoom":6,"min_x":35,"max_x":30,"min_y":20,"max_y":23}"#).is_err());
+        assert!(serde_json::from_str::<TileRange>(r#"{"zoom":2,"min_x":0,"max_x":4,"min_y":0,"max_y":3}"#).is_err());
+    }
+
+    #[test]
+    fn serde_polygon() {
+        let p = Polygon::new(vec![LatLon::new(0., 0.).unwrap(), LatLon::new(1., 0.).unwrap(), LatLon::new(0., 1.).unwrap()], vec![]).unwrap();
+        let json = serde_json::to_string(&p).unwrap();
+        assert_eq!(json, r#"{"exterior":[{"lat":0.0,"lon":0.0},{"lat":1.0,"lon":0.0},{"lat":0.0,"lon":1.0}],"holes":[]}"#);
+        assert_eq!(serde_json::from_str::<Polygon>(&json).unwrap(), p);
+        assert_eq!(serde_json::from_str::<Polygon>(r#"{"exterior":[{"lat":0,"lon":0},{"lat":1,"lon":0},{"lat":0,"lon":1}]}"#).unwrap(), p);
+        assert!(serde_json::from_str::<Polygon>(r#"{"exterior":[{"lat":0,"lon":0},{"lat":1,"lon":0}]}"#).is_err());
+    }
+
+    #[test]
+    fn serde_reports() {
+        let mut tiles = TileSet::new();
+        tiles.insert_range(&TileRange::new(6, 30, 31, 20, 23).unwrap());
+        assert_eq!(serde_json::to_string(&tiles).unwrap(), "[[6,30,20,23],[6,31,20,23]]");
+        assert_eq!(serde_json::from_str::<TileSet>("[[6,30,20,21],[6,30,22,23],[6,31,20,23]]").unwrap(), tiles);
+        assert!(serde_json::from_str::<TileSet>("[[2,30,20,21]]").is_err());
+
+        assert_eq!(serde_json::to_string(&FsLayout::ModTileMeta).unwrap(), r#""meta""#);
+        assert_eq!(serde_json::from_str::<PriorityKey>(r#""hot_spot_distance""#).unwrap(), PriorityKey::HotSpotDistance);
+
+        let bundle = BundleId::for_tile(&Tile::new(10, 300, 700).unwrap());
+        assert_eq!(serde_json::to_string(&bundle).unwrap(), r#"{"zoom":10,"row":640,"col":256}"#);
+        assert_eq!(serde_json::from_str::<BundleId>(r#"{"zoom":10,"row":640,"col":256}"#).unwrap(), bundle);
+        assert!(serde_json::from_str::<BundleId>(r#"{"zoom":10,"row":641,"col":256}"#).is_err());
+
+        let mut hits = TileHits::new();
+        hits.add_line(r#"1.2.3.4 - - [10/Oct/2020:13:55:36 +0000] "GET /6/35/23.png HTTP/1.1" 200 100 "-" "-""#);
+        hits.add_line(r#"1.2.3.4 - - [10/Oct/2020:13:55:36 +0000] "GET /2/1/1.png HTTP/1.1" 200 100 "-" "-""#);
+        hits.add_line("nonsense");
+        let json = serde_json::to_string(&hits).unwrap();
+        assert_eq!(json, r#"{"templates":[],"successful_only":false,"hits":[[{"zoom":2,"x":1,"y":1},1],[{"zoom":6,"x":35,"y":23},1]],"lines":3,"unparsable":1,"other_requests":0}"#);
+        assert_eq!(serde_json::from_str::<TileHits>(&json).unwrap(), hits);
+
+        let estimate = Estimate{ zooms: vec![
+            ZoomEstimate{ zoom: 0, tiles: 1, metatiles: 1, land_tiles: 1, bytes: 1000, render_hours: 0.5 },
+            ZoomEstimate{ zoom: 1, tiles: 4, metatiles: 1, land_tiles: 2, bytes: 3000, render_hours: 0.5 },
+        ]};
+        assert_eq!(serde_json::from_str::<Estimate>(&serde_json::to_string(&estimate).unwrap()).unwrap(), estimate);
+        assert!(serde_json::from_str::<Estimate>(r#"{"zooms":[{"zoom":0,"tiles":1,"metatiles":1,"land_tiles":2,"bytes":0,"render_hours":0}]}"#).is_err());
+
+        let mut sample = Sample::default();
+        sample.zooms.insert(5, SampleZoom{ tiles: 10, land_tiles: 4, land_bytes: 4000, sea_tile_size: 100 });
+        let json = serde_json::to_string(&sample).unwrap();
+        assert_eq!(json, r#"{"5":{"tiles":10,"land_tiles":4,"land_bytes":4000,"sea_tile_size":100}}"#);
+        assert_eq!(serde_json::from_str::<Sample>(&json).unwrap(), sample);
+
+        let report = MigrationReport{ copied: 3, duplicates: 1, skipped: 0, bytes: 100, unparsable: vec!["README".to_string()] };
+        assert_eq!(serde_json::from_str::<MigrationReport>(&serde_json::to_string(&report).unwrap()).unwrap(), report);
+        assert!(serde_json::from_str::<MigrationReport>(r#"{"copied":1,"duplicates":2,"skipped":0,"bytes":0,"unparsable":[]}"#).is_err());
+    }
+
+    #[test]
+    fn serde_metatile_policy() {
+        let p = MetatilePolicy::per_zoom(&[(0, 8, 8), (12, 8, 4)]).unwrap();
+        let json = serde_json::to_string(&p).unwrap();
+        assert_eq!(json, "[[0,8,8],[12,8,4]]");
+        assert_eq!(serde_json::from_str::<MetatilePolicy>(&json).unwrap(), p);
+        assert!(serde_json::from_str::<MetatilePolicy>("[[2,8,8]]").is_err());
+        assert!(serde_json::from_str::<MetatilePolicy>("[[0,8,3]]").is_err());
+        assert!(serde_json::from_str::<MetatilePolicy>("[]").is_err());
+    }
+}
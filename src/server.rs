@@ -0,0 +1,274 @@
+//! Serving the tiles in a `TileStore` over HTTP, e.g. for local development instead of a real
+//! web server. Needs the `server` feature.
+//!
+//! ```no_run
+//! # use slippy_map_tiles::{FsTileStore, FsLayout, UrlTemplate};
+//! # use slippy_map_tiles::server::TileServer;
+//! let store = FsTileStore::new("/var/cache/tiles", FsLayout::Zxy, "png");
+//! let mut server = TileServer::new(Box::new(store), "png");
+//! server.add_template(UrlTemplate::new("/tms/{z}/{x}/{-y}.png").unwrap());
+//! server.serve(std::net::TcpListener::bind("127.0.0.1:8080").unwrap()).unwrap();
+//! ```
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::TcpListener;
+
+use tiny_http;
+
+use {BBox, LatLon, TileJson, UrlTemplate};
+use store::TileStore;
+
+/// The path of the TileJSON document
+const TILEJSON_PATH: &str = "/tilejson.json";
+
+/// A response to an HTTP request
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: Option<&str>, body: Vec<u8>) -> Response {
+        let mut headers = vec![("Access-Control-Allow-Origin", "*".to_string())];
+        if let Some(content_type) = content_type {
+            headers.push(("Content-Type", content_type.to_string()));
+        }
+        Response{ status, headers, body }
+    }
+
+    fn text(status: u16, body: &str) -> Response {
+        Response::new(status, Some("text/plain; charset=utf-8"), format!("{}\n", body).into_bytes())
+    }
+
+    /// The HTTP status code
+    pub fn status(&self) -> u16 { self.status }
+
+    /// The value of this header (the name is case insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|h| h.0.eq_ignore_ascii_case(name)).map(|h| h.1.as_str())
+    }
+
+    /// All the headers
+    pub fn headers(&self) -> &[(&'static str, String)] { &self.headers }
+
+    /// The body
+    pub fn body(&self) -> &[u8] { &self.body }
+}
+
+/// The content type of tiles with this extension
+fn content_type(ext: &str) -> &'static str {
+    match ext.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "pbf" | "mvt" => "application/vnd.mapbox-vector-tile",
+        "json" | "geojson" => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Serves the tiles in a `TileStore`.
+///
+/// Tiles are found at the URL templates' paths, by default `/{z}/{x}/{y}.EXT`. Missing tiles are
+/// 404, and empty (0 byte) tiles 204. Tiles have an `ETag`, for conditional requests, and a
+/// `Cache-Control` max age. A TileJSON document is at `/tilejson.json`.
+pub struct TileServer {
+    store: Box<dyn TileStore>,
+    ext: String,
+    templates: Vec<UrlTemplate>,
+    max_age: u32,
+    tilejson: Option<TileJson>,
+}
+
+impl fmt::Debug for TileServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TileServer").field("ext", &self.ext).field("templates", &self.templates).field("max_age", &self.max_age).field("tilejson", &self.tilejson).finish()
+    }
+}
+
+impl TileServer {
+    /// Serve the tiles in `store`, which have this file extension (e.g. `png`), at
+    /// `/{z}/{x}/{y}.EXT`, with a max age of 1 hour.
+    pub fn new(store: Box<dyn TileStore>, ext: &str) -> TileServer {
+        let ext = ext.replace(['{', '}'], "");
+        let template = UrlTemplate::new(&format!("/{{z}}/{{x}}/{{y}}.{}", ext)).unwrap();
+        TileServer{ store, ext, templates: vec![template], max_age: 3600, tilejson: None }
+    }
+
+    /// Also serve tiles at this template's path, e.g. `/tms/{z}/{x}/{-y}.png` or
+    /// `/{quadkey}.png`.
+    pub fn add_template(&mut self, template: UrlTemplate) {
+        self.templates.push(template);
+    }
+
+    /// How long clients can cache tiles for, in seconds
+    pub fn set_max_age(&mut self, max_age: u32) {
+        self.max_age = max_age;
+    }
+
+    /// Serve this TileJSON. Without one, a TileJSON for the whole world, zooms 0 to 22, with
+    /// the `/{z}/{x}/{y}.EXT` URL on the requested host, is served.
+    pub fn set_tilejson(&mut self, tilejson: Option<TileJson>) {
+        self.tilejson = tilejson;
+    }
+
+    /// The response to a request for this URL (the path, and maybe a query string), with
+    /// these headers.
+    pub fn handle(&self, method: &str, url: &str, headers: &[(&str, &str)]) -> Response {
+        let header = |name: &str| headers.iter().find(|h| h.0.eq_ignore_ascii_case(name)).map(|h| h.1);
+        if method != "GET" && method != "HEAD" {
+            let mut response = Response::text(405, "method not allowed");
+            response.headers.push(("Allow", "GET, HEAD".to_string()));
+            return response;
+        }
+
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        if path == TILEJSON_PATH {
+            let tilejson = self.tilejson.clone().unwrap_or_else(|| {
+                let url = format!("http://{}{}", header("Host").unwrap_or("localhost"), self.templates[0].as_str());
+                let world = BBox::new(85.05112, -180., -85.05112, 180.).unwrap();
+                TileJson::new(vec![url], world, 0, 22, LatLon::new(0., 0.).unwrap()).unwrap()
+            });
+            return Response::new(200, Some("application/json"), tilejson.to_json().into_bytes());
+        }
+
+        let tile = match self.templates.iter().filter_map(|t| t.parse(url)).next() {
+            None => return Response::text(404, "not found"),
+            Some(t) => t,
+        };
+        let data = match self.store.get(&tile) {
+            Err(e) => return Response::text(500, &e.to_string()),
+            Ok(None) => return Response::text(404, "no such tile"),
+            Ok(Some(d)) => d,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let etag = format!("\"{:016x}\"", hasher.finish());
+        let not_modified = header("If-None-Match").is_some_and(|v| v.split(',').any(|e| e.trim() == etag || e.trim() == "*"));
+
+        let mut response = if not_modified {
+            Response::new(304, None, Vec::new())
+        } else if data.is_empty() {
+            Response::new(204, None, Vec::new())
+        } else {
+            let gzipped = data.starts_with(&[0x1f, 0x8b]);
+            let mut response = Response::new(200, Some(content_type(&self.ext)), data);
+            if gzipped && content_type(&self.ext) == "application/vnd.mapbox-vector-tile" {
+                response.headers.push(("Content-Encoding", "gzip".to_string()));
+            }
+            response
+        };
+        response.headers.push(("ETag", etag));
+        response.headers.push(("Cache-Control", format!("public, max-age={}", self.max_age)));
+        response
+    }
+
+    /// Answer requests on this listener, one at a time, forever (or until there's an error
+    /// accepting connections).
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        let server = tiny_http::Server::from_listener(listener, None).map_err(|e| io::Error::other(e.to_string()))?;
+        loop {
+            let request = server.recv()?;
+            let headers: Vec<(String, String)> = request.headers().iter().map(|h| (h.field.as_str().to_string(), h.value.to_string())).collect();
+            let headers: Vec<(&str, &str)> = headers.iter().map(|h| (h.0.as_str(), h.1.as_str())).collect();
+            let response = self.handle(request.method().as_str(), request.url(), &headers);
+
+            let mut http_response = tiny_http::Response::from_data(response.body).with_status_code(response.status);
+            for (name, value) in response.headers {
+                http_response.add_header(tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap());
+            }
+            // The client has gone, which isn't our problem
+            let _ = request.respond(http_response);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use {Tile, FsTileStore, FsLayout};
+    use tempfile::tempdir;
+
+    #[test]
+    fn handle() {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path(), FsLayout::Zxy, "pbf");
+        store.put(&Tile::new(3, 1, 2).unwrap(), &[0x1f, 0x8b, 8, 0]).unwrap();
+        store.put(&Tile::new(3, 1, 3).unwrap(), b"").unwrap();
+        let mut server = TileServer::new(Box::new(store), "pbf");
+        server.add_template(UrlTemplate::new("/q/{quadkey}.pbf").unwrap());
+        server.set_max_age(60);
+
+        let response = server.handle("GET", "/3/1/2.pbf?v=1", &[]);
+        assert_eq!((response.status(), response.body()), (200, &[0x1f, 0x8b, 8, 0][..]));
+        assert_eq!(response.header("content-type"), Some("application/vnd.mapbox-vector-tile"));
+        assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+        assert_eq!(response.header("Cache-Control"), Some("public, max-age=60"));
+        let etag = response.header("ETag").unwrap().to_string();
+        assert_eq!(server.handle("HEAD", "/q/021.pbf", &[]).header("ETag"), Some(etag.as_str()));
+        let response = server.handle("GET", "/3/1/2.pbf", &[("if-none-match", &format!("\"abc\", {}", etag))]);
+        assert_eq!((response.status(), response.body().len()), (304, 0));
+
+        assert_eq!(server.handle("GET", "/3/1/3.pbf", &[]).status(), 204);
+        assert_eq!(server.handle("GET", "/3/1/4.pbf", &[]).status(), 404);
+        assert_eq!(server.handle("GET", "/3/1/2.png", &[]).status(), 404);
+        assert_eq!(server.handle("DELETE", "/3/1/2.pbf", &[]).header("Allow"), Some("GET, HEAD"));
+
+        let response = server.handle("GET", "/tilejson.json", &[("Host", "127.0.0.1:8080")]);
+        let tilejson: TileJson = ::std::str::from_utf8(response.body()).unwrap().parse().unwrap();
+        assert_eq!(tilejson.tiles(), &["http://127.0.0.1:8080/{z}/{x}/{y}.pbf".to_string()]);
+    }
+
+    /// Send a raw HTTP/1.0 request, returning the response's head and body
+    fn request(addr: &::std::net::SocketAddr, request: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let i = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        (String::from_utf8(response[..i].to_vec()).unwrap(), response[i+4..].to_vec())
+    }
+
+    #[test]
+    fn serve() {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path(), FsLayout::Zxy, "png");
+        store.put(&Tile::new(2, 1, 1).unwrap(), b"\x89PNG").unwrap();
+        store.put(&Tile::new(2, 1, 2).unwrap(), b"").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let store_dir = dir.path().to_path_buf();
+        thread::spawn(move || {
+            let mut server = TileServer::new(Box::new(FsTileStore::new(&store_dir, FsLayout::Zxy, "png")), "png");
+            server.add_template(UrlTemplate::new("/tms/{z}/{x}/{-y}.png").unwrap());
+            server.serve(listener)
+        });
+
+        let (head, body) = request(&addr, "GET /2/1/1.png HTTP/1.0\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 200") || head.starts_with("HTTP/1.0 200"), "{}", head);
+        assert!(head.contains("Content-Type: image/png"), "{}", head);
+        assert!(head.contains("Cache-Control: public, max-age=3600"), "{}", head);
+        assert_eq!(body, b"\x89PNG");
+        let etag = head.lines().find(|l| l.starts_with("ETag: ")).unwrap()[6..].to_string();
+
+        let (head, body) = request(&addr, &format!("GET /tms/2/1/2.png HTTP/1.0\r\nIf-None-Match: {}\r\n\r\n", etag));
+        assert!(head.contains(" 304 "), "{}", head);
+        assert!(body.is_empty());
+        assert!(request(&addr, "GET /2/1/2.png HTTP/1.0\r\n\r\n").0.contains(" 204 "));
+        assert!(request(&addr, "GET /2/3/3.png HTTP/1.0\r\n\r\n").0.contains(" 404 "));
+
+        let (_, body) = request(&addr, &format!("GET /tilejson.json HTTP/1.0\r\nHost: {}\r\n\r\n", addr));
+        let tilejson: TileJson = String::from_utf8(body).unwrap().parse().unwrap();
+        assert_eq!(tilejson.tiles(), &[format!("http://{}/{{z}}/{{x}}/{{y}}.png", addr)]);
+    }
+}
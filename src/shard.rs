@@ -0,0 +1,315 @@
+//! Splitting tiles & metatiles into disjoint shards, so that many workers can each process their
+//! own share of an iterator.
+//!
+//! Every worker makes the same `Sharder`, then worker `k` (of `N`) only looks at shard `k`. Which
+//! shard a tile is in only depends on the tile, so any iterator (bbox, all, file list, ...) can
+//! be sharded, and the shards are the same on every machine.
+//!
+//! ```
+//! use slippy_map_tiles::{BBox, MetatilesIterator, Sharder, ShardStrategy, ShardExt};
+//!
+//! let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+//! let sharder = Sharder::new(ShardStrategy::Hash, 4).unwrap();
+//! for metatile in MetatilesIterator::new_for_bbox_zoom(8, &Some(bbox), 0, 10).shard(sharder, 2) {
+//!     // render it
+//! }
+//! ```
+use {Tile, Metatile, ModTileMetatile, BBox, TileRange, xy_to_zorder};
+
+/// How to decide which shard a tile is in
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum ShardStrategy {
+    /// Each zoom's z-order curve is cut into equal, contiguous, ranges. Tiles which are near each
+    /// other are usually in the same shard. This is balanced for the whole world, but not for a
+    /// bbox, unless the `Sharder` is made with `Sharder::balanced_for_bbox`.
+    ZorderRange,
+
+    /// A hash of the zoom/x/y. Balanced for any set of tiles, but neighbouring tiles are in
+    /// different shards.
+    Hash,
+
+    /// All the tiles inside the same tile at this zoom are in the same shard, which is chosen by
+    /// the hash of that tile. Tiles at, or above, this zoom are hashed as themselves.
+    Ancestor(u8),
+}
+
+/// Things which can be sharded. `zoom`, `x` & `y` are in tiles.
+pub trait Shardable {
+    /// The zoom, x & y of this
+    fn shard_key(&self) -> (u8, u32, u32);
+
+    /// How many tiles wide (or high, if that's smaller) this is
+    fn shard_scale(&self) -> u8 { 1 }
+}
+
+impl Shardable for Tile {
+    fn shard_key(&self) -> (u8, u32, u32) { (self.zoom, self.x, self.y) }
+}
+
+impl Shardable for Metatile {
+    fn shard_key(&self) -> (u8, u32, u32) { (self.zoom, self.x, self.y) }
+    fn shard_scale(&self) -> u8 { self.width.min(self.height) }
+}
+
+impl Shardable for ModTileMetatile {
+    fn shard_key(&self) -> (u8, u32, u32) { self.inner.shard_key() }
+    fn shard_scale(&self) -> u8 { self.inner.shard_scale() }
+}
+
+/// For a bbox, the z-order split points & shard sizes for each zoom
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct BBoxSplits {
+    scale: u8,
+    minzoom: u8,
+    // `splits[zoom - minzoom]` are the z-orders where shards 1, 2, ... start
+    splits: Vec<Vec<u128>>,
+    sizes: Vec<usize>,
+}
+
+/// Decides which shard things are in.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Sharder {
+    strategy: ShardStrategy,
+    count: u32,
+    bbox_splits: Option<BBoxSplits>,
+}
+
+impl Sharder {
+    /// A sharder for `count` shards. `None` if `count` is 0.
+    pub fn new(strategy: ShardStrategy, count: u32) -> Option<Sharder> {
+        if count == 0 {
+            return None;
+        }
+        Some(Sharder{ strategy, count, bbox_splits: None })
+    }
+
+    /// A `ZorderRange` sharder where, for every zoom from `minzoom` to `maxzoom`, each shard has
+    /// the same number (±1) of this bbox's metatiles of this scale (use a scale of 1 for tiles).
+    /// The sizes of each shard are known up front, with `sizes()`. Tiles outside these zooms are
+    /// split as for the whole world.
+    ///
+    /// `None` if `count` is 0, the scale isn't a power of 2, there are too many metatiles, or the
+    /// bbox has no tiles (it crosses the antimeridian, like `TileRange::new_for_bbox`).
+    pub fn balanced_for_bbox(bbox: &BBox, minzoom: u8, maxzoom: u8, scale: u8, count: u32) -> Option<Sharder> {
+        if count == 0 || !scale.is_power_of_two() || minzoom > maxzoom || maxzoom > 31 {
+            return None;
+        }
+        let scale_bits = scale.trailing_zeros() as u8;
+        let mut splits = Vec::with_capacity((maxzoom - minzoom) as usize + 1);
+        let mut sizes = vec![0usize; count as usize];
+
+        for zoom in minzoom..=maxzoom {
+            let range = TileRange::new_for_bbox_metatiles(bbox, zoom, (scale, scale))?;
+            let total = range.num_tiles() as u128;
+            let bits = zoom.saturating_sub(scale_bits);
+            let rect = (range.min_x(), range.min_y(), range.max_x(), range.max_y());
+
+            // Each shard starts at the first z-order which has this many metatiles before it
+            let targets: Vec<u128> = (0..=count as u128).map(|k| total * k / count as u128).collect();
+            let zoom_splits: Vec<u128> = targets[1..count as usize].iter()
+                .map(|&target| first_zorder_with_count(bits, rect, target))
+                .collect();
+
+            for (size, t) in sizes.iter_mut().zip(targets.windows(2)) {
+                *size = size.checked_add((t[1] - t[0]) as usize)?;
+            }
+            splits.push(zoom_splits);
+        }
+
+        Some(Sharder{ strategy: ShardStrategy::ZorderRange, count, bbox_splits: Some(BBoxSplits{ scale, minzoom, splits, sizes }) })
+    }
+
+    /// How this shards
+    pub fn strategy(&self) -> ShardStrategy { self.strategy }
+
+    /// How many shards there are
+    pub fn count(&self) -> u32 { self.count }
+
+    /// For a `balanced_for_bbox` sharder, how many metatiles of the bbox are in each shard.
+    pub fn sizes(&self) -> Option<&[usize]> {
+        self.bbox_splits.as_ref().map(|s| s.sizes.as_slice())
+    }
+
+    /// Which shard (from `0` to `count()-1`) this is in
+    pub fn shard_of<T: Shardable>(&self, item: &T) -> u32 {
+        let (zoom, x, y) = item.shard_key();
+        match self.strategy {
+            ShardStrategy::Hash => (hash_tile(zoom, x, y) % self.count as u64) as u32,
+            ShardStrategy::Ancestor(ancestor_zoom) => {
+                let (zoom, x, y) = if zoom > ancestor_zoom {
+                    let shift = zoom - ancestor_zoom;
+                    (ancestor_zoom, x >> shift, y >> shift)
+                } else {
+                    (zoom, x, y)
+                };
+                (hash_tile(zoom, x, y) % self.count as u64) as u32
+            },
+            ShardStrategy::ZorderRange => {
+                if let Some(ref b) = self.bbox_splits {
+                    if zoom >= b.minzoom && ((zoom - b.minzoom) as usize) < b.splits.len() {
+                        let scale = b.scale as u32;
+                        let zorder = xy_to_zorder(x / scale, y / scale) as u128;
+                        return b.splits[(zoom - b.minzoom) as usize].iter().take_while(|&&s| s <= zorder).count() as u32;
+                    }
+                }
+                let scale = item.shard_scale().max(1);
+                let bits = zoom.saturating_sub(scale.trailing_zeros() as u8);
+                let zorder = xy_to_zorder(x / scale as u32, y / scale as u32) as u128;
+                ((zorder * self.count as u128) >> (2 * bits as u32)).min(self.count as u128 - 1) as u32
+            },
+        }
+    }
+
+    /// Is this in shard `index`?
+    pub fn contains<T: Shardable>(&self, index: u32, item: &T) -> bool {
+        self.shard_of(item) == index
+    }
+}
+
+/// Only returns the items, from another iterator, which are in one shard
+pub struct ShardIterator<I> {
+    inner: I,
+    sharder: Sharder,
+    index: u32,
+}
+
+impl<I> Iterator for ShardIterator<I> where I: Iterator, I::Item: Shardable {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let (sharder, index) = (&self.sharder, self.index);
+        self.inner.by_ref().find(|item| sharder.contains(index, item))
+    }
+}
+
+/// Adds `.shard(...)` to iterators of tiles & metatiles
+pub trait ShardExt: Iterator + Sized {
+    /// Only iterate over the items in shard `index` of this sharder.
+    ///
+    /// Panics if `index` isn't less than the number of shards.
+    fn shard(self, sharder: Sharder, index: u32) -> ShardIterator<Self> {
+        assert!(index < sharder.count(), "shard index must be less than the number of shards");
+        ShardIterator{ inner: self, sharder, index }
+    }
+}
+
+impl<I> ShardExt for I where I: Iterator, I::Item: Shardable {}
+
+/// SplitMix64 finaliser. Stable across versions & platforms, so shards are too.
+fn splitmix64(z: u64) -> u64 {
+    let z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub(crate) fn hash_tile(zoom: u8, x: u32, y: u32) -> u64 {
+    splitmix64(splitmix64(zoom as u64) ^ xy_to_zorder(x, y))
+}
+
+/// How many cells of `rect` (x1, y1, x2, y2, inclusive) have a z-order less than `limit`, in the
+/// block of `2^bits` cells at `(bx, by)`, whose first z-order is `base`.
+fn count_below(bx: u64, by: u64, bits: u8, base: u128, rect: (u32, u32, u32, u32), limit: u128) -> u128 {
+    let size = 1u64 << bits;
+    let (x1, y1, x2, y2) = (rect.0 as u64, rect.1 as u64, rect.2 as u64, rect.3 as u64);
+    let (ix1, iy1, ix2, iy2) = (bx.max(x1), by.max(y1), (bx + size - 1).min(x2), (by + size - 1).min(y2));
+    if base >= limit || ix1 > ix2 || iy1 > iy2 {
+        return 0;
+    }
+    if base + (1u128 << (2 * bits as u32)) <= limit {
+        return ((ix2 - ix1 + 1) as u128) * ((iy2 - iy1 + 1) as u128);
+    }
+
+    let half = size >> 1;
+    let quarter = 1u128 << (2 * (bits - 1) as u32);
+    (0..4u64).map(|c| {
+        count_below(bx + (c & 1) * half, by + (c >> 1) * half, bits - 1, base + c as u128 * quarter, rect, limit)
+    }).sum()
+}
+
+/// The smallest z-order which has `target` cells of `rect` before it
+fn first_zorder_with_count(bits: u8, rect: (u32, u32, u32, u32), target: u128) -> u128 {
+    let (mut lo, mut hi) = (0u128, 1u128 << (2 * bits as u32));
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if count_below(0, 0, bits, 0, rect, mid) >= target {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    lo
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {MetatilesIterator, size_bbox_zoom_metatiles};
+
+    fn ie_bbox() -> BBox {
+        BBox::new(55.7, -11.32, 51.11, -4.97).unwrap()
+    }
+
+    #[test]
+    fn shards_are_disjoint() {
+        let all: Vec<Metatile> = MetatilesIterator::new_for_bbox_zoom(8, &Some(ie_bbox()), 0, 12).collect();
+        for strategy in &[ShardStrategy::ZorderRange, ShardStrategy::Hash, ShardStrategy::Ancestor(6)] {
+            let sharder = Sharder::new(*strategy, 3).unwrap();
+            let mut seen: Vec<Metatile> = (0..3)
+                .flat_map(|k| MetatilesIterator::new_for_bbox_zoom(8, &Some(ie_bbox()), 0, 12).shard(sharder.clone(), k))
+                .collect();
+            assert_eq!(seen.len(), all.len());
+            seen.sort_by_key(|mt| (mt.zoom(), mt.x(), mt.y()));
+            seen.dedup();
+            assert_eq!(seen.len(), all.len());
+        }
+
+        assert!(Sharder::new(ShardStrategy::Hash, 0).is_none());
+    }
+
+    #[test]
+    fn shard_strategies() {
+        let sharder = Sharder::new(ShardStrategy::ZorderRange, 4).unwrap();
+        assert_eq!(sharder.shard_of(&Tile::new(0, 0, 0).unwrap()), 0);
+        assert_eq!(sharder.shard_of(&Tile::new(1, 1, 0).unwrap()), 1);
+        assert_eq!(sharder.shard_of(&Tile::new(1, 1, 1).unwrap()), 3);
+        assert_eq!(sharder.shard_of(&Tile::new(5, 31, 31).unwrap()), 3);
+        assert_eq!(sharder.shard_of(&Metatile::new(8, 5, 24, 24).unwrap()), 3);
+        assert_eq!(Tile::all_to_zoom(4).shard(sharder, 2).count(), (341 - 1) / 4);
+
+        // Everything inside 4/8/5 is in the same shard as it
+        let sharder = Sharder::new(ShardStrategy::Ancestor(4), 10).unwrap();
+        let parent = Tile::new(4, 8, 5).unwrap();
+        let shard = sharder.shard_of(&parent);
+        assert!(parent.all_subtiles_iter().take(1000).all(|t| sharder.shard_of(&t) == shard));
+
+        // Hashes must never change
+        let sharder = Sharder::new(ShardStrategy::Hash, 1000).unwrap();
+        assert_eq!(hash_tile(0, 0, 0), splitmix64(splitmix64(0)));
+        assert_eq!(sharder.shard_of(&Tile::new(10, 547, 380).unwrap()), (hash_tile(10, 547, 380) % 1000) as u32);
+        assert_eq!(splitmix64(0), 0xE220_A839_7B1D_CDAF);
+    }
+
+    #[test]
+    fn balanced_for_bbox() {
+        let sharder = Sharder::balanced_for_bbox(&ie_bbox(), 0, 12, 8, 5).unwrap();
+        let sizes = sharder.sizes().unwrap().to_vec();
+        let total: usize = (0..=12).map(|z| size_bbox_zoom_metatiles(&ie_bbox(), z, 8).unwrap()).sum();
+        assert_eq!(sizes.iter().sum::<usize>(), total);
+        assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 13);
+
+        for (k, size) in sizes.iter().enumerate() {
+            let actual = MetatilesIterator::new_for_bbox_zoom(8, &Some(ie_bbox()), 0, 12).shard(sharder.clone(), k as u32).count();
+            assert_eq!(actual, *size);
+        }
+
+        let rect = (3, 2, 6, 5);
+        assert_eq!(count_below(0, 0, 3, 0, rect, 64), 16);
+        assert_eq!(count_below(0, 0, 3, 0, rect, xy_to_zorder(4, 4) as u128), 10);
+
+        assert!(Sharder::balanced_for_bbox(&ie_bbox(), 0, 12, 3, 5).is_none());
+        assert!(Sharder::balanced_for_bbox(&BBox::new(10., 170., 0., -170.).unwrap(), 0, 12, 8, 5).is_none());
+        assert!(Sharder::new(ShardStrategy::Hash, 0).is_none());
+    }
+}
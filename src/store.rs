@@ -0,0 +1,682 @@
+//! Reading & writing tiles in a tile cache, through one interface.
+//!
+//! ```
+//! # use slippy_map_tiles::{Tile, TileRange};
+//! # use slippy_map_tiles::store::{TileStore, FsTileStore, FsLayout};
+//! # extern crate tempfile;
+//! # let tmp = tempfile::tempdir().unwrap();
+//! # let dir = tmp.path();
+//! let store = FsTileStore::new(&dir, FsLayout::Zxy, "png");
+//! let tile = Tile::new(3, 2, 5).unwrap();
+//! store.put(&tile, b"png data").unwrap();
+//! assert_eq!(store.get(&tile).unwrap(), Some(b"png data".to_vec()));
+//! assert_eq!(store.list(&TileRange::all(3).unwrap()).unwrap(), vec![tile]);
+//! ```
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use {Tile, TileRange, Metatile};
+
+/// Size & modification time of one stored tile
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct TileMetadata {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl TileMetadata {
+    pub fn new(size: u64, modified: Option<SystemTime>) -> TileMetadata {
+        TileMetadata{ size, modified }
+    }
+
+    /// Size of the tile, in bytes
+    pub fn size(&self) -> u64 { self.size }
+
+    /// When the tile was last written, if the store knows
+    pub fn modified(&self) -> Option<SystemTime> { self.modified }
+}
+
+/// Somewhere tiles are stored
+pub trait TileStore {
+    /// The data for this tile, or `None` if it isn't stored.
+    fn get(&self, tile: &Tile) -> io::Result<Option<Vec<u8>>>;
+
+    /// Store this tile, replacing it if it's already stored.
+    fn put(&self, tile: &Tile, data: &[u8]) -> io::Result<()>;
+
+    /// Remove this tile. Returns false if it wasn't stored.
+    fn delete(&self, tile: &Tile) -> io::Result<bool>;
+
+    /// Metadata for this tile, or `None` if it isn't stored.
+    fn metadata(&self, tile: &Tile) -> io::Result<Option<TileMetadata>>;
+
+    /// True iff this tile is stored
+    fn exists(&self, tile: &Tile) -> io::Result<bool> {
+        Ok(self.metadata(tile)?.is_some())
+    }
+
+    /// All the stored tiles in this range, in the same order as `TileRange::iter`.
+    fn list(&self, range: &TileRange) -> io::Result<Vec<Tile>>;
+
+    /// Call `f` with everything stored at these zooms, in no particular order. Things which
+    /// aren't tiles (e.g. files with other names) are included as `ScanEntry::Unparsable`.
+    fn scan(&self, zooms: RangeInclusive<u8>, f: &mut dyn FnMut(ScanEntry) -> io::Result<()>) -> io::Result<()>;
+
+    /// `scan`, with the metadata of each tile (`None` for `ScanEntry::Unparsable`). By default
+    /// this calls `metadata` for each tile.
+    fn scan_metadata(&self, zooms: RangeInclusive<u8>, f: &mut dyn FnMut(ScanEntry, Option<TileMetadata>) -> io::Result<()>) -> io::Result<()> {
+        self.scan(zooms, &mut |entry| {
+            let metadata = match entry {
+                ScanEntry::Tile(ref t) => self.metadata(t)?,
+                ScanEntry::Unparsable(_) => None,
+            };
+            f(entry, metadata)
+        })
+    }
+
+    /// Store this tile, which has the same data as `original`, which is already stored. Stores
+    /// can share the data, rather than storing it again. By default this is `put`.
+    fn put_copy(&self, tile: &Tile, original: &Tile, data: &[u8]) -> io::Result<()> {
+        let _ = original;
+        self.put(tile, data)
+    }
+
+    /// Make sure everything written so far is stored. By default this does nothing.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Something found by `TileStore::scan`
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ScanEntry {
+    /// A stored tile
+    Tile(Tile),
+    /// Something which isn't a tile, e.g. the path of a file with the wrong name
+    Unparsable(String),
+}
+
+/// How a `FsTileStore` lays out files
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum FsLayout {
+    /// `z/x/y.ext` (`Tile::zxy_path`)
+    Zxy,
+    /// TileCache (`Tile::tc_path`)
+    Tc,
+    /// MapProxy (`Tile::mp_path`)
+    Mp,
+    /// TileStache safe (`Tile::ts_path`)
+    Ts,
+    /// mod_tile `.meta` files, each with the 8×8 tiles of a `ModTileMetatile`
+    ModTileMeta,
+}
+
+impl FromStr for FsLayout {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zxy" => Ok(FsLayout::Zxy),
+            "tc" => Ok(FsLayout::Tc),
+            "mp" => Ok(FsLayout::Mp),
+            "ts" => Ok(FsLayout::Ts),
+            "meta" => Ok(FsLayout::ModTileMeta),
+            _ => Err("unknown layout, expected zxy, tc, mp, ts or meta"),
+        }
+    }
+}
+
+impl FsLayout {
+    /// Number of numeric path parts after the zoom
+    fn num_parts(&self) -> usize {
+        match *self {
+            FsLayout::Zxy => 2,
+            FsLayout::Mp | FsLayout::Ts => 4,
+            FsLayout::ModTileMeta => 5,
+            FsLayout::Tc => 6,
+        }
+    }
+
+    /// x & y from the numeric path parts after the zoom (for `ModTileMeta`, of the metatile)
+    pub(crate) fn xy_from_parts(&self, p: &[u32]) -> (u32, u32) {
+        match *self {
+            FsLayout::Zxy => (p[0], p[1]),
+            FsLayout::Tc => (p[0]*1_000_000 + p[1]*1_000 + p[2], p[3]*1_000_000 + p[4]*1_000 + p[5]),
+            FsLayout::Mp => (p[0]*10_000 + p[1], p[2]*10_000 + p[3]),
+            FsLayout::Ts => (p[0]*1_000 + p[1], p[2]*1_000 + p[3]),
+            FsLayout::ModTileMeta => p.iter().fold((0, 0), |(x, y), b| ((x << 4) | (b >> 4), (y << 4) | (b & 0x0f))),
+        }
+    }
+}
+
+/// mod_tile metatiles are always 8×8, and the file always has room for all 64 tiles
+const META_SIZE: u8 = 8;
+const META_COUNT: usize = 64;
+const META_HEADER_SIZE: u64 = 20 + 8 * META_COUNT as u64;
+
+/// When listing a range with more tiles than this, walk the directory rather than looking for
+/// each tile.
+const LIST_PROBE_LIMIT: u64 = 10_000;
+
+/// Where each tile is in a `.meta` file, as (offset, size)
+type MetaIndex = Vec<(u64, u64)>;
+
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Tiles stored as files in a directory. Writes are atomic (a temporary file is written, then
+/// renamed), so readers never see part of a tile. With `FsLayout::ModTileMeta`, changing a tile
+/// rewrites its whole `.meta` file, so 2 processes shouldn't write to the same metatile at once.
+#[derive(Debug, Clone)]
+pub struct FsTileStore {
+    root: PathBuf,
+    layout: FsLayout,
+    ext: String,
+}
+
+impl FsTileStore {
+    /// Store tiles under `root`. `ext` is the file extension for tiles (ignored for
+    /// `ModTileMeta`, which always uses `meta`).
+    pub fn new<P: AsRef<Path>>(root: P, layout: FsLayout, ext: &str) -> FsTileStore {
+        FsTileStore{ root: root.as_ref().to_path_buf(), layout, ext: ext.to_string() }
+    }
+
+    pub fn root(&self) -> &Path { &self.root }
+
+    pub fn layout(&self) -> FsLayout { self.layout }
+
+    pub fn ext(&self) -> &str { &self.ext }
+
+    /// The file this tile is stored in
+    pub fn path(&self, tile: &Tile) -> PathBuf {
+        let rel = match self.layout {
+            FsLayout::Zxy => tile.zxy_path(&self.ext),
+            FsLayout::Tc => tile.tc_path(&self.ext),
+            FsLayout::Mp => tile.mp_path(&self.ext),
+            FsLayout::Ts => tile.ts_path(&self.ext),
+            FsLayout::ModTileMeta => tile.modtile_metatile().unwrap().path("meta"),
+        };
+        self.root.join(rel)
+    }
+
+    fn file_ext(&self) -> &str {
+        if self.layout == FsLayout::ModTileMeta { "meta" } else { &self.ext }
+    }
+
+    /// The tile (or for `ModTileMeta`, the first tile of the metatile) stored in this file, from
+    /// the path relative to the zoom directory. `None` if it's not a tile file.
+    fn tile_for_path(&self, zoom: u8, rel: &Path) -> Option<Tile> {
+        let mut parts = rel.iter().map(|p| p.to_str()).collect::<Option<Vec<&str>>>()?;
+        let last = parts.pop()?;
+        let ext = format!(".{}", self.file_ext());
+        if !last.ends_with(&ext) {
+            return None;
+        }
+        parts.push(&last[..last.len() - ext.len()]);
+        if parts.len() != self.layout.num_parts() || !parts.iter().all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit())) {
+            return None;
+        }
+        let nums = parts.iter().map(|p| p.parse().ok()).collect::<Option<Vec<u32>>>()?;
+        let (x, y) = self.layout.xy_from_parts(&nums);
+        let tile = Tile::new(zoom, x, y)?;
+        // Only the exact path, not other ways to write the same numbers
+        if self.path(&tile) != self.root.join(zoom.to_string()).join(rel) {
+            return None;
+        }
+        Some(tile)
+    }
+
+    /// Call `f` with every file under this directory, and the tile (see `tile_for_path`) it's
+    /// for, if any.
+    fn walk(&self, zoom: u8, dir: &Path, rel: &Path, f: &mut dyn FnMut(&Path, Option<Tile>) -> io::Result<()>) -> io::Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let rel = rel.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                self.walk(zoom, &entry.path(), &rel, f)?;
+            } else {
+                f(&entry.path(), self.tile_for_path(zoom, &rel))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The tiles in the file for this tile, and their metadata
+    fn tiles_in_file(&self, tile: &Tile) -> io::Result<Vec<(Tile, TileMetadata)>> {
+        if self.layout != FsLayout::ModTileMeta {
+            return Ok(self.metadata(tile)?.map(|m| (*tile, m)).into_iter().collect());
+        }
+        let mt = meta_for(tile);
+        Ok(match self.read_meta_index(tile)? {
+            None => vec![],
+            Some((file, index)) => {
+                let modified = file.metadata().and_then(|m| m.modified()).ok();
+                index.iter().enumerate()
+                    .filter(|&(_, &(_, size))| size > 0)
+                    .filter_map(|(i, &(_, size))| {
+                        let t = Tile::new(tile.zoom, mt.x + i as u32 / META_SIZE as u32, mt.y + i as u32 % META_SIZE as u32)?;
+                        Some((t, TileMetadata::new(size, modified)))
+                    })
+                    .collect()
+            },
+        })
+    }
+
+    /// `scan`, with the metadata if `with_metadata`, or for `.meta` files, where it's read
+    /// anyway
+    fn scan_files(&self, zooms: RangeInclusive<u8>, with_metadata: bool, f: &mut dyn FnMut(ScanEntry, Option<TileMetadata>) -> io::Result<()>) -> io::Result<()> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(e) => e,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let zoom = entry.file_name().to_str().and_then(|n| n.parse::<u8>().ok().filter(|z| z.to_string() == n && *z <= 31));
+            let zoom = match zoom {
+                Some(z) if entry.file_type()?.is_dir() => z,
+                _ => {
+                    f(ScanEntry::Unparsable(entry.path().display().to_string()), None)?;
+                    continue;
+                },
+            };
+            if !zooms.contains(&zoom) {
+                continue;
+            }
+            self.walk(zoom, &entry.path(), Path::new(""), &mut |path, tile| {
+                let tile = match tile {
+                    None => return f(ScanEntry::Unparsable(path.display().to_string()), None),
+                    Some(t) => t,
+                };
+                if self.layout != FsLayout::ModTileMeta {
+                    let metadata = if with_metadata { self.metadata(&tile)? } else { None };
+                    return f(ScanEntry::Tile(tile), metadata);
+                }
+                match self.tiles_in_file(&tile) {
+                    Ok(tiles) => tiles.into_iter().try_for_each(|(t, m)| f(ScanEntry::Tile(t), Some(m))),
+                    Err(ref e) if e.kind() == io::ErrorKind::InvalidData || e.kind() == io::ErrorKind::UnexpectedEof => {
+                        f(ScanEntry::Unparsable(path.display().to_string()), None)
+                    },
+                    Err(e) => Err(e),
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The open `.meta` file for this tile, and its index of (offset, size). `None` if there is
+    /// no file.
+    fn read_meta_index(&self, tile: &Tile) -> io::Result<Option<(File, MetaIndex)>> {
+        let mut file = match File::open(self.path(tile)) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut header = vec![0; META_HEADER_SIZE as usize];
+        file.read_exact(&mut header)?;
+        let int = |i: usize| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&header[i*4..i*4+4]);
+            i32::from_le_bytes(b)
+        };
+        let mt = meta_for(tile);
+        if &header[0..4] != b"META" || int(1) != META_COUNT as i32 || (int(2), int(3), int(4)) != (mt.x as i32, mt.y as i32, mt.zoom as i32) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a valid .meta file for this tile"));
+        }
+        let len = file.metadata()?.len();
+        let mut index = Vec::with_capacity(META_COUNT);
+        for i in 0..META_COUNT {
+            let (offset, size) = (int(5 + 2*i), int(6 + 2*i));
+            if offset < 0 || size < 0 || offset as u64 + size as u64 > len {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid .meta file, a tile is outside the file"));
+            }
+            index.push((offset as u64, size as u64));
+        }
+        Ok(Some((file, index)))
+    }
+
+    /// All the tiles in the `.meta` file for this tile
+    fn read_meta(&self, tile: &Tile) -> io::Result<Vec<Option<Vec<u8>>>> {
+        let mut tiles = vec![None; META_COUNT];
+        if let Some((mut file, index)) = self.read_meta_index(tile)? {
+            for (i, &(offset, size)) in index.iter().enumerate() {
+                if size > 0 {
+                    tiles[i] = Some(read_at(&mut file, offset, size)?);
+                }
+            }
+        }
+        Ok(tiles)
+    }
+
+    /// Write the `.meta` file for this tile, or remove it if there are no tiles.
+    fn write_meta(&self, tile: &Tile, tiles: &[Option<Vec<u8>>]) -> io::Result<()> {
+        if tiles.iter().all(|t| t.is_none()) {
+            return match fs::remove_file(self.path(tile)) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                r => r,
+            };
+        }
+        let mt = meta_for(tile);
+        let mut data = Vec::new();
+        data.extend_from_slice(b"META");
+        for v in &[META_COUNT as i32, mt.x as i32, mt.y as i32, mt.zoom as i32] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut offset = META_HEADER_SIZE;
+        for t in tiles {
+            let size = t.as_ref().map_or(0, |t| t.len() as u64);
+            if offset + size > i32::MAX as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "metatile is too large for a .meta file"));
+            }
+            data.extend_from_slice(&(offset as i32).to_le_bytes());
+            data.extend_from_slice(&(size as i32).to_le_bytes());
+            offset += size;
+        }
+        for t in tiles.iter().flatten() {
+            data.extend_from_slice(t);
+        }
+        write_atomic(&self.path(tile), &data)
+    }
+}
+
+/// The mod_tile metatile with this tile
+fn meta_for(tile: &Tile) -> Metatile {
+    Metatile::new(META_SIZE, tile.zoom, tile.x, tile.y).unwrap()
+}
+
+/// Where this tile is in its `.meta` file's index
+fn meta_index(tile: &Tile) -> usize {
+    let mt = meta_for(tile);
+    ((tile.x - mt.x) * META_SIZE as u32 + (tile.y - mt.y)) as usize
+}
+
+fn read_at(file: &mut File, offset: u64, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; size as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// A new temporary file name next to `path`, creating the directory if needed.
+fn tmp_path(path: &Path) -> io::Result<PathBuf> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir)?;
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("tile");
+    Ok(dir.join(format!(".{}.{}.{}.tmp", name, process::id(), TMP_COUNTER.fetch_add(1, Ordering::SeqCst))))
+}
+
+/// Write to a temporary file next to `path`, then rename it, creating the directory if needed.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path)?;
+    let result = File::create(&tmp).and_then(|mut f| f.write_all(data)).and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+impl TileStore for FsTileStore {
+    fn get(&self, tile: &Tile) -> io::Result<Option<Vec<u8>>> {
+        if self.layout == FsLayout::ModTileMeta {
+            return match self.read_meta_index(tile)? {
+                None => Ok(None),
+                Some((mut file, index)) => match index[meta_index(tile)] {
+                    (_, 0) => Ok(None),
+                    (offset, size) => read_at(&mut file, offset, size).map(Some),
+                },
+            };
+        }
+        match fs::read(self.path(tile)) {
+            Ok(data) => Ok(Some(data)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, tile: &Tile, data: &[u8]) -> io::Result<()> {
+        if self.layout == FsLayout::ModTileMeta {
+            let mut tiles = self.read_meta(tile)?;
+            tiles[meta_index(tile)] = Some(data.to_vec());
+            return self.write_meta(tile, &tiles);
+        }
+        write_atomic(&self.path(tile), data)
+    }
+
+    fn delete(&self, tile: &Tile) -> io::Result<bool> {
+        if self.layout == FsLayout::ModTileMeta {
+            let mut tiles = self.read_meta(tile)?;
+            if tiles[meta_index(tile)].take().is_none() {
+                return Ok(false);
+            }
+            return self.write_meta(tile, &tiles).map(|_| true);
+        }
+        match fs::remove_file(self.path(tile)) {
+            Ok(()) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn metadata(&self, tile: &Tile) -> io::Result<Option<TileMetadata>> {
+        let path = self.path(tile);
+        let size = if self.layout == FsLayout::ModTileMeta {
+            match self.read_meta_index(tile)? {
+                None => return Ok(None),
+                Some((_, index)) => match index[meta_index(tile)] {
+                    (_, 0) => return Ok(None),
+                    (_, size) => size,
+                },
+            }
+        } else {
+            match fs::metadata(&path) {
+                Ok(m) => m.len(),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        };
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        Ok(Some(TileMetadata::new(size, modified)))
+    }
+
+    fn scan(&self, zooms: RangeInclusive<u8>, f: &mut dyn FnMut(ScanEntry) -> io::Result<()>) -> io::Result<()> {
+        self.scan_files(zooms, false, &mut |entry, _| f(entry))
+    }
+
+    /// Each `.meta` file's index is only read once.
+    fn scan_metadata(&self, zooms: RangeInclusive<u8>, f: &mut dyn FnMut(ScanEntry, Option<TileMetadata>) -> io::Result<()>) -> io::Result<()> {
+        self.scan_files(zooms, true, f)
+    }
+
+    /// Hard links the file, if both tiles are in their own files.
+    fn put_copy(&self, tile: &Tile, original: &Tile, data: &[u8]) -> io::Result<()> {
+        if self.layout == FsLayout::ModTileMeta {
+            return self.put(tile, data);
+        }
+        let path = self.path(tile);
+        let tmp = tmp_path(&path)?;
+        if fs::hard_link(self.path(original), &tmp).is_err() {
+            // e.g. the filesystem doesn't support hard links
+            return self.put(tile, data);
+        }
+        fs::rename(&tmp, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+    }
+
+    fn list(&self, range: &TileRange) -> io::Result<Vec<Tile>> {
+        // The files to look in, as one tile from each
+        let files: Vec<Tile> = if range.num_tiles() <= LIST_PROBE_LIMIT {
+            let mut seen = HashSet::new();
+            range.iter().filter(|t| self.layout != FsLayout::ModTileMeta || seen.insert(meta_for(t))).collect()
+        } else {
+            let mut files = Vec::new();
+            let dir = self.root.join(range.zoom().to_string());
+            self.walk(range.zoom(), &dir, Path::new(""), &mut |_, tile| {
+                files.extend(tile.filter(|t| self.layout == FsLayout::ModTileMeta || range.contains(t)));
+                Ok(())
+            })?;
+            files
+        };
+
+        let mut tiles = Vec::new();
+        for file in files {
+            tiles.extend(self.tiles_in_file(&file)?.into_iter().map(|(t, _)| t).filter(|t| range.contains(t)));
+        }
+        tiles.sort_by_key(|t| (t.x, t.y));
+        Ok(tiles)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    /// A store in a new directory, which is removed when the `TempDir` is dropped
+    fn temp_store(layout: FsLayout) -> (TempDir, FsTileStore) {
+        let dir = tempdir().unwrap();
+        let store = FsTileStore::new(dir.path(), layout, "png");
+        (dir, store)
+    }
+
+    fn check_store(store: &FsTileStore) {
+        let a = Tile::new(10, 547, 380).unwrap();
+        let b = Tile::new(10, 549, 381).unwrap();
+        let c = Tile::new(10, 1000, 1000).unwrap();
+        assert_eq!(store.get(&a).unwrap(), None);
+        assert!(!store.exists(&a).unwrap());
+        assert!(!store.delete(&a).unwrap());
+
+        store.put(&a, b"a").unwrap();
+        store.put(&b, b"bb").unwrap();
+        store.put(&c, b"c").unwrap();
+        store.put(&a, b"aaa").unwrap();
+        assert_eq!(store.get(&a).unwrap(), Some(b"aaa".to_vec()));
+        assert_eq!(store.get(&b).unwrap(), Some(b"bb".to_vec()));
+        assert!(store.exists(&b).unwrap());
+        assert!(!store.exists(&Tile::new(10, 548, 380).unwrap()).unwrap());
+        let meta = store.metadata(&a).unwrap().unwrap();
+        assert_eq!(meta.size(), 3);
+        assert!(meta.modified().is_some());
+
+        let range = TileRange::new(10, 540, 560, 370, 390).unwrap();
+        assert_eq!(store.list(&range).unwrap(), vec![a, b]);
+        // Large enough to walk the directory
+        assert_eq!(store.list(&TileRange::all(10).unwrap()).unwrap(), vec![a, b, c]);
+        assert_eq!(store.list(&TileRange::all(11).unwrap()).unwrap(), vec![]);
+
+        assert!(store.delete(&a).unwrap());
+        assert!(!store.delete(&a).unwrap());
+        assert_eq!(store.get(&a).unwrap(), None);
+        assert_eq!(store.list(&TileRange::all(10).unwrap()).unwrap(), vec![b, c]);
+        assert!(fs::read_dir(store.path(&b).parent().unwrap()).unwrap().all(|e| !e.unwrap().file_name().to_str().unwrap().ends_with(".tmp")));
+    }
+
+    #[test]
+    fn file_layouts() {
+        for &(name, layout) in &[("zxy", FsLayout::Zxy), ("tc", FsLayout::Tc), ("mp", FsLayout::Mp), ("ts", FsLayout::Ts)] {
+            let (_dir, store) = temp_store(layout);
+            check_store(&store);
+            assert_eq!(name.parse(), Ok(layout));
+        }
+    }
+
+    #[test]
+    fn mod_tile_meta() {
+        let (_dir, store) = temp_store(FsLayout::ModTileMeta);
+        let tile = Tile::new(10, 547, 380).unwrap();
+        assert_eq!(store.path(&tile), store.root().join("10/0/0/33/39/8.meta"));
+        check_store(&store);
+
+        // 547/380 & 549/381 are in the same metatile
+        let (_dir, store) = temp_store(FsLayout::ModTileMeta);
+        store.put(&tile, b"abc").unwrap();
+        store.put(&Tile::new(10, 551, 383).unwrap(), b"de").unwrap();
+        let data = fs::read(store.path(&tile)).unwrap();
+        assert_eq!(data.len() as u64, META_HEADER_SIZE + 5);
+        assert_eq!(&data[..20], &[b'M', b'E', b'T', b'A', 64, 0, 0, 0, 0x20, 2, 0, 0, 0x78, 1, 0, 0, 10, 0, 0, 0]);
+        // 547/380 is the 28th tile (x major), right after the header
+        assert_eq!(&data[20 + 8*28..20 + 8*29], &[0x14, 2, 0, 0, 3, 0, 0, 0]);
+        assert_eq!(&data[data.len() - 5..], b"abcde");
+
+        let mut sizes = Vec::new();
+        store.scan_metadata(0..=31, &mut |e, m| {
+            if let ScanEntry::Tile(t) = e {
+                assert_eq!(m, store.metadata(&t).unwrap());
+                sizes.push((t.zxy(), m.unwrap().size()));
+            }
+            Ok(())
+        }).unwrap();
+        sizes.sort();
+        assert_eq!(sizes, vec![("10/547/380".to_string(), 3), ("10/551/383".to_string(), 2)]);
+
+        store.delete(&tile).unwrap();
+        assert!(store.path(&tile).exists());
+        store.delete(&Tile::new(10, 551, 383).unwrap()).unwrap();
+        assert!(!store.path(&tile).exists());
+
+        // Corrupt or truncated files, with tiles outside the file
+        store.put(&tile, b"abc").unwrap();
+        let mut data = fs::read(store.path(&tile)).unwrap();
+        data[20 + 8*28 + 4..20 + 8*29].copy_from_slice(&(-5i32).to_le_bytes());
+        fs::write(store.path(&tile), &data).unwrap();
+        assert_eq!(store.get(&tile).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        data[20 + 8*28 + 4..20 + 8*29].copy_from_slice(&4i32.to_le_bytes());
+        fs::write(store.path(&tile), &data).unwrap();
+        assert_eq!(store.get(&tile).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        fs::write(store.path(&tile), b"not a meta file at all").unwrap();
+        assert!(store.get(&tile).is_err());
+        assert_eq!(scan(&store), (vec![], vec![store.path(&tile).display().to_string()]));
+    }
+
+    fn scan(store: &FsTileStore) -> (Vec<Tile>, Vec<String>) {
+        let (mut tiles, mut unparsable) = (Vec::new(), Vec::new());
+        store.scan(0..=31, &mut |e| {
+            match e {
+                ScanEntry::Tile(t) => tiles.push(t),
+                ScanEntry::Unparsable(p) => unparsable.push(p),
+            }
+            Ok(())
+        }).unwrap();
+        tiles.sort_by_key(|t| (t.zoom, t.x, t.y));
+        unparsable.sort();
+        (tiles, unparsable)
+    }
+
+    #[test]
+    fn scan_and_copy() {
+        let (_dir, store) = temp_store(FsLayout::Mp);
+        let (a, b) = (Tile::new(3, 2, 5).unwrap(), Tile::new(12, 1000, 2000).unwrap());
+        store.put(&a, b"a").unwrap();
+        store.put(&b, b"b").unwrap();
+        store.put_copy(&Tile::new(12, 1000, 2001).unwrap(), &b, b"b").unwrap();
+        assert_eq!(store.get(&Tile::new(12, 1000, 2001).unwrap()).unwrap(), Some(b"b".to_vec()));
+        fs::write(store.root().join("README"), b"").unwrap();
+        fs::write(store.root().join("3/0000/0002/0000/0005.jpg"), b"").unwrap();
+        fs::write(store.root().join("3/0000/0002/0000/5.png"), b"").unwrap();
+        fs::create_dir_all(store.root().join("03")).unwrap();
+
+        let root = store.root().display().to_string();
+        assert_eq!(scan(&store), (
+            vec![a, b, Tile::new(12, 1000, 2001).unwrap()],
+            vec![format!("{}/03", root), format!("{}/3/0000/0002/0000/0005.jpg", root), format!("{}/3/0000/0002/0000/5.png", root), format!("{}/README", root)],
+        ));
+
+        let mut tiles = Vec::new();
+        store.scan(4..=31, &mut |e| { tiles.push(e); Ok(()) }).unwrap();
+        assert_eq!(tiles.len(), 4);
+    }
+}
@@ -0,0 +1,332 @@
+//! [TileJSON 3.0](https://github.com/mapbox/tilejson-spec/tree/master/3.0.0), which web map
+//! clients use to find out about a tile layer. Needs the `json` feature.
+//!
+//! ```
+//! use slippy_map_tiles::{BBox, LatLon, TileJson};
+//!
+//! let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+//! let centre = LatLon::new(53.35, -6.26).unwrap();
+//! let mut tilejson = TileJson::new(vec!["https://tile.example.org/{z}/{x}/{y}.png".to_string()], bbox, 0, 18, centre).unwrap();
+//! tilejson.set_name(Some("Ireland".to_string()));
+//!
+//! let json = tilejson.to_json();
+//! let parsed: TileJson = json.parse().unwrap();
+//! assert_eq!(parsed, tilejson);
+//! ```
+use std::str::FromStr;
+
+use serde_json::{self, Value, Map};
+
+use {BBox, LatLon, UrlTemplate, f32_json};
+
+/// Highest zoom allowed by the TileJSON spec
+const TILEJSON_MAX_ZOOM: u8 = 30;
+
+/// A TileJSON document
+#[derive(PartialEq, Debug, Clone)]
+pub struct TileJson {
+    pub(crate) tiles: Vec<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) version: Option<String>,
+    pub(crate) attribution: Option<String>,
+    pub(crate) scheme: Option<String>,
+    pub(crate) minzoom: u8,
+    pub(crate) maxzoom: u8,
+    pub(crate) bounds: BBox,
+    pub(crate) centre: LatLon,
+    pub(crate) centre_zoom: u8,
+
+    // Everything else (e.g. vector_layers), kept as is so it survives a round trip
+    pub(crate) other: Map<String, Value>,
+}
+
+impl TileJson {
+    /// A TileJSON for these tile URL templates, covering `bounds` from `minzoom` to `maxzoom`. The
+    /// centre zoom is `minzoom`. Returns `Err` if this isn't valid (see `validate`).
+    pub fn new(tiles: Vec<String>, bounds: BBox, minzoom: u8, maxzoom: u8, centre: LatLon) -> Result<Self, &'static str> {
+        let tilejson = TileJson {
+            tiles,
+            name: None,
+            description: None,
+            version: None,
+            attribution: None,
+            scheme: None,
+            minzoom,
+            maxzoom,
+            bounds,
+            centre,
+            centre_zoom: minzoom,
+            other: Map::new(),
+        };
+        tilejson.validate()?;
+
+        Ok(tilejson)
+    }
+
+    /// Check this is a valid TileJSON: there is at least one URL template, the bounds are in
+    /// order, `minzoom` ≤ `maxzoom` ≤ 30, and the centre (& centre zoom) are within the bounds &
+    /// zooms.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.tiles.is_empty() {
+            return Err("no tile URLs");
+        }
+        for template in &self.tiles {
+            UrlTemplate::new(template)?;
+        }
+        if self.bounds.left() > self.bounds.right() || self.bounds.bottom() > self.bounds.top() {
+            return Err("bounds are not in [left, bottom, right, top] order");
+        }
+        if self.minzoom > self.maxzoom {
+            return Err("minzoom is greater than maxzoom");
+        }
+        if self.maxzoom > TILEJSON_MAX_ZOOM {
+            return Err("maxzoom is greater than 30");
+        }
+        let (lat, lon) = (self.centre.lat(), self.centre.lon());
+        if lat < self.bounds.bottom() || lat > self.bounds.top() || lon < self.bounds.left() || lon > self.bounds.right() {
+            return Err("centre is not within bounds");
+        }
+        if self.centre_zoom < self.minzoom || self.centre_zoom > self.maxzoom {
+            return Err("centre zoom is not between minzoom and maxzoom");
+        }
+        if let Some(ref scheme) = self.scheme {
+            if scheme != "xyz" && scheme != "tms" {
+                return Err("scheme is not xyz or tms");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The URL templates for the tiles
+    pub fn tiles(&self) -> &[String] { &self.tiles }
+
+    pub fn name(&self) -> Option<&str> { self.name.as_deref() }
+
+    pub fn set_name(&mut self, name: Option<String>) { self.name = name; }
+
+    pub fn description(&self) -> Option<&str> { self.description.as_deref() }
+
+    pub fn set_description(&mut self, description: Option<String>) { self.description = description; }
+
+    /// Version of the tileset (not of TileJSON)
+    pub fn version(&self) -> Option<&str> { self.version.as_deref() }
+
+    pub fn set_version(&mut self, version: Option<String>) { self.version = version; }
+
+    pub fn attribution(&self) -> Option<&str> { self.attribution.as_deref() }
+
+    pub fn set_attribution(&mut self, attribution: Option<String>) { self.attribution = attribution; }
+
+    /// `xyz` (the default) or `tms`
+    pub fn scheme(&self) -> Option<&str> { self.scheme.as_deref() }
+
+    pub fn minzoom(&self) -> u8 { self.minzoom }
+
+    pub fn maxzoom(&self) -> u8 { self.maxzoom }
+
+    pub fn bounds(&self) -> &BBox { &self.bounds }
+
+    pub fn centre(&self) -> &LatLon { &self.centre }
+
+    pub fn center(&self) -> &LatLon { self.centre() }
+
+    /// The zoom a map should start at
+    pub fn centre_zoom(&self) -> u8 { self.centre_zoom }
+
+    /// Set the zoom a map should start at. Returns `Err`, and doesn't change it, if it's not
+    /// between `minzoom` & `maxzoom`.
+    pub fn set_centre_zoom(&mut self, zoom: u8) -> Result<(), &'static str> {
+        if zoom < self.minzoom || zoom > self.maxzoom {
+            return Err("centre zoom is not between minzoom and maxzoom");
+        }
+        self.centre_zoom = zoom;
+        Ok(())
+    }
+
+    /// This as TileJSON 3.0.0
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    pub(crate) fn to_json_value(&self) -> Value {
+        let mut m = self.other.clone();
+        m.insert("tilejson".to_string(), Value::String("3.0.0".to_string()));
+        m.insert("tiles".to_string(), Value::Array(self.tiles.iter().map(|t| Value::String(t.clone())).collect()));
+        for &(key, value) in &[("name", &self.name), ("description", &self.description), ("version", &self.version), ("attribution", &self.attribution), ("scheme", &self.scheme)] {
+            if let Some(ref value) = *value {
+                m.insert(key.to_string(), Value::String(value.clone()));
+            }
+        }
+        m.insert("minzoom".to_string(), Value::from(self.minzoom));
+        m.insert("maxzoom".to_string(), Value::from(self.maxzoom));
+        m.insert("bounds".to_string(), Value::Array(vec![f32_json(self.bounds.left()), f32_json(self.bounds.bottom()), f32_json(self.bounds.right()), f32_json(self.bounds.top())]));
+        m.insert("center".to_string(), Value::Array(vec![f32_json(self.centre.lon()), f32_json(self.centre.lat()), Value::from(self.centre_zoom)]));
+        Value::Object(m)
+    }
+}
+
+impl FromStr for TileJson {
+    type Err = &'static str;
+
+    /// Parse a TileJSON document, filling in the spec's defaults for missing values, and
+    /// validating it.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let root: Value = serde_json::from_str(s).map_err(|_| "invalid JSON")?;
+        TileJson::from_json_value(root)
+    }
+}
+
+impl TileJson {
+    pub(crate) fn from_json_value(root: Value) -> Result<Self, &'static str> {
+        let mut m = match root {
+            Value::Object(m) => m,
+            _ => { return Err("TileJSON is not a JSON object"); },
+        };
+
+        match m.remove("tilejson") {
+            Some(Value::String(ref v)) if v.starts_with("3.") || v.starts_with("2.") => {},
+            Some(Value::String(_)) => { return Err("unsupported tilejson version"); },
+            _ => { return Err("no tilejson version"); },
+        }
+
+        let tiles = match m.remove("tiles") {
+            Some(Value::Array(tiles)) => tiles.into_iter().map(|t| match t {
+                Value::String(t) => Ok(t),
+                _ => Err("tile URL is not a string"),
+            }).collect::<Result<Vec<String>, _>>()?,
+            _ => { return Err("no tiles"); },
+        };
+
+        let mut string_field = |key: &str| -> Result<Option<String>, &'static str> {
+            match m.remove(key) {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::String(s)) => Ok(Some(s)),
+                Some(_) => Err("field is not a string"),
+            }
+        };
+        let name = string_field("name")?;
+        let description = string_field("description")?;
+        let version = string_field("version")?;
+        let attribution = string_field("attribution")?;
+        let scheme = string_field("scheme")?;
+
+        let minzoom = zoom_field(m.remove("minzoom"), 0)?;
+        let maxzoom = zoom_field(m.remove("maxzoom"), TILEJSON_MAX_ZOOM)?;
+
+        let bounds = match m.remove("bounds") {
+            None => BBox::new(85.051_13, -180., -85.051_13, 180.).unwrap(),
+            Some(Value::Array(b)) => {
+                if b.len() != 4 {
+                    return Err("bounds does not have 4 numbers");
+                }
+                let b = b.iter().map(|v| v.as_f64().map(|v| v as f32).ok_or("bounds are not numbers")).collect::<Result<Vec<f32>, _>>()?;
+                BBox::new(b[3], b[0], b[1], b[2]).ok_or("invalid bounds")?
+            },
+            Some(_) => { return Err("bounds is not an array"); },
+        };
+
+        let (centre, centre_zoom) = match m.remove("center") {
+            None => {
+                // The spec doesn't give a default, so use the middle of the bounds
+                let centre = LatLon::new((bounds.top() + bounds.bottom()) / 2., (bounds.left() + bounds.right()) / 2.).ok_or("invalid bounds")?;
+                (centre, minzoom)
+            },
+            Some(Value::Array(c)) => {
+                if c.len() != 3 {
+                    return Err("center does not have 3 numbers");
+                }
+                let lon = c[0].as_f64().ok_or("center is not numbers")?;
+                let lat = c[1].as_f64().ok_or("center is not numbers")?;
+                let zoom = zoom_field(Some(c[2].clone()), minzoom)?;
+                (LatLon::new(lat as f32, lon as f32).ok_or("invalid center")?, zoom)
+            },
+            Some(_) => { return Err("center is not an array"); },
+        };
+
+        let tilejson = TileJson{ tiles, name, description, version, attribution, scheme, minzoom, maxzoom, bounds, centre, centre_zoom, other: m };
+        tilejson.validate()?;
+
+        Ok(tilejson)
+    }
+}
+
+fn zoom_field(value: Option<Value>, default: u8) -> Result<u8, &'static str> {
+    match value {
+        None | Some(Value::Null) => Ok(default),
+        Some(v) => {
+            // Some generators write the centre zoom as a float
+            let z = v.as_f64().ok_or("zoom is not a number")?;
+            if z < 0. || z > TILEJSON_MAX_ZOOM as f64 || z.fract() != 0. {
+                Err("invalid zoom")
+            } else {
+                Ok(z as u8)
+            }
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ireland() -> TileJson {
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let centre = LatLon::new(53.35, -6.26).unwrap();
+        TileJson::new(vec!["https://tile.example.org/{z}/{x}/{y}.png".to_string()], bbox, 2, 18, centre).unwrap()
+    }
+
+    #[test]
+    fn tilejson_to_json() {
+        let mut tilejson = ireland();
+        tilejson.set_name(Some("Ireland".to_string()));
+        tilejson.set_centre_zoom(10).unwrap();
+        assert!(tilejson.set_centre_zoom(19).is_err());
+        assert_eq!(tilejson.centre_zoom(), 10);
+
+        let json: Value = serde_json::from_str(&tilejson.to_json()).unwrap();
+        assert_eq!(json["tilejson"], "3.0.0");
+        assert_eq!(json["tiles"][0], "https://tile.example.org/{z}/{x}/{y}.png");
+        assert_eq!(json["name"], "Ireland");
+        assert_eq!(json["minzoom"], 2);
+        assert_eq!(json["maxzoom"], 18);
+        assert_eq!(json["bounds"].to_string(), "[-11.32,51.11,-4.97,55.7]");
+        assert_eq!(json["center"].to_string(), "[-6.26,53.35,10]");
+        assert!(json.get("description").is_none());
+    }
+
+    #[test]
+    fn tilejson_parse() {
+        let tilejson: TileJson = r#"{"tilejson": "3.0.0", "tiles": ["/{z}/{x}/{y}.pbf"], "vector_layers": [{"id": "roads", "fields": {}}]}"#.parse().unwrap();
+        assert_eq!(tilejson.minzoom(), 0);
+        assert_eq!(tilejson.maxzoom(), 30);
+        assert_eq!(tilejson.bounds(), &BBox::new(85.051_13, -180., -85.051_13, 180.).unwrap());
+        assert_eq!(tilejson.centre(), &LatLon::new(0., 0.).unwrap());
+        // Unknown fields survive a round trip
+        assert!(tilejson.to_json().contains(r#""vector_layers":[{"fields":{},"id":"roads"}]"#));
+
+        let tilejson = ireland();
+        assert_eq!(tilejson.to_json().parse(), Ok(tilejson));
+    }
+
+    #[test]
+    fn tilejson_validation() {
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let centre = LatLon::new(53.35, -6.26).unwrap();
+        let tiles = vec!["/{z}/{x}/{y}.png".to_string()];
+        assert!(TileJson::new(vec![], bbox.clone(), 0, 10, centre.clone()).is_err());
+        assert!(TileJson::new(vec!["/{z}/{x}.png".to_string()], bbox.clone(), 0, 10, centre.clone()).is_err());
+        assert!(TileJson::new(tiles.clone(), bbox.clone(), 11, 10, centre.clone()).is_err());
+        assert!(TileJson::new(tiles.clone(), bbox.clone(), 0, 31, centre.clone()).is_err());
+        assert!(TileJson::new(tiles.clone(), bbox, 0, 10, LatLon::new(0., 0.).unwrap()).is_err());
+        // top & bottom swapped
+        assert!(TileJson::new(tiles, BBox::new(51.11, -11.32, 55.7, -4.97).unwrap(), 0, 10, centre).is_err());
+
+        assert!("{}".parse::<TileJson>().is_err());
+        assert!(r#"{"tilejson": "3.0.0", "tiles": ["/{z}/{x}/{y}.png"], "bounds": [10, 0, 0, 10]}"#.parse::<TileJson>().is_err());
+        assert!(r#"{"tilejson": "3.0.0", "tiles": ["/{z}/{x}/{y}.png"], "minzoom": 5, "maxzoom": 4}"#.parse::<TileJson>().is_err());
+        assert!(r#"{"tilejson": "3.0.0", "tiles": ["/{z}/{x}/{y}.png"], "center": [0, 0, 31]}"#.parse::<TileJson>().is_err());
+    }
+}
@@ -0,0 +1,593 @@
+//! Reading & writing lists of tiles & metatiles, one per line.
+//!
+//! Blank lines, and lines starting with `#`, are skipped. Files can be gzipped (detected from
+//! the contents), and the filename `-` means stdin/stdout.
+//!
+//! ```
+//! use slippy_map_tiles::{Tile, TileListReader, TileListFormat};
+//!
+//! let list = "# tiles to render\n6/35/23\n\n6/35/24\n";
+//! let tiles: Vec<Tile> = TileListReader::new(list.as_bytes(), TileListFormat::Auto)
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//! assert_eq!(tiles, vec![Tile::new(6, 35, 23).unwrap(), Tile::new(6, 35, 24).unwrap()]);
+//! ```
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+use {Tile, Metatile};
+
+/// How each line of a tile list is written
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum TileListFormat {
+    /// Work it out from each line: `z/x/y` if it has a `/`, CSV if it has a `,`, otherwise
+    /// `z x y`. When writing, this is `z/x/y`.
+    Auto,
+
+    /// `z/x/y`. Metatiles are `scale z/x/y`, or `WxH z/x/y`.
+    Zxy,
+
+    /// `z x y`
+    SpaceSeparated,
+
+    /// `z,x,y`. If the first line is a header (e.g. `x,y,zoom`), it says what order the columns
+    /// are in, and other columns are ignored. A `scale` column (`8` or `4x2`) is used for
+    /// metatiles.
+    Csv,
+
+    /// `x y z`, as used by mod_tile's `render_list`
+    RenderList,
+}
+
+/// An error reading a tile list
+#[derive(Debug)]
+pub enum TileListError {
+    /// Couldn't read the list
+    Io(io::Error),
+
+    /// This line isn't a valid tile
+    Parse {
+        /// The line number, starting from 1
+        line: usize,
+        /// The line itself
+        content: String,
+        /// What's wrong with it
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for TileListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TileListError::Io(ref e) => write!(f, "{}", e),
+            TileListError::Parse{ line, ref content, reason } => write!(f, "line {}: {} ({:?})", line, reason, content),
+        }
+    }
+}
+
+impl Error for TileListError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TileListError::Io(ref e) => Some(e),
+            TileListError::Parse{ .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for TileListError {
+    fn from(e: io::Error) -> TileListError {
+        TileListError::Io(e)
+    }
+}
+
+/// Open this file (`-` for stdin) for reading, un-gzipping it if needed
+pub(crate) fn open_input<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn BufRead + Send>> {
+    let input: Box<dyn Read + Send> = if path.as_ref() == Path::new("-") {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let mut input = BufReader::new(input);
+    if input.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(input))))
+    } else {
+        Ok(Box::new(input))
+    }
+}
+
+/// The order of the CSV columns
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct CsvColumns {
+    scale: Option<usize>,
+    zoom: usize,
+    x: usize,
+    y: usize,
+}
+
+const DEFAULT_CSV_COLUMNS: CsvColumns = CsvColumns{ scale: None, zoom: 0, x: 1, y: 2 };
+
+/// One parsed line: width & height (for metatiles), zoom, x & y
+type Entry = (Option<(u8, u8)>, u8, u32, u32);
+
+fn parse_number<T: ::std::str::FromStr>(s: &str) -> Result<T, &'static str> {
+    s.trim().parse().map_err(|_| "invalid number")
+}
+
+/// A metatile size, `8` or `4x2`
+fn parse_size(s: &str) -> Result<(u8, u8), &'static str> {
+    match s.find('x') {
+        None => { let scale = parse_number(s)?; Ok((scale, scale)) },
+        Some(i) => Ok((parse_number(&s[..i])?, parse_number(&s[i+1..])?)),
+    }
+}
+
+/// A metatile size as text, the opposite of `parse_size`
+fn size_to_string((width, height): (u8, u8)) -> String {
+    if width == height { width.to_string() } else { format!("{}x{}", width, height) }
+}
+
+fn parse_zxy(line: &str) -> Result<Entry, &'static str> {
+    let (scale, zxy) = match line.find(' ') {
+        None => (None, line),
+        Some(i) => (Some(parse_size(&line[..i])?), line[i+1..].trim()),
+    };
+    let parts: Vec<&str> = zxy.split('/').collect();
+    if parts.len() != 3 {
+        return Err("expected z/x/y");
+    }
+    Ok((scale, parse_number(parts[0])?, parse_number(parts[1])?, parse_number(parts[2])?))
+}
+
+fn parse_whitespace<'a>(line: &'a str, expected: &'static str) -> Result<[&'a str; 3], &'static str> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 3 {
+        return Err(expected);
+    }
+    Ok([parts[0], parts[1], parts[2]])
+}
+
+/// If this is a CSV header, the order of the columns
+fn parse_csv_header(line: &str) -> Option<Result<CsvColumns, &'static str>> {
+    if !line.chars().any(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let names: Vec<String> = line.split(',').map(|c| c.trim().trim_matches('"').to_lowercase()).collect();
+    let find = |wanted: &[&str]| names.iter().position(|n| wanted.contains(&n.as_str()));
+    Some(match (find(&["z", "zoom"]), find(&["x"]), find(&["y"])) {
+        (Some(zoom), Some(x), Some(y)) => Ok(CsvColumns{ scale: find(&["scale"]), zoom, x, y }),
+        _ => Err("CSV header needs z, x & y columns"),
+    })
+}
+
+fn parse_csv(line: &str, columns: &CsvColumns) -> Result<Entry, &'static str> {
+    let parts: Vec<&str> = line.split(',').map(|c| c.trim().trim_matches('"')).collect();
+    let get = |i: usize| parts.get(i).cloned().ok_or("not enough CSV columns");
+    let scale = match columns.scale {
+        Some(i) => Some(parse_size(get(i)?)?),
+        None => None,
+    };
+    Ok((scale, parse_number(get(columns.zoom)?)?, parse_number(get(columns.x)?)?, parse_number(get(columns.y)?)?))
+}
+
+/// Reads tiles from a list, one per line. Each item is a `Result`, so you can decide what to do
+/// with invalid lines. Reading carries on after an invalid line, but stops after an I/O error.
+pub struct TileListReader<R> {
+    reader: R,
+    format: TileListFormat,
+    csv_columns: Option<CsvColumns>,
+    line_no: usize,
+    offset: u64,
+    done: bool,
+}
+
+impl<R> fmt::Debug for TileListReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TileListReader").field("format", &self.format).field("line_no", &self.line_no).field("offset", &self.offset).finish()
+    }
+}
+
+impl TileListReader<Box<dyn BufRead + Send>> {
+    /// Open this file, which may be gzipped. `-` is stdin.
+    pub fn open<P: AsRef<Path>>(path: P, format: TileListFormat) -> io::Result<Self> {
+        Ok(TileListReader::new(open_input(path)?, format))
+    }
+}
+
+impl<R: BufRead> TileListReader<R> {
+    /// Read the list from this reader
+    pub fn new(reader: R, format: TileListFormat) -> Self {
+        TileListReader{ reader, format, csv_columns: None, line_no: 0, offset: 0, done: false }
+    }
+
+    /// The line number of the last line read
+    pub fn line_number(&self) -> usize {
+        self.line_no
+    }
+
+    /// How many bytes (uncompressed) have been read so far
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Skip forward to this byte offset (from `offset()`), e.g. to carry on after a restart. It
+    /// must be at the start of a line.
+    pub fn skip_to_offset(&mut self, offset: u64) -> Result<(), TileListError> {
+        let mut line = String::new();
+        while self.offset < offset {
+            line.clear();
+            let n = self.reader.read_line(&mut line)?;
+            if n == 0 {
+                return Err(TileListError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "tile list is shorter than the offset")));
+            }
+            self.line_no += 1;
+            self.offset += n as u64;
+            if self.csv_columns.is_none() && self.format == TileListFormat::Csv && !is_skipped(&line) {
+                self.csv_columns = Some(parse_csv_header(line.trim()).and_then(Result::ok).unwrap_or(DEFAULT_CSV_COLUMNS));
+            }
+        }
+        if self.offset != offset {
+            return Err(TileListError::Io(io::Error::new(io::ErrorKind::InvalidInput, "offset isn't at the start of a line")));
+        }
+        Ok(())
+    }
+
+    /// The next line, parsed.
+    fn next_entry(&mut self) -> Option<Result<Entry, TileListError>> {
+        let mut line = String::new();
+        loop {
+            if self.done {
+                return None;
+            }
+            line.clear();
+            let n = match self.reader.read_line(&mut line) {
+                Ok(0) => { self.done = true; return None; },
+                Ok(n) => n,
+                Err(e) => { self.done = true; return Some(Err(e.into())); },
+            };
+            self.line_no += 1;
+            self.offset += n as u64;
+            if is_skipped(&line) {
+                continue;
+            }
+            let trimmed = line.trim();
+
+            let format = match self.format {
+                TileListFormat::Auto if trimmed.contains('/') => TileListFormat::Zxy,
+                TileListFormat::Auto if trimmed.contains(',') => TileListFormat::Csv,
+                TileListFormat::Auto => TileListFormat::SpaceSeparated,
+                f => f,
+            };
+            let parsed = match format {
+                TileListFormat::Zxy | TileListFormat::Auto => parse_zxy(trimmed),
+                TileListFormat::SpaceSeparated => parse_whitespace(trimmed, "expected z x y")
+                    .and_then(|p| Ok((None, parse_number(p[0])?, parse_number(p[1])?, parse_number(p[2])?))),
+                TileListFormat::RenderList => parse_whitespace(trimmed, "expected x y z")
+                    .and_then(|p| Ok((None, parse_number(p[2])?, parse_number(p[0])?, parse_number(p[1])?))),
+                TileListFormat::Csv => {
+                    match self.csv_columns {
+                        Some(ref columns) => parse_csv(trimmed, columns),
+                        None => match parse_csv_header(trimmed) {
+                            Some(Ok(columns)) => { self.csv_columns = Some(columns); continue; },
+                            Some(Err(e)) => { self.csv_columns = Some(DEFAULT_CSV_COLUMNS); Err(e) },
+                            None => { self.csv_columns = Some(DEFAULT_CSV_COLUMNS); parse_csv(trimmed, &DEFAULT_CSV_COLUMNS) },
+                        },
+                    }
+                },
+            };
+            return Some(parsed.map_err(|reason| self.error(trimmed, reason)));
+        }
+    }
+
+    fn error(&self, content: &str, reason: &'static str) -> TileListError {
+        TileListError::Parse{ line: self.line_no, content: content.to_string(), reason }
+    }
+}
+
+/// Blank lines & comments
+fn is_skipped(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+impl<R: BufRead> Iterator for TileListReader<R> {
+    type Item = Result<Tile, TileListError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.next_entry()?;
+        Some(entry.and_then(|(scale, zoom, x, y)| {
+            if let Some(size) = scale {
+                return Err(TileListError::Parse{ line: self.line_no, content: format!("{} {}/{}/{}", size_to_string(size), zoom, x, y), reason: "expected a tile, not a metatile" });
+            }
+            Tile::new(zoom, x, y).ok_or_else(|| TileListError::Parse{ line: self.line_no, content: format!("{}/{}/{}", zoom, x, y), reason: "invalid tile" })
+        }))
+    }
+}
+
+/// Reads metatiles from a list, one per line. Lines can be metatiles (`scale z/x/y`, `WxH z/x/y`,
+/// or CSV with a `scale` column), or tiles, which are turned into the metatile, of the default scale,
+/// which they're in.
+pub struct MetatileListReader<R> {
+    inner: TileListReader<R>,
+    scale: u8,
+}
+
+impl<R> fmt::Debug for MetatileListReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MetatileListReader").field("inner", &self.inner).field("scale", &self.scale).finish()
+    }
+}
+
+impl MetatileListReader<Box<dyn BufRead + Send>> {
+    /// Open this file, which may be gzipped. `-` is stdin.
+    pub fn open<P: AsRef<Path>>(path: P, format: TileListFormat, default_scale: u8) -> io::Result<Self> {
+        Ok(MetatileListReader::new(open_input(path)?, format, default_scale))
+    }
+}
+
+impl<R: BufRead> MetatileListReader<R> {
+    /// Read the list from this reader. Tiles are turned into metatiles of `default_scale`.
+    pub fn new(reader: R, format: TileListFormat, default_scale: u8) -> Self {
+        MetatileListReader{ inner: TileListReader::new(reader, format), scale: default_scale }
+    }
+
+    /// The line number of the last line read
+    pub fn line_number(&self) -> usize {
+        self.inner.line_number()
+    }
+
+    /// How many bytes (uncompressed) have been read so far
+    pub fn offset(&self) -> u64 {
+        self.inner.offset()
+    }
+
+    /// Skip forward to this byte offset (from `offset()`), e.g. to carry on after a restart. It
+    /// must be at the start of a line.
+    pub fn skip_to_offset(&mut self, offset: u64) -> Result<(), TileListError> {
+        self.inner.skip_to_offset(offset)
+    }
+}
+
+impl<R: BufRead> Iterator for MetatileListReader<R> {
+    type Item = Result<Metatile, TileListError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.inner.next_entry()?;
+        let (line, default_scale) = (self.inner.line_no, self.scale);
+        Some(entry.and_then(|(scale, zoom, x, y)| {
+            let (width, height) = scale.unwrap_or((default_scale, default_scale));
+            Metatile::new_with_size(width, height, zoom, x, y).ok_or_else(|| TileListError::Parse{ line, content: format!("{} {}/{}/{}", size_to_string((width, height)), zoom, x, y), reason: "invalid metatile" })
+        }))
+    }
+}
+
+/// Writes a list of tiles or metatiles, one per line
+pub struct TileListWriter<W: Write> {
+    out: Output<W>,
+    format: TileListFormat,
+    header_written: bool,
+}
+
+enum Output<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Output::Plain(ref mut w) => w.write(buf),
+            Output::Gzip(ref mut w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Output::Plain(ref mut w) => w.flush(),
+            Output::Gzip(ref mut w) => w.flush(),
+        }
+    }
+}
+
+impl TileListWriter<Box<dyn Write + Send>> {
+    /// Create this file, gzipped if it ends in `.gz`. `-` is stdout. Call `finish` when done,
+    /// otherwise errors writing the end of the file are lost.
+    pub fn create<P: AsRef<Path>>(path: P, format: TileListFormat) -> io::Result<Self> {
+        let path = path.as_ref();
+        if path == Path::new("-") {
+            Ok(TileListWriter::new(Box::new(io::stdout()), format))
+        } else if path.extension().is_some_and(|e| e == "gz") {
+            Ok(TileListWriter::new_gzip(Box::new(BufWriter::new(File::create(path)?)), format))
+        } else {
+            Ok(TileListWriter::new(Box::new(BufWriter::new(File::create(path)?)), format))
+        }
+    }
+}
+
+impl<W: Write> TileListWriter<W> {
+    /// Write the list to this
+    pub fn new(out: W, format: TileListFormat) -> Self {
+        TileListWriter{ out: Output::Plain(out), format, header_written: false }
+    }
+
+    /// Write the list, gzipped, to this
+    pub fn new_gzip(out: W, format: TileListFormat) -> Self {
+        TileListWriter{ out: Output::Gzip(GzEncoder::new(out, Compression::default())), format, header_written: false }
+    }
+
+    /// Write this tile
+    pub fn write_tile(&mut self, tile: &Tile) -> io::Result<()> {
+        let (z, x, y) = (tile.zoom(), tile.x(), tile.y());
+        match self.format {
+            TileListFormat::Auto | TileListFormat::Zxy => writeln!(self.out, "{}/{}/{}", z, x, y),
+            TileListFormat::SpaceSeparated => writeln!(self.out, "{} {} {}", z, x, y),
+            TileListFormat::RenderList => writeln!(self.out, "{} {} {}", x, y, z),
+            TileListFormat::Csv => {
+                if !self.header_written {
+                    writeln!(self.out, "z,x,y")?;
+                    self.header_written = true;
+                }
+                writeln!(self.out, "{},{},{}", z, x, y)
+            },
+        }
+    }
+
+    /// Write this metatile. For `Csv` this has a `scale` column, for `Zxy` & `Auto` it is
+    /// `scale z/x/y` (or `WxH z/x/y` for non-square metatiles). `SpaceSeparated` & `RenderList`
+    /// have no way to give the size, so this is an `InvalidInput` error for them.
+    pub fn write_metatile(&mut self, metatile: &Metatile) -> io::Result<()> {
+        let (s, z, x, y) = (size_to_string((metatile.width(), metatile.height())), metatile.zoom(), metatile.x(), metatile.y());
+        match self.format {
+            TileListFormat::Auto | TileListFormat::Zxy => writeln!(self.out, "{} {}/{}/{}", s, z, x, y),
+            TileListFormat::Csv => {
+                if !self.header_written {
+                    writeln!(self.out, "scale,z,x,y")?;
+                    self.header_written = true;
+                }
+                writeln!(self.out, "{},{},{},{}", s, z, x, y)
+            },
+            TileListFormat::SpaceSeparated | TileListFormat::RenderList => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, format!("can't write metatiles in the {:?} format", self.format)))
+            },
+        }
+    }
+
+    /// Flush everything written so far
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Finish the gzip stream (if gzipped), flush, and return the writer
+    pub fn finish(self) -> io::Result<W> {
+        let mut out = match self.out {
+            Output::Plain(w) => w,
+            Output::Gzip(w) => w.finish()?,
+        };
+        out.flush()?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn read(list: &str, format: TileListFormat) -> Vec<Result<String, String>> {
+        TileListReader::new(list.as_bytes(), format)
+            .map(|r| r.map(|t| t.zxy()).map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn tile_list_formats() {
+        let ok = vec![Ok("6/35/23".to_string()), Ok("7/1/2".to_string())];
+        assert_eq!(read("6/35/23\n7/1/2\n", TileListFormat::Auto), ok);
+        assert_eq!(read("6/35/23\r\n7/1/2", TileListFormat::Zxy), ok);
+        assert_eq!(read("  # comment\n\n6 35 23\n7\t1\t2\n", TileListFormat::SpaceSeparated), ok);
+        assert_eq!(read("35 23 6\n1 2 7\n", TileListFormat::RenderList), ok);
+        assert_eq!(read("6,35,23\n7,1,2\n", TileListFormat::Csv), ok);
+        assert_eq!(read("x,y,zoom,size\n35,23,6,10\n1,2,7,20\n", TileListFormat::Csv), ok);
+        assert_eq!(read("6/35/23\n7,1,2\n", TileListFormat::Auto), ok);
+    }
+
+    #[test]
+    fn tile_list_errors() {
+        let res = read("6/35/23\n\n6/35\n1/2/0\n8 1/0/0\n7/1/2\n", TileListFormat::Zxy);
+        assert_eq!(res, vec![
+            Ok("6/35/23".to_string()),
+            Err(r#"line 3: expected z/x/y ("6/35")"#.to_string()),
+            Err(r#"line 4: invalid tile ("1/2/0")"#.to_string()),
+            Err(r#"line 5: expected a tile, not a metatile ("8 1/0/0")"#.to_string()),
+            Ok("7/1/2".to_string()),
+        ]);
+        assert!(read("a,b,c\n1,0,0\n", TileListFormat::Csv)[0].is_err());
+        assert!(read("1 0\n", TileListFormat::RenderList)[0].is_err());
+    }
+
+    #[test]
+    fn metatile_list() {
+        let list = "8 3/0/0\n3/300/0\n6/35/23\n";
+        let res: Vec<_> = MetatileListReader::new(list.as_bytes(), TileListFormat::Auto, 4).collect();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].as_ref().unwrap(), &Metatile::new(8, 3, 0, 0).unwrap());
+        assert!(res[1].is_err());
+        assert_eq!(res[2].as_ref().unwrap(), &Metatile::new(4, 6, 32, 20).unwrap());
+
+        let mut reader = MetatileListReader::new("scale,z,x,y\n8,3,0,0\n8,5,8,8\n".as_bytes(), TileListFormat::Csv, 1);
+        assert_eq!(reader.next().unwrap().unwrap(), Metatile::new(8, 3, 0, 0).unwrap());
+        assert_eq!((reader.line_number(), reader.offset()), (2, 20));
+
+        let mut reader = MetatileListReader::new("scale,z,x,y\n8,3,0,0\n8,5,8,8\n".as_bytes(), TileListFormat::Csv, 1);
+        reader.skip_to_offset(20).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), Metatile::new(8, 5, 8, 8).unwrap());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn tile_list_writer_round_trip() {
+        let tiles = vec![Tile::new(6, 35, 23).unwrap(), Tile::new(0, 0, 0).unwrap()];
+        for format in &[TileListFormat::Auto, TileListFormat::Zxy, TileListFormat::SpaceSeparated, TileListFormat::Csv, TileListFormat::RenderList] {
+            let mut writer = TileListWriter::new(Vec::new(), *format);
+            for t in &tiles {
+                writer.write_tile(t).unwrap();
+            }
+            let out = writer.finish().unwrap();
+            let read: Vec<Tile> = TileListReader::new(out.as_slice(), *format).collect::<Result<_, _>>().unwrap();
+            assert_eq!(read, tiles);
+        }
+
+        let mut writer = TileListWriter::new(Vec::new(), TileListFormat::Zxy);
+        writer.write_tile(&tiles[0]).unwrap();
+        writer.write_metatile(&Metatile::new(8, 6, 32, 16).unwrap()).unwrap();
+        writer.write_metatile(&Metatile::new_with_size(4, 2, 6, 32, 16).unwrap()).unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), "6/35/23\n8 6/32/16\n4x2 6/32/16\n");
+        let read: Vec<Metatile> = MetatileListReader::new(out.as_slice(), TileListFormat::Zxy, 8).collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, vec![Metatile::new(8, 6, 32, 16).unwrap(), Metatile::new(8, 6, 32, 16).unwrap(), Metatile::new_with_size(4, 2, 6, 32, 16).unwrap()]);
+
+        for format in &[TileListFormat::SpaceSeparated, TileListFormat::RenderList] {
+            let mut writer = TileListWriter::new(Vec::new(), *format);
+            let err = writer.write_metatile(&Metatile::new(8, 6, 32, 16).unwrap()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(writer.finish().unwrap().is_empty());
+        }
+
+        let mut writer = TileListWriter::new(Vec::new(), TileListFormat::Csv);
+        writer.write_metatile(&Metatile::new_with_size(4, 2, 6, 32, 16).unwrap()).unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), "scale,z,x,y\n4x2,6,32,16\n");
+        let read: Vec<Metatile> = MetatileListReader::new(out.as_slice(), TileListFormat::Csv, 1).collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, vec![Metatile::new_with_size(4, 2, 6, 32, 16).unwrap()]);
+    }
+
+    #[test]
+    fn tile_list_gzip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("list.txt.gz");
+        {
+            let mut writer = TileListWriter::create(&path, TileListFormat::Zxy).unwrap();
+            writer.write_tile(&Tile::new(6, 35, 23).unwrap()).unwrap();
+            writer.write_metatile(&Metatile::new(8, 6, 32, 16).unwrap()).unwrap();
+            writer.finish().unwrap();
+        }
+        let mut raw = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut raw).unwrap();
+        assert_eq!(&raw[..2], &[0x1f, 0x8b]);
+
+        let metatiles: Vec<Metatile> = MetatileListReader::open(&path, TileListFormat::Auto, 8).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(metatiles, vec![Metatile::new(8, 6, 32, 16).unwrap(), Metatile::new(8, 6, 32, 16).unwrap()]);
+
+        let mut writer = TileListWriter::new_gzip(Vec::new(), TileListFormat::Zxy);
+        writer.write_tile(&Tile::new(6, 35, 23).unwrap()).unwrap();
+        let mut list = String::new();
+        MultiGzDecoder::new(writer.finish().unwrap().as_slice()).read_to_string(&mut list).unwrap();
+        assert_eq!(list, "6/35/23\n");
+    }
+}
@@ -0,0 +1,736 @@
+//! OGC Two Dimensional Tile Matrix Sets (OGC 17-083r4).
+//!
+//! The rest of this crate presumes the usual EPSG:3857 XYZ tiling. A `TileMatrixSet` describes
+//! any other tiling, e.g. plate carrée (`WorldCRS84Quad`) or a national grid, and has generic
+//! versions of the corner/bbox/point-to-tile conversions that `Tile` has.
+//!
+//! ```
+//! use slippy_map_tiles::TileMatrixSet;
+//!
+//! let tms = TileMatrixSet::world_crs84_quad();
+//! // There are 2 tiles at zoom 0
+//! assert!(tms.tile(0, 1, 0).is_some());
+//! assert!(tms.tile(0, 2, 0).is_none());
+//! ```
+#[cfg(feature="json")]
+use serde_json::{self, Value, Map};
+
+use {Tile, LatLon, BBox};
+
+/// Radius of the WGS84 ellipsoid, as used by EPSG:3857
+const EARTH_RADIUS: f64 = 6_378_137.;
+
+/// Size of a pixel, as defined by OGC, in metres
+const STANDARD_PIXEL_SIZE: f64 = 0.00028;
+
+/// How many metres one degree is, at the equator
+const METRES_PER_DEGREE: f64 = 2. * ::std::f64::consts::PI * EARTH_RADIUS / 360.;
+
+const WEB_MERCATOR_QUAD_EXTENT: f64 = 20_037_508.342789244;
+
+/// Most tile matrices a tile matrix set can have
+#[cfg(any(feature="json", feature="serde"))]
+const MAX_TILE_MATRICES: usize = 100;
+
+/// Which corner of the tile matrix the `point_of_origin` is.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum CornerOfOrigin {
+    TopLeft,
+    BottomLeft,
+}
+
+/// What kind of CRS a tile matrix set uses. We only know how to convert to/from lat/lon for some.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+enum CrsKind {
+    WebMercator,
+    Geographic,
+    Other,
+}
+
+/// A tile of a `TileMatrixSet`: the zoom (index of the tile matrix), column & row.
+///
+/// Unlike a `Tile`, it needn't be in a square 2ᶻ×2ᶻ grid, so it has none of `Tile`'s Web Mercator
+/// maths. Use the `TileMatrixSet` to get its bounds & corners.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct MatrixTile {
+    pub(crate) zoom: u8,
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+}
+
+impl MatrixTile {
+    pub fn zoom(&self) -> u8 { self.zoom }
+
+    /// Column of this tile in its tile matrix
+    pub fn x(&self) -> u32 { self.x }
+
+    /// Row of this tile in its tile matrix
+    pub fn y(&self) -> u32 { self.y }
+
+    /// The Web Mercator `Tile` with this zoom, x & y, if there is one. Only the same place for a
+    /// `WebMercatorQuad` tile.
+    pub fn to_tile(&self) -> Option<Tile> {
+        Tile::new(self.zoom, self.x, self.y)
+    }
+}
+
+impl From<Tile> for MatrixTile {
+    /// The `WebMercatorQuad` tile for this `Tile`
+    fn from(tile: Tile) -> MatrixTile {
+        MatrixTile{ zoom: tile.zoom, x: tile.x, y: tile.y }
+    }
+}
+
+/// One zoom level of a `TileMatrixSet`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TileMatrix {
+    pub(crate) id: String,
+    pub(crate) scale_denominator: f64,
+    pub(crate) cell_size: f64,
+    pub(crate) corner_of_origin: CornerOfOrigin,
+    // Always (easting, northing), regardless of the axis order of the CRS
+    pub(crate) point_of_origin: (f64, f64),
+    pub(crate) tile_width: u32,
+    pub(crate) tile_height: u32,
+    pub(crate) matrix_width: u32,
+    pub(crate) matrix_height: u32,
+}
+
+impl TileMatrix {
+    /// Identifier of this tile matrix, usually the zoom as a string.
+    pub fn id(&self) -> &str { &self.id }
+
+    pub fn scale_denominator(&self) -> f64 { self.scale_denominator }
+
+    /// Size of one pixel, in CRS units.
+    pub fn cell_size(&self) -> f64 { self.cell_size }
+
+    pub fn corner_of_origin(&self) -> CornerOfOrigin { self.corner_of_origin }
+
+    /// The origin of this matrix as (easting, northing), in CRS units.
+    pub fn point_of_origin(&self) -> (f64, f64) { self.point_of_origin }
+
+    /// Width of each tile, in pixels
+    pub fn tile_width(&self) -> u32 { self.tile_width }
+
+    /// Height of each tile, in pixels
+    pub fn tile_height(&self) -> u32 { self.tile_height }
+
+    /// Number of tiles across
+    pub fn matrix_width(&self) -> u32 { self.matrix_width }
+
+    /// Number of tiles down
+    pub fn matrix_height(&self) -> u32 { self.matrix_height }
+
+    /// Is this x/y a tile in this matrix
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.matrix_width && y < self.matrix_height
+    }
+
+    fn tile_span_x(&self) -> f64 {
+        self.cell_size * self.tile_width as f64
+    }
+
+    fn tile_span_y(&self) -> f64 {
+        self.cell_size * self.tile_height as f64
+    }
+
+    /// The (min x, min y, max x, max y) of this tile, in CRS units.
+    pub fn tile_crs_bounds(&self, x: u32, y: u32) -> (f64, f64, f64, f64) {
+        let (span_x, span_y) = (self.tile_span_x(), self.tile_span_y());
+        let minx = self.point_of_origin.0 + x as f64 * span_x;
+        match self.corner_of_origin {
+            CornerOfOrigin::TopLeft => {
+                let maxy = self.point_of_origin.1 - y as f64 * span_y;
+                (minx, maxy - span_y, minx + span_x, maxy)
+            },
+            CornerOfOrigin::BottomLeft => {
+                let miny = self.point_of_origin.1 + y as f64 * span_y;
+                (minx, miny, minx + span_x, miny + span_y)
+            },
+        }
+    }
+
+    /// The (min x, min y, max x, max y) of the whole matrix, in CRS units.
+    pub fn crs_bounds(&self) -> (f64, f64, f64, f64) {
+        let first = self.tile_crs_bounds(0, 0);
+        let last = self.tile_crs_bounds(self.matrix_width - 1, self.matrix_height - 1);
+        (first.0.min(last.0), first.1.min(last.1), first.2.max(last.2), first.3.max(last.3))
+    }
+
+    /// The x/y of the tile which contains this CRS point. Points on the right/bottom edge of the
+    /// matrix are in the last tile. `None` if the point is outside this matrix.
+    pub fn crs_to_tile_xy(&self, crs_x: f64, crs_y: f64) -> Option<(u32, u32)> {
+        let (minx, miny, maxx, maxy) = self.crs_bounds();
+        if crs_x < minx || crs_x > maxx || crs_y < miny || crs_y > maxy {
+            return None;
+        }
+
+        let x = ((crs_x - self.point_of_origin.0) / self.tile_span_x()).floor();
+        let y = match self.corner_of_origin {
+            CornerOfOrigin::TopLeft => (self.point_of_origin.1 - crs_y) / self.tile_span_y(),
+            CornerOfOrigin::BottomLeft => (crs_y - self.point_of_origin.1) / self.tile_span_y(),
+        }.floor();
+
+        let x = (x.max(0.) as u32).min(self.matrix_width - 1);
+        let y = (y.max(0.) as u32).min(self.matrix_height - 1);
+
+        Some((x, y))
+    }
+
+    #[cfg(feature="json")]
+    /// Parse one tile matrix from the OGC 2.0 JSON encoding. `northing_first` is the axis order
+    /// of the `pointOfOrigin`, and `metres_per_unit` is used to calculate a missing `cellSize`.
+    pub(crate) fn from_json_value(tm: &Value, northing_first: bool, metres_per_unit: f64) -> Result<Self, &'static str> {
+        let tm = tm.as_object().ok_or("tile matrix is not a JSON object")?;
+        if tm.contains_key("variableMatrixWidths") {
+            return Err("variableMatrixWidths are not supported");
+        }
+
+        let tm_id = match tm.get("id") {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Number(n)) => n.to_string(),
+            _ => { return Err("tile matrix has no id"); },
+        };
+        let scale_denominator = tm.get("scaleDenominator").and_then(|v| v.as_f64()).ok_or("tile matrix has no scaleDenominator")?;
+        let cell_size = match tm.get("cellSize").and_then(|v| v.as_f64()) {
+            Some(c) => c,
+            None => cell_size_for_scale(scale_denominator, metres_per_unit),
+        };
+        let corner_of_origin = match tm.get("cornerOfOrigin").and_then(|v| v.as_str()) {
+            None | Some("topLeft") => CornerOfOrigin::TopLeft,
+            Some("bottomLeft") => CornerOfOrigin::BottomLeft,
+            Some(_) => { return Err("invalid cornerOfOrigin"); },
+        };
+        let origin = tm.get("pointOfOrigin").and_then(|v| v.as_array()).ok_or("tile matrix has no pointOfOrigin")?;
+        if origin.len() != 2 {
+            return Err("invalid pointOfOrigin");
+        }
+        let origin = (origin[0].as_f64().ok_or("invalid pointOfOrigin")?, origin[1].as_f64().ok_or("invalid pointOfOrigin")?);
+        let point_of_origin = if northing_first { (origin.1, origin.0) } else { origin };
+
+        let tile_width = json_u32(tm, "tileWidth")?;
+        let tile_height = json_u32(tm, "tileHeight")?;
+        let matrix_width = json_u32(tm, "matrixWidth")?;
+        let matrix_height = json_u32(tm, "matrixHeight")?;
+
+        TileMatrix {
+            id: tm_id,
+            scale_denominator,
+            cell_size,
+            corner_of_origin,
+            point_of_origin,
+            tile_width,
+            tile_height,
+            matrix_width,
+            matrix_height,
+        }.check()
+    }
+
+    #[cfg(any(feature="json", feature="serde"))]
+    /// `Err` if this tile matrix has no size
+    pub(crate) fn check(self) -> Result<Self, &'static str> {
+        if self.cell_size <= 0. || self.cell_size.is_nan() || self.tile_width == 0 || self.tile_height == 0 || self.matrix_width == 0 || self.matrix_height == 0 {
+            return Err("tile matrix has zero size");
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature="json")]
+    pub(crate) fn to_json_value(&self) -> Value {
+        let mut m = Map::new();
+        m.insert("id".to_string(), Value::String(self.id.clone()));
+        m.insert("scaleDenominator".to_string(), json_number(self.scale_denominator));
+        m.insert("cellSize".to_string(), json_number(self.cell_size));
+        m.insert("cornerOfOrigin".to_string(), Value::String(match self.corner_of_origin {
+            CornerOfOrigin::TopLeft => "topLeft",
+            CornerOfOrigin::BottomLeft => "bottomLeft",
+        }.to_string()));
+        m.insert("pointOfOrigin".to_string(), Value::Array(vec![json_number(self.point_of_origin.0), json_number(self.point_of_origin.1)]));
+        m.insert("tileWidth".to_string(), Value::from(self.tile_width));
+        m.insert("tileHeight".to_string(), Value::from(self.tile_height));
+        m.insert("matrixWidth".to_string(), Value::from(self.matrix_width));
+        m.insert("matrixHeight".to_string(), Value::from(self.matrix_height));
+        Value::Object(m)
+    }
+}
+
+/// A Tile Matrix Set, i.e. a tiling scheme: a CRS and a list of tile matrices, one per zoom.
+///
+/// The `zoom` of a `MatrixTile` is the index into the list of tile matrices.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TileMatrixSet {
+    pub(crate) id: String,
+    pub(crate) title: Option<String>,
+    pub(crate) uri: Option<String>,
+    pub(crate) crs: String,
+    pub(crate) well_known_scale_set: Option<String>,
+    pub(crate) tile_matrices: Vec<TileMatrix>,
+}
+
+impl TileMatrixSet {
+    /// The usual EPSG:3857 'slippy map' tiling, as used by the rest of this crate.
+    pub fn web_mercator_quad() -> Self {
+        let tile_matrices = (0..25).map(|z| {
+            let cell_size = 2. * WEB_MERCATOR_QUAD_EXTENT / 256. / 2f64.powi(z);
+            TileMatrix {
+                id: format!("{}", z),
+                scale_denominator: cell_size / STANDARD_PIXEL_SIZE,
+                cell_size,
+                corner_of_origin: CornerOfOrigin::TopLeft,
+                point_of_origin: (-WEB_MERCATOR_QUAD_EXTENT, WEB_MERCATOR_QUAD_EXTENT),
+                tile_width: 256,
+                tile_height: 256,
+                matrix_width: 2u32.pow(z as u32),
+                matrix_height: 2u32.pow(z as u32),
+            }
+        }).collect();
+
+        TileMatrixSet {
+            id: "WebMercatorQuad".to_string(),
+            title: Some("Google Maps Compatible for the World".to_string()),
+            uri: Some("http://www.opengis.net/def/tilematrixset/OGC/1.0/WebMercatorQuad".to_string()),
+            crs: "http://www.opengis.net/def/crs/EPSG/0/3857".to_string(),
+            well_known_scale_set: Some("http://www.opengis.net/def/wkss/OGC/1.0/GoogleMapsCompatible".to_string()),
+            tile_matrices,
+        }
+    }
+
+    /// Plate carrée (EPSG:4326 / CRS84) tiling, with 2×1 tiles at zoom 0.
+    pub fn world_crs84_quad() -> Self {
+        let tile_matrices = (0..25).map(|z| {
+            let cell_size = 180. / 256. / 2f64.powi(z);
+            TileMatrix {
+                id: format!("{}", z),
+                scale_denominator: cell_size * METRES_PER_DEGREE / STANDARD_PIXEL_SIZE,
+                cell_size,
+                corner_of_origin: CornerOfOrigin::TopLeft,
+                point_of_origin: (-180., 90.),
+                tile_width: 256,
+                tile_height: 256,
+                matrix_width: 2u32.pow(z as u32 + 1),
+                matrix_height: 2u32.pow(z as u32),
+            }
+        }).collect();
+
+        TileMatrixSet {
+            id: "WorldCRS84Quad".to_string(),
+            title: Some("CRS84 for the World".to_string()),
+            uri: Some("http://www.opengis.net/def/tilematrixset/OGC/1.0/WorldCRS84Quad".to_string()),
+            crs: "http://www.opengis.net/def/crs/OGC/1.3/CRS84".to_string(),
+            well_known_scale_set: Some("http://www.opengis.net/def/wkss/OGC/1.0/GoogleCRS84Quad".to_string()),
+            tile_matrices,
+        }
+    }
+
+    #[cfg(feature="json")]
+    /// Load a tile matrix set from its OGC 2.0 JSON encoding.
+    ///
+    /// If a tile matrix has no `cellSize`, it is calculated from the `scaleDenominator`.
+    /// `variableMatrixWidths` are not supported.
+    ///
+    /// Needs the `json` feature.
+    pub fn from_json(json: &str) -> Result<Self, &'static str> {
+        let root: Value = serde_json::from_str(json).map_err(|_| "invalid JSON")?;
+        TileMatrixSet::from_json_value(&root)
+    }
+
+    #[cfg(feature="json")]
+    pub(crate) fn from_json_value(root: &Value) -> Result<Self, &'static str> {
+        let root = root.as_object().ok_or("tile matrix set is not a JSON object")?;
+
+        let id = root.get("id").and_then(|v| v.as_str()).ok_or("no id")?.to_string();
+        let title = root.get("title").and_then(|v| v.as_str()).map(|s| s.to_string());
+        let uri = root.get("uri").and_then(|v| v.as_str()).map(|s| s.to_string());
+        let well_known_scale_set = root.get("wellKnownScaleSet").and_then(|v| v.as_str()).map(|s| s.to_string());
+        // crs can be a URI, or an object with a URI
+        let crs = match root.get("crs") {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Object(o)) => o.get("uri").and_then(|v| v.as_str()).ok_or("crs has no uri")?.to_string(),
+            _ => { return Err("no crs"); },
+        };
+
+        let first_axis = match root.get("orderedAxes").and_then(|v| v.as_array()) {
+            Some(axes) => Some(axes.first().and_then(|v| v.as_str()).ok_or("invalid orderedAxes")?),
+            None => None,
+        };
+        let northing_first = northing_first(&crs, first_axis);
+        let metres_per_unit = metres_per_unit(&crs);
+
+        let matrices = root.get("tileMatrices").and_then(|v| v.as_array()).ok_or("no tileMatrices")?;
+        if matrices.len() > MAX_TILE_MATRICES {
+            return Err("too many tileMatrices");
+        }
+
+        let tile_matrices = matrices.iter().map(|tm| TileMatrix::from_json_value(tm, northing_first, metres_per_unit)).collect::<Result<Vec<_>, _>>()?;
+
+        TileMatrixSet {
+            id,
+            title,
+            uri,
+            crs,
+            well_known_scale_set,
+            tile_matrices,
+        }.check()
+    }
+
+    #[cfg(any(feature="json", feature="serde"))]
+    /// `Err` if this has no tile matrices, or too many
+    pub(crate) fn check(self) -> Result<Self, &'static str> {
+        if self.tile_matrices.is_empty() {
+            return Err("no tileMatrices");
+        }
+        if self.tile_matrices.len() > MAX_TILE_MATRICES {
+            return Err("too many tileMatrices");
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature="json")]
+    /// The OGC 2.0 JSON encoding of this tile matrix set.
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    #[cfg(feature="json")]
+    pub(crate) fn to_json_value(&self) -> Value {
+        let mut m = Map::new();
+        m.insert("id".to_string(), Value::String(self.id.clone()));
+        if let Some(ref title) = self.title {
+            m.insert("title".to_string(), Value::String(title.clone()));
+        }
+        if let Some(ref uri) = self.uri {
+            m.insert("uri".to_string(), Value::String(uri.clone()));
+        }
+        m.insert("crs".to_string(), Value::String(self.crs.clone()));
+        m.insert("orderedAxes".to_string(), Value::Array(ordered_axes(&self.crs).iter().map(|a| Value::String(a.to_string())).collect()));
+        if let Some(ref wkss) = self.well_known_scale_set {
+            m.insert("wellKnownScaleSet".to_string(), Value::String(wkss.clone()));
+        }
+        m.insert("tileMatrices".to_string(), Value::Array(self.tile_matrices.iter().map(|tm| tm.to_json_value()).collect()));
+
+        Value::Object(m)
+    }
+
+    pub fn id(&self) -> &str { &self.id }
+
+    pub fn title(&self) -> Option<&str> { self.title.as_deref() }
+
+    pub fn uri(&self) -> Option<&str> { self.uri.as_deref() }
+
+    /// URI of the CRS of this tile matrix set
+    pub fn crs(&self) -> &str { &self.crs }
+
+    pub fn well_known_scale_set(&self) -> Option<&str> { self.well_known_scale_set.as_deref() }
+
+    pub fn tile_matrices(&self) -> &[TileMatrix] { &self.tile_matrices }
+
+    /// The tile matrix for this zoom, if there is one
+    pub fn tile_matrix(&self, zoom: u8) -> Option<&TileMatrix> {
+        self.tile_matrices.get(zoom as usize)
+    }
+
+    /// The highest zoom of this tile matrix set
+    pub fn maxzoom(&self) -> u8 {
+        (self.tile_matrices.len() - 1) as u8
+    }
+
+    /// Constructs a tile, returning `None` if the x/y are not in the tile matrix for this zoom.
+    /// Unlike `Tile::new`, this allows tiles which are not in a square 2ᶻ×2ᶻ grid.
+    pub fn tile(&self, zoom: u8, x: u32, y: u32) -> Option<MatrixTile> {
+        let tm = self.tile_matrix(zoom)?;
+        if tm.contains(x, y) {
+            Some(MatrixTile{ zoom, x, y })
+        } else {
+            None
+        }
+    }
+
+    /// The (min x, min y, max x, max y) of this tile, in CRS units.
+    pub fn tile_crs_bounds(&self, tile: &MatrixTile) -> Option<(f64, f64, f64, f64)> {
+        let tm = self.tile_matrix(tile.zoom)?;
+        if !tm.contains(tile.x, tile.y) {
+            return None;
+        }
+        Some(tm.tile_crs_bounds(tile.x, tile.y))
+    }
+
+    /// The tile at this zoom which contains this point (in CRS units).
+    pub fn crs_to_tile(&self, crs_x: f64, crs_y: f64, zoom: u8) -> Option<MatrixTile> {
+        let tm = self.tile_matrix(zoom)?;
+        let (x, y) = tm.crs_to_tile_xy(crs_x, crs_y)?;
+        Some(MatrixTile{ zoom, x, y })
+    }
+
+    /// Convert this lat/lon to (easting, northing) in this CRS. `None` if we don't know how to
+    /// project to this CRS.
+    pub fn lat_lon_to_crs(&self, point: &LatLon) -> Option<(f64, f64)> {
+        let (lat, lon) = (point.lat() as f64, point.lon() as f64);
+        match crs_kind(&self.crs) {
+            CrsKind::Geographic => Some((lon, lat)),
+            CrsKind::WebMercator => {
+                let max_lat = ::std::f64::consts::PI.sinh().atan().to_degrees();
+                let lat = lat.max(-max_lat).min(max_lat);
+                let x = EARTH_RADIUS * lon.to_radians();
+                let y = EARTH_RADIUS * (::std::f64::consts::FRAC_PI_4 + lat.to_radians() / 2.).tan().ln();
+                Some((x, y))
+            },
+            CrsKind::Other => None,
+        }
+    }
+
+    /// Convert this (easting, northing) in this CRS to a lat/lon. `None` if we don't know how to
+    /// unproject from this CRS, or the result is not a valid lat/lon.
+    pub fn crs_to_lat_lon(&self, crs_x: f64, crs_y: f64) -> Option<LatLon> {
+        match crs_kind(&self.crs) {
+            CrsKind::Geographic => LatLon::new(crs_y as f32, crs_x as f32),
+            CrsKind::WebMercator => {
+                let lon = (crs_x / EARTH_RADIUS).to_degrees();
+                let lat = (crs_y / EARTH_RADIUS).sinh().atan().to_degrees();
+                LatLon::new(lat as f32, lon as f32)
+            },
+            CrsKind::Other => None,
+        }
+    }
+
+    /// The tile at this zoom which contains this point
+    pub fn lat_lon_to_tile(&self, point: &LatLon, zoom: u8) -> Option<MatrixTile> {
+        let (x, y) = self.lat_lon_to_crs(point)?;
+        self.crs_to_tile(x, y, zoom)
+    }
+
+    /// Returns the LatLon of the top left, i.e. north west corner, of this tile.
+    pub fn nw_corner(&self, tile: &MatrixTile) -> Option<LatLon> {
+        let (minx, _, _, maxy) = self.tile_crs_bounds(tile)?;
+        self.crs_to_lat_lon(minx, maxy)
+    }
+
+    /// Returns the LatLon of the top right, i.e. north east corner, of this tile.
+    pub fn ne_corner(&self, tile: &MatrixTile) -> Option<LatLon> {
+        let (_, _, maxx, maxy) = self.tile_crs_bounds(tile)?;
+        self.crs_to_lat_lon(maxx, maxy)
+    }
+
+    /// Returns the LatLon of the bottom left, i.e. south west corner, of this tile.
+    pub fn sw_corner(&self, tile: &MatrixTile) -> Option<LatLon> {
+        let (minx, miny, _, _) = self.tile_crs_bounds(tile)?;
+        self.crs_to_lat_lon(minx, miny)
+    }
+
+    /// Returns the LatLon of the bottom right, i.e. south east corner, of this tile.
+    pub fn se_corner(&self, tile: &MatrixTile) -> Option<LatLon> {
+        let (_, miny, maxx, _) = self.tile_crs_bounds(tile)?;
+        self.crs_to_lat_lon(maxx, miny)
+    }
+
+    /// Returns the LatLon for the centre of this tile.
+    pub fn centre_point(&self, tile: &MatrixTile) -> Option<LatLon> {
+        let (minx, miny, maxx, maxy) = self.tile_crs_bounds(tile)?;
+        self.crs_to_lat_lon((minx + maxx) / 2., (miny + maxy) / 2.)
+    }
+
+    /// The BBox for this tile.
+    pub fn bbox(&self, tile: &MatrixTile) -> Option<BBox> {
+        let nw = self.nw_corner(tile)?;
+        let se = self.se_corner(tile)?;
+        Some(BBox::new_from_points(&nw, &se))
+    }
+}
+
+fn crs_kind(crs: &str) -> CrsKind {
+    let crs = crs.to_uppercase();
+    let is_code = |code: &str| {
+        crs.ends_with(&format!("EPSG/0/{}", code)) || crs.ends_with(&format!("EPSG::{}", code)) || crs == format!("EPSG:{}", code)
+    };
+
+    if is_code("3857") || is_code("900913") {
+        CrsKind::WebMercator
+    } else if crs.ends_with("CRS84") || is_code("4326") {
+        CrsKind::Geographic
+    } else {
+        CrsKind::Other
+    }
+}
+
+#[cfg(any(feature="json", feature="serde"))]
+/// Is the first axis of this CRS northing? From the first of the `orderedAxes`, if there are any
+pub(crate) fn northing_first(crs: &str, first_axis: Option<&str>) -> bool {
+    match first_axis {
+        Some(first) => {
+            let first = first.to_lowercase();
+            first == "lat" || first == "y" || first == "n" || first == "northing"
+        },
+        None => crs_kind(crs) == CrsKind::Geographic && !crs.contains("CRS84"),
+    }
+}
+
+#[cfg(any(feature="json", feature="serde"))]
+/// The `orderedAxes` to write. Points are always stored easting first. CRS84 is lon/lat, as is
+/// the WorldCRS84Quad in OGC 17-083r4 Annex D
+pub(crate) fn ordered_axes(crs: &str) -> [&'static str; 2] {
+    match crs_kind(crs) {
+        CrsKind::Geographic => ["Lon", "Lat"],
+        _ => ["X", "Y"],
+    }
+}
+
+#[cfg(any(feature="json", feature="serde"))]
+/// How many metres one unit of this CRS is, for calculating a missing `cellSize`
+pub(crate) fn metres_per_unit(crs: &str) -> f64 {
+    match crs_kind(crs) {
+        CrsKind::Geographic => METRES_PER_DEGREE,
+        _ => 1.,
+    }
+}
+
+#[cfg(any(feature="json", feature="serde"))]
+/// The `cellSize` of a tile matrix with this scale denominator
+pub(crate) fn cell_size_for_scale(scale_denominator: f64, metres_per_unit: f64) -> f64 {
+    scale_denominator * STANDARD_PIXEL_SIZE / metres_per_unit
+}
+
+#[cfg(feature="json")]
+fn json_u32(obj: &Map<String, Value>, key: &'static str) -> Result<u32, &'static str> {
+    obj.get(key).and_then(|v| v.as_u64()).and_then(|v| if v <= u32::MAX as u64 { Some(v as u32) } else { None }).ok_or("tile matrix has invalid size")
+}
+
+#[cfg(feature="json")]
+fn json_number(n: f64) -> Value {
+    serde_json::Number::from_f64(n).map(Value::Number).unwrap_or(Value::Null)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn web_mercator_quad_matches_tile() {
+        let tms = TileMatrixSet::web_mercator_quad();
+        assert_eq!(tms.maxzoom(), 24);
+        assert!(tms.tile(0, 1, 0).is_none());
+
+        let t = Tile::new(6, 35, 23).unwrap();
+        let mt = tms.tile(6, 35, 23).unwrap();
+        assert_eq!(mt, MatrixTile::from(t));
+        assert_eq!(mt.to_tile(), Some(t));
+        let nw = tms.nw_corner(&mt).unwrap();
+        assert!((nw.lat() - t.nw_corner().lat()).abs() < 1e-4);
+        assert!((nw.lon() - t.nw_corner().lon()).abs() < 1e-4);
+        let se = tms.se_corner(&mt).unwrap();
+        assert!((se.lat() - t.se_corner().lat()).abs() < 1e-4);
+        assert!((se.lon() - t.se_corner().lon()).abs() < 1e-4);
+
+        let p = LatLon::new(53.35, -6.26).unwrap();
+        let (x, y) = ::lat_lon_to_tile(p.lat(), p.lon(), 12);
+        assert_eq!(tms.lat_lon_to_tile(&p, 12), tms.tile(12, x, y));
+
+        let tm = tms.tile_matrix(0).unwrap();
+        assert!((tm.scale_denominator() - 559082264.0287178).abs() < 1e-3);
+    }
+
+    #[test]
+    fn world_crs84_quad() {
+        let tms = TileMatrixSet::world_crs84_quad();
+        let t0 = tms.tile(0, 0, 0).unwrap();
+        let t1 = tms.tile(0, 1, 0).unwrap();
+        assert!(tms.tile(0, 0, 1).is_none());
+        assert!(tms.tile(0, 2, 0).is_none());
+
+        assert_eq!(tms.tile_crs_bounds(&t0), Some((-180., -90., 0., 90.)));
+        assert_eq!(tms.tile_crs_bounds(&t1), Some((0., -90., 180., 90.)));
+        assert_eq!(tms.bbox(&t1), BBox::new(90., 0., -90., 180.));
+        // Not a Web Mercator tile, which would be 0/1/0 with a different bbox
+        assert_eq!((t1.zoom(), t1.x(), t1.y()), (0, 1, 0));
+        assert_eq!(t1.to_tile(), None);
+
+        assert_eq!(tms.lat_lon_to_tile(&LatLon::new(45., 90.).unwrap(), 1), tms.tile(1, 3, 0));
+        assert_eq!(tms.lat_lon_to_tile(&LatLon::new(-90., 180.).unwrap(), 1), tms.tile(1, 3, 1));
+        assert_eq!(tms.crs_to_tile(181., 0., 1), None);
+
+        let tm = tms.tile_matrix(0).unwrap();
+        assert!((tm.scale_denominator() - 279541132.0143589).abs() < 1e-3);
+    }
+
+    #[test]
+    #[cfg(feature="json")]
+    fn world_crs84_quad_json() {
+        // From OGC 17-083r4 Annex D
+        let expected: Value = serde_json::from_str(r#"{
+            "id": "WorldCRS84Quad",
+            "title": "CRS84 for the World",
+            "uri": "http://www.opengis.net/def/tilematrixset/OGC/1.0/WorldCRS84Quad",
+            "crs": "http://www.opengis.net/def/crs/OGC/1.3/CRS84",
+            "orderedAxes": ["Lon", "Lat"],
+            "wellKnownScaleSet": "http://www.opengis.net/def/wkss/OGC/1.0/GoogleCRS84Quad"
+        }"#).unwrap();
+        let json: Value = serde_json::from_str(&TileMatrixSet::world_crs84_quad().to_json()).unwrap();
+        for (k, v) in expected.as_object().unwrap() {
+            assert_eq!(json.get(k), Some(v), "{}", k);
+        }
+
+        let matrices = json["tileMatrices"].as_array().unwrap();
+        assert_eq!(matrices.len(), 25);
+        let tm = &matrices[1];
+        assert_eq!(tm["id"], "1");
+        assert_eq!(tm["cellSize"].as_f64(), Some(0.3515625));
+        assert_eq!(tm["cornerOfOrigin"], "topLeft");
+        assert_eq!(tm["pointOfOrigin"][0].as_f64(), Some(-180.));
+        assert_eq!(tm["pointOfOrigin"][1].as_f64(), Some(90.));
+        assert_eq!(tm["matrixWidth"], 4);
+        assert_eq!(tm["matrixHeight"], 2);
+        assert!((tm["scaleDenominator"].as_f64().unwrap() - 139770566.0071794).abs() < 1e-3);
+
+        // And it reads back the same
+        let tms = TileMatrixSet::from_json(&json.to_string()).unwrap();
+        assert_eq!(tms.tile_matrix(1).unwrap().point_of_origin(), (-180., 90.));
+    }
+
+    #[test]
+    #[cfg(feature="json")]
+    fn custom_from_json() {
+        // A made up national grid, origin at the bottom left, and no cellSize
+        let json = r#"{
+            "id": "TestGrid",
+            "crs": {"uri": "http://www.opengis.net/def/crs/EPSG/0/27700"},
+            "tileMatrices": [
+                {"id": "0", "scaleDenominator": 3657142.857142857, "cornerOfOrigin": "bottomLeft",
+                 "pointOfOrigin": [0, 0], "tileWidth": 256, "tileHeight": 256, "matrixWidth": 3, "matrixHeight": 5},
+                {"id": "1", "scaleDenominator": 1828571.4285714286, "cornerOfOrigin": "bottomLeft",
+                 "pointOfOrigin": [0, 0], "tileWidth": 256, "tileHeight": 256, "matrixWidth": 6, "matrixHeight": 10}
+            ]
+        }"#;
+        let tms = TileMatrixSet::from_json(json).unwrap();
+        assert_eq!(tms.id(), "TestGrid");
+        assert_eq!(tms.maxzoom(), 1);
+        let tm = tms.tile_matrix(0).unwrap();
+        assert!((tm.cell_size() - 1024.).abs() < 1e-6);
+
+        // 262144m wide tiles, counted from the bottom
+        let t = tms.crs_to_tile(300_000., 10_000., 0).unwrap();
+        assert_eq!((t.zoom(), t.x(), t.y()), (0, 1, 0));
+        let (minx, miny, maxx, maxy) = tms.tile_crs_bounds(&t).unwrap();
+        assert!((minx - 262_144.).abs() < 1e-6 && miny.abs() < 1e-6);
+        assert!((maxx - 524_288.).abs() < 1e-6 && (maxy - 262_144.).abs() < 1e-6);
+
+        // Can't convert lat/lon for a CRS we don't know
+        assert_eq!(tms.nw_corner(&t), None);
+
+        assert_eq!(TileMatrixSet::from_json(&tms.to_json()), Ok(tms));
+    }
+
+    #[test]
+    #[cfg(feature="json")]
+    fn from_json_axis_order() {
+        // EPSG:4326 is lat/lon order
+        let json = r#"{"id": "x", "crs": "http://www.opengis.net/def/crs/EPSG/0/4326",
+            "tileMatrices": [{"id": "0", "scaleDenominator": 279541132.0143589, "pointOfOrigin": [90, -180],
+            "tileWidth": 256, "tileHeight": 256, "matrixWidth": 2, "matrixHeight": 1}]}"#;
+        let tms = TileMatrixSet::from_json(json).unwrap();
+        assert_eq!(tms.tile_matrix(0).unwrap().point_of_origin(), (-180., 90.));
+        assert!((tms.tile_matrix(0).unwrap().cell_size() - 0.703125).abs() < 1e-9);
+
+        assert!(TileMatrixSet::from_json("[]").is_err());
+        assert!(TileMatrixSet::from_json(r#"{"id": "x", "crs": "foo", "tileMatrices": []}"#).is_err());
+    }
+}
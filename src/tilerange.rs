@@ -0,0 +1,223 @@
+//! Rectangular ranges of tiles at one zoom.
+use {Tile, BBox, bbox_tile_corners};
+
+/// All the tiles, at one zoom, with x from `min_x` to `max_x`, and y from `min_y` to `max_y`
+/// (inclusive).
+///
+/// ```
+/// # use slippy_map_tiles::{Tile, TileRange};
+/// let range = TileRange::new(3, 2, 3, 4, 6).unwrap();
+/// assert_eq!(range.num_tiles(), 6);
+/// assert!(range.contains(&Tile::new(3, 2, 5).unwrap()));
+/// assert_eq!(range.iter().next(), Tile::new(3, 2, 4));
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct TileRange {
+    zoom: u8,
+    min_x: u32,
+    max_x: u32,
+    min_y: u32,
+    max_y: u32,
+}
+
+impl TileRange {
+    /// Returns `None` if the range is empty (a min is greater than a max), not valid for this
+    /// zoom, or the zoom is more than 31.
+    pub fn new(zoom: u8, min_x: u32, max_x: u32, min_y: u32, max_y: u32) -> Option<TileRange> {
+        if zoom > 31 || min_x > max_x || min_y > max_y || max_x >= 1 << zoom || max_y >= 1 << zoom {
+            None
+        } else {
+            Some(TileRange{ zoom, min_x, max_x, min_y, max_y })
+        }
+    }
+
+    /// Every tile at this zoom. `None` if the zoom is more than 31.
+    pub fn all(zoom: u8) -> Option<TileRange> {
+        if zoom > 31 {
+            return None;
+        }
+        let max = (1u32 << zoom) - 1;
+        TileRange::new(zoom, 0, max, 0, max)
+    }
+
+    /// The tiles which cover this bbox at this zoom. `None` if the zoom is more than 31, or the
+    /// bbox crosses the antimeridian (`left` is more than `right`) or is upside down.
+    pub fn new_for_bbox(bbox: &BBox, zoom: u8) -> Option<TileRange> {
+        if zoom > 31 || bbox.left() > bbox.right() || bbox.bottom() > bbox.top() {
+            return None;
+        }
+        let ((x1, y1), (x2, y2)) = bbox_tile_corners(bbox, zoom);
+        TileRange::new(zoom, x1, x2, y1, y2)
+    }
+
+    /// The metatiles, of this width & height, which cover this bbox at this zoom, in metatile
+    /// coordinates (i.e. tile x & y divided by the width & height). `None` like `new_for_bbox`.
+    pub(crate) fn new_for_bbox_metatiles(bbox: &BBox, zoom: u8, (width, height): (u8, u8)) -> Option<TileRange> {
+        let tiles = TileRange::new_for_bbox(bbox, zoom)?;
+        let (width, height) = (width.max(1) as u32, height.max(1) as u32);
+        TileRange::new(zoom, tiles.min_x / width, tiles.max_x / width, tiles.min_y / height, tiles.max_y / height)
+    }
+
+    /// Zoom of these tiles
+    pub fn zoom(&self) -> u8 { self.zoom }
+
+    /// Smallest x
+    pub fn min_x(&self) -> u32 { self.min_x }
+
+    /// Largest x
+    pub fn max_x(&self) -> u32 { self.max_x }
+
+    /// Smallest y
+    pub fn min_y(&self) -> u32 { self.min_y }
+
+    /// Largest y
+    pub fn max_y(&self) -> u32 { self.max_y }
+
+    /// How many tiles are in this range
+    pub fn num_tiles(&self) -> u64 {
+        (self.max_x - self.min_x + 1) as u64 * (self.max_y - self.min_y + 1) as u64
+    }
+
+    /// Is this tile in this range?
+    pub fn contains(&self, tile: &Tile) -> bool {
+        tile.zoom == self.zoom && tile.x >= self.min_x && tile.x <= self.max_x && tile.y >= self.min_y && tile.y <= self.max_y
+    }
+
+    /// The BBox which these tiles cover
+    pub fn bbox(&self) -> BBox {
+        let nw = Tile{ zoom: self.zoom, x: self.min_x, y: self.min_y }.nw_corner();
+        let se = Tile{ zoom: self.zoom, x: self.max_x, y: self.max_y }.se_corner();
+        BBox::new_from_points(&nw, &se)
+    }
+
+    /// Iterate over the tiles, by x then y.
+    pub fn iter(&self) -> TileRangeIterator {
+        TileRangeIterator{ range: *self, next: Some((self.min_x, self.min_y)) }
+    }
+
+    /// Split this in 2, along a quadtree boundary, across the longest side. `None` if this is
+    /// only one tile.
+    #[cfg_attr(not(feature="rayon"), allow(dead_code))]
+    pub(crate) fn split(&self) -> Option<(TileRange, TileRange)> {
+        fn split_point(min: u32, max: u32) -> u32 {
+            // The start of the largest aligned block which starts inside, but after `min`
+            let bit = 31 - (min ^ max).leading_zeros();
+            (max >> bit) << bit
+        }
+
+        if self.max_x - self.min_x >= self.max_y - self.min_y && self.max_x > self.min_x {
+            let mid = split_point(self.min_x, self.max_x);
+            Some((TileRange{ max_x: mid - 1, ..*self }, TileRange{ min_x: mid, ..*self }))
+        } else if self.max_y > self.min_y {
+            let mid = split_point(self.min_y, self.max_y);
+            Some((TileRange{ max_y: mid - 1, ..*self }, TileRange{ min_y: mid, ..*self }))
+        } else {
+            None
+        }
+    }
+}
+
+impl IntoIterator for TileRange {
+    type Item = Tile;
+    type IntoIter = TileRangeIterator;
+
+    fn into_iter(self) -> TileRangeIterator {
+        self.iter()
+    }
+}
+
+/// Iterates over the tiles in a `TileRange`
+#[derive(Debug, Clone)]
+pub struct TileRangeIterator {
+    range: TileRange,
+    next: Option<(u32, u32)>,
+}
+
+impl Iterator for TileRangeIterator {
+    type Item = Tile;
+
+    fn next(&mut self) -> Option<Tile> {
+        let (x, y) = self.next?;
+        self.next = if y < self.range.max_y {
+            Some((x, y + 1))
+        } else if x < self.range.max_x {
+            Some((x + 1, self.range.min_y))
+        } else {
+            None
+        };
+        Some(Tile{ zoom: self.range.zoom, x, y })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = match self.next {
+            None => 0,
+            Some((x, y)) => {
+                let height = (self.range.max_y - self.range.min_y + 1) as u64;
+                (self.range.max_x - x) as u64 * height + (self.range.max_y - y + 1) as u64
+            },
+        };
+        if remaining > usize::MAX as u64 {
+            (usize::MAX, None)
+        } else {
+            (remaining as usize, Some(remaining as usize))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tile_range() {
+        assert!(TileRange::new(1, 0, 2, 0, 0).is_none());
+        assert!(TileRange::new(1, 1, 0, 0, 0).is_none());
+        assert!(TileRange::new(32, 0, 0, 0, 0).is_none());
+
+        let range = TileRange::all(2).unwrap();
+        assert_eq!(range.num_tiles(), 16);
+        assert_eq!(range.iter().count(), 16);
+        assert_eq!(range.iter().size_hint(), (16, Some(16)));
+        let mut iter = range.iter();
+        iter.nth(5);
+        assert_eq!(iter.size_hint(), (10, Some(10)));
+        assert_eq!(iter.count(), 10);
+
+        let range = TileRange::new(3, 2, 3, 4, 6).unwrap();
+        let tiles: Vec<String> = range.into_iter().map(|t| t.zxy()).collect();
+        assert_eq!(tiles, vec!["3/2/4", "3/2/5", "3/2/6", "3/3/4", "3/3/5", "3/3/6"]);
+        assert!(!range.contains(&Tile::new(3, 4, 4).unwrap()));
+        assert!(!range.contains(&Tile::new(2, 2, 3).unwrap()));
+
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        let range = TileRange::new_for_bbox(&bbox, 5).unwrap();
+        assert_eq!(range, TileRange::new(5, 14, 15, 10, 10).unwrap());
+        assert!(range.bbox().overlaps_bbox(&bbox));
+        assert_eq!(TileRange::new_for_bbox(&BBox::new(85., 0., -85., 180.).unwrap(), 1), TileRange::new(1, 1, 1, 0, 1));
+
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        assert_eq!(TileRange::new_for_bbox(&bbox, 12), TileRange::new(12, 1919, 1991, 1281, 1369));
+        assert_eq!(TileRange::new_for_bbox_metatiles(&bbox, 12, (8, 8)), TileRange::new(12, 239, 248, 160, 171));
+        assert_eq!(TileRange::new_for_bbox_metatiles(&bbox, 12, (4, 2)), TileRange::new(12, 479, 497, 640, 684));
+        // Crosses the antimeridian
+        let bbox = BBox::new(10., 170., 0., -170.).unwrap();
+        assert_eq!(TileRange::new_for_bbox(&bbox, 0), None);
+        assert_eq!(TileRange::new_for_bbox_metatiles(&bbox, 12, (8, 8)), None);
+    }
+
+    #[test]
+    fn tile_range_split() {
+        let range = TileRange::new(4, 3, 12, 5, 6).unwrap();
+        let (a, b) = range.split().unwrap();
+        assert_eq!(a, TileRange::new(4, 3, 7, 5, 6).unwrap());
+        assert_eq!(b, TileRange::new(4, 8, 12, 5, 6).unwrap());
+        let (a, b) = TileRange::new(4, 3, 3, 5, 6).unwrap().split().unwrap();
+        assert_eq!((a.max_y(), b.min_y()), (5, 6));
+        assert!(TileRange::new(4, 3, 3, 5, 5).unwrap().split().is_none());
+
+        let (a, b) = TileRange::all(3).unwrap().split().unwrap();
+        assert_eq!(a, TileRange::new(3, 0, 3, 0, 7).unwrap());
+        assert_eq!(b, TileRange::new(3, 4, 7, 0, 7).unwrap());
+    }
+}
@@ -0,0 +1,271 @@
+//! URL templates for tiles, like `https://tile.example.org/{z}/{x}/{y}.png`
+//!
+//! The zoom can be `{z}`, `{zoom}` or `{TileMatrix}`, the x `{x}` or `{TileCol}`, and the y `{y}`
+//! or `{TileRow}`, so RESTful WMTS templates work too. `{-y}` is the TMS y (counted from the
+//! bottom), and `{quadkey}` (or `{q}`) a Bing Maps quadkey, which can be used instead of the zoom,
+//! x & y. Any other `{placeholder}` matches one path segment when parsing, and is left as is when
+//! making a URL.
+//!
+//! ```
+//! use slippy_map_tiles::{Tile, UrlTemplate};
+//!
+//! let template = UrlTemplate::new("https://tile.example.org/{z}/{x}/{y}.png").unwrap();
+//! let t = Tile::new(10, 547, 380).unwrap();
+//! assert_eq!(template.url(&t), "https://tile.example.org/10/547/380.png");
+//! assert_eq!(template.parse("https://tile.example.org/10/547/380.png"), Some(t));
+//! ```
+use regex::{self, Regex};
+
+use {Tile, flip_y};
+
+/// What a `{placeholder}` in a template is
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Part {
+    Literal(String),
+    Zoom,
+    X,
+    Y,
+    TmsY,
+    Quadkey,
+    Other(String),
+}
+
+/// A URL template for tiles
+#[derive(Debug, Clone)]
+pub struct UrlTemplate {
+    template: String,
+    parts: Vec<Part>,
+    regex: Regex,
+}
+
+impl PartialEq for UrlTemplate {
+    fn eq(&self, other: &UrlTemplate) -> bool {
+        self.template == other.template
+    }
+}
+
+impl UrlTemplate {
+    /// Parse this template. Returns `Err` if there are unbalanced `{`/`}`, or the template
+    /// doesn't include all of the zoom, x & y (or TMS y), or a quadkey.
+    ///
+    /// ```
+    /// # use slippy_map_tiles::{Tile, UrlTemplate};
+    /// let t = Tile::new(3, 3, 5).unwrap();
+    /// assert_eq!(UrlTemplate::new("/tms/{z}/{x}/{-y}.png").unwrap().url(&t), "/tms/3/3/2.png");
+    /// assert_eq!(UrlTemplate::new("/bing/{quadkey}.jpeg").unwrap().parse("/bing/213.jpeg"), Some(t));
+    /// ```
+    pub fn new(template: &str) -> Result<Self, &'static str> {
+        let parts = split_template(template)?;
+        let has_y = parts.contains(&Part::Y) || parts.contains(&Part::TmsY);
+        if !parts.contains(&Part::Quadkey) && (!parts.contains(&Part::Zoom) || !parts.contains(&Part::X) || !has_y) {
+            return Err("URL template needs a zoom, x and y, or a quadkey");
+        }
+
+        // When parsing, we only look at the path, since the same tiles can be served from many
+        // hosts.
+        let mut re = String::from(r"^(?:[a-zA-Z][a-zA-Z0-9+.-]*://[^/]*)?");
+        let mut seen = (false, false, false, false, false);
+        for (i, part) in parts.iter().enumerate() {
+            match *part {
+                Part::Literal(ref s) => {
+                    let s = if i == 0 { strip_origin(s) } else { s.as_str() };
+                    re.push_str(&regex::quote(s));
+                },
+                Part::Zoom if !seen.0 => { seen.0 = true; re.push_str("(?P<zoom>[0-9]{1,2})"); },
+                Part::X if !seen.1 => { seen.1 = true; re.push_str("(?P<x>[0-9]{1,10})"); },
+                Part::Y if !seen.2 => { seen.2 = true; re.push_str("(?P<y>[0-9]{1,10})"); },
+                Part::TmsY if !seen.3 => { seen.3 = true; re.push_str("(?P<tmsy>[0-9]{1,10})"); },
+                Part::Quadkey if !seen.4 => { seen.4 = true; re.push_str("(?P<quadkey>[0-3]{0,31})"); },
+                Part::Zoom | Part::X | Part::Y | Part::TmsY => { re.push_str("[0-9]{1,10}"); },
+                Part::Quadkey => { re.push_str("[0-3]{0,31}"); },
+                Part::Other(_) => { re.push_str("[^/?#]+"); },
+            }
+        }
+        re.push_str(r"(?:[?#].*)?$");
+
+        let regex = Regex::new(&re).map_err(|_| "invalid URL template")?;
+
+        Ok(UrlTemplate{ template: template.to_string(), parts, regex })
+    }
+
+    /// The template, as given to `new`
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// The URL for this tile.
+    pub fn url(&self, tile: &Tile) -> String {
+        self.parts.iter().map(|part| {
+            match *part {
+                Part::Literal(ref s) => s.clone(),
+                Part::Zoom => tile.zoom().to_string(),
+                Part::X => tile.x().to_string(),
+                Part::Y => tile.y().to_string(),
+                Part::TmsY => flip_y(tile.zoom(), tile.y()).unwrap().to_string(),
+                Part::Quadkey => tile.quadkey(),
+                Part::Other(ref name) => format!("{{{}}}", name),
+            }
+        }).collect()
+    }
+
+    /// This template, with the zoom/x/y placeholders replaced with the WMTS names
+    /// (`{TileMatrix}`, `{TileCol}`, `{TileRow}`).
+    pub fn wmts_template(&self) -> String {
+        self.parts.iter().map(|part| {
+            match *part {
+                Part::Literal(ref s) => s.clone(),
+                Part::Zoom => "{TileMatrix}".to_string(),
+                Part::X => "{TileCol}".to_string(),
+                Part::Y => "{TileRow}".to_string(),
+                Part::TmsY => "{-y}".to_string(),
+                Part::Quadkey => "{quadkey}".to_string(),
+                Part::Other(ref name) => format!("{{{}}}", name),
+            }
+        }).collect()
+    }
+
+    /// Replace the `{name}` placeholder with `value`.
+    pub fn with_value(&self, name: &str, value: &str) -> UrlTemplate {
+        let template: String = self.parts.iter().map(|part| {
+            match *part {
+                Part::Literal(ref s) => s.clone(),
+                Part::Zoom => "{z}".to_string(),
+                Part::X => "{x}".to_string(),
+                Part::Y => "{y}".to_string(),
+                Part::TmsY => "{-y}".to_string(),
+                Part::Quadkey => "{quadkey}".to_string(),
+                Part::Other(ref n) if n == name => value.replace(['{', '}'], ""),
+                Part::Other(ref n) => format!("{{{}}}", n),
+            }
+        }).collect();
+        UrlTemplate::new(&template).unwrap()
+    }
+
+    /// Parse a URL (or just the path part of it) back into a tile. Returns `None` if this URL
+    /// doesn't match the template, or the tile is invalid.
+    pub fn parse(&self, url: &str) -> Option<Tile> {
+        let caps = self.regex.captures(url)?;
+        if let Some(quadkey) = caps.name("quadkey") {
+            return Tile::from_quadkey(quadkey);
+        }
+        let zoom = caps.name("zoom")?.parse().ok()?;
+        let x = caps.name("x")?.parse().ok()?;
+        let y = match caps.name("y") {
+            Some(y) => y.parse().ok()?,
+            None => flip_y(zoom, caps.name("tmsy")?.parse().ok()?)?,
+        };
+        Tile::new(zoom, x, y)
+    }
+}
+
+/// Remove the `scheme://host` from the start of a URL
+fn strip_origin(s: &str) -> &str {
+    match s.find("://") {
+        None => s,
+        Some(i) => {
+            let rest = &s[i+3..];
+            match rest.find('/') {
+                None => "",
+                Some(j) => &rest[j..],
+            }
+        }
+    }
+}
+
+fn split_template(template: &str) -> Result<Vec<Part>, &'static str> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while !rest.is_empty() {
+        match rest.find('{') {
+            None => {
+                if rest.contains('}') {
+                    return Err("unbalanced } in URL template");
+                }
+                parts.push(Part::Literal(rest.to_string()));
+                break;
+            },
+            Some(start) => {
+                if rest[..start].contains('}') {
+                    return Err("unbalanced } in URL template");
+                }
+                if start > 0 {
+                    parts.push(Part::Literal(rest[..start].to_string()));
+                }
+                let end = rest[start..].find('}').ok_or("unbalanced { in URL template")? + start;
+                let name = &rest[start+1..end];
+                if name.is_empty() || name.contains('{') {
+                    return Err("invalid placeholder in URL template");
+                }
+                parts.push(match name {
+                    "z" | "zoom" | "TileMatrix" => Part::Zoom,
+                    "x" | "TileCol" => Part::X,
+                    "y" | "TileRow" => Part::Y,
+                    "-y" => Part::TmsY,
+                    "quadkey" | "q" => Part::Quadkey,
+                    _ => Part::Other(name.to_string()),
+                });
+                rest = &rest[end+1..];
+            }
+        }
+    }
+
+    Ok(parts)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn url_template_xyz() {
+        let template = UrlTemplate::new("/tiles/{z}/{x}/{y}.png").unwrap();
+        let t = Tile::new(3, 1, 2).unwrap();
+        assert_eq!(template.url(&t), "/tiles/3/1/2.png");
+        assert_eq!(template.parse("/tiles/3/1/2.png"), Some(t));
+        assert_eq!(template.parse("http://example.org/tiles/3/1/2.png"), Some(t));
+        assert_eq!(template.parse("/tiles/3/1/2.png?v=2"), Some(t));
+        assert_eq!(template.parse("/tiles/3/1/2.jpg"), None);
+        assert_eq!(template.parse("/tiles/3/10/2.png"), None);
+        assert_eq!(template.parse("/foo/tiles/3/1/2.png"), None);
+    }
+
+    #[test]
+    fn url_template_wmts() {
+        let template = UrlTemplate::new("https://example.org/wmts/{Layer}/{Style}/{TileMatrixSet}/{TileMatrix}/{TileRow}/{TileCol}.png").unwrap();
+        let t = Tile::new(5, 10, 20).unwrap();
+        assert_eq!(template.parse("https://example.org/wmts/roads/default/GoogleMapsCompatible/5/20/10.png"), Some(t));
+        assert_eq!(template.parse("/wmts/roads/default/GoogleMapsCompatible/5/20/10.png"), Some(t));
+        assert_eq!(template.url(&t), "https://example.org/wmts/{Layer}/{Style}/{TileMatrixSet}/5/20/10.png");
+
+        let template = UrlTemplate::new("/{Layer}/{z}/{x}/{y}.png").unwrap();
+        assert_eq!(template.wmts_template(), "/{Layer}/{TileMatrix}/{TileCol}/{TileRow}.png");
+        assert_eq!(template.with_value("Layer", "roads").url(&t), "/roads/5/10/20.png");
+    }
+
+    #[test]
+    fn url_template_tms_quadkey() {
+        let template = UrlTemplate::new("/tms/1.0.0/osm/{z}/{x}/{-y}.png").unwrap();
+        let t = Tile::new(3, 1, 2).unwrap();
+        assert_eq!(template.url(&t), "/tms/1.0.0/osm/3/1/5.png");
+        assert_eq!(template.parse("/tms/1.0.0/osm/3/1/5.png"), Some(t));
+        assert_eq!(template.parse("/tms/1.0.0/osm/3/1/8.png"), None);
+        assert_eq!(template.wmts_template(), "/tms/1.0.0/osm/{TileMatrix}/{TileCol}/{-y}.png");
+
+        let template = UrlTemplate::new("https://example.org/{layer}/{q}.jpeg?g=1").unwrap();
+        assert_eq!(template.url(&t), "https://example.org/{layer}/021.jpeg?g=1");
+        assert_eq!(template.parse("/aerial/021.jpeg?g=1"), Some(t));
+        assert_eq!(template.parse("/aerial/.jpeg?g=1"), Tile::new(0, 0, 0));
+        assert_eq!(template.parse("/aerial/024.jpeg?g=1"), None);
+        assert_eq!(template.with_value("layer", "aerial").as_str(), "https://example.org/aerial/{quadkey}.jpeg?g=1");
+    }
+
+    #[test]
+    fn url_template_invalid() {
+        assert!(UrlTemplate::new("/{z}/{x}.png").is_err());
+        assert!(UrlTemplate::new("/{z}/{-y}.png").is_err());
+        assert!(UrlTemplate::new("/{z}/{x}/{y.png").is_err());
+        assert!(UrlTemplate::new("/{z}/{x}/y}.png").is_err());
+        assert!(UrlTemplate::new("/{z}/{}/{x}/{y}.png").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Publishing tiles over WMTS 1.0.0 (RESTful).
+//!
+//! ```
+//! use slippy_map_tiles::BBox;
+//! use slippy_map_tiles::wmts::{WmtsLayer, get_capabilities};
+//!
+//! let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+//! let layer = WmtsLayer::new("ireland", "Ireland", vec!["image/png".to_string()], bbox, 0, 18,
+//!                            "https://tile.example.org/ireland/{z}/{x}/{y}.png").unwrap();
+//! let xml = get_capabilities("Example tiles", &[layer]);
+//! assert!(xml.contains("<TileMatrixSet>GoogleMapsCompatible</TileMatrixSet>"));
+//! ```
+use {Tile, BBox, TileMatrixSet, UrlTemplate, lat_lon_to_tile};
+
+/// Identifier of the WebMercatorQuad tile matrix set in WMTS 1.0.0
+pub const GOOGLE_MAPS_COMPATIBLE: &str = "GoogleMapsCompatible";
+
+/// A layer of tiles (in the usual EPSG:3857 tiling) to publish over WMTS.
+#[derive(PartialEq, Debug, Clone)]
+pub struct WmtsLayer {
+    identifier: String,
+    title: String,
+    formats: Vec<String>,
+    bbox: BBox,
+    minzoom: u8,
+    maxzoom: u8,
+    url_template: UrlTemplate,
+}
+
+impl WmtsLayer {
+    /// A new layer. `formats` are MIME types, e.g. `image/png`. The URL template can use `{z}`,
+    /// `{x}`, `{y}` or the WMTS names. If there is more than one format, use `{ext}` in the template
+    /// for the file extension of each format.
+    ///
+    /// Returns `None` if there are no formats, the zooms are invalid (`minzoom` > `maxzoom`, or
+    /// above 24), or the URL template is invalid.
+    pub fn new(identifier: &str, title: &str, formats: Vec<String>, bbox: BBox, minzoom: u8, maxzoom: u8, url_template: &str) -> Option<Self> {
+        if formats.is_empty() || minzoom > maxzoom || maxzoom > 24 {
+            return None;
+        }
+        let url_template = UrlTemplate::new(url_template).ok()?;
+
+        Some(WmtsLayer{ identifier: identifier.to_string(), title: title.to_string(), formats, bbox, minzoom, maxzoom, url_template })
+    }
+
+    pub fn identifier(&self) -> &str { &self.identifier }
+
+    pub fn title(&self) -> &str { &self.title }
+
+    pub fn formats(&self) -> &[String] { &self.formats }
+
+    pub fn bbox(&self) -> &BBox { &self.bbox }
+
+    pub fn minzoom(&self) -> u8 { self.minzoom }
+
+    pub fn maxzoom(&self) -> u8 { self.maxzoom }
+
+    pub fn url_template(&self) -> &UrlTemplate { &self.url_template }
+
+    /// Parse a RESTful WMTS tile URL for this layer back into a tile. Returns `None` if it doesn't
+    /// match the URL template, or it's outside this layer's zooms.
+    pub fn parse_tile_url(&self, url: &str) -> Option<Tile> {
+        let tile = self.url_template.parse(url)?;
+        if tile.zoom() < self.minzoom || tile.zoom() > self.maxzoom {
+            None
+        } else {
+            Some(tile)
+        }
+    }
+
+    /// The (min col, max col, min row, max row) of the tiles which this layer has at this zoom.
+    fn tile_limits(&self, zoom: u8) -> (u32, u32, u32, u32) {
+        let max = 2u32.pow(zoom as u32) - 1;
+        let (minx, miny) = lat_lon_to_tile(self.bbox.top(), self.bbox.left(), zoom);
+        let (maxx, maxy) = lat_lon_to_tile(self.bbox.bottom(), self.bbox.right(), zoom);
+        (minx.min(max), maxx.min(max), miny.min(max), maxy.min(max))
+    }
+
+    fn write_xml(&self, xml: &mut String) {
+        xml.push_str("    <Layer>\n");
+        xml.push_str(&format!("      <ows:Title>{}</ows:Title>\n", escape(&self.title)));
+        xml.push_str("      <ows:WGS84BoundingBox>\n");
+        xml.push_str(&format!("        <ows:LowerCorner>{} {}</ows:LowerCorner>\n", self.bbox.left(), self.bbox.bottom()));
+        xml.push_str(&format!("        <ows:UpperCorner>{} {}</ows:UpperCorner>\n", self.bbox.right(), self.bbox.top()));
+        xml.push_str("      </ows:WGS84BoundingBox>\n");
+        xml.push_str(&format!("      <ows:Identifier>{}</ows:Identifier>\n", escape(&self.identifier)));
+        xml.push_str("      <Style isDefault=\"true\">\n        <ows:Identifier>default</ows:Identifier>\n      </Style>\n");
+        for format in &self.formats {
+            xml.push_str(&format!("      <Format>{}</Format>\n", escape(format)));
+        }
+
+        xml.push_str("      <TileMatrixSetLink>\n");
+        xml.push_str(&format!("        <TileMatrixSet>{}</TileMatrixSet>\n", GOOGLE_MAPS_COMPATIBLE));
+        xml.push_str("        <TileMatrixSetLimits>\n");
+        for zoom in self.minzoom..=self.maxzoom {
+            let (mincol, maxcol, minrow, maxrow) = self.tile_limits(zoom);
+            xml.push_str("          <TileMatrixLimits>\n");
+            xml.push_str(&format!("            <TileMatrix>{}</TileMatrix>\n", zoom));
+            xml.push_str(&format!("            <MinTileRow>{}</MinTileRow>\n", minrow));
+            xml.push_str(&format!("            <MaxTileRow>{}</MaxTileRow>\n", maxrow));
+            xml.push_str(&format!("            <MinTileCol>{}</MinTileCol>\n", mincol));
+            xml.push_str(&format!("            <MaxTileCol>{}</MaxTileCol>\n", maxcol));
+            xml.push_str("          </TileMatrixLimits>\n");
+        }
+        xml.push_str("        </TileMatrixSetLimits>\n");
+        xml.push_str("      </TileMatrixSetLink>\n");
+
+        let template = self.url_template.with_value("TileMatrixSet", GOOGLE_MAPS_COMPATIBLE).with_value("Style", "default").with_value("Layer", &self.identifier);
+        for format in &self.formats {
+            let template = template.with_value("ext", format_extension(format));
+            xml.push_str(&format!("      <ResourceURL format=\"{}\" resourceType=\"tile\" template=\"{}\"/>\n", escape(format), escape(&template.wmts_template())));
+        }
+        xml.push_str("    </Layer>\n");
+    }
+}
+
+/// The WMTS 1.0.0 `GetCapabilities` XML document for these layers, all in the
+/// `GoogleMapsCompatible` tile matrix set.
+pub fn get_capabilities(service_title: &str, layers: &[WmtsLayer]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<Capabilities xmlns=\"http://www.opengis.net/wmts/1.0\" xmlns:ows=\"http://www.opengis.net/ows/1.1\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns:gml=\"http://www.opengis.net/gml\" xsi:schemaLocation=\"http://www.opengis.net/wmts/1.0 http://schemas.opengis.net/wmts/1.0/wmtsGetCapabilities_response.xsd\" version=\"1.0.0\">\n");
+    xml.push_str("  <ows:ServiceIdentification>\n");
+    xml.push_str(&format!("    <ows:Title>{}</ows:Title>\n", escape(service_title)));
+    xml.push_str("    <ows:ServiceType>OGC WMTS</ows:ServiceType>\n");
+    xml.push_str("    <ows:ServiceTypeVersion>1.0.0</ows:ServiceTypeVersion>\n");
+    xml.push_str("  </ows:ServiceIdentification>\n");
+    xml.push_str("  <Contents>\n");
+
+    for layer in layers {
+        layer.write_xml(&mut xml);
+    }
+
+    // Only include the zooms that are used
+    let maxzoom = layers.iter().map(|l| l.maxzoom).max().unwrap_or(0);
+    let tms = TileMatrixSet::web_mercator_quad();
+    xml.push_str("    <TileMatrixSet>\n");
+    xml.push_str(&format!("      <ows:Identifier>{}</ows:Identifier>\n", GOOGLE_MAPS_COMPATIBLE));
+    xml.push_str("      <ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>\n");
+    xml.push_str("      <WellKnownScaleSet>urn:ogc:def:wkss:OGC:1.0:GoogleMapsCompatible</WellKnownScaleSet>\n");
+    for tm in tms.tile_matrices().iter().take(maxzoom as usize + 1) {
+        let (origin_x, origin_y) = tm.point_of_origin();
+        xml.push_str("      <TileMatrix>\n");
+        xml.push_str(&format!("        <ows:Identifier>{}</ows:Identifier>\n", tm.id()));
+        xml.push_str(&format!("        <ScaleDenominator>{}</ScaleDenominator>\n", tm.scale_denominator()));
+        xml.push_str(&format!("        <TopLeftCorner>{} {}</TopLeftCorner>\n", origin_x, origin_y));
+        xml.push_str(&format!("        <TileWidth>{}</TileWidth>\n", tm.tile_width()));
+        xml.push_str(&format!("        <TileHeight>{}</TileHeight>\n", tm.tile_height()));
+        xml.push_str(&format!("        <MatrixWidth>{}</MatrixWidth>\n", tm.matrix_width()));
+        xml.push_str(&format!("        <MatrixHeight>{}</MatrixHeight>\n", tm.matrix_height()));
+        xml.push_str("      </TileMatrix>\n");
+    }
+    xml.push_str("    </TileMatrixSet>\n");
+
+    xml.push_str("  </Contents>\n");
+    xml.push_str("</Capabilities>\n");
+
+    xml
+}
+
+/// The usual file extension for this MIME type
+fn format_extension(format: &str) -> &str {
+    match format {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "application/vnd.mapbox-vector-tile" | "application/x-protobuf" => "pbf",
+        _ => format.rsplit('/').next().unwrap_or(format),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ireland() -> WmtsLayer {
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        WmtsLayer::new("ie", "Ireland & NI", vec!["image/png".to_string(), "image/jpeg".to_string()], bbox, 0, 6, "https://example.org/{Layer}/{TileMatrixSet}/{z}/{x}/{y}.{ext}").unwrap()
+    }
+
+    #[test]
+    fn wmts_layer_new() {
+        let bbox = BBox::new(55.7, -11.32, 51.11, -4.97).unwrap();
+        assert!(WmtsLayer::new("ie", "Ireland", vec![], bbox.clone(), 0, 6, "/{z}/{x}/{y}.png").is_none());
+        assert!(WmtsLayer::new("ie", "Ireland", vec!["image/png".to_string()], bbox.clone(), 7, 6, "/{z}/{x}/{y}.png").is_none());
+        assert!(WmtsLayer::new("ie", "Ireland", vec!["image/png".to_string()], bbox.clone(), 0, 6, "/{z}/{x}.png").is_none());
+        assert!(WmtsLayer::new("ie", "Ireland", vec!["image/png".to_string()], bbox, 0, 6, "/{z}/{x}/{y}.png").is_some());
+    }
+
+    #[test]
+    fn capabilities() {
+        let xml = get_capabilities("Test", &[ireland()]);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Capabilities "));
+        assert!(xml.contains("<ows:Title>Ireland &amp; NI</ows:Title>"));
+        assert!(xml.contains("<ows:LowerCorner>-11.32 51.11</ows:LowerCorner>"));
+        assert!(xml.contains("<ows:UpperCorner>-4.97 55.7</ows:UpperCorner>"));
+        assert!(xml.contains("<ResourceURL format=\"image/png\" resourceType=\"tile\" template=\"https://example.org/ie/GoogleMapsCompatible/{TileMatrix}/{TileCol}/{TileRow}.png\"/>"));
+        assert!(xml.contains("<ResourceURL format=\"image/jpeg\" resourceType=\"tile\" template=\"https://example.org/ie/GoogleMapsCompatible/{TileMatrix}/{TileCol}/{TileRow}.jpg\"/>"));
+        assert!(xml.contains("<TileMatrix>6</TileMatrix>\n            <MinTileRow>20</MinTileRow>\n            <MaxTileRow>21</MaxTileRow>\n            <MinTileCol>29</MinTileCol>\n            <MaxTileCol>31</MaxTileCol>"));
+
+        // Tile matrices 0 to 6
+        assert_eq!(xml.matches("<TileMatrix>\n").count(), 7);
+        assert!(xml.contains("<ScaleDenominator>559082264.0287178</ScaleDenominator>"));
+        assert!(xml.contains("<TopLeftCorner>-20037508.342789244 20037508.342789244</TopLeftCorner>"));
+        assert!(xml.contains("<MatrixWidth>64</MatrixWidth>"));
+        assert!(xml.ends_with("</Capabilities>\n"));
+    }
+
+    #[test]
+    fn parse_wmts_url() {
+        let layer = ireland();
+        assert_eq!(layer.parse_tile_url("https://example.org/ie/GoogleMapsCompatible/6/30/20.png"), Tile::new(6, 30, 20));
+        assert_eq!(layer.parse_tile_url("/ie/GoogleMapsCompatible/6/30/20.jpg"), Tile::new(6, 30, 20));
+        assert_eq!(layer.parse_tile_url("/ie/GoogleMapsCompatible/7/30/20.png"), None);
+        assert_eq!(layer.parse_tile_url("/ie/GoogleMapsCompatible/6/30.png"), None);
+    }
+}